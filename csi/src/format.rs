@@ -46,3 +46,63 @@ pub(crate) async fn prepare_device(
         String::from_utf8(output.stderr).unwrap()
     ))
 }
+
+/// Grow the filesystem on a device to fill the (already resized) device.
+/// Returns false if the device does not contain a filesystem, ie. it is
+/// used as a raw block volume, in which case there is nothing to do.
+pub(crate) async fn resize_filesystem(
+    device: &str,
+    mountpoint: &str,
+) -> Result<bool, String> {
+    debug!("Probing device {}", device);
+
+    let probe = Probe::new_from_filename(device)
+        .map_err(|error| format!("probe setup failed: {}", error))?;
+
+    if let Err(error) = probe.do_probe() {
+        return Err(format!("probe failed: {}", error));
+    }
+
+    let fstype = match probe.lookup_value("TYPE") {
+        Ok(fs) => fs,
+        Err(_) => {
+            debug!("No filesystem found on device {}", device);
+            return Ok(false);
+        }
+    };
+
+    // xfs can only be grown while mounted, and takes the mountpoint
+    let (binary, target) = match fstype.as_str() {
+        "ext2" | "ext3" | "ext4" => ("resize2fs", device),
+        "xfs" => ("xfs_growfs", mountpoint),
+        _ => {
+            return Err(format!(
+                "resizing a {} filesystem is not supported",
+                fstype
+            ))
+        }
+    };
+
+    debug!("Resizing {} filesystem on device {}", fstype, device);
+
+    let output = Command::new(binary)
+        .arg(target)
+        .output()
+        .map_err(|error| format!("failed to execute {}: {}", binary, error))?;
+
+    trace!(
+        "Output from {} command: {}",
+        binary,
+        String::from_utf8(output.stdout.clone()).unwrap()
+    );
+
+    if output.status.success() {
+        return Ok(true);
+    }
+
+    Err(format!(
+        "{} command failed: {}",
+        binary,
+        String::from_utf8(output.stderr).unwrap()
+    ))
+}
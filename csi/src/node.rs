@@ -6,6 +6,7 @@ use std::{
     vec::Vec,
 };
 
+use tokio::time::sleep;
 use tonic::{Code, Request, Response, Status};
 
 macro_rules! failure {
@@ -28,6 +29,7 @@ use crate::{
         unpublish_fs_volume,
         unstage_fs_volume,
     },
+    format::resize_filesystem,
 };
 
 #[derive(Clone, Debug)]
@@ -127,6 +129,49 @@ async fn detach(uuid: &Uuid, errheader: String) -> Result<(), Status> {
     Ok(())
 }
 
+/// Ask the kernel to re-read the capacity of an attached device, and wait
+/// for it to reach the required size (the rescan is asynchronous).
+/// Returns the new capacity of the device in bytes.
+async fn rescan_device(
+    device_path: &str,
+    required: u64,
+) -> Result<u64, String> {
+    let name = device_path.trim_start_matches("/dev/");
+    let path = Path::new("/sys/block").join(name);
+
+    // NVMe namespaces are rescanned through their controller,
+    // SCSI (iSCSI) disks through the device itself.
+    let file = if name.starts_with("nvme") {
+        "rescan_controller"
+    } else {
+        "rescan"
+    };
+
+    sysfs::write_value(&path.join("device"), file, 1).map_err(|error| {
+        format!("failed to rescan device {}: {}", device_path, error)
+    })?;
+
+    let mut capacity = 0;
+
+    for _ in 0 ..= ATTACH_RETRIES {
+        // sysfs reports the size in 512 byte sectors regardless
+        // of the logical block size of the device
+        capacity =
+            sysfs::parse_value::<u64>(&path, "size").map_err(|error| {
+                format!("failed to read size of {}: {}", device_path, error)
+            })? * 512;
+        if capacity >= required {
+            return Ok(capacity);
+        }
+        sleep(ATTACH_TIMEOUT_INTERVAL).await;
+    }
+
+    Err(format!(
+        "device {} has size {} after rescan, expected at least {}",
+        device_path, capacity, required
+    ))
+}
+
 impl Node {}
 #[tonic::async_trait]
 impl node_server::Node for Node {
@@ -156,11 +201,13 @@ impl node_server::Node for Node {
         &self,
         _request: Request<NodeGetCapabilitiesRequest>,
     ) -> Result<Response<NodeGetCapabilitiesResponse>, Status> {
-        let caps = vec![node_service_capability::rpc::Type::StageUnstageVolume];
+        let caps = vec![
+            node_service_capability::rpc::Type::StageUnstageVolume,
+            node_service_capability::rpc::Type::ExpandVolume,
+        ];
 
         debug!("NodeGetCapabilities request: {:?}", caps);
 
-        // We don't support stage/unstage and expand volume rpcs
         Ok(Response::new(NodeGetCapabilitiesResponse {
             capabilities: caps
                 .into_iter()
@@ -357,8 +404,91 @@ impl node_server::Node for Node {
         request: Request<NodeExpandVolumeRequest>,
     ) -> Result<Response<NodeExpandVolumeResponse>, Status> {
         let msg = request.into_inner();
-        error!("Unimplemented {:?}", msg);
-        Err(Status::new(Code::Unimplemented, "Method not implemented"))
+
+        trace!("node_expand_volume {:?}", msg);
+
+        if msg.volume_id.is_empty() {
+            return Err(failure!(
+                Code::InvalidArgument,
+                "Failed to expand volume: missing volume id"
+            ));
+        }
+
+        if msg.volume_path.is_empty() {
+            return Err(failure!(
+                Code::InvalidArgument,
+                "Failed to expand volume {}: missing volume path",
+                &msg.volume_id
+            ));
+        }
+
+        let uuid = Uuid::parse_str(&msg.volume_id).map_err(|error| {
+            failure!(
+                Code::InvalidArgument,
+                "Failed to expand volume {}: not a valid UUID: {}",
+                &msg.volume_id,
+                error
+            )
+        })?;
+
+        let device = Device::lookup(&uuid)
+            .await
+            .map_err(|error| {
+                failure!(
+                    Code::Internal,
+                    "Failed to expand volume {}: error locating device: {}",
+                    &msg.volume_id,
+                    error
+                )
+            })?
+            .ok_or_else(|| {
+                failure!(
+                    Code::NotFound,
+                    "Failed to expand volume {}: device not found",
+                    &msg.volume_id
+                )
+            })?;
+
+        let device_path = device.devname();
+        let required = msg
+            .capacity_range
+            .as_ref()
+            .map_or(0, |range| range.required_bytes.max(0) as u64);
+
+        debug!("Expanding volume {} on {}", &msg.volume_id, device_path);
+
+        let capacity =
+            rescan_device(&device_path, required)
+                .await
+                .map_err(|error| {
+                    failure!(
+                        Code::Internal,
+                        "Failed to expand volume {}: {}",
+                        &msg.volume_id,
+                        error
+                    )
+                })?;
+
+        // raw block volumes have no filesystem and need nothing further
+        if resize_filesystem(&device_path, &msg.volume_path)
+            .await
+            .map_err(|error| {
+                failure!(
+                    Code::Internal,
+                    "Failed to expand volume {}: {}",
+                    &msg.volume_id,
+                    error
+                )
+            })?
+        {
+            info!("Filesystem on volume {} expanded", &msg.volume_id);
+        }
+
+        info!("Volume {} expanded to {} bytes", &msg.volume_id, capacity);
+
+        Ok(Response::new(NodeExpandVolumeResponse {
+            capacity_bytes: capacity as i64,
+        }))
     }
 
     async fn node_stage_volume(
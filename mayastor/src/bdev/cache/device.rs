@@ -0,0 +1,656 @@
+//!
+//! Block device, descriptor and IO handle of a device with a read cache.
+//!
+//! Reads entirely held by the cache are served from the cache device, other
+//! reads are served by the cached device and the lines they entirely cover
+//! are written to the cache before the read completes. Writes, unmaps and
+//! write zeroes invalidate the lines they touch both when they are submitted
+//! and when they complete, so that data read concurrently is never cached
+//! once the write has completed.
+
+use std::{os::raw::c_void, sync::Arc};
+
+use async_trait::async_trait;
+
+use spdk_sys::iovec;
+
+use crate::{
+    bdev::device_open,
+    core::{
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        BlockDeviceIoStats,
+        CoreError,
+        DeviceEventListener,
+        DeviceIoController,
+        DmaBuf,
+        DmaError,
+        GenericStatusCode,
+        IoCompletionCallback,
+        IoCompletionCallbackArg,
+        IoCompletionStatus,
+        IoType,
+        NvmeCommandStatus,
+    },
+};
+
+use super::CacheState;
+
+/// A block device with a read cache in front of it.
+pub(crate) struct CacheBlockDevice {
+    /// the cached device
+    device: Box<dyn BlockDevice>,
+    state: Arc<CacheState>,
+}
+
+impl CacheBlockDevice {
+    pub(super) fn new(
+        device: Box<dyn BlockDevice>,
+        state: Arc<CacheState>,
+    ) -> Self {
+        Self {
+            device,
+            state,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BlockDevice for CacheBlockDevice {
+    fn size_in_bytes(&self) -> u64 {
+        self.device.size_in_bytes()
+    }
+
+    fn block_len(&self) -> u64 {
+        self.device.block_len()
+    }
+
+    fn num_blocks(&self) -> u64 {
+        self.device.num_blocks()
+    }
+
+    fn uuid(&self) -> uuid::Uuid {
+        self.device.uuid()
+    }
+
+    fn product_name(&self) -> String {
+        self.device.product_name()
+    }
+
+    fn driver_name(&self) -> String {
+        self.device.driver_name()
+    }
+
+    fn device_name(&self) -> String {
+        self.device.device_name()
+    }
+
+    /// IO buffers must suit both the cached device and the cache
+    fn alignment(&self) -> u64 {
+        self.device.alignment().max(self.state.alignment)
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        self.device.io_type_supported(io_type)
+    }
+
+    async fn io_stats(&self) -> Result<BlockDeviceIoStats, CoreError> {
+        self.device.io_stats().await
+    }
+
+    fn claimed_by(&self) -> Option<String> {
+        self.device.claimed_by()
+    }
+
+    /// open the cached device and the cache, which starts out empty
+    fn open(
+        &self,
+        read_write: bool,
+    ) -> Result<Box<dyn BlockDeviceDescriptor>, CoreError> {
+        let device = self.device.open(read_write)?;
+        let cache = device_open(&self.state.cache_name, true)?;
+        self.state.map.invalidate_all();
+        Ok(Box::new(CacheDescriptor {
+            device,
+            cache,
+            state: Arc::clone(&self.state),
+        }))
+    }
+
+    fn get_io_controller(&self) -> Option<Box<dyn DeviceIoController>> {
+        self.device.get_io_controller()
+    }
+
+    fn add_event_listener(
+        &self,
+        listener: DeviceEventListener,
+    ) -> Result<(), CoreError> {
+        self.device.add_event_listener(listener)
+    }
+}
+
+/// Descriptor of a device with a read cache, holding descriptors of both the
+/// cached device and the cache.
+struct CacheDescriptor {
+    device: Box<dyn BlockDeviceDescriptor>,
+    cache: Box<dyn BlockDeviceDescriptor>,
+    state: Arc<CacheState>,
+}
+
+impl BlockDeviceDescriptor for CacheDescriptor {
+    fn get_device(&self) -> Box<dyn BlockDevice> {
+        Box::new(CacheBlockDevice::new(
+            self.device.get_device(),
+            Arc::clone(&self.state),
+        ))
+    }
+
+    fn into_handle(
+        self: Box<Self>,
+    ) -> Result<Box<dyn BlockDeviceHandle>, CoreError> {
+        let device = self.get_device();
+        let this = *self;
+        Ok(Box::new(CacheHandle {
+            device,
+            handle: this.device.into_handle()?,
+            cache: this.cache.into_handle()?,
+            state: this.state,
+        }))
+    }
+
+    fn get_io_handle(&self) -> Result<Box<dyn BlockDeviceHandle>, CoreError> {
+        Ok(Box::new(CacheHandle {
+            device: self.get_device(),
+            handle: self.device.get_io_handle()?,
+            cache: self.cache.get_io_handle()?,
+            state: Arc::clone(&self.state),
+        }))
+    }
+
+    fn unclaim(&self) {
+        self.device.unclaim();
+        self.cache.unclaim();
+    }
+}
+
+/// IO handle of a device with a read cache.
+struct CacheHandle {
+    device: Box<dyn BlockDevice>,
+    /// handle of the cached device
+    handle: Box<dyn BlockDeviceHandle>,
+    /// handle of the cache
+    cache: Box<dyn BlockDeviceHandle>,
+    state: Arc<CacheState>,
+}
+
+/// Context of an IO submitted through a cache handle.
+struct IoCtx {
+    handle: *const CacheHandle,
+    cb: IoCompletionCallback,
+    cb_arg: IoCompletionCallbackArg,
+    iov: *mut iovec,
+    iovcnt: i32,
+    offset_blks: u64,
+    num_blks: u64,
+    /// state of the cache lines a read is served from
+    snapshot: Vec<u64>,
+    /// first line, number of lines and offset within the cache of the lines
+    /// filled by a read
+    fill: Option<(u64, u64, u64)>,
+    /// part of the IO vectors written to the cache
+    fill_iov: Vec<iovec>,
+}
+
+impl IoCtx {
+    /// the handle the IO was submitted to, which outlives the IO
+    fn handle<'a>(&self) -> &'a CacheHandle {
+        unsafe { &*self.handle }
+    }
+
+    /// complete the IO to the submitter
+    fn complete(self: Box<Self>, status: IoCompletionStatus) {
+        (self.cb)(&*self.handle().device, status, self.cb_arg);
+    }
+
+    /// release the lines claimed by a read
+    fn release(&self, filled: bool) {
+        if let Some((first, count, _)) = self.fill {
+            let map = &self.handle().state.map;
+            (first .. first + count).for_each(|l| map.release(l, filled));
+        }
+    }
+}
+
+/// The part of the IO vectors covering `len` bytes starting at byte `skip`.
+fn slice_iov(iov: *mut iovec, iovcnt: i32, skip: u64, len: u64) -> Vec<iovec> {
+    let iovs = unsafe { std::slice::from_raw_parts(iov, iovcnt as usize) };
+    let (mut skip, mut len) = (skip, len);
+    let mut sliced = Vec::new();
+    for v in iovs {
+        let v_len = v.iov_len as u64;
+        if skip >= v_len {
+            skip -= v_len;
+            continue;
+        }
+        let n = (v_len - skip).min(len);
+        sliced.push(iovec {
+            iov_base: unsafe { (v.iov_base as *mut u8).add(skip as usize) }
+                as *mut c_void,
+            iov_len: n as _,
+        });
+        len -= n;
+        skip = 0;
+        if len == 0 {
+            break;
+        }
+    }
+    sliced
+}
+
+/// completion status of an IO which could not be resubmitted
+fn resubmit_failed() -> IoCompletionStatus {
+    IoCompletionStatus::NvmeError(NvmeCommandStatus::GenericCommandStatus(
+        GenericStatusCode::InternalDeviceError,
+    ))
+}
+
+impl CacheHandle {
+    fn ctx(
+        &self,
+        iov: *mut iovec,
+        iovcnt: i32,
+        offset_blks: u64,
+        num_blks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Box<IoCtx> {
+        Box::new(IoCtx {
+            handle: self,
+            cb,
+            cb_arg,
+            iov,
+            iovcnt,
+            offset_blks,
+            num_blks,
+            snapshot: Vec::new(),
+            fill: None,
+            fill_iov: Vec::new(),
+        })
+    }
+
+    /// read from the cached device, filling the cache with the lines the
+    /// read entirely covers if asked to
+    fn read_device(
+        &self,
+        mut ctx: Box<IoCtx>,
+        fill: bool,
+    ) -> Result<(), (CoreError, Box<IoCtx>)> {
+        if fill {
+            ctx.fill = self.state.map.claim(ctx.offset_blks, ctx.num_blks);
+        }
+
+        let (iov, iovcnt, offset, num) =
+            (ctx.iov, ctx.iovcnt, ctx.offset_blks, ctx.num_blks);
+        let ctx = Box::into_raw(ctx);
+        self.handle
+            .readv_blocks(
+                iov,
+                iovcnt,
+                offset,
+                num,
+                device_read_done,
+                ctx as *mut c_void,
+            )
+            .map_err(|e| {
+                let ctx = unsafe { Box::from_raw(ctx) };
+                ctx.release(false);
+                (e, ctx)
+            })
+    }
+}
+
+/// completion of a read served from the cache, the data is only used if the
+/// lines were not evicted or invalidated while being read
+fn cache_read_done(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    ctx: *mut c_void,
+) {
+    let mut ctx = unsafe { Box::from_raw(ctx as *mut IoCtx) };
+    let handle = ctx.handle();
+    if status == IoCompletionStatus::Success
+        && handle.state.map.unchanged(ctx.offset_blks, &ctx.snapshot)
+    {
+        return ctx.complete(status);
+    }
+
+    ctx.snapshot.clear();
+    if let Err((e, ctx)) = handle.read_device(ctx, false) {
+        error!(
+            "{}: failed to resubmit read: {}",
+            handle.device.device_name(),
+            e
+        );
+        ctx.complete(resubmit_failed());
+    }
+}
+
+/// completion of a read served from the cached device
+fn device_read_done(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    ctx: *mut c_void,
+) {
+    let mut ctx = unsafe { Box::from_raw(ctx as *mut IoCtx) };
+    let (first, count, cache_offset) = match ctx.fill {
+        Some(fill) if status == IoCompletionStatus::Success => fill,
+        _ => {
+            ctx.release(false);
+            return ctx.complete(status);
+        }
+    };
+
+    let handle = ctx.handle();
+    let line_blks = handle.state.map.line_blks();
+    let block_len = handle.device.block_len();
+    ctx.fill_iov = slice_iov(
+        ctx.iov,
+        ctx.iovcnt,
+        (first * line_blks - ctx.offset_blks) * block_len,
+        count * line_blks * block_len,
+    );
+
+    let (iov, iovcnt) = (ctx.fill_iov.as_mut_ptr(), ctx.fill_iov.len() as i32);
+    let ctx = Box::into_raw(ctx);
+    if handle
+        .cache
+        .writev_blocks(
+            iov,
+            iovcnt,
+            cache_offset,
+            count * line_blks,
+            cache_write_done,
+            ctx as *mut c_void,
+        )
+        .is_err()
+    {
+        let ctx = unsafe { Box::from_raw(ctx) };
+        ctx.release(false);
+        ctx.complete(status);
+    }
+}
+
+/// completion of the write of the data read from the cached device to the
+/// cache, the read itself has succeeded
+fn cache_write_done(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    ctx: *mut c_void,
+) {
+    let ctx = unsafe { Box::from_raw(ctx as *mut IoCtx) };
+    ctx.release(status == IoCompletionStatus::Success);
+    ctx.complete(IoCompletionStatus::Success);
+}
+
+/// completion of an IO modifying the cached device
+fn write_done(
+    _device: &dyn BlockDevice,
+    status: IoCompletionStatus,
+    ctx: *mut c_void,
+) {
+    let ctx = unsafe { Box::from_raw(ctx as *mut IoCtx) };
+    ctx.handle()
+        .state
+        .map
+        .invalidate(ctx.offset_blks, ctx.num_blks);
+    ctx.complete(status);
+}
+
+#[async_trait(?Send)]
+impl BlockDeviceHandle for CacheHandle {
+    fn get_device(&self) -> &dyn BlockDevice {
+        &*self.device
+    }
+
+    fn dma_malloc(&self, size: u64) -> Result<DmaBuf, DmaError> {
+        DmaBuf::new(size, self.device.alignment())
+    }
+
+    async fn read_at(
+        &self,
+        offset: u64,
+        buffer: &mut DmaBuf,
+    ) -> Result<u64, CoreError> {
+        self.handle.read_at(offset, buffer).await
+    }
+
+    async fn write_at(
+        &self,
+        offset: u64,
+        buffer: &DmaBuf,
+    ) -> Result<u64, CoreError> {
+        let block_len = self.device.block_len();
+        let (offset_blks, num_blks) = (
+            offset / block_len,
+            (offset % block_len + buffer.len() + block_len - 1) / block_len,
+        );
+        self.state.map.invalidate(offset_blks, num_blks);
+        let result = self.handle.write_at(offset, buffer).await;
+        self.state.map.invalidate(offset_blks, num_blks);
+        result
+    }
+
+    fn readv_blocks(
+        &self,
+        iov: *mut iovec,
+        iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        let mut ctx =
+            self.ctx(iov, iovcnt, offset_blocks, num_blocks, cb, cb_arg);
+
+        if let Some((cache_offset, snapshot)) =
+            self.state.map.lookup(offset_blocks, num_blocks)
+        {
+            ctx.snapshot = snapshot;
+            let ctx = Box::into_raw(ctx);
+            match self.cache.readv_blocks(
+                iov,
+                iovcnt,
+                cache_offset,
+                num_blocks,
+                cache_read_done,
+                ctx as *mut c_void,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(_) => {
+                    let mut ctx = unsafe { Box::from_raw(ctx) };
+                    ctx.snapshot.clear();
+                    return self.read_device(ctx, false).map_err(|(e, _)| e);
+                }
+            }
+        }
+
+        self.read_device(ctx, true).map_err(|(e, _)| e)
+    }
+
+    fn writev_blocks(
+        &self,
+        iov: *mut iovec,
+        iovcnt: i32,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate(offset_blocks, num_blocks);
+        let ctx = Box::into_raw(self.ctx(
+            iov,
+            iovcnt,
+            offset_blocks,
+            num_blocks,
+            cb,
+            cb_arg,
+        ));
+        self.handle
+            .writev_blocks(
+                iov,
+                iovcnt,
+                offset_blocks,
+                num_blocks,
+                write_done,
+                ctx as *mut c_void,
+            )
+            .map_err(|e| {
+                drop(unsafe { Box::from_raw(ctx) });
+                e
+            })
+    }
+
+    fn reset(
+        &self,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        self.handle.reset(cb, cb_arg)
+    }
+
+    fn unmap_blocks(
+        &self,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate(offset_blocks, num_blocks);
+        let ctx = Box::into_raw(self.ctx(
+            std::ptr::null_mut(),
+            0,
+            offset_blocks,
+            num_blocks,
+            cb,
+            cb_arg,
+        ));
+        self.handle
+            .unmap_blocks(
+                offset_blocks,
+                num_blocks,
+                write_done,
+                ctx as *mut c_void,
+            )
+            .map_err(|e| {
+                drop(unsafe { Box::from_raw(ctx) });
+                e
+            })
+    }
+
+    fn write_zeroes(
+        &self,
+        offset_blocks: u64,
+        num_blocks: u64,
+        cb: IoCompletionCallback,
+        cb_arg: IoCompletionCallbackArg,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate(offset_blocks, num_blocks);
+        let ctx = Box::into_raw(self.ctx(
+            std::ptr::null_mut(),
+            0,
+            offset_blocks,
+            num_blocks,
+            cb,
+            cb_arg,
+        ));
+        self.handle
+            .write_zeroes(
+                offset_blocks,
+                num_blocks,
+                write_done,
+                ctx as *mut c_void,
+            )
+            .map_err(|e| {
+                drop(unsafe { Box::from_raw(ctx) });
+                e
+            })
+    }
+
+    async fn nvme_admin_custom(&self, opcode: u8) -> Result<(), CoreError> {
+        self.handle.nvme_admin_custom(opcode).await
+    }
+
+    async fn nvme_admin(
+        &self,
+        nvme_cmd: &spdk_sys::spdk_nvme_cmd,
+        buffer: Option<&mut DmaBuf>,
+    ) -> Result<(), CoreError> {
+        self.handle.nvme_admin(nvme_cmd, buffer).await
+    }
+
+    async fn nvme_identify_ctrlr(&self) -> Result<DmaBuf, CoreError> {
+        self.handle.nvme_identify_ctrlr().await
+    }
+
+    async fn create_snapshot(&self) -> Result<u64, CoreError> {
+        self.handle.create_snapshot().await
+    }
+
+    /// another host may have written to the device before the reservation
+    /// changed hands
+    async fn nvme_resv_register(
+        &self,
+        current_key: u64,
+        new_key: u64,
+        register_action: u8,
+        cptpl: u8,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate_all();
+        self.handle
+            .nvme_resv_register(current_key, new_key, register_action, cptpl)
+            .await
+    }
+
+    async fn nvme_resv_acquire(
+        &self,
+        current_key: u64,
+        preempt_key: u64,
+        acquire_action: u8,
+        resv_type: u8,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate_all();
+        self.handle
+            .nvme_resv_acquire(
+                current_key,
+                preempt_key,
+                acquire_action,
+                resv_type,
+            )
+            .await
+    }
+
+    async fn nvme_resv_report(
+        &self,
+        cdw11: u32,
+        buffer: &mut DmaBuf,
+    ) -> Result<(), CoreError> {
+        self.handle.nvme_resv_report(cdw11, buffer).await
+    }
+
+    /// the command may write to the device
+    async fn io_passthru(
+        &self,
+        nvme_cmd: &spdk_sys::spdk_nvme_cmd,
+        buffer: Option<&mut DmaBuf>,
+    ) -> Result<(), CoreError> {
+        self.state.map.invalidate_all();
+        let result = self.handle.io_passthru(nvme_cmd, buffer).await;
+        self.state.map.invalidate_all();
+        result
+    }
+
+    async fn host_id(&self) -> Result<[u8; 16], CoreError> {
+        self.handle.host_id().await
+    }
+}
@@ -0,0 +1,230 @@
+//! Direct mapped index of the lines of a device held by its cache.
+//!
+//! Line `n` of the device can only be held by slot `n % slots` of the cache.
+//! Each slot is a single atomic word made of the state of the slot, the tag
+//! of the line it holds (`n / slots`) and a version which is bumped on every
+//! change, so that readers of the cache can tell whether the slots they read
+//! from changed underneath them.
+//!
+//! A slot is claimed by a read that missed before the data is read from the
+//! device (`Filling`). A write to the line meanwhile marks it `Stale`, so
+//! that the data read before the write is never made valid.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const STATE_BITS: u32 = 2;
+const TAG_BITS: u32 = 32;
+const STATE_MASK: u64 = (1 << STATE_BITS) - 1;
+const TAG_MASK: u64 = ((1 << TAG_BITS) - 1) << STATE_BITS;
+const VERSION_SHIFT: u32 = STATE_BITS + TAG_BITS;
+
+/// the slot does not hold any data
+const INVALID: u64 = 0;
+/// the slot holds the data of the line it is tagged with
+const VALID: u64 = 1;
+/// the data of the line is being read from the device and written to the
+/// slot
+const FILLING: u64 = 2;
+/// the line was written to while being filled, the data must not be used
+const STALE: u64 = 3;
+
+fn state(word: u64) -> u64 {
+    word & STATE_MASK
+}
+
+fn tag(word: u64) -> u64 {
+    (word & TAG_MASK) >> STATE_BITS
+}
+
+/// the word for the given state and tag, with the version of `word` bumped
+fn next(word: u64, state: u64, tag: u64) -> u64 {
+    let version = (word >> VERSION_SHIFT).wrapping_add(1);
+    (version << VERSION_SHIFT) | (tag << STATE_BITS) | state
+}
+
+#[derive(Debug)]
+pub(crate) struct CacheMap {
+    /// number of blocks in a line
+    line_blks: u64,
+    slots: Vec<AtomicU64>,
+}
+
+impl CacheMap {
+    /// Create an empty map of `slots` lines of `line_blks` blocks each.
+    pub(crate) fn new(line_blks: u64, slots: u64) -> Self {
+        Self {
+            line_blks,
+            slots: (0 .. slots).map(|_| AtomicU64::new(INVALID)).collect(),
+        }
+    }
+
+    /// number of blocks in a line
+    pub(crate) fn line_blks(&self) -> u64 {
+        self.line_blks
+    }
+
+    fn slot(&self, line: u64) -> (&AtomicU64, u64) {
+        let slots = self.slots.len() as u64;
+        let tag = (line / slots) & (TAG_MASK >> STATE_BITS);
+        (&self.slots[(line % slots) as usize], tag)
+    }
+
+    /// The first and last lines touched by the given range, if they occupy
+    /// consecutive slots.
+    fn span(&self, offset_blks: u64, num_blks: u64) -> Option<(u64, u64)> {
+        if num_blks == 0 || self.slots.is_empty() {
+            return None;
+        }
+        let first = offset_blks / self.line_blks;
+        let last = (offset_blks + num_blks - 1) / self.line_blks;
+        let slots = self.slots.len() as u64;
+        if last - first >= slots || first % slots > last % slots {
+            return None;
+        }
+        Some((first, last))
+    }
+
+    /// The cache offset of the range and a snapshot of the slots holding
+    /// it, if all of the range is cached.
+    pub(crate) fn lookup(
+        &self,
+        offset_blks: u64,
+        num_blks: u64,
+    ) -> Option<(u64, Vec<u64>)> {
+        let (first, last) = self.span(offset_blks, num_blks)?;
+        let snapshot = (first ..= last)
+            .map(|line| {
+                let (slot, tag) = self.slot(line);
+                let word = slot.load(Ordering::Acquire);
+                if state(word) == VALID && self::tag(word) == tag {
+                    Some(word)
+                } else {
+                    None
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let slots = self.slots.len() as u64;
+        let offset =
+            (first % slots) * self.line_blks + offset_blks % self.line_blks;
+        Some((offset, snapshot))
+    }
+
+    /// True if the slots of the range did not change since the snapshot was
+    /// taken.
+    pub(crate) fn unchanged(&self, offset_blks: u64, snapshot: &[u64]) -> bool {
+        let first = offset_blks / self.line_blks;
+        snapshot.iter().enumerate().all(|(i, word)| {
+            let (slot, _) = self.slot(first + i as u64);
+            slot.load(Ordering::Acquire) == *word
+        })
+    }
+
+    /// Claim the slots of the lines entirely covered by the range, so that
+    /// they can be filled with the data read from the device. Returns the
+    /// claimed lines and their offset within the cache, either all of the
+    /// lines are claimed or none of them.
+    pub(crate) fn claim(
+        &self,
+        offset_blks: u64,
+        num_blks: u64,
+    ) -> Option<(u64, u64, u64)> {
+        let first = (offset_blks + self.line_blks - 1) / self.line_blks;
+        let end = (offset_blks + num_blks) / self.line_blks;
+        if end <= first {
+            return None;
+        }
+        let (first, last) =
+            self.span(first * self.line_blks, (end - first) * self.line_blks)?;
+
+        for line in first ..= last {
+            let (slot, tag) = self.slot(line);
+            let word = slot.load(Ordering::Acquire);
+            let claimed = matches!(state(word), INVALID | VALID)
+                && slot
+                    .compare_exchange(
+                        word,
+                        next(word, FILLING, tag),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok();
+            if !claimed {
+                (first .. line).for_each(|l| self.release(l, false));
+                return None;
+            }
+        }
+
+        let slots = self.slots.len() as u64;
+        Some((first, last - first + 1, (first % slots) * self.line_blks))
+    }
+
+    /// Release a claimed line, making it valid if it was filled and not
+    /// written to in the meantime.
+    pub(crate) fn release(&self, line: u64, filled: bool) {
+        let (slot, tag) = self.slot(line);
+        let mut word = slot.load(Ordering::Acquire);
+        loop {
+            let new = if filled && state(word) == FILLING {
+                next(word, VALID, tag)
+            } else {
+                next(word, INVALID, tag)
+            };
+            match slot.compare_exchange(
+                word,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(current) => word = current,
+            }
+        }
+    }
+
+    /// Invalidate the lines touched by the range.
+    pub(crate) fn invalidate(&self, offset_blks: u64, num_blks: u64) {
+        if num_blks == 0 || self.slots.is_empty() {
+            return;
+        }
+        let first = offset_blks / self.line_blks;
+        let last = (offset_blks + num_blks - 1) / self.line_blks;
+        if last - first >= self.slots.len() as u64 {
+            return self.invalidate_all();
+        }
+        for line in first ..= last {
+            let (slot, tag) = self.slot(line);
+            Self::invalidate_slot(slot, Some(tag));
+        }
+    }
+
+    /// Invalidate all of the cache.
+    pub(crate) fn invalidate_all(&self) {
+        self.slots
+            .iter()
+            .for_each(|slot| Self::invalidate_slot(slot, None));
+    }
+
+    fn invalidate_slot(slot: &AtomicU64, tag: Option<u64>) {
+        let mut word = slot.load(Ordering::Acquire);
+        loop {
+            if tag.map_or(false, |t| self::tag(word) != t) {
+                return;
+            }
+            let new = match state(word) {
+                VALID => next(word, INVALID, self::tag(word)),
+                FILLING => next(word, STALE, self::tag(word)),
+                _ => return,
+            };
+            match slot.compare_exchange(
+                word,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(current) => word = current,
+            }
+        }
+    }
+}
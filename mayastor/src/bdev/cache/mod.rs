@@ -0,0 +1,217 @@
+//!
+//! Read cache for devices which are accessed over the network.
+//!
+//! A `cache` parameter on the URI of a device puts a local device in front
+//! of it, e.g.
+//!
+//! ```text
+//! nvmf://10.0.0.2:8420/nqn:replica1?cache=malloc:///c0?size_mb=256
+//! ```
+//!
+//! Parameters of the cache device other than the first one must be
+//! percent-encoded. The cache device must be an aio, uring or malloc device
+//! with the same block size as the cached device.
+//!
+//! Data read from the cached device is kept in the cache and served from it
+//! until it is written to. The cache is volatile: it is emptied whenever the
+//! device is opened, when the reservations of the device change hands and
+//! when the nexus the device is a child of fails over, as the device may
+//! have been written to by another nexus in the meantime. The cache device
+//! is destroyed together with the cached device.
+
+use std::{collections::HashMap, convert::TryFrom, sync::Arc};
+
+use async_trait::async_trait;
+use nix::errno::Errno;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use url::Url;
+
+use crate::{
+    bdev::{
+        device_create,
+        device_destroy,
+        device_lookup,
+        nvmx,
+        CreateDestroy,
+        GetName,
+        SpdkBlockDevice,
+        Uri,
+    },
+    core::BlockDevice,
+    nexus_uri::NexusBdevError,
+};
+
+use device::CacheBlockDevice;
+use map::CacheMap;
+
+mod device;
+mod map;
+
+/// Size of the lines the cache is made of.
+const LINE_SIZE: u64 = 4096;
+
+/// Caches by name of the cached device.
+static CACHES: Lazy<RwLock<HashMap<String, Arc<CacheState>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// The cache of a device, shared by all of its handles.
+#[derive(Debug)]
+pub(crate) struct CacheState {
+    /// name of the cache device
+    cache_name: String,
+    /// alignment required by the cache device
+    alignment: u64,
+    map: CacheMap,
+}
+
+/// A device with a read cache in front of it.
+#[derive(Debug)]
+pub(super) struct Cache {
+    /// name of the cached device
+    name: String,
+    /// URI of the cached device
+    uri: String,
+    /// URI of the cache device
+    cache_uri: String,
+}
+
+/// Convert a URI with a cache parameter to a Cache "object"
+impl TryFrom<&Url> for Cache {
+    type Error = NexusBdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let mut cache_uri = None;
+        let mut parameters = Vec::new();
+        for (key, value) in url.query_pairs().into_owned() {
+            if key == "cache" {
+                cache_uri = Some(value);
+            } else {
+                parameters.push((key, value));
+            }
+        }
+
+        let cache_uri =
+            cache_uri.ok_or_else(|| NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: String::from("no cache parameter"),
+            })?;
+
+        let cache_url =
+            Url::parse(&cache_uri).map_err(|e| NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: format!("invalid cache URI '{}': {}", cache_uri, e),
+            })?;
+        if !matches!(cache_url.scheme(), "aio" | "uring" | "malloc")
+            || cache_url.query_pairs().any(|(key, _)| key == "cache")
+        {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: format!(
+                    "the cache must be a local device, not '{}'",
+                    cache_uri
+                ),
+            });
+        }
+
+        let mut device_url = url.clone();
+        if parameters.is_empty() {
+            device_url.set_query(None);
+        } else {
+            device_url
+                .query_pairs_mut()
+                .clear()
+                .extend_pairs(parameters);
+        }
+
+        Ok(Cache {
+            name: Uri::parse(device_url.as_str())?.get_name(),
+            uri: device_url.to_string(),
+            cache_uri,
+        })
+    }
+}
+
+impl GetName for Cache {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Cache {
+    type Error = NexusBdevError;
+
+    /// Create the cached device and its cache
+    async fn create(&self) -> Result<String, Self::Error> {
+        let cache_name = device_create(&self.cache_uri).await?;
+        let name = match device_create(&self.uri).await {
+            Ok(name) => name,
+            Err(e) => {
+                let _ = device_destroy(&self.cache_uri).await;
+                return Err(e);
+            }
+        };
+
+        let (device, cache) =
+            match (device_lookup(&name), device_lookup(&cache_name)) {
+                (Some(device), Some(cache))
+                    if device.block_len() == cache.block_len() =>
+                {
+                    (device, cache)
+                }
+                _ => {
+                    error!(
+                        "{}: cache {} is missing or has a different block size",
+                        name, self.cache_uri
+                    );
+                    let _ = device_destroy(&self.uri).await;
+                    let _ = device_destroy(&self.cache_uri).await;
+                    return Err(NexusBdevError::CreateBdevInvalidParams {
+                        source: Errno::EINVAL,
+                        name,
+                    });
+                }
+            };
+
+        let line_blks = (LINE_SIZE / device.block_len()).max(1);
+        let state = CacheState {
+            cache_name,
+            alignment: cache.alignment(),
+            map: CacheMap::new(line_blks, cache.num_blocks() / line_blks),
+        };
+        info!(
+            "{}: caching reads in {} ({} bytes)",
+            name,
+            self.cache_uri,
+            cache.size_in_bytes()
+        );
+        CACHES.write().insert(name.clone(), Arc::new(state));
+
+        Ok(name)
+    }
+
+    /// Destroy the cached device and its cache
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        CACHES.write().remove(&self.name);
+        let result = device_destroy(&self.uri).await;
+        device_destroy(&self.cache_uri).await?;
+        result
+    }
+}
+
+/// Lookup a device with a read cache by its name.
+pub fn lookup_by_name(name: &str) -> Option<Box<dyn BlockDevice>> {
+    let state = CACHES.read().get(name).cloned()?;
+    let device = nvmx::lookup_by_name(name)
+        .or_else(|| SpdkBlockDevice::lookup_by_name(name))?;
+    Some(Box::new(CacheBlockDevice::new(device, state)))
+}
+
+/// Drop the cached data of the device, if it has a cache.
+pub(crate) fn invalidate(name: &str) {
+    if let Some(state) = CACHES.read().get(name) {
+        debug!("{}: dropping the read cache", name);
+        state.map.invalidate_all();
+    }
+}
@@ -31,7 +31,7 @@ use crate::{
     nexus_uri::{self, NexusBdevError},
 };
 
-use super::{aio, loopback, malloc, null, nvme, nvmx, uring};
+use super::{aio, cache, iscsi, loopback, malloc, null, nvme, nvmx, uring};
 
 impl Uri {
     pub fn parse(
@@ -44,13 +44,21 @@ impl Uri {
             uri: uri.to_string(),
         })?;
 
+        // any device can have a read cache in front of it
+        if url.query_pairs().any(|(key, _)| key == "cache") {
+            return Ok(Box::new(cache::Cache::try_from(&url)?));
+        }
+
         match url.scheme() {
             "aio" => Ok(Box::new(aio::Aio::try_from(&url)?)),
             "bdev" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
+            "iscsi" => Ok(Box::new(iscsi::Iscsi::try_from(&url)?)),
             "loopback" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
             "malloc" => Ok(Box::new(malloc::Malloc::try_from(&url)?)),
             "null" => Ok(Box::new(null::Null::try_from(&url)?)),
-            "nvmf" => Ok(Box::new(nvmx::NvmfDeviceTemplate::try_from(&url)?)),
+            "nvmf" | "nvmf+tcp" | "nvmf+rdma" => {
+                Ok(Box::new(nvmx::NvmfDeviceTemplate::try_from(&url)?))
+            }
             "pcie" => Ok(Box::new(nvme::NVMe::try_from(&url)?)),
             "uring" => Ok(Box::new(uring::Uring::try_from(&url)?)),
 
@@ -86,8 +94,11 @@ pub(crate) fn reject_unknown_parameters(
 // Lookup up a block device via its symbolic name.
 pub fn device_lookup(name: &str) -> Option<Box<dyn BlockDevice>> {
     debug!("Looking up device by name: {}", name);
-    // First try to lookup NVMF devices, then try to lookup SPDK native devices.
-    nvmx::lookup_by_name(name).or_else(|| SpdkBlockDevice::lookup_by_name(name))
+    // First try to lookup devices with a read cache, then NVMF devices, then
+    // SPDK native devices.
+    cache::lookup_by_name(name)
+        .or_else(|| nvmx::lookup_by_name(name))
+        .or_else(|| SpdkBlockDevice::lookup_by_name(name))
 }
 
 pub async fn device_create(uri: &str) -> Result<String, NexusBdevError> {
@@ -102,6 +113,10 @@ pub fn device_open(
     name: &str,
     read_write: bool,
 ) -> Result<Box<dyn BlockDeviceDescriptor>, CoreError> {
+    if let Some(device) = cache::lookup_by_name(name) {
+        return device.open(read_write);
+    }
+
     // First try to open NVMF devices, then try to lookup SPDK native devices.
     nvmx::open_by_name(name, read_write)
         .or_else(|_| SpdkBlockDevice::open_by_name(name, read_write))
@@ -0,0 +1,213 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    ffi::CString,
+    os::raw::{c_int, c_void},
+};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use snafu::ResultExt;
+use url::{Position, Url};
+
+use spdk_sys::{create_iscsi_disk, delete_iscsi_disk, spdk_bdev};
+
+use crate::{
+    bdev::{dev::reject_unknown_parameters, util::uri, CreateDestroy, GetName},
+    core::{Bdev, MayastorEnvironment},
+    ffihelper::{cb_arg, done_errno_cb, errno_result_from_i32, ErrnoResult},
+    nexus_uri::{self, NexusBdevError},
+};
+
+/// returns the IQN this node logs in to iSCSI targets with, so that targets
+/// can restrict access to it
+fn initiator_iqn() -> String {
+    format!(
+        "iqn.2019-05.io.openebs:initiator:{}",
+        MayastorEnvironment::global_or_default().node_name
+    )
+}
+
+#[derive(Debug)]
+pub(super) struct Iscsi {
+    name: String,
+    alias: String,
+    iqn: String,
+    url: String,
+    uuid: Option<uuid::Uuid>,
+}
+
+/// Convert an iSCSI URI of the form iscsi://host[:port]/iqn[/lun] to an
+/// Iscsi "object"
+impl TryFrom<&Url> for Iscsi {
+    type Error = NexusBdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        if url.host_str().is_none() {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: String::from("missing host"),
+            });
+        }
+
+        let segments = uri::segments(url);
+
+        if segments.is_empty() || segments[0].is_empty() {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: String::from("no target IQN"),
+            });
+        }
+
+        if segments.len() > 2 {
+            return Err(NexusBdevError::UriInvalid {
+                uri: url.to_string(),
+                message: String::from("too many path segments"),
+            });
+        }
+
+        let lun: u32 = match segments.get(1) {
+            Some(value) => {
+                value.parse().context(nexus_uri::IntParamParseError {
+                    uri: url.to_string(),
+                    parameter: String::from("lun"),
+                    value: value.to_string(),
+                })?
+            }
+            None => 0,
+        };
+
+        let mut parameters: HashMap<String, String> =
+            url.query_pairs().into_owned().collect();
+
+        let uuid = uri::uuid(parameters.remove("uuid")).context(
+            nexus_uri::UuidParamParseError {
+                uri: url.to_string(),
+            },
+        )?;
+
+        reject_unknown_parameters(url, parameters)?;
+
+        let target = &url[Position::BeforeHost .. Position::BeforePath];
+
+        Ok(Iscsi {
+            name: url[Position::BeforeHost .. Position::AfterPath].into(),
+            alias: url.to_string(),
+            iqn: initiator_iqn(),
+            url: format!("iscsi://{}/{}/{}", target, segments[0], lun),
+            uuid,
+        })
+    }
+}
+
+impl GetName for Iscsi {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Iscsi {
+    type Error = NexusBdevError;
+
+    /// Create an iSCSI bdev by logging in to the target
+    async fn create(&self) -> Result<String, Self::Error> {
+        extern "C" fn done_iscsi_create_cb(
+            arg: *mut c_void,
+            _bdev: *mut spdk_bdev,
+            errno: c_int,
+        ) {
+            let sender = unsafe {
+                Box::from_raw(arg as *mut oneshot::Sender<ErrnoResult<()>>)
+            };
+
+            sender
+                .send(errno_result_from_i32((), errno))
+                .expect("done callback receiver side disappeared");
+        }
+
+        if Bdev::lookup_by_name(&self.name).is_some() {
+            return Err(NexusBdevError::BdevExists {
+                name: self.get_name(),
+            });
+        }
+
+        let cname = CString::new(self.get_name()).unwrap();
+        let curl = CString::new(self.url.clone()).unwrap();
+        let ciqn = CString::new(self.iqn.clone()).unwrap();
+
+        let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
+
+        let errno = unsafe {
+            create_iscsi_disk(
+                cname.as_ptr(),
+                curl.as_ptr(),
+                ciqn.as_ptr(),
+                Some(done_iscsi_create_cb),
+                cb_arg(sender),
+            )
+        };
+
+        errno_result_from_i32((), errno).context(
+            nexus_uri::CreateBdevInvalidParams {
+                name: self.get_name(),
+            },
+        )?;
+
+        receiver
+            .await
+            .context(nexus_uri::CancelBdev {
+                name: self.get_name(),
+            })?
+            .context(nexus_uri::CreateBdev {
+                name: self.get_name(),
+            })?;
+
+        if let Some(mut bdev) = Bdev::lookup_by_name(&self.name) {
+            if let Some(uuid) = self.uuid {
+                bdev.set_uuid(uuid);
+            }
+
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+
+            return Ok(self.get_name());
+        }
+
+        Err(NexusBdevError::BdevNotFound {
+            name: self.get_name(),
+        })
+    }
+
+    /// Destroy the given iSCSI bdev, logging out of the target
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        match Bdev::lookup_by_name(&self.name) {
+            Some(bdev) => {
+                let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
+                unsafe {
+                    delete_iscsi_disk(
+                        bdev.as_ptr(),
+                        Some(done_errno_cb),
+                        cb_arg(sender),
+                    );
+                }
+                receiver
+                    .await
+                    .context(nexus_uri::CancelBdev {
+                        name: self.get_name(),
+                    })?
+                    .context(nexus_uri::DestroyBdev {
+                        name: self.get_name(),
+                    })
+            }
+            None => Err(NexusBdevError::BdevNotFound {
+                name: self.get_name(),
+            }),
+        }
+    }
+}
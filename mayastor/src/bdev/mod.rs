@@ -9,6 +9,7 @@ pub use nexus::{
         nexus_lookup,
         Nexus,
         NexusNvmeParams,
+        NexusReadPolicy,
         NexusState,
         NexusStatus,
         VerboseError,
@@ -22,8 +23,10 @@ pub use nexus::{
         NexusMetaData,
     },
     nexus_persistence::{ChildInfo, NexusInfo},
+    nexus_recovery::{nexus_recover, spawn_nexus_recovery},
 };
 pub use nvmx::{
+    host_nqn,
     nvme_io_ctx_pool_init,
     NvmeController,
     NvmeControllerState,
@@ -31,8 +34,10 @@ pub use nvmx::{
 };
 
 mod aio;
+pub(crate) mod cache;
 pub(crate) mod dev;
 pub(crate) mod device;
+mod iscsi;
 mod loopback;
 mod malloc;
 pub(crate) mod nexus;
@@ -40,6 +45,7 @@ mod null;
 mod nvme;
 mod nvmf;
 pub(crate) mod nvmx;
+pub(crate) mod qos;
 mod uring;
 pub mod util;
 
@@ -22,18 +22,24 @@ macro_rules! c_str {
 
 pub mod nexus_bdev;
 pub mod nexus_bdev_children;
+pub mod nexus_bdev_migrate;
 pub mod nexus_bdev_rebuild;
 pub mod nexus_bdev_snapshot;
+pub mod nexus_bdev_verify;
 mod nexus_channel;
 pub(crate) mod nexus_child;
+pub mod nexus_dirty_log;
 pub mod nexus_fn_table;
 pub mod nexus_io;
+pub mod nexus_io_errors;
 pub mod nexus_label;
 pub mod nexus_metadata;
 pub mod nexus_module;
 pub mod nexus_nbd;
 pub mod nexus_persistence;
+pub mod nexus_recovery;
 pub mod nexus_share;
+pub mod nexus_slow_child;
 
 #[derive(Deserialize)]
 struct NexusShareArgs {
@@ -9,6 +9,7 @@ use std::{
     fmt::{Display, Formatter},
     os::raw::c_void,
     ptr::NonNull,
+    sync::Arc,
 };
 
 use crossbeam::atomic::AtomicCell;
@@ -23,6 +24,7 @@ use spdk_sys::{spdk_bdev, spdk_bdev_register, spdk_bdev_unregister};
 
 use crate::{
     bdev::{
+        cache,
         device_destroy,
         nexus::{
             self,
@@ -45,16 +47,21 @@ use crate::{
         CoreError,
         Cores,
         IoDevice,
+        IoLatency,
         IoType,
         Protocol,
+        QosLimiter,
+        QosLimits,
         Reactor,
         Share,
         MWQ,
     },
     ffihelper::errno_result_from_i32,
+    lvs::Error as LvsError,
     nexus_uri::NexusBdevError,
     rebuild::RebuildError,
     subsys::{NvmfError, NvmfSubsystem},
+    verify::{VerifyError, VerifyJob},
 };
 
 pub static NVME_MIN_CNTLID: u16 = 1;
@@ -116,6 +123,13 @@ pub enum Error {
     ShareNvmfNexus { source: CoreError, name: String },
     #[snafu(display("Failed to unshare nexus {}", name))]
     UnshareNexus { source: CoreError, name: String },
+    #[snafu(display("Failed to set the allowed hosts of nexus {}", name))]
+    AllowedHosts { source: CoreError, name: String },
+    #[snafu(display(
+        "Removing all allowed hosts of nexus {} would allow any host",
+        name
+    ))]
+    AllowAnyHost { name: String },
     #[snafu(display(
         "Failed to read child label of nexus {}: {}",
         name,
@@ -247,10 +261,58 @@ pub enum Error {
         name: String,
         source: RebuildError,
     },
+    #[snafu(display("Failed to create verify job for nexus {}", name))]
+    CreateVerify { source: VerifyError, name: String },
+    #[snafu(display("Verify job not found for nexus {}", name))]
+    VerifyJobNotFound { source: VerifyError, name: String },
+    #[snafu(display(
+        "Failed to execute verify operation on job of nexus {}",
+        name,
+    ))]
+    VerifyOperation { source: VerifyError, name: String },
+    #[snafu(display(
+        "Child {} of nexus {} can not be the verify source as it is {}",
+        child,
+        name,
+        state
+    ))]
+    InvalidVerifySource {
+        child: String,
+        name: String,
+        state: String,
+    },
     #[snafu(display("Invalid ShareProtocol value {}", sp_value))]
     InvalidShareProtocol { sp_value: i32 },
     #[snafu(display("Invalid NvmeAnaState value {}", ana_value))]
     InvalidNvmeAnaState { ana_value: i32 },
+    #[snafu(display("Invalid read policy value {}", policy_value))]
+    InvalidReadPolicy { policy_value: i32 },
+    #[snafu(display(
+        "Cannot resize nexus {} from {} to {} bytes, it can only grow",
+        name,
+        current,
+        size
+    ))]
+    InvalidResize {
+        name: String,
+        current: u64,
+        size: u64,
+    },
+    #[snafu(display("Cannot resize nexus {} while it is {}", name, state))]
+    ResizeNotOnline { name: String, state: String },
+    #[snafu(display(
+        "Cannot resize nexus {} while child {} is {}",
+        name,
+        child,
+        state
+    ))]
+    ResizeChildNotOpen {
+        name: String,
+        child: String,
+        state: String,
+    },
+    #[snafu(display("Failed to notify the new size of nexus {}", name))]
+    NotifyResize { source: Errno, name: String },
     #[snafu(display("Invalid arguments for nexus {}: {}", name, args))]
     InvalidArguments { name: String, args: String },
     #[snafu(display("Failed to create nexus {}", name))]
@@ -268,10 +330,76 @@ pub enum Error {
         name: String,
         state: String,
     },
+    #[snafu(display(
+        "Child {} of nexus {} can not be migrated as it is {}",
+        child,
+        name,
+        state
+    ))]
+    MigrateSourceNotHealthy {
+        child: String,
+        name: String,
+        state: String,
+    },
+    #[snafu(display(
+        "Failed to migrate child {} of nexus {} to {}, which is {} after its rebuild",
+        child,
+        name,
+        dst,
+        state
+    ))]
+    MigrateRebuild {
+        child: String,
+        dst: String,
+        name: String,
+        state: String,
+    },
+    #[snafu(display("Nexus {} has no healthy child to recover from", name))]
+    RecoverNoHealthyChild { name: String },
     #[snafu(display("Failed to get BdevHandle for snapshot operation"))]
     FailedGetHandle,
     #[snafu(display("Failed to create snapshot on nexus {}", name))]
     FailedCreateSnapshot { name: String, source: CoreError },
+    #[snafu(display("Nexus {} has no healthy child to snapshot", name))]
+    SnapshotNoHealthyChild { name: String },
+    #[snafu(display("Failed to drain the IO of nexus {} for snapshot", name))]
+    SnapshotQuiesce { source: Errno, name: String },
+    #[snafu(display("Failed to snapshot child {} of nexus {}", child, name))]
+    SnapshotChild {
+        source: CoreError,
+        child: String,
+        name: String,
+    },
+    #[snafu(display(
+        "Failed to snapshot local child {} of nexus {}",
+        child,
+        name
+    ))]
+    SnapshotLocalChild {
+        source: LvsError,
+        child: String,
+        name: String,
+    },
+    #[snafu(display(
+        "Failed to destroy the snapshot of child {} of nexus {}",
+        child,
+        name
+    ))]
+    DestroySnapshotChild {
+        source: CoreError,
+        child: String,
+        name: String,
+    },
+    #[snafu(display(
+        "Failed to destroy the snapshot of local child {} of nexus {}",
+        child,
+        name
+    ))]
+    DestroySnapshotLocalChild {
+        source: LvsError,
+        child: String,
+        name: String,
+    },
     #[snafu(display("NVMf subsystem error: {}", e))]
     SubsysNvmf { e: String },
     #[snafu(display("failed to pause {} current state {:?}", name, state))]
@@ -310,6 +438,12 @@ impl From<Error> for tonic::Status {
             Error::NotSharedNvmf {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            Error::AllowedHosts {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::AllowAnyHost {
+                ..
+            } => Status::failed_precondition(e.to_string()),
             Error::CreateChild {
                 ..
             } => Status::invalid_argument(e.to_string()),
@@ -328,6 +462,44 @@ impl From<Error> for tonic::Status {
             Error::ChildNotFound {
                 ..
             } => Status::not_found(e.to_string()),
+            Error::InvalidReadPolicy {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::InvalidResize {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            Error::SnapshotNoHealthyChild {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::MigrateSourceNotHealthy {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ResizeNotOnline {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::ResizeChildNotOpen {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::InvalidVerifySource {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::VerifyJobNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            Error::CreateVerify {
+                source:
+                    VerifyError::NotEnoughChildren {
+                        ..
+                    },
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            Error::WriteLabel {
+                source:
+                    LabelError::ChildTooSmall {
+                        ..
+                    },
+                ..
+            } => Status::failed_precondition(e.to_string()),
             e => Status::new(Code::Internal, e.to_string()),
         }
     }
@@ -358,6 +530,9 @@ pub struct NexusNvmeParams {
     pub(crate) max_cntlid: u16,
     /// NVMe reservation key for children
     pub(crate) resv_key: u64,
+    /// NQNs of the hosts allowed to connect when shared over NVMf, any host
+    /// may connect when empty
+    pub(crate) allowed_hosts: Vec<String>,
 }
 
 impl Default for NexusNvmeParams {
@@ -366,6 +541,7 @@ impl Default for NexusNvmeParams {
             min_cntlid: NVME_MIN_CNTLID,
             max_cntlid: NVME_MAX_CNTLID,
             resv_key: 0x1234_5678,
+            allowed_hosts: Vec::new(),
         }
     }
 }
@@ -380,6 +556,9 @@ impl NexusNvmeParams {
     pub fn set_resv_key(&mut self, resv_key: u64) {
         self.resv_key = resv_key;
     }
+    pub fn set_allowed_hosts(&mut self, allowed_hosts: Vec<String>) {
+        self.allowed_hosts = allowed_hosts;
+    }
 }
 
 /// The main nexus structure
@@ -415,6 +594,15 @@ pub struct Nexus {
     pause_waiters: Vec<oneshot::Sender<i32>>,
     /// information saved to a persistent store
     pub nexus_info: futures::lock::Mutex<NexusInfo>,
+    /// policy used to select the child a read is sent to
+    read_policy: AtomicCell<NexusReadPolicy>,
+    /// rate limiter of the IO submitted to the nexus, if any
+    qos: parking_lot::Mutex<Option<Arc<QosLimiter>>>,
+    /// bumped whenever the QoS limits change
+    qos_generation: AtomicCell<u64>,
+    /// uris of the children whose local replica has been restricted to the
+    /// host NQN of the child
+    pub(crate) restricted_replicas: Vec<String>,
 }
 
 unsafe impl core::marker::Sync for Nexus {}
@@ -442,6 +630,39 @@ pub enum NexusState {
     Reconfiguring,
 }
 
+/// Policy used by the IO channels to select the child to read from
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum NexusReadPolicy {
+    /// cycle through all healthy children
+    RoundRobin,
+    /// round-robin over local children, falling back to all children if
+    /// none of them is local
+    PreferLocal,
+    /// the child with the fewest reads in flight on the current channel
+    LeastOutstanding,
+    /// the child with the lowest average read latency on the current channel
+    LowestLatency,
+}
+
+impl Default for NexusReadPolicy {
+    fn default() -> Self {
+        NexusReadPolicy::RoundRobin
+    }
+}
+
+impl ToString for NexusReadPolicy {
+    fn to_string(&self) -> String {
+        match *self {
+            NexusReadPolicy::RoundRobin => "round_robin",
+            NexusReadPolicy::PreferLocal => "prefer_local",
+            NexusReadPolicy::LeastOutstanding => "least_outstanding",
+            NexusReadPolicy::LowestLatency => "lowest_latency",
+        }
+        .parse()
+        .unwrap()
+    }
+}
+
 impl ToString for NexusState {
     fn to_string(&self) -> String {
         match *self {
@@ -532,6 +753,10 @@ impl Nexus {
             pause_state: AtomicCell::new(NexusPauseState::Unpaused),
             pause_waiters: Vec::new(),
             nexus_info: futures::lock::Mutex::new(Default::default()),
+            read_policy: AtomicCell::new(NexusReadPolicy::default()),
+            qos: parking_lot::Mutex::new(None),
+            qos_generation: AtomicCell::new(0),
+            restricted_replicas: Vec::new(),
         });
 
         // set the UUID of the underlying bdev
@@ -617,6 +842,26 @@ impl Nexus {
         );
     }
 
+    /// remove the QoS limits of the nexus and stop the QoS pollers of its
+    /// channels, the IOs which were held back are submitted
+    async fn stop_qos(&self) {
+        let (s, r) = oneshot::channel::<i32>();
+        self.set_qos_limits(QosLimits::default());
+
+        let ctx = Box::new(ReconfigureCtx::new(
+            s,
+            NonNull::new(self.as_ptr()).unwrap(),
+        ));
+
+        NexusChannel::stop_qos(self.as_ptr(), ctx);
+        r.await.expect("stop QoS sender already dropped");
+    }
+
+    /// collect the latency histograms of the IO completed by the nexus
+    pub fn latency(&self) -> oneshot::Receiver<IoLatency> {
+        NexusChannel::collect_latency(self.as_ptr())
+    }
+
     /// Opens the Nexus instance for IO
     pub async fn open(&mut self) -> Result<(), Error> {
         debug!("Opening nexus {}", self.name);
@@ -703,6 +948,9 @@ impl Nexus {
         let _ = self.unshare_nexus().await;
         assert_eq!(self.share_handle, None);
 
+        // the QoS pollers of the channels must not outlive the nexus
+        self.stop_qos().await;
+
         // no-op when not shared and will be removed once the old share bits are
         // gone
         self.bdev.unshare().await.unwrap();
@@ -713,6 +961,9 @@ impl Nexus {
             self.cancel_child_rebuild_jobs(child.get_name()).await;
         }
 
+        self.cancel_verify(None).await;
+        let _ = VerifyJob::remove(&self.name);
+
         for child in self.children.iter_mut() {
             info!("Destroying child bdev {}", child.get_name());
             if let Err(e) = child.close().await {
@@ -724,6 +975,7 @@ impl Nexus {
                 );
             }
         }
+        self.release_replicas().await;
         // Persist the fact that the nexus destruction has completed.
         self.persist(PersistOp::Shutdown).await;
 
@@ -976,6 +1228,12 @@ impl Nexus {
             if let Some(subsystem) = NvmfSubsystem::nqn_lookup(&self.name) {
                 subsystem.pause().await?;
                 let res = subsystem.set_ana_state(ana_state as u32).await;
+                // another nexus may write to the children while this one is
+                // not the optimized path, the data cached so far is of no use
+                self.children
+                    .iter()
+                    .filter_map(|c| c.get_device().ok())
+                    .for_each(|d| cache::invalidate(&d.device_name()));
                 subsystem.resume().await?;
                 return Ok(res?);
             }
@@ -986,6 +1244,134 @@ impl Nexus {
         })
     }
 
+    /// Grow the nexus to `size` bytes. All children must be open and
+    /// large enough to hold the new size, i.e. replicas must have been
+    /// resized first, as the label of a child which is not open could not
+    /// be rewritten. Consumers of the nexus bdev are notified of the new
+    /// size by means of a resize event.
+    pub async fn resize(&mut self, size: u64) -> Result<(), Error> {
+        if size == self.size {
+            return Ok(());
+        }
+
+        if size < self.size {
+            return Err(Error::InvalidResize {
+                name: self.name.clone(),
+                current: self.size,
+                size,
+            });
+        }
+
+        let status = self.status();
+        if status != NexusStatus::Online {
+            return Err(Error::ResizeNotOnline {
+                name: self.name.clone(),
+                state: status.to_string(),
+            });
+        }
+
+        if let Some(child) = self.children.iter().find(|c| !c.is_open()) {
+            return Err(Error::ResizeChildNotOpen {
+                name: self.name.clone(),
+                child: child.get_name().to_string(),
+                state: child.state().to_string(),
+            });
+        }
+
+        // The labels live outside of the data partition, but pause anyway
+        // so that no child is faulted or added while they are rewritten.
+        // The dirty logs are replaced while paused as well, and the channels
+        // refreshed so that they let go of the old ones.
+        self.pause().await?;
+        let result = self.resize_child_labels(size).await;
+        if result.is_ok() {
+            self.children
+                .iter_mut()
+                .for_each(|c| c.resize_dirty_log(size));
+            self.reconfigure(DrEvent::Resize).await;
+        }
+        self.resume().await?;
+
+        let num_blocks = result.context(WriteLabel {
+            name: self.name.clone(),
+        })?;
+
+        self.size = size;
+        self.bdev
+            .notify_block_count(num_blocks)
+            .context(NotifyResize {
+                name: self.name.clone(),
+            })?;
+
+        info!(
+            "{}: resized to {} bytes ({} blocks)",
+            self.name, size, num_blocks
+        );
+        Ok(())
+    }
+
+    /// get the policy used to select the child to read from
+    pub fn read_policy(&self) -> NexusReadPolicy {
+        self.read_policy.load()
+    }
+
+    /// set the policy used to select the child to read from, the IO channels
+    /// pick it up with the next read they submit
+    pub fn set_read_policy(&self, policy: NexusReadPolicy) {
+        let prev = self.read_policy.swap(policy);
+        if prev != policy {
+            info!(
+                "{}: read policy changed from {} to {}",
+                self.name,
+                prev.to_string(),
+                policy.to_string()
+            );
+        }
+    }
+
+    /// get the QoS limits of the nexus
+    pub fn qos_limits(&self) -> QosLimits {
+        self.qos
+            .lock()
+            .as_ref()
+            .map(|qos| qos.limits())
+            .unwrap_or_default()
+    }
+
+    /// number of IOs which were held back by the QoS limits since they were
+    /// last set
+    pub fn qos_throttled(&self) -> u64 {
+        self.qos
+            .lock()
+            .as_ref()
+            .map(|qos| qos.throttled())
+            .unwrap_or_default()
+    }
+
+    /// set the QoS limits of the nexus, setting all limits to 0 removes them.
+    /// The IO channels pick up the new limits with the next IO they submit.
+    pub fn set_qos_limits(&self, limits: QosLimits) {
+        let qos = if limits.is_unlimited() {
+            None
+        } else {
+            Some(Arc::new(QosLimiter::new(limits)))
+        };
+        *self.qos.lock() = qos;
+        self.qos_generation.fetch_add(1);
+        info!("{}: QoS limits set to {:?}", self.name, limits);
+    }
+
+    /// returns the generation of the QoS limits, which changes whenever the
+    /// limits are set
+    pub(crate) fn qos_generation(&self) -> u64 {
+        self.qos_generation.load()
+    }
+
+    /// returns the rate limiter of the nexus, if any
+    pub(crate) fn qos_limiter(&self) -> Option<Arc<QosLimiter>> {
+        self.qos.lock().clone()
+    }
+
     /// register the bdev with SPDK and set the callbacks for io channel
     /// creation. Once this function is called, the device is visible and can
     /// be used for IO.
@@ -1198,6 +1584,7 @@ async fn nexus_create_internal(
                 name, child, error
             );
             ni.close_children().await;
+            ni.release_replicas().await;
             nexus_list.retain(|n| n.name != name);
             return Err(Error::CreateChild {
                 source: error,
@@ -1222,6 +1609,7 @@ async fn nexus_create_internal(
                 // TODO: children may already be destroyed
                 let _ = device_destroy(&child.name).await;
             }
+            ni.release_replicas().await;
             nexus_list.retain(|n| n.name != name);
             Err(Error::NexusCreate {
                 name: String::from(name),
@@ -1231,11 +1619,15 @@ async fn nexus_create_internal(
         Err(error) => {
             error!("failed to open nexus {}: {}", name, error);
             ni.close_children().await;
+            ni.release_replicas().await;
             nexus_list.retain(|n| n.name != name);
             Err(error)
         }
 
-        Ok(_) => Ok(()),
+        Ok(_) => {
+            ni.monitor_slow_children();
+            Ok(())
+        }
     }
 }
 
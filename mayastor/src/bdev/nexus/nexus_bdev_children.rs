@@ -23,16 +23,18 @@
 //! When reconfiguring the nexus, we traverse all our children, create new IO
 //! channels for all children that are in the open state.
 
-use std::cmp::min;
+use std::{cmp::min, convert::TryFrom};
 
 use futures::future::join_all;
 use snafu::ResultExt;
+use url::Url;
 
 use crate::{
     bdev::{
         device_create,
         device_destroy,
         device_lookup,
+        host_nqn,
         lookup_nexus_child,
         nexus::{
             nexus_bdev::{
@@ -45,12 +47,16 @@ use crate::{
             },
             nexus_channel::DrEvent,
             nexus_child::{ChildState, NexusChild},
+            nexus_persistence::PersistOp,
         },
         Reason,
         VerboseError,
     },
-    core::DeviceEventType,
+    core::{Bdev, DeviceEventType},
+    lvs::Lvol,
     nexus_uri::NexusBdevError,
+    rebuild::RebuildMode,
+    subsys::NvmfSubsystem,
 };
 
 impl Nexus {
@@ -79,6 +85,7 @@ impl Nexus {
         uri: &str,
     ) -> Result<(), NexusBdevError> {
         assert_eq!(*self.state.lock(), NexusState::Init);
+        self.restrict_replica(uri).await;
         let name = device_create(uri).await?;
         self.children.push(NexusChild::new(
             uri.to_string(),
@@ -107,7 +114,7 @@ impl Nexus {
         let status = self.add_child_only(uri).await?;
 
         if !norebuild {
-            if let Err(e) = self.start_rebuild(uri).await {
+            if let Err(e) = self.start_rebuild(uri, RebuildMode::Full).await {
                 // todo: CAS-253 retry starting the rebuild again when ready
                 error!(
                     "Child added but rebuild failed to start: {}",
@@ -132,6 +139,7 @@ impl Nexus {
         &mut self,
         uri: &str,
     ) -> Result<NexusStatus, Error> {
+        self.restrict_replica(uri).await;
         let name = device_create(uri).await.context(CreateChild {
             name: self.name.clone(),
         })?;
@@ -211,6 +219,8 @@ impl Nexus {
                     // todo: how to signal this?
                 }
 
+                self.persist(PersistOp::Add(uri.to_owned())).await;
+
                 Ok(self.status())
             }
             Err(e) => {
@@ -259,6 +269,8 @@ impl Nexus {
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(uri).await;
 
+        self.cancel_verify(Some(uri)).await;
+
         let idx = match self.children.iter().position(|c| c.get_name() == uri) {
             None => return Ok(()),
             Some(val) => val,
@@ -274,6 +286,8 @@ impl Nexus {
 
         self.children.remove(idx);
         self.child_count -= 1;
+        self.release_replica(uri).await;
+        self.persist(PersistOp::Remove(uri.to_owned())).await;
 
         self.start_rebuild_jobs(cancelled_rebuilding_children).await;
         Ok(())
@@ -289,6 +303,8 @@ impl Nexus {
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(name).await;
 
+        self.cancel_verify(Some(name)).await;
+
         if let Some(child) =
             self.children.iter_mut().find(|c| c.get_name() == name)
         {
@@ -339,6 +355,8 @@ impl Nexus {
         let cancelled_rebuilding_children =
             self.cancel_child_rebuild_jobs(name).await;
 
+        self.cancel_verify(Some(name)).await;
+
         let result =
             match self.children.iter_mut().find(|c| c.get_name() == name) {
                 Some(child) => {
@@ -379,7 +397,9 @@ impl Nexus {
                 child: name.to_owned(),
                 name: self.name.clone(),
             })?;
-            self.start_rebuild(name).await.map(|_| {})?;
+            self.start_rebuild(name, RebuildMode::Partial)
+                .await
+                .map(|_| {})?;
             Ok(self.status())
         } else {
             Err(Error::ChildNotFound {
@@ -419,6 +439,29 @@ impl Nexus {
                     }
                 }
             }
+            DeviceEventType::DeviceResized => {
+                match lookup_nexus_child(device) {
+                    Some(child) => {
+                        // The nexus itself is only grown on request, once all
+                        // of its children are large enough.
+                        info!(
+                            "{}: child {} has been resized to {} blocks",
+                            child.get_nexus_name(),
+                            child.get_name(),
+                            child
+                                .get_device()
+                                .map(|d| d.num_blocks())
+                                .unwrap_or_default(),
+                        );
+                    }
+                    None => {
+                        info!(
+                            "Ignoring {:?} event for device {}",
+                            event, device
+                        );
+                    }
+                }
+            }
             _ => {
                 info!("Ignoring {:?} event for device {}", event, device);
             }
@@ -531,9 +574,96 @@ impl Nexus {
             }
         }
 
+        // The children of a newly opened nexus are considered to be in sync,
+        // so any write they miss from now on can be tracked.
+        for child in self.children.iter() {
+            if let Some(log) = child.dirty_log() {
+                log.mark_synced();
+            }
+        }
+
         Ok(())
     }
 
+    /// Restrict the subsystem of a replica which is exported by this node,
+    /// and which the child with the given uri connects to over NVMf, to the
+    /// host NQN of the child so that it cannot be connected to twice by
+    /// mistake. Any hosts the replica already allows remain allowed.
+    /// The subsystems of replicas on other nodes have to be restricted
+    /// through the allowed host calls of those nodes.
+    async fn restrict_replica(&mut self, uri: &str) {
+        let (bdev, host) = match local_replica(uri) {
+            Some(replica) => replica,
+            None => return,
+        };
+        let mut hosts = bdev.allowed_hosts();
+        if hosts.contains(&host) {
+            return;
+        }
+        hosts.push(host);
+
+        let result = match Lvol::try_from(bdev.clone()) {
+            Ok(lvol) => {
+                lvol.set_allowed_hosts(hosts).await.map_err(|e| e.verbose())
+            }
+            Err(_) => bdev.set_allowed_hosts(&hosts).map_err(|e| e.verbose()),
+        };
+        match result {
+            Ok(_) => self.restricted_replicas.push(uri.to_string()),
+            Err(e) => warn!(
+                "{}: failed to restrict the replica of child {}: {}",
+                self.name, uri, e
+            ),
+        }
+    }
+
+    /// Undo the restriction of the replica of the child with the given uri,
+    /// if this nexus has restricted it. The replica allows any host again
+    /// when the host NQN of the child was the only one it allowed.
+    pub(crate) async fn release_replica(&mut self, uri: &str) {
+        let idx = match self.restricted_replicas.iter().position(|u| u == uri) {
+            Some(idx) => idx,
+            None => return,
+        };
+        self.restricted_replicas.remove(idx);
+
+        let (bdev, host) = match local_replica(uri) {
+            Some(replica) => replica,
+            None => return,
+        };
+        let mut hosts = bdev.allowed_hosts();
+        if !hosts.contains(&host) {
+            return;
+        }
+        hosts.retain(|h| h != &host);
+
+        let result = match Lvol::try_from(bdev.clone()) {
+            Ok(lvol) if hosts.is_empty() => {
+                lvol.allow_any_host().await.map_err(|e| e.verbose())
+            }
+            Ok(lvol) => {
+                lvol.set_allowed_hosts(hosts).await.map_err(|e| e.verbose())
+            }
+            Err(_) if hosts.is_empty() => {
+                bdev.allow_any_host().map_err(|e| e.verbose())
+            }
+            Err(_) => bdev.set_allowed_hosts(&hosts).map_err(|e| e.verbose()),
+        };
+        if let Err(e) = result {
+            warn!(
+                "{}: failed to release the replica of child {}: {}",
+                self.name, uri, e
+            );
+        }
+    }
+
+    /// Undo the restrictions of all replicas this nexus has restricted.
+    pub(crate) async fn release_replicas(&mut self) {
+        for uri in self.restricted_replicas.clone() {
+            self.release_replica(&uri).await;
+        }
+    }
+
     pub async fn destroy_child(&mut self, name: &str) -> Result<(), Error> {
         if let Some(child) = self.child_lookup(name) {
             child.destroy().await.map_err(|source| Error::DestroyChild {
@@ -582,3 +712,23 @@ impl Nexus {
         }
     }
 }
+
+/// Returns the bdev of the replica exported by this node which the child with
+/// the given uri connects to over NVMf, and the host NQN the child connects
+/// with.
+fn local_replica(uri: &str) -> Option<(Bdev, String)> {
+    let url = Url::parse(uri).ok().filter(|url| {
+        matches!(url.scheme(), "nvmf" | "nvmf+tcp" | "nvmf+rdma")
+    })?;
+    let bdev = url
+        .path_segments()
+        .and_then(|mut s| s.next())
+        .and_then(NvmfSubsystem::lookup)
+        .and_then(|subsystem| subsystem.bdev())?;
+
+    let host = url
+        .query_pairs()
+        .find(|(k, _)| k == "hostnqn")
+        .map_or_else(host_nqn, |(_, v)| v.to_string());
+    Some((bdev, host))
+}
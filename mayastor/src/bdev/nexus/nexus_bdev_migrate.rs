@@ -0,0 +1,132 @@
+//! Implements the migration of a child of a nexus to a new child, typically
+//! to move a replica to another pool without taking the nexus down.
+//!
+//! The new child is added to the nexus and fully rebuilt while the old child
+//! stays in the IO path. Once the rebuild has completed, the nexus is paused
+//! and the new child takes the place of the old one, both in the persistent
+//! nexus information and in the nexus itself. When any of these steps fails,
+//! the new child is removed again, leaving the nexus as it was.
+
+use crate::{
+    bdev::{
+        nexus::{
+            nexus_bdev::{nexus_lookup, Error, Nexus, NexusStatus},
+            nexus_child::ChildState,
+            nexus_persistence::PersistOp,
+        },
+        VerboseError,
+    },
+    rebuild::RebuildMode,
+};
+
+impl Nexus {
+    /// Migrate the child `src` to the new child `dst`, returning once `dst`
+    /// has replaced `src` or, on failure, has been removed again.
+    pub async fn migrate_child(
+        &mut self,
+        src: &str,
+        dst: &str,
+    ) -> Result<NexusStatus, Error> {
+        match self.children.iter().find(|c| c.get_name() == src) {
+            Some(c) if c.state() == ChildState::Open => {}
+            Some(c) => {
+                return Err(Error::MigrateSourceNotHealthy {
+                    child: src.to_owned(),
+                    name: self.name.clone(),
+                    state: c.state().to_string(),
+                })
+            }
+            None => {
+                return Err(Error::ChildNotFound {
+                    child: src.to_owned(),
+                    name: self.name.clone(),
+                })
+            }
+        }
+
+        info!("{}: migrating child {} to {}", self.name, src, dst);
+        self.add_child(dst, true).await?;
+
+        let name = self.name.clone();
+        let result = match self.start_rebuild(dst, RebuildMode::Full).await {
+            Ok(complete) => {
+                // the sender is dropped once the job is done and the state of
+                // the child has been updated accordingly
+                let _ = complete.await;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+
+        // the nexus may have gone away while it was rebuilding
+        let nexus = nexus_lookup(&name).ok_or(Error::NexusNotFound {
+            name: name.clone(),
+        })?;
+
+        let result = match result.and_then(|_| nexus.check_rebuilt(src, dst)) {
+            Ok(_) => nexus.swap_child(src, dst).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!(
+                "{}: failed to migrate child {} to {}: {}",
+                name,
+                src,
+                dst,
+                e.verbose()
+            );
+            if let Err(e) = nexus.remove_child(dst).await {
+                error!(
+                    "{}: failed to remove child {} after the failed migration: {}",
+                    name,
+                    dst,
+                    e.verbose()
+                );
+            }
+            return Err(e);
+        }
+
+        info!("{}: migrated child {} to {}", name, src, dst);
+        Ok(nexus.status())
+    }
+
+    /// Check that the rebuild of the new child `dst` of a migration has left
+    /// it healthy.
+    fn check_rebuilt(&self, src: &str, dst: &str) -> Result<(), Error> {
+        match self.children.iter().find(|c| c.get_name() == dst) {
+            Some(c) if c.state() == ChildState::Open => Ok(()),
+            Some(c) => Err(Error::MigrateRebuild {
+                child: src.to_owned(),
+                dst: dst.to_owned(),
+                name: self.name.clone(),
+                state: c.state().to_string(),
+            }),
+            None => Err(Error::ChildNotFound {
+                child: dst.to_owned(),
+                name: self.name.clone(),
+            }),
+        }
+    }
+
+    /// Replace the child `src` by the rebuilt child `dst`. The nexus is
+    /// paused so that no IO is in flight while its children change.
+    async fn swap_child(&mut self, src: &str, dst: &str) -> Result<(), Error> {
+        self.pause().await?;
+
+        self.persist(PersistOp::Replace((src.to_owned(), dst.to_owned())))
+            .await;
+        let result = self.remove_child(src).await;
+        if result.is_err() {
+            self.persist(PersistOp::Replace((dst.to_owned(), src.to_owned())))
+                .await;
+        }
+
+        // the children have been swapped by now, so a failure to resume must
+        // not undo the migration
+        if let Err(e) = self.resume().await {
+            error!("{}: failed to resume: {}", self.name, e.verbose());
+        }
+        result
+    }
+}
@@ -29,6 +29,7 @@ use crate::{
         ClientOperations,
         RebuildError,
         RebuildJob,
+        RebuildMode,
         RebuildState,
         RebuildStats,
     },
@@ -36,12 +37,16 @@ use crate::{
 
 impl Nexus {
     /// Starts a rebuild job and returns a receiver channel
-    /// which can be used to await the rebuild completion
+    /// which can be used to await the rebuild completion.
+    /// A partial rebuild only copies the regions written to while the child
+    /// was out of the IO path, falling back to a full rebuild when those are
+    /// not known.
     pub async fn start_rebuild(
         &mut self,
         name: &str,
+        mode: RebuildMode,
     ) -> Result<Receiver<RebuildState>, Error> {
-        trace!("{}: start rebuild request for {}", self.name, name);
+        trace!("{}: start {} rebuild request for {}", self.name, mode, name);
 
         let src_child_name = match self
             .children
@@ -54,12 +59,12 @@ impl Nexus {
             }),
         }?;
 
-        let dst_child_name =
+        let (dst_child_name, dirty_log) =
             match self.children.iter().find(|c| c.get_name() == name) {
                 Some(c)
                     if c.state() == ChildState::Faulted(Reason::OutOfSync) =>
                 {
-                    Ok(c.name.clone())
+                    Ok((c.name.clone(), c.dirty_log()))
                 }
                 Some(c) => Err(Error::ChildNotDegraded {
                     child: name.to_owned(),
@@ -72,6 +77,20 @@ impl Nexus {
                 }),
             }?;
 
+        let dirty_log = match mode {
+            RebuildMode::Partial => match dirty_log {
+                Some(log) if log.is_valid() => Some(log),
+                _ => {
+                    info!(
+                        "{}: no dirty log for child {}, rebuilding it fully",
+                        self.name, name
+                    );
+                    None
+                }
+            },
+            RebuildMode::Full => None,
+        };
+
         let job = RebuildJob::create(
             &self.name,
             &src_child_name,
@@ -80,6 +99,7 @@ impl Nexus {
                 start: self.data_ent_offset,
                 end: self.bdev.num_blocks() + self.data_ent_offset,
             },
+            dirty_log,
             |nexus, job| {
                 Reactors::current().send_future(async move {
                     Nexus::notify_rebuild(nexus, job).await;
@@ -217,7 +237,9 @@ impl Nexus {
     /// todo: how to proceed if no healthy child is found?
     pub async fn start_rebuild_jobs(&mut self, child_names: Vec<String>) {
         for name in child_names {
-            if let Err(e) = self.start_rebuild(&name).await {
+            if let Err(e) =
+                self.start_rebuild(&name, RebuildMode::Partial).await
+            {
                 error!("Failed to start rebuild: {}", e.verbose());
             }
         }
@@ -260,6 +282,9 @@ impl Nexus {
 
         match job.state() {
             RebuildState::Completed => {
+                if let Some(log) = recovering_child.dirty_log() {
+                    log.mark_synced();
+                }
                 recovering_child.set_state(ChildState::Open);
                 info!(
                     "Child {} has been rebuilt successfully",
@@ -353,6 +378,7 @@ impl From<RebuildStats> for RebuildStatsReply {
             block_size: stats.block_size,
             tasks_total: stats.tasks_total,
             tasks_active: stats.tasks_active,
+            partial: stats.partial,
         }
     }
 }
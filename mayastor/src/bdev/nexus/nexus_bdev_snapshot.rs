@@ -1,28 +1,269 @@
 //! Implements snapshot operations on a nexus.
 
-use rpc::mayastor::CreateSnapshotReply;
+use nix::errno::Errno;
+use snafu::ResultExt;
+use url::Url;
+
+use rpc::mayastor::{ChildSnapshot, CreateSnapshotReply};
 
 use crate::{
-    bdev::nexus::nexus_bdev::{Error, Nexus},
-    core::BdevHandle,
+    bdev::nexus::{
+        nexus_bdev::{
+            DestroySnapshotChild,
+            DestroySnapshotLocalChild,
+            Error,
+            Nexus,
+            SnapshotChild,
+            SnapshotLocalChild,
+            SnapshotQuiesce,
+            VerboseError,
+        },
+        nexus_child::{ChildState, NexusChild},
+    },
+    core::{nvme_admin_opc, Bdev, RangeContext},
     lvs::Lvol,
+    subsys,
 };
 
 impl Nexus {
-    /// Create a snapshot on all children
-    pub async fn create_snapshot(&self) -> Result<CreateSnapshotReply, Error> {
-        if let Ok(h) = BdevHandle::open_with_bdev(&self.bdev, false) {
-            match h.create_snapshot().await {
-                Ok(t) => Ok(CreateSnapshotReply {
-                    name: Lvol::format_snapshot_name(&self.bdev.name(), t),
-                }),
-                Err(e) => Err(Error::FailedCreateSnapshot {
-                    name: self.bdev.name(),
-                    source: e,
-                }),
+    /// Create a crash consistent snapshot of the nexus. The nexus is paused
+    /// and every healthy child is snapshotted at the same point in time,
+    /// using a snapshot id which is shared by all children.
+    pub async fn create_snapshot(
+        &mut self,
+    ) -> Result<CreateSnapshotReply, Error> {
+        let snapshot_id = subsys::snapshot_time();
+
+        self.pause().await?;
+        let result = self.snapshot_children(snapshot_id).await;
+        self.resume().await?;
+
+        result
+    }
+
+    /// Snapshot all healthy children using the given snapshot id. Writes to
+    /// the nexus are drained, and held off until all children have been
+    /// snapshotted, by locking the entire LBA range of the nexus.
+    pub(crate) async fn snapshot_children(
+        &self,
+        snapshot_id: u64,
+    ) -> Result<CreateSnapshotReply, Error> {
+        let children = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .collect::<Vec<_>>();
+
+        if children.is_empty() {
+            return Err(Error::SnapshotNoHealthyChild {
+                name: self.name.clone(),
+            });
+        }
+
+        let desc = Bdev::open_by_name(&self.name, false).map_err(|_| {
+            Error::SnapshotQuiesce {
+                source: Errno::ENODEV,
+                name: self.name.clone(),
             }
-        } else {
-            Err(Error::FailedGetHandle)
+        })?;
+        let ch = desc.get_channel().ok_or_else(|| Error::SnapshotQuiesce {
+            source: Errno::ENOMEM,
+            name: self.name.clone(),
+        })?;
+
+        let mut ctx = RangeContext::new(0, self.bdev.num_blocks());
+        desc.lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(SnapshotQuiesce {
+                name: self.name.clone(),
+            })?;
+
+        info!(
+            "{}: writes drained, snapshotting {} children with id {}",
+            self.name,
+            children.len(),
+            snapshot_id
+        );
+
+        let mut snapshots = Vec::with_capacity(children.len());
+        let mut snapshotted = Vec::with_capacity(children.len());
+        let mut result = Ok(());
+        for child in children {
+            match self.snapshot_child(child, snapshot_id).await {
+                Ok(name) => {
+                    snapshots.push(ChildSnapshot {
+                        uri: child.get_name().to_string(),
+                        name,
+                    });
+                    snapshotted.push(child);
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        let unlocked = desc.unlock_lba_range(&mut ctx, &ch).await.context(
+            SnapshotQuiesce {
+                name: self.name.clone(),
+            },
+        );
+
+        if let Err(e) = result.and(unlocked) {
+            // the snapshots of the other children are of no use on their own
+            self.destroy_child_snapshots(&snapshotted, snapshot_id)
+                .await;
+            return Err(e);
         }
+
+        info!(
+            "{}: created snapshot {} on {} children",
+            self.name,
+            snapshot_id,
+            snapshots.len()
+        );
+
+        Ok(CreateSnapshotReply {
+            name: Lvol::format_snapshot_name(&self.bdev.name(), snapshot_id),
+            snapshot_id,
+            children: snapshots,
+        })
+    }
+
+    /// Returns the lvol of a local child, which is either the lvol itself or
+    /// an encrypted lvol underneath its crypto bdev, or None for a remote
+    /// child
+    fn local_lvol(&self, child: &NexusChild) -> Result<Option<Lvol>, Error> {
+        let device = child.get_device().map_err(|_| Error::ChildNotFound {
+            child: child.get_name().to_string(),
+            name: self.name.clone(),
+        })?;
+
+        if child.is_local() != Some(true) {
+            return Ok(None);
+        }
+
+        let bdev =
+            Bdev::lookup_by_name(&device.device_name()).ok_or_else(|| {
+                Error::ChildNotFound {
+                    child: child.get_name().to_string(),
+                    name: self.name.clone(),
+                }
+            })?;
+        Lvol::from_share_bdev(bdev)
+            .map(Some)
+            .context(SnapshotLocalChild {
+                child: child.get_name().to_string(),
+                name: self.name.clone(),
+            })
+    }
+
+    /// Snapshot a single child, local replicas are snapshotted directly
+    /// whereas remote replicas are sent the snapshot admin command.
+    /// Returns the name of the snapshot created on the child.
+    async fn snapshot_child(
+        &self,
+        child: &NexusChild,
+        snapshot_id: u64,
+    ) -> Result<String, Error> {
+        if let Some(lvol) = self.local_lvol(child)? {
+            let snapshot_name =
+                Lvol::format_snapshot_name(&lvol.name(), snapshot_id);
+            lvol.snapshot(&snapshot_name).await.context(
+                SnapshotLocalChild {
+                    child: child.get_name().to_string(),
+                    name: self.name.clone(),
+                },
+            )?;
+            return Ok(snapshot_name);
+        }
+
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::CREATE_SNAPSHOT.into());
+        subsys::set_snapshot_id(&mut cmd, snapshot_id);
+
+        let device = child.get_device().map_err(|_| Error::ChildNotFound {
+            child: child.get_name().to_string(),
+            name: self.name.clone(),
+        })?;
+        let handle = child.get_io_handle().context(SnapshotChild {
+            child: child.get_name().to_string(),
+            name: self.name.clone(),
+        })?;
+        handle.nvme_admin(&cmd, None).await.context(SnapshotChild {
+            child: child.get_name().to_string(),
+            name: self.name.clone(),
+        })?;
+
+        // the replica names the snapshot after itself, and the NQN of a
+        // replica ends with the name of the replica
+        let base = Url::parse(child.get_name())
+            .ok()
+            .and_then(|u| u.path().rsplit(':').next().map(String::from))
+            .unwrap_or_else(|| device.device_name());
+        Ok(Lvol::format_snapshot_name(&base, snapshot_id))
+    }
+
+    /// Destroy the snapshots with the given id which were created on the
+    /// given children, logging the snapshots which could not be destroyed
+    async fn destroy_child_snapshots(
+        &self,
+        children: &[&NexusChild],
+        snapshot_id: u64,
+    ) {
+        for child in children {
+            match self.destroy_child_snapshot(child, snapshot_id).await {
+                Ok(()) => info!(
+                    "{}: destroyed snapshot {} of child {}",
+                    self.name,
+                    snapshot_id,
+                    child.get_name()
+                ),
+                Err(e) => error!(
+                    "{}: snapshot {} of child {} is left behind: {}",
+                    self.name,
+                    snapshot_id,
+                    child.get_name(),
+                    e.verbose()
+                ),
+            }
+        }
+    }
+
+    /// Destroy the snapshot with the given id of a single child, local
+    /// replicas are handled directly whereas remote replicas are sent the
+    /// destroy snapshot admin command.
+    async fn destroy_child_snapshot(
+        &self,
+        child: &NexusChild,
+        snapshot_id: u64,
+    ) -> Result<(), Error> {
+        if let Some(lvol) = self.local_lvol(child)? {
+            let snapshot_name =
+                Lvol::format_snapshot_name(&lvol.name(), snapshot_id);
+            return lvol.destroy_snapshot(&snapshot_name).await.context(
+                DestroySnapshotLocalChild {
+                    child: child.get_name().to_string(),
+                    name: self.name.clone(),
+                },
+            );
+        }
+
+        let mut cmd = spdk_sys::spdk_nvme_cmd::default();
+        cmd.set_opc(nvme_admin_opc::DESTROY_SNAPSHOT.into());
+        subsys::set_snapshot_id(&mut cmd, snapshot_id);
+
+        let handle = child.get_io_handle().context(DestroySnapshotChild {
+            child: child.get_name().to_string(),
+            name: self.name.clone(),
+        })?;
+        handle
+            .nvme_admin(&cmd, None)
+            .await
+            .context(DestroySnapshotChild {
+                child: child.get_name().to_string(),
+                name: self.name.clone(),
+            })
     }
 }
@@ -0,0 +1,238 @@
+use futures::channel::oneshot::Receiver;
+use snafu::ResultExt;
+
+use rpc::mayastor::{VerifyMismatch as RpcMismatch, VerifyStatsReply};
+
+use crate::{
+    bdev::{
+        nexus::{
+            nexus_bdev::{
+                nexus_lookup,
+                CreateVerify,
+                Error,
+                Nexus,
+                VerifyJobNotFound,
+                VerifyOperation,
+            },
+            nexus_child::ChildState,
+        },
+        VerboseError,
+    },
+    core::Reactors,
+    verify::{VerifyJob, VerifyMismatch, VerifyOperations, VerifyState},
+};
+
+impl Nexus {
+    /// Starts a verify job comparing the healthy children of the nexus
+    /// against `source`, or the first healthy child if none is given, and
+    /// returns a receiver channel which can be used to await its completion.
+    /// With `repair` set, mismatching blocks are overwritten with the data of
+    /// the source child.
+    /// A finished job is kept until the next one is started, so that its
+    /// results can still be queried.
+    pub async fn start_verify(
+        &mut self,
+        repair: bool,
+        source: Option<&str>,
+    ) -> Result<Receiver<VerifyState>, Error> {
+        trace!(
+            "{}: start verify request, repair: {}, source: {:?}",
+            self.name,
+            repair,
+            source
+        );
+
+        let mut children = self
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+
+        if let Some(source) = source {
+            match self.children.iter().find(|c| c.get_name() == source) {
+                Some(c) if c.state() == ChildState::Open => {
+                    children.retain(|c| c != source);
+                    children.insert(0, source.to_owned());
+                }
+                Some(c) => {
+                    return Err(Error::InvalidVerifySource {
+                        child: source.to_owned(),
+                        name: self.name.clone(),
+                        state: c.state().to_string(),
+                    })
+                }
+                None => {
+                    return Err(Error::ChildNotFound {
+                        child: source.to_owned(),
+                        name: self.name.clone(),
+                    })
+                }
+            }
+        }
+
+        // the results of a finished job are discarded by the next one
+        if VerifyJob::lookup(&self.name).map_or(false, |j| j.state().done()) {
+            let _ = VerifyJob::remove(&self.name);
+        }
+
+        let job = VerifyJob::create(
+            &self.name,
+            &children,
+            std::ops::Range::<u64> {
+                start: self.data_ent_offset,
+                end: self.bdev.num_blocks() + self.data_ent_offset,
+            },
+            repair,
+            |nexus| {
+                Reactors::current().send_future(async move {
+                    Nexus::notify_verify(nexus).await;
+                });
+            },
+        )
+        .context(CreateVerify {
+            name: self.name.clone(),
+        })?;
+
+        job.as_client().start().context(VerifyOperation {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Stop the verify job of the nexus, or discard the results of a job
+    /// that has already finished
+    pub async fn stop_verify(&self) -> Result<(), Error> {
+        let job = self.get_verify_job()?;
+        if job.state().done() {
+            VerifyJob::remove(&self.name).context(VerifyJobNotFound {
+                name: self.name.clone(),
+            })?;
+            return Ok(());
+        }
+        job.as_client().stop().context(VerifyOperation {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Pause the verify job of the nexus
+    pub async fn pause_verify(&self) -> Result<(), Error> {
+        let job = self.get_verify_job()?.as_client();
+        job.pause().context(VerifyOperation {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Resume the previously paused verify job of the nexus
+    pub async fn resume_verify(&self) -> Result<(), Error> {
+        let job = self.get_verify_job()?.as_client();
+        job.resume().context(VerifyOperation {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Return the progress and the mismatches found by the verify job
+    pub async fn get_verify_stats(&self) -> Result<VerifyStatsReply, Error> {
+        let job = self.get_verify_job()?;
+        let stats = job.as_client().stats();
+
+        Ok(VerifyStatsReply {
+            state: job.state().to_string(),
+            source: job.children[0].clone(),
+            children: job.children[1 ..].to_vec(),
+            blocks_total: stats.blocks_total,
+            blocks_verified: stats.blocks_verified,
+            progress: stats.progress,
+            segment_size_blks: stats.segment_size_blks,
+            block_size: stats.block_size,
+            tasks_total: stats.tasks_total,
+            tasks_active: stats.tasks_active,
+            blocks_mismatched: stats.blocks_mismatched,
+            blocks_repaired: stats.blocks_repaired,
+            mismatches: job
+                .mismatches()
+                .iter()
+                .map(RpcMismatch::from)
+                .collect(),
+            error: job.error_desc(),
+        })
+    }
+
+    /// Terminates the verify job of the nexus if it compares the child with
+    /// the given name, or regardless of its children if none is given, and
+    /// waits for it to have stopped
+    pub async fn cancel_verify(&self, child: Option<&str>) {
+        let job = match VerifyJob::lookup(&self.name) {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        if let Some(child) = child {
+            if !job.children.iter().any(|c| c == child) {
+                return;
+            }
+        }
+
+        if let Err(e) = job.as_client().terminate().await {
+            error!(
+                "Failed to wait on the verify job of nexus {} to terminate with error {}",
+                self.name, e
+            );
+        }
+    }
+
+    /// Return the verify job of the nexus
+    fn get_verify_job(&self) -> Result<&mut VerifyJob, Error> {
+        VerifyJob::lookup(&self.name).context(VerifyJobNotFound {
+            name: self.name.clone(),
+        })
+    }
+
+    /// Verify updated callback when the state of a verify job changes
+    async fn notify_verify(nexus: String) {
+        if nexus_lookup(&nexus).is_none() {
+            // the nexus has been destroyed in the meantime
+            let _ = VerifyJob::remove(&nexus);
+            return;
+        }
+
+        let job = match VerifyJob::lookup(&nexus) {
+            Ok(job) => job,
+            Err(e) => return debug!("{}", e.verbose()),
+        };
+
+        match job.state() {
+            VerifyState::Completed => {
+                let stats = job.as_client().stats();
+                if stats.blocks_mismatched == 0 {
+                    info!("The children of nexus {} are consistent", nexus);
+                } else {
+                    warn!(
+                        "{} blocks differ between the children of nexus {}, {} of which have been repaired",
+                        stats.blocks_mismatched, nexus, stats.blocks_repaired
+                    );
+                }
+            }
+            VerifyState::Failed => {
+                error!(
+                    "Verify job of nexus {} failed, error: {}",
+                    nexus,
+                    job.error_desc()
+                );
+            }
+            state => {
+                info!("Verify job of nexus {} is {}", nexus, state);
+            }
+        }
+    }
+}
+
+impl From<&VerifyMismatch> for RpcMismatch {
+    fn from(m: &VerifyMismatch) -> Self {
+        RpcMismatch {
+            offset_blks: m.offset_blks,
+            num_blks: m.num_blks,
+            children: m.children.clone(),
+            repaired: m.repaired,
+        }
+    }
+}
@@ -1,12 +1,20 @@
 //!
 //! IO is driven by means of so called channels.
-use std::{ffi::c_void, fmt::Debug, ptr::NonNull};
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    fmt::Debug,
+    ptr::NonNull,
+    sync::Arc,
+};
 
 use futures::channel::oneshot;
+use merge::Merge;
 
 use spdk_sys::{
     spdk_for_each_channel,
     spdk_for_each_channel_continue,
+    spdk_get_ticks,
     spdk_io_channel,
     spdk_io_channel_iter,
     spdk_io_channel_iter_get_channel,
@@ -15,8 +23,26 @@ use spdk_sys::{
 };
 
 use crate::{
-    bdev::{nexus::nexus_child::ChildState, Nexus, Reason},
-    core::{BlockDeviceHandle, Cores, Mthread},
+    bdev::{
+        nexus::{
+            nexus_bdev::NexusReadPolicy,
+            nexus_child::ChildState,
+            nexus_dirty_log::DirtyLog,
+            nexus_io::NexusBio,
+        },
+        Nexus,
+        Reason,
+    },
+    core::{
+        poller,
+        BlockDeviceHandle,
+        Cores,
+        IoLatency,
+        IoType,
+        Mthread,
+        QosCredits,
+        QosLimiter,
+    },
 };
 
 /// io channel, per core
@@ -30,23 +56,74 @@ pub(crate) struct NexusChannel {
 pub(crate) struct NexusChannelInner {
     pub(crate) writers: Vec<Box<dyn BlockDeviceHandle>>,
     pub(crate) readers: Vec<Box<dyn BlockDeviceHandle>>,
+    /// read statistics, one entry per reader
+    pub(crate) reader_stats: Vec<ReaderStats>,
+    /// id given to the statistics of the next child which becomes a reader
+    next_reader_id: u64,
+    /// number of reads submitted through this channel
+    selections: u64,
+    /// dirty logs of children which are out of the IO path
+    pub(crate) dirty_logs: Vec<Arc<DirtyLog>>,
     pub(crate) previous: usize,
     pub(crate) fail_fast: u32,
+    /// rate limiter of the nexus, if any
+    qos: Option<Arc<QosLimiter>>,
+    /// generation of the QoS limits of the nexus the limiter belongs to
+    qos_generation: u64,
+    /// tokens this channel has taken from the rate limiter
+    qos_credits: QosCredits,
+    /// IOs held back by the rate limiter, in order of submission
+    throttled: VecDeque<NexusBio>,
+    /// submits the held back IOs as the rate limiter allows for it, paused
+    /// while no IOs are held back
+    qos_poller: Option<poller::Poller<'static>>,
+    /// latency of the IOs completed through this channel
+    pub(crate) latency: IoLatency,
     device: *mut c_void,
 }
 
+/// Reads submitted after which the lowest latency policy selects a child
+/// round-robin, to keep the latency of all children up to date.
+const READ_PROBE_INTERVAL: u64 = 128;
+
+/// Interval at which IOs held back by the QoS limits are resubmitted.
+const QOS_POLL_INTERVAL_US: u64 = 100;
+
+/// Per channel read statistics of a child, used by the read policies.
+#[derive(Debug, Default)]
+pub(crate) struct ReaderStats {
+    /// identifies the statistics for as long as the child is a reader, reads
+    /// in flight refer to it as the index of the reader may change
+    id: u64,
+    /// device name of the child
+    name: String,
+    /// the child is not accessed over the network
+    local: bool,
+    /// reads in flight
+    outstanding: u64,
+    /// moving average of the read latency in ticks
+    latency: u64,
+}
+
 impl Debug for NexusChannelInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "readers = {}, writers = {}",
+            "readers = {}, writers = {}, dirty logs = {}",
             self.readers.len(),
-            self.writers.len()
+            self.writers.len(),
+            self.dirty_logs.len()
         )
     }
 }
 
 #[derive(Debug)]
+/// Context of the collection of the latency histograms of the channels.
+struct LatencyCtx {
+    sender: oneshot::Sender<IoLatency>,
+    latency: IoLatency,
+}
+
 /// reconfigure context holding among others
 /// the completion channel.
 pub struct ReconfigureCtx {
@@ -79,25 +156,205 @@ pub enum DrEvent {
     ChildRemove,
     /// Child rebuild event
     ChildRebuild,
+    /// Nexus resize event
+    Resize,
 }
 
 impl NexusChannelInner {
-    /// very simplistic routine to rotate between children for read operations
-    /// note that the channels can be None during a reconfigure; this is usually
-    /// not the case but a side effect of using the async. As we poll
+    /// select the child to read from according to the read policy of the
+    /// nexus. Note that the channels can be None during a reconfigure; this is
+    /// usually not the case but a side effect of using the async. As we poll
     /// threads more often depending on what core we are on etc, we might be
     /// "awaiting' while the thread is already trying to submit IO.
     pub(crate) fn child_select(&mut self) -> Option<usize> {
         if self.readers.is_empty() {
-            None
-        } else {
-            if self.previous < self.readers.len() - 1 {
-                self.previous += 1;
+            return None;
+        }
+
+        let nexus = unsafe { Nexus::from_raw(self.device) };
+        self.selections += 1;
+
+        let selected = match nexus.read_policy() {
+            NexusReadPolicy::RoundRobin => self.next_reader(|_| true),
+            NexusReadPolicy::PreferLocal => self
+                .next_reader(|s| s.local)
+                .or_else(|| self.next_reader(|_| true)),
+            NexusReadPolicy::LeastOutstanding => {
+                self.min_reader(|s| s.outstanding)
+            }
+            NexusReadPolicy::LowestLatency => {
+                if self.selections % READ_PROBE_INTERVAL == 0 {
+                    self.next_reader(|_| true)
+                } else {
+                    self.min_reader(|s| s.latency)
+                }
+            }
+        };
+
+        if let Some(i) = selected {
+            self.previous = i;
+        }
+        selected
+    }
+
+    /// the first reader after the previously selected one which matches the
+    /// filter, wrapping around
+    fn next_reader(
+        &self,
+        filter: impl Fn(&ReaderStats) -> bool,
+    ) -> Option<usize> {
+        let len = self.readers.len();
+        (1 ..= len)
+            .map(|n| (self.previous + n) % len)
+            .find(|i| self.reader_stats.get(*i).map_or(true, &filter))
+    }
+
+    /// the reader with the lowest key, ties are broken round-robin
+    fn min_reader(&self, key: impl Fn(&ReaderStats) -> u64) -> Option<usize> {
+        let len = self.readers.len();
+        (1 ..= len)
+            .map(|n| (self.previous + n) % len)
+            .min_by_key(|i| self.reader_stats.get(*i).map_or(0, &key))
+    }
+
+    /// the read statistics of the child with the given device name, which
+    /// are carried over from the current readers so that reads in flight
+    /// remain accounted for when the readers change
+    fn reader_stats_of(&mut self, name: String, local: bool) -> ReaderStats {
+        match self.reader_stats.iter().position(|s| s.name == name) {
+            Some(i) => self.reader_stats.remove(i),
+            None => {
+                self.next_reader_id += 1;
+                ReaderStats {
+                    id: self.next_reader_id,
+                    name,
+                    local,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// account for a read submitted to the reader at index `i`, returns the
+    /// id of its statistics to account for the completion of the read with
+    pub(crate) fn read_submitted(&mut self, i: usize) -> Option<u64> {
+        self.reader_stats.get_mut(i).map(|stats| {
+            stats.outstanding += 1;
+            stats.id
+        })
+    }
+
+    /// account for a completed read which was submitted at `start` ticks to
+    /// the reader with the statistics of the given id. Reads of children
+    /// which are no longer readers are ignored.
+    pub(crate) fn read_completed(&mut self, id: u64, start: u64) {
+        if let Some(stats) = self.reader_stats.iter_mut().find(|s| s.id == id) {
+            let elapsed = unsafe { spdk_get_ticks() }.saturating_sub(start);
+            stats.outstanding = stats.outstanding.saturating_sub(1);
+            stats.latency = if stats.latency == 0 {
+                elapsed
             } else {
-                self.previous = 0;
+                (stats.latency * 7 + elapsed) / 8
+            };
+        }
+    }
+
+    /// Returns the IO if it can be submitted right away, otherwise the IO is
+    /// held back until the QoS limits of the nexus allow for it. Only reads
+    /// and writes are subject to the limits.
+    pub(crate) fn qos_admit(&mut self, io: NexusBio) -> Option<NexusBio> {
+        self.qos_refresh();
+
+        let qos = match &self.qos {
+            Some(qos) => qos,
+            None => return Some(io),
+        };
+
+        let io_type = io.io_type();
+        if !matches!(io_type, IoType::Read | IoType::Write) {
+            return Some(io);
+        }
+
+        // IOs held back before this one go first
+        if self.throttled.is_empty()
+            && qos.admit(
+                &mut self.qos_credits,
+                io_type,
+                io.num_blocks() * io.block_len(),
+            )
+        {
+            return Some(io);
+        }
+
+        qos.throttle();
+        self.throttled.push_back(io);
+
+        if let Some(poller) = self.qos_poller.as_mut() {
+            if self.throttled.len() == 1 {
+                poller.resume();
+            }
+        } else {
+            let inner = self as *mut NexusChannelInner;
+            self.qos_poller = Some(
+                poller::Builder::new()
+                    .with_name("nexus_qos")
+                    .with_interval(QOS_POLL_INTERVAL_US)
+                    .with_poll_fn(move || unsafe {
+                        (*inner).submit_throttled()
+                    })
+                    .build(),
+            );
+        }
+        None
+    }
+
+    /// Stop the QoS poller of the channel and submit the IOs it was holding
+    /// back, which is done when the limits of the nexus are removed for good.
+    pub(crate) fn qos_stop(&mut self) {
+        self.qos_refresh();
+        self.qos_poller.take();
+        self.throttled.drain(..).for_each(|io| io.submit());
+    }
+
+    /// pick up the rate limiter of the nexus when its QoS limits have changed
+    fn qos_refresh(&mut self) {
+        let nexus = unsafe { Nexus::from_raw(self.device) };
+        let generation = nexus.qos_generation();
+        if generation != self.qos_generation {
+            self.qos_generation = generation;
+            self.qos = nexus.qos_limiter();
+            self.qos_credits = QosCredits::default();
+        }
+    }
+
+    /// submit the IOs held back by the rate limiter, for as far as the limits
+    /// allow for it
+    fn submit_throttled(&mut self) -> i32 {
+        self.qos_refresh();
+
+        let mut submitted = 0;
+        while let Some(io) = self.throttled.front() {
+            if let Some(qos) = &self.qos {
+                let bytes = io.num_blocks() * io.block_len();
+                if !qos.admit(&mut self.qos_credits, io.io_type(), bytes) {
+                    break;
+                }
+            }
+
+            if let Some(io) = self.throttled.pop_front() {
+                io.submit();
+                submitted += 1;
+            }
+        }
+
+        // nothing to do until an IO is held back again
+        if self.throttled.is_empty() {
+            if let Some(poller) = self.qos_poller.as_mut() {
+                poller.pause();
             }
-            Some(self.previous)
         }
+
+        (submitted > 0) as i32
     }
 
     /// Remove a child from the readers and/or writers
@@ -116,8 +373,17 @@ impl NexusChannelInner {
             self.writers.len(),
             self.readers.len(),
         );
-        self.readers
-            .retain(|c| c.get_device().device_name() != name);
+        let mut i = 0;
+        while i < self.readers.len() {
+            if self.readers[i].get_device().device_name() == name {
+                self.readers.remove(i);
+                if i < self.reader_stats.len() {
+                    self.reader_stats.remove(i);
+                }
+            } else {
+                i += 1;
+            }
+        }
         self.writers
             .retain(|c| c.get_device().device_name() != name);
 
@@ -129,7 +395,66 @@ impl NexusChannelInner {
             self.readers.len(),
             nexus.children.len()
         );
-        self.fault_child(name)
+        let faulted = self.fault_child(name);
+        self.track_child_writes(name);
+        faulted
+    }
+
+    /// Record writes to the nexus in the dirty logs of all children that are
+    /// out of the IO path.
+    pub(crate) fn mark_dirty(&self, offset: u64, num_blocks: u64) {
+        self.dirty_logs
+            .iter()
+            .for_each(|log| log.mark(offset, num_blocks));
+    }
+
+    /// Record a write which failed on the given child device, the child
+    /// misses it even though it was still in the IO path of this channel.
+    pub(crate) fn mark_child_dirty(
+        &self,
+        name: &str,
+        offset: u64,
+        num_blocks: u64,
+    ) {
+        let nexus = unsafe { Nexus::from_raw(self.device) };
+        if let Some(log) = nexus
+            .children
+            .iter()
+            .find(|c| c.get_device().map_or(false, |d| d.device_name() == name))
+            .and_then(|c| c.dirty_log())
+        {
+            log.mark(offset, num_blocks);
+        }
+    }
+
+    /// Start recording writes for the child which has just been taken out of
+    /// the IO path of this channel.
+    fn track_child_writes(&mut self, name: &str) {
+        let nexus = unsafe { Nexus::from_raw(self.device) };
+        if let Some(log) = nexus
+            .children
+            .iter()
+            .find(|c| c.get_device().map_or(false, |d| d.device_name() == name))
+            .and_then(|c| c.dirty_log())
+        {
+            if log.is_active()
+                && !self.dirty_logs.iter().any(|l| Arc::ptr_eq(l, &log))
+            {
+                self.dirty_logs.push(log);
+            }
+        }
+    }
+
+    /// Collect the active dirty logs of all children that do not receive
+    /// write IO.
+    fn collect_dirty_logs(nexus: &Nexus) -> Vec<Arc<DirtyLog>> {
+        nexus
+            .children
+            .iter()
+            .filter(|c| c.state() != ChildState::Open && !c.rebuilding())
+            .filter_map(|c| c.dirty_log())
+            .filter(|log| log.is_active())
+            .collect()
     }
 
     /// Fault the child by marking its status.
@@ -151,11 +476,15 @@ impl NexusChannelInner {
                 }
             })
             .any(|c| {
-                ChildState::Open
+                let faulted = ChildState::Open
                     == c.state.compare_and_swap(
                         ChildState::Open,
                         ChildState::Faulted(Reason::IoError),
-                    )
+                    );
+                if faulted {
+                    c.start_dirty_log();
+                }
+                faulted
             })
     }
 
@@ -190,6 +519,7 @@ impl NexusChannelInner {
 
         let mut writers = Vec::new();
         let mut readers = Vec::new();
+        let mut reader_stats = Vec::new();
 
         // iterate over all our children which are in the open state
         nexus
@@ -198,6 +528,10 @@ impl NexusChannelInner {
             .filter(|c| c.state() == ChildState::Open)
             .for_each(|c| match (c.get_io_handle(), c.get_io_handle()) {
                 (Ok(w), Ok(r)) => {
+                    reader_stats.push(self.reader_stats_of(
+                        r.get_device().device_name(),
+                        c.is_local().unwrap_or(false),
+                    ));
                     writers.push(w);
                     readers.push(r);
                 }
@@ -228,6 +562,8 @@ impl NexusChannelInner {
 
         self.writers = writers;
         self.readers = readers;
+        self.reader_stats = reader_stats;
+        self.dirty_logs = Self::collect_dirty_logs(nexus);
 
         trace!(
             "{}: New number of IO channels write:{} read:{} out of {} children",
@@ -254,9 +590,19 @@ impl NexusChannel {
         let mut channels = Box::new(NexusChannelInner {
             writers: Vec::new(),
             readers: Vec::new(),
+            reader_stats: Vec::new(),
+            next_reader_id: 0,
+            selections: 0,
+            dirty_logs: NexusChannelInner::collect_dirty_logs(nexus),
             previous: 0,
             device,
             fail_fast: 0,
+            qos: nexus.qos_limiter(),
+            qos_generation: nexus.qos_generation(),
+            qos_credits: QosCredits::default(),
+            throttled: VecDeque::new(),
+            qos_poller: None,
+            latency: IoLatency::default(),
         });
 
         nexus
@@ -265,6 +611,11 @@ impl NexusChannel {
             .filter(|c| c.state() == ChildState::Open)
             .for_each(|c| match (c.get_io_handle(), c.get_io_handle()) {
                 (Ok(w), Ok(r)) => {
+                    let stats = channels.reader_stats_of(
+                        r.get_device().device_name(),
+                        c.is_local().unwrap_or(false),
+                    );
+                    channels.reader_stats.push(stats);
                     channels.writers.push(w);
                     channels.readers.push(r);
                 }
@@ -284,6 +635,10 @@ impl NexusChannel {
         let inner = NexusChannel::from_raw(ctx).inner_mut();
         inner.writers.clear();
         inner.readers.clear();
+        inner.reader_stats.clear();
+        inner.dirty_logs.clear();
+        inner.qos_poller.take();
+        inner.throttled.drain(..).for_each(|io| io.fail());
     }
 
     /// function called when we receive a Dynamic Reconfigure event (DR)
@@ -332,6 +687,70 @@ impl NexusChannel {
         unsafe { spdk_for_each_channel_continue(ch_iter, 0) };
     }
 
+    /// stop the QoS pollers of all the channels of the nexus
+    pub(crate) fn stop_qos(device: *mut c_void, ctx: Box<ReconfigureCtx>) {
+        unsafe {
+            spdk_for_each_channel(
+                device,
+                Some(NexusChannel::stop_qos_channel),
+                Box::into_raw(ctx).cast(),
+                Some(Self::reconfigure_completed),
+            );
+        }
+    }
+
+    /// stop the QoS poller of a channel, submitting the IOs it held back
+    extern "C" fn stop_qos_channel(ch_iter: *mut spdk_io_channel_iter) {
+        let channel = unsafe { spdk_io_channel_iter_get_channel(ch_iter) };
+        let inner = Self::inner_from_channel(channel);
+        inner.qos_stop();
+        unsafe { spdk_for_each_channel_continue(ch_iter, 0) };
+    }
+
+    /// collect the latency histograms of all the channels of the nexus
+    pub(crate) fn collect_latency(
+        device: *mut c_void,
+    ) -> oneshot::Receiver<IoLatency> {
+        let (sender, receiver) = oneshot::channel();
+        let ctx = Box::new(LatencyCtx {
+            sender,
+            latency: IoLatency::default(),
+        });
+
+        unsafe {
+            spdk_for_each_channel(
+                device,
+                Some(NexusChannel::channel_latency),
+                Box::into_raw(ctx).cast(),
+                Some(Self::collect_latency_completed),
+            );
+        }
+        receiver
+    }
+
+    /// add the latency histograms of a channel to those collected so far
+    extern "C" fn channel_latency(ch_iter: *mut spdk_io_channel_iter) {
+        let ctx = unsafe {
+            &mut *(spdk_io_channel_iter_get_ctx(ch_iter) as *mut LatencyCtx)
+        };
+        let channel = unsafe { spdk_io_channel_iter_get_channel(ch_iter) };
+        ctx.latency.merge(Self::inner_from_channel(channel).latency);
+        unsafe { spdk_for_each_channel_continue(ch_iter, 0) };
+    }
+
+    /// pass on the latency histograms collected from all the channels
+    extern "C" fn collect_latency_completed(
+        ch_iter: *mut spdk_io_channel_iter,
+        _status: i32,
+    ) {
+        let ctx: Box<LatencyCtx> = unsafe {
+            Box::from_raw(
+                spdk_io_channel_iter_get_ctx(ch_iter) as *mut LatencyCtx
+            )
+        };
+        let _ = ctx.sender.send(ctx.latency);
+    }
+
     /// Converts a raw pointer to a nexusChannel. Note that the memory is not
     /// allocated by us.
     pub(crate) fn from_raw<'a>(n: *mut c_void) -> &'a mut Self {
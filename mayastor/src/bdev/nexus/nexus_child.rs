@@ -1,4 +1,7 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::Arc,
+};
 
 use crossbeam::atomic::AtomicCell;
 use futures::{channel::mpsc, SinkExt, StreamExt};
@@ -15,6 +18,8 @@ use crate::{
             instances,
             nexus_channel::DrEvent,
             nexus_child::ChildState::Faulted,
+            nexus_dirty_log::DirtyLog,
+            nexus_io_errors::{IoErrorKind, IoErrors},
         },
         nexus_lookup,
         Guid,
@@ -35,7 +40,7 @@ use crate::{
     },
     nexus_uri::NexusBdevError,
     persistent_store::PersistentStore,
-    rebuild::{ClientOperations, RebuildJob},
+    rebuild::{ClientOperations, RebuildJob, SEGMENT_SIZE},
     spdk_sys::{
         spdk_nvme_registered_ctrlr_extended_data,
         spdk_nvme_reservation_status_extended_data,
@@ -108,6 +113,8 @@ pub enum Reason {
     IoError,
     /// the child has been explicitly faulted due to a rpc call
     Rpc,
+    /// the child has been much slower than the other children for too long
+    Slow,
 }
 
 impl Display for Reason {
@@ -125,6 +132,12 @@ impl Display for Reason {
             }
             Self::IoError => write!(f, "The child had too many I/O errors"),
             Self::Rpc => write!(f, "The child is faulted due to a rpc call"),
+            Self::Slow => {
+                write!(
+                    f,
+                    "The child is too slow compared to the other children"
+                )
+            }
         }
     }
 }
@@ -182,6 +195,12 @@ pub struct NexusChild {
     device: Option<Box<dyn BlockDevice>>,
     #[serde(skip_serializing)]
     device_descriptor: Option<Box<dyn BlockDeviceDescriptor>>,
+    /// Regions written to while the child was out of the IO path.
+    #[serde(skip_serializing)]
+    dirty_log: Option<Arc<DirtyLog>>,
+    /// IO errors experienced by the child
+    #[serde(skip_serializing)]
+    io_errors: IoErrors,
 }
 
 impl Debug for NexusChild {
@@ -203,6 +222,9 @@ impl NexusChild {
     pub(crate) fn set_state(&self, state: ChildState) {
         let prev_state = self.state.swap(state);
         self.prev_state.store(prev_state);
+        if prev_state == ChildState::Open && state != ChildState::Open {
+            self.start_dirty_log();
+        }
         trace!(
             "{}: child {}: state change from {} to {}",
             self.parent,
@@ -278,12 +300,51 @@ impl NexusChild {
         })?;
         self.device_descriptor = Some(desc);
 
+        // Keep an existing log around: it describes what the child missed
+        // while it was closed.
+        if self.dirty_log.is_none() {
+            let block_len = dev.block_len();
+            self.dirty_log = Some(Arc::new(DirtyLog::new(
+                parent_size / block_len,
+                SEGMENT_SIZE / block_len,
+            )));
+        }
+
         self.set_state(ChildState::Open);
 
         debug!("{}: child {} opened successfully", self.parent, self.name);
         Ok(self.name.clone())
     }
 
+    /// Return the dirty region log of this child, if it has been opened.
+    pub(crate) fn dirty_log(&self) -> Option<Arc<DirtyLog>> {
+        self.dirty_log.clone()
+    }
+
+    /// Replace the dirty log after the nexus has been resized to
+    /// `parent_size` bytes. The child is expected to be in sync.
+    pub(crate) fn resize_dirty_log(&mut self, parent_size: u64) {
+        if let Some(dev) = self.device.as_ref() {
+            let block_len = dev.block_len();
+            let log = DirtyLog::new(
+                parent_size / block_len,
+                SEGMENT_SIZE / block_len,
+            );
+            log.mark_synced();
+            if let Some(prev) = self.dirty_log.replace(Arc::new(log)) {
+                prev.invalidate();
+            }
+        }
+    }
+
+    /// Start recording writes which this child misses while it is out of
+    /// the IO path, provided it was in sync up to now.
+    pub(crate) fn start_dirty_log(&self) {
+        if let Some(log) = self.dirty_log.as_ref() {
+            log.activate();
+        }
+    }
+
     /// Check if we're open
     pub(crate) fn is_open(&self) -> bool {
         matches!(
@@ -478,7 +539,9 @@ impl NexusChild {
     /// subsequently be rebuilt.
     pub(crate) async fn fault(&mut self, reason: Reason) {
         match reason {
-            Reason::OutOfSync => {
+            // slow children are kept open so that they can be rebuilt once
+            // they have recovered
+            Reason::OutOfSync | Reason::Slow => {
                 self.set_state(ChildState::Faulted(reason));
             }
             _ => {
@@ -517,7 +580,7 @@ impl NexusChild {
         &self.parent
     }
 
-    /// Online a previously offlined child.
+    /// Online a previously offlined or retired child.
     /// The child is set out-of-sync so that it will be rebuilt.
     /// TODO: channels need to be updated when block devices are opened.
     pub(crate) async fn online(
@@ -526,27 +589,31 @@ impl NexusChild {
     ) -> Result<String, ChildError> {
         // Only online a child if it was previously set offline. Check for a
         // "Closed" state as that is what offlining a child will set it to.
+        // A child retired because of IO errors has lost its block device as
+        // well, and its dirty log holds the writes it failed or missed.
         match self.state.load() {
-            ChildState::Closed => {
-                // Re-create the block device as it will have been previously
-                // destroyed.
-                let name = device_create(&self.name).await.context(
-                    ChildBdevCreate {
-                        child: self.name.clone(),
-                    },
-                )?;
-
-                self.device = device_lookup(&name);
-                if self.device.is_none() {
-                    warn!(
-                        "{}: failed to lookup device after successful creation",
-                        self.name,
-                    );
-                }
+            ChildState::Closed => {}
+            ChildState::Faulted(Reason::IoError) if self.device.is_none() => {
+                self.set_state(ChildState::Closed);
             }
             _ => return Err(ChildError::ChildNotClosed {}),
         }
 
+        // Re-create the block device as it will have been previously
+        // destroyed.
+        let name =
+            device_create(&self.name).await.context(ChildBdevCreate {
+                child: self.name.clone(),
+            })?;
+
+        self.device = device_lookup(&name);
+        if self.device.is_none() {
+            warn!(
+                "{}: failed to lookup device after successful creation",
+                self.name,
+            );
+        }
+
         let result = self.open(parent_size);
         self.set_state(ChildState::Faulted(Reason::OutOfSync));
         result
@@ -699,6 +766,8 @@ impl NexusChild {
             device,
             parent,
             device_descriptor: None,
+            dirty_log: None,
+            io_errors: IoErrors::default(),
             state: AtomicCell::new(ChildState::Init),
             prev_state: AtomicCell::new(ChildState::Init),
             remove_channel: mpsc::channel(0),
@@ -730,6 +799,17 @@ impl NexusChild {
         }
     }
 
+    /// Account for a failed IO of the child. Returns true when the child has
+    /// exhausted its error budget and must be retired.
+    pub(crate) fn record_io_error(&self, kind: IoErrorKind) -> bool {
+        self.io_errors.record(kind)
+    }
+
+    /// Number of media and transport errors experienced by the child.
+    pub fn io_errors(&self) -> (u64, u64) {
+        (self.io_errors.media(), self.io_errors.transport())
+    }
+
     /// Return the rebuild job which is rebuilding this child, if rebuilding.
     fn get_rebuild_job(&self) -> Option<&mut RebuildJob> {
         let job = RebuildJob::lookup(&self.name).ok()?;
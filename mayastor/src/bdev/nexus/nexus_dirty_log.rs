@@ -0,0 +1,209 @@
+//!
+//! Dirty region log (DRL) for nexus children.
+//!
+//! While a child is out of the IO path (faulted, offline or otherwise
+//! degraded) the nexus records which regions of the data partition were
+//! written to. When the child comes back, a partial rebuild only has to copy
+//! the regions marked in the log rather than the entire device.
+//!
+//! The log is only meaningful if the child was in sync with the other
+//! children at the moment tracking started. A child that has never been
+//! rebuilt (i.e. a newly added child) has an invalid log and always requires a
+//! full rebuild.
+//!
+//! Each bit covers a region which is a multiple of the rebuild segment size
+//! so that a dirty region always maps onto whole rebuild segments. The region
+//! size grows with the device size to keep the bitmap bounded.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Upper bound of the number of regions (bits) tracked per child.
+const MAX_REGIONS: u64 = 1 << 20;
+
+/// Bitmap of regions written to while a child was out of the IO path.
+#[derive(Debug)]
+pub struct DirtyLog {
+    /// number of blocks covered by a single bit
+    region_blks: u64,
+    /// number of blocks tracked by the log
+    num_blocks: u64,
+    /// one bit per region
+    bits: Vec<AtomicU64>,
+    /// writes are currently being recorded
+    active: AtomicBool,
+    /// the child was in sync when tracking started
+    valid: AtomicBool,
+}
+
+impl DirtyLog {
+    /// Create a new (invalid) log covering `num_blocks` blocks where each
+    /// region is a multiple of `segment_blks` blocks.
+    pub fn new(num_blocks: u64, segment_blks: u64) -> Self {
+        let segment_blks = std::cmp::max(segment_blks, 1);
+        let segments = (num_blocks + segment_blks - 1) / segment_blks;
+        let multiplier =
+            std::cmp::max((segments + MAX_REGIONS - 1) / MAX_REGIONS, 1);
+        let region_blks = segment_blks * multiplier;
+        let regions = (num_blocks + region_blks - 1) / region_blks;
+        let words = ((regions + 63) / 64) as usize;
+
+        Self {
+            region_blks,
+            num_blocks,
+            bits: (0 .. words).map(|_| AtomicU64::new(0)).collect(),
+            active: AtomicBool::new(false),
+            valid: AtomicBool::new(false),
+        }
+    }
+
+    /// Number of blocks covered by a single region.
+    pub fn region_blks(&self) -> u64 {
+        self.region_blks
+    }
+
+    /// The child is known to be in sync with its peers: discard any recorded
+    /// regions and stop tracking.
+    pub fn mark_synced(&self) {
+        self.active.store(false, Ordering::SeqCst);
+        self.clear();
+        self.valid.store(true, Ordering::SeqCst);
+    }
+
+    /// Discard the log; the child needs a full rebuild from now on.
+    pub fn invalidate(&self) {
+        self.valid.store(false, Ordering::SeqCst);
+        self.active.store(false, Ordering::SeqCst);
+        self.clear();
+    }
+
+    /// Start recording writes. This is a no-op if the log is not valid as
+    /// the child requires a full rebuild anyway.
+    pub fn activate(&self) {
+        if self.is_valid() && !self.active.swap(true, Ordering::SeqCst) {
+            trace!("dirty log activated ({} blks/region)", self.region_blks);
+        }
+    }
+
+    /// Writes are being recorded.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// The log accounts for every write the child has missed.
+    pub fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::SeqCst)
+    }
+
+    /// Record a write of `num_blocks` blocks starting at `offset`, both
+    /// relative to the start of the data partition.
+    pub fn mark(&self, offset: u64, num_blocks: u64) {
+        if num_blocks == 0 || offset >= self.num_blocks {
+            return;
+        }
+
+        let end = std::cmp::min(offset + num_blocks, self.num_blocks);
+        let first = offset / self.region_blks;
+        let last = (end - 1) / self.region_blks;
+
+        for region in first ..= last {
+            self.bits[(region / 64) as usize]
+                .fetch_or(1 << (region % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns true if the region containing `blk` is dirty.
+    pub fn is_dirty(&self, blk: u64) -> bool {
+        if blk >= self.num_blocks {
+            return false;
+        }
+        let region = blk / self.region_blks;
+        self.bits[(region / 64) as usize].load(Ordering::Relaxed)
+            & (1 << (region % 64))
+            != 0
+    }
+
+    /// Returns the first block at or after `blk` which lies within a dirty
+    /// region, if any.
+    pub fn next_dirty(&self, blk: u64) -> Option<u64> {
+        let mut region = blk / self.region_blks;
+        let regions =
+            (self.num_blocks + self.region_blks - 1) / self.region_blks;
+
+        while region < regions {
+            let word = self.bits[(region / 64) as usize]
+                .load(Ordering::Relaxed)
+                >> (region % 64);
+            if word == 0 {
+                // nothing left in this word, skip to the next one
+                region = (region / 64 + 1) * 64;
+                continue;
+            }
+            region += u64::from(word.trailing_zeros());
+            if region < regions {
+                return Some(std::cmp::max(blk, region * self.region_blks));
+            }
+        }
+        None
+    }
+
+    /// Number of blocks covered by dirty regions.
+    pub fn dirty_blocks(&self) -> u64 {
+        let mut total = 0;
+        let mut blk = 0;
+        while let Some(next) = self.next_dirty(blk) {
+            let end = std::cmp::min(
+                (next / self.region_blks + 1) * self.region_blks,
+                self.num_blocks,
+            );
+            total += end - next;
+            blk = end;
+        }
+        total
+    }
+
+    fn clear(&self) {
+        self.bits.iter().for_each(|w| w.store(0, Ordering::SeqCst));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DirtyLog;
+
+    #[test]
+    fn dirty_log_mark_and_iterate() {
+        let log = DirtyLog::new(1000, 8);
+        assert_eq!(log.region_blks(), 8);
+        assert!(!log.is_valid());
+
+        log.activate();
+        assert!(!log.is_active());
+
+        log.mark_synced();
+        log.activate();
+        assert!(log.is_active());
+
+        log.mark(0, 1);
+        log.mark(17, 10);
+        log.mark(996, 100);
+
+        assert!(log.is_dirty(7));
+        assert!(!log.is_dirty(8));
+        assert_eq!(log.next_dirty(1), Some(1));
+        assert_eq!(log.next_dirty(8), Some(16));
+        assert_eq!(log.next_dirty(32), Some(992));
+        assert_eq!(log.next_dirty(1000), None);
+        assert_eq!(log.dirty_blocks(), 8 + 16 + 8);
+
+        log.mark_synced();
+        assert_eq!(log.next_dirty(0), None);
+        assert!(!log.is_active());
+    }
+
+    #[test]
+    fn dirty_log_region_bound() {
+        let log = DirtyLog::new(u64::pow(2, 32), 128);
+        assert_eq!(log.region_blks() % 128, 0);
+        assert!(u64::pow(2, 32) / log.region_blks() <= super::MAX_REGIONS);
+    }
+}
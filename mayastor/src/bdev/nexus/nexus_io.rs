@@ -7,19 +7,26 @@ use std::{
 use libc::c_void;
 use nix::errno::Errno;
 
-use spdk_sys::{spdk_bdev_io, spdk_bdev_io_get_buf, spdk_io_channel};
+use spdk_sys::{
+    spdk_bdev_io,
+    spdk_bdev_io_get_buf,
+    spdk_get_ticks,
+    spdk_io_channel,
+};
 
 use crate::{
     bdev::{
         nexus::{
             nexus_bdev::NEXUS_PRODUCT_ID,
             nexus_channel::{NexusChannel, NexusChannelInner},
+            nexus_io_errors::IoErrorKind,
         },
         nexus_lookup,
         Nexus,
         NexusStatus,
     },
     core::{
+        nvme_admin_opc,
         Bio,
         BlockDevice,
         BlockDeviceHandle,
@@ -33,6 +40,7 @@ use crate::{
         NvmeCommandStatus,
         Reactors,
     },
+    subsys,
 };
 
 #[allow(unused_macros)]
@@ -97,38 +105,46 @@ pub struct NioCtx {
     channel: NonNull<spdk_io_channel>,
     /// the IO must fail regardless of when it completes
     must_fail: bool,
+    /// id of the statistics of the reader a read was submitted to
+    reader: Option<u64>,
+    /// ticks at which a read was submitted
+    read_start: u64,
 }
 
-pub(crate) fn nexus_submit_io(mut io: NexusBio) {
-    if let Err(_e) = match io.cmd() {
-        IoType::Read => io.readv(),
-        // these IOs are submitted to all the underlying children
-        IoType::Write | IoType::WriteZeros | IoType::Reset | IoType::Unmap => {
-            io.submit_all()
-        }
-        IoType::Flush => {
-            io.ok();
-            Ok(())
-        }
-        IoType::NvmeAdmin => {
-            io.fail();
-            Err(CoreError::NotSupported {
-                source: Errno::EINVAL,
-            })
-        }
-        _ => {
-            trace!(?io, "not supported");
-            io.fail();
-            Err(CoreError::NotSupported {
-                source: Errno::EOPNOTSUPP,
-            })
-        }
-    } {
-        //trace!(?e, ?io, "Error during IO submission");
+pub(crate) fn nexus_submit_io(io: NexusBio) {
+    // IOs that exceed the QoS limits of the nexus are submitted later on
+    if let Some(io) = io.inner_channel().qos_admit(io.clone()) {
+        io.submit();
     }
 }
 
 impl NexusBio {
+    /// submit the IO to the children of the nexus
+    pub(crate) fn submit(mut self) {
+        if let Err(_e) = match self.cmd() {
+            IoType::Read => self.readv(),
+            // these IOs are submitted to all the underlying children
+            IoType::Write
+            | IoType::WriteZeros
+            | IoType::Reset
+            | IoType::Unmap => self.submit_all(),
+            IoType::Flush => {
+                self.ok();
+                Ok(())
+            }
+            IoType::NvmeAdmin => self.nvme_admin(),
+            _ => {
+                trace!(?self, "not supported");
+                self.fail();
+                Err(CoreError::NotSupported {
+                    source: Errno::EOPNOTSUPP,
+                })
+            }
+        } {
+            //trace!(?e, ?io, "Error during IO submission");
+        }
+    }
+
     /// helper function to wrap the raw pointers into new types. From here we
     /// should not be dealing with any raw pointers.
     pub unsafe fn nexus_bio_setup(
@@ -182,6 +198,14 @@ impl NexusBio {
 
         self.ctx_as_mut().in_flight -= 1;
 
+        if self.cmd() == IoType::Read {
+            let ctx = self.ctx();
+            if let Some(reader) = ctx.reader {
+                let start = ctx.read_start;
+                self.inner_channel().read_completed(reader, start);
+            }
+        }
+
         if success {
             self.ok_checked();
         } else {
@@ -209,6 +233,8 @@ impl NexusBio {
                 self.retry_checked();
                 //self.fail();
             } else {
+                let submitted = unsafe { (*self.as_ptr()).internal.submit_tsc };
+                self.inner_channel().latency.record(self.cmd(), submitted);
                 self.ok();
             }
         }
@@ -233,8 +259,41 @@ impl NexusBio {
                 )
             };
             debug!(?self, "resubmitting IO");
-            nexus_submit_io(bio);
+            bio.submit();
+        }
+    }
+
+    /// handle an NVMe admin command sent to the nexus itself. Only the
+    /// snapshot command is supported, which snapshots all children.
+    fn nvme_admin(&mut self) -> Result<(), CoreError> {
+        let cmd = self.nvme_cmd();
+        if cmd.opc() != nvme_admin_opc::CREATE_SNAPSHOT.into() {
+            self.fail();
+            return Err(CoreError::NotSupported {
+                source: Errno::EINVAL,
+            });
         }
+
+        let snapshot_id = subsys::snapshot_id(&cmd);
+        let name = self.nexus_as_ref().name.clone();
+        let thread = Mthread::current().unwrap();
+        let bio = self.clone();
+
+        // snapshots must be created on the master core, the IO must be
+        // completed on the thread it was submitted on
+        Reactors::master().send_future(async move {
+            let success = match nexus_lookup(&name) {
+                Some(nexus) => nexus
+                    .snapshot_children(snapshot_id)
+                    .await
+                    .map_err(|e| error!("{}: {}", name, e))
+                    .is_ok(),
+                None => false,
+            };
+            thread.with(|| if success { bio.ok() } else { bio.fail() });
+        });
+
+        Ok(())
     }
 
     /// reference to the inner channels. The inner channel contains the specific
@@ -303,7 +362,11 @@ impl NexusBio {
 
                 self.fail();
             } else {
-                self.ctx_as_mut().in_flight = 1;
+                let reader = inner.read_submitted(i);
+                let ctx = self.ctx_as_mut();
+                ctx.in_flight = 1;
+                ctx.reader = reader;
+                ctx.read_start = unsafe { spdk_get_ticks() };
             }
             r
         } else {
@@ -409,6 +472,16 @@ impl NexusBio {
         // Name of the device which experiences I/O submission failures.
         let mut failed_device = None;
 
+        // Children which are out of the IO path miss this write, remember
+        // the range so that they can be partially rebuilt later on.
+        if matches!(
+            self.cmd(),
+            IoType::Write | IoType::WriteZeros | IoType::Unmap
+        ) {
+            self.inner_channel()
+                .mark_dirty(self.offset(), self.num_blocks());
+        }
+
         let result = self.inner_channel().writers.iter().try_for_each(|h| {
             match self.cmd() {
                 IoType::Write => self.submit_write(h.as_ref()),
@@ -444,6 +517,7 @@ impl NexusBio {
         // device should not be retired in case of ENOMEM.
         if result.is_err() {
             let device = failed_device.unwrap();
+            self.mark_failed_write(&device);
             // set the IO as failed in the submission stage.
             self.ctx_as_mut().must_fail = true;
             if self.inner_channel().remove_child(&device) {
@@ -466,6 +540,21 @@ impl NexusBio {
         result
     }
 
+    /// Record the range of a write which failed on the given child device in
+    /// the dirty log of that child.
+    fn mark_failed_write(&self, device: &str) {
+        if matches!(
+            self.cmd(),
+            IoType::Write | IoType::WriteZeros | IoType::Unmap
+        ) {
+            self.inner_channel().mark_child_dirty(
+                device,
+                self.offset(),
+                self.num_blocks(),
+            );
+        }
+    }
+
     fn do_retire(&self, child: String) {
         Reactors::master().send_future(Self::child_retire(
             self.nexus_as_ref().name.clone(),
@@ -515,6 +604,22 @@ impl NexusBio {
         );
 
         let child = child.device_name();
+
+        // Errors within the error budget of the child are not worth a
+        // rebuild, the IO is retried instead.
+        if !retry && !self.error_budget_exhausted(&child, status) {
+            warn!(
+                "{} IO failed with {:?} within its error budget, retrying",
+                child, status
+            );
+            return self.ok_checked();
+        }
+
+        // The child is leaving the IO path, so remember the range of this
+        // write for the partial rebuild. This also covers writes failing on
+        // other cores which have not yet seen the child being faulted.
+        self.mark_failed_write(&child);
+
         // check if this child needs to be retired
         let needs_retire = self.inner_channel().fault_child(&child);
         // The child state was not faulted yet, so this is the first IO
@@ -531,6 +636,22 @@ impl NexusBio {
         self.fail_checked();
     }
 
+    /// Account for a failed IO of the given device. Returns true when the
+    /// child has exhausted its error budget, or is no longer known.
+    fn error_budget_exhausted(
+        &self,
+        device: &str,
+        status: IoCompletionStatus,
+    ) -> bool {
+        self.nexus_as_ref()
+            .children
+            .iter()
+            .find(|c| {
+                c.get_device().map_or(false, |d| d.device_name() == device)
+            })
+            .map_or(true, |c| c.record_io_error(IoErrorKind::from(status)))
+    }
+
     /// Retire a child for this nexus.
     async fn child_retire(nexus_name: String, device: String) {
         if let Some(nexus) = nexus_lookup(&nexus_name) {
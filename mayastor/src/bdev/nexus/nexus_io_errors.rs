@@ -0,0 +1,115 @@
+//!
+//! IO error accounting of nexus children.
+//!
+//! A single failed IO does not necessarily mean that a child is gone, in
+//! particular when it is accessed over the network. Rather than retiring a
+//! child on its first error, every child gets an error budget: a number of
+//! errors it may experience within a window of time. The IOs that failed
+//! within the budget are retried, once the budget is exhausted the child is
+//! retired.
+//!
+//! Media errors, i.e. errors of the NVMe media and data integrity status code
+//! type such as the end-to-end protection check failures listed in
+//! `NvmeMediaErrorStatusCode`, say something about the data on the child and
+//! have a budget of their own, separate from that of the transport errors.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use spdk_sys::{spdk_get_ticks, spdk_get_ticks_hz};
+
+use crate::{
+    core::{IoCompletionStatus, NvmeCommandStatus},
+    subsys::Config,
+};
+
+/// Kind of error a child IO failed with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoErrorKind {
+    /// media or data integrity error reported by the device
+    Media,
+    /// any other error, typically caused by the path to the device
+    Transport,
+}
+
+impl From<IoCompletionStatus> for IoErrorKind {
+    fn from(status: IoCompletionStatus) -> Self {
+        match status {
+            IoCompletionStatus::NvmeError(
+                NvmeCommandStatus::MediaDataIntegrityErrors,
+            ) => Self::Media,
+            _ => Self::Transport,
+        }
+    }
+}
+
+/// Errors of a single kind, in total and within the current window.
+#[derive(Debug, Default)]
+struct ErrorCounter {
+    /// errors since the child was created
+    total: AtomicU64,
+    /// ticks at which the current window started
+    window_start: AtomicU64,
+    /// errors within the current window
+    in_window: AtomicU64,
+}
+
+impl ErrorCounter {
+    /// account for an error, returning the number of errors within the
+    /// window it falls in
+    fn record(&self, now: u64, window: u64) -> u64 {
+        self.total.fetch_add(1, Ordering::Relaxed);
+
+        let start = self.window_start.load(Ordering::Relaxed);
+        if now.saturating_sub(start) >= window
+            && self
+                .window_start
+                .compare_exchange(
+                    start,
+                    now,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            self.in_window.store(0, Ordering::Relaxed);
+        }
+
+        self.in_window.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// IO error counters of a child, updated from all cores.
+#[derive(Debug, Default)]
+pub struct IoErrors {
+    media: ErrorCounter,
+    transport: ErrorCounter,
+}
+
+impl IoErrors {
+    /// Account for an IO error of the given kind. Returns true when the
+    /// error budget of the child is exhausted.
+    pub fn record(&self, kind: IoErrorKind) -> bool {
+        let config = Config::get();
+        let opts = &config.nexus_opts;
+        let (counter, limit) = match kind {
+            IoErrorKind::Media => (&self.media, opts.child_media_error_limit),
+            IoErrorKind::Transport => {
+                (&self.transport, opts.child_transport_error_limit)
+            }
+        };
+
+        let window =
+            opts.child_error_window_secs * unsafe { spdk_get_ticks_hz() };
+        counter.record(unsafe { spdk_get_ticks() }, window) >= limit
+    }
+
+    /// number of media errors since the child was created
+    pub fn media(&self) -> u64 {
+        self.media.total.load(Ordering::Relaxed)
+    }
+
+    /// number of transport errors since the child was created
+    pub fn transport(&self) -> u64 {
+        self.transport.total.load(Ordering::Relaxed)
+    }
+}
@@ -114,10 +114,18 @@ pub enum LabelError {
         block_size
     ))]
     DeviceTooSmall { num_blocks: u64, block_size: u64 },
+    #[snafu(display(
+        "Child {} is too small to accommodate a data partition of {} bytes",
+        name,
+        size
+    ))]
+    ChildTooSmall { name: String, size: u64 },
     #[snafu(display("Child data offsets differ for nexus {}", name))]
     DataOffsetMismatch { name: String },
     #[snafu(display("Nexus {} has no children", name))]
     MissingChildren { name: String },
+    #[snafu(display("Child {} is not open", name))]
+    ChildNotOpen { name: String },
     #[snafu(display(
         "Error setting MetaDataIndex address for child {}: {}",
         name,
@@ -1253,6 +1261,35 @@ impl NexusChild {
         Ok(label)
     }
 
+    /// Check that the child device is large enough to accommodate a data
+    /// partition of the given size.
+    fn check_label_size(&self, size: u64) -> Result<(), LabelError> {
+        let handle = self.get_io_handle().context(HandleError {
+            name: self.name.clone(),
+        })?;
+
+        let bdev = handle.get_device();
+        let label = NexusLabel::generate_label(
+            GptGuid::from(bdev.uuid()),
+            bdev.block_len(),
+            bdev.num_blocks(),
+            size,
+        )?;
+
+        let data_blocks = Aligned::get_blocks(size, bdev.block_len());
+        match label.get_partition("MayaData") {
+            Some(entry)
+                if entry.ent_end + 1 - entry.ent_start >= data_blocks =>
+            {
+                Ok(())
+            }
+            _ => Err(LabelError::ChildTooSmall {
+                name: self.name.clone(),
+                size,
+            }),
+        }
+    }
+
     /// Validate a child device.
     /// Ensure that both primary and secondary disk labels
     /// are synced with the device as required. Usually called
@@ -1447,6 +1484,60 @@ impl Nexus {
 
         Ok(())
     }
+
+    /// Write a new label with a data partition of the given size to each
+    /// child device, after checking that all of them are open and large
+    /// enough.
+    /// Returns the resulting size of the nexus in blocks.
+    /// Once a child has grown, its existing label no longer matches the size
+    /// of the device, so the label is regenerated rather than updated; the
+    /// offset of the data partition stays the same.
+    pub(crate) async fn resize_child_labels(
+        &mut self,
+        size: u64,
+    ) -> Result<u64, LabelError> {
+        if self.children.is_empty() {
+            return Err(LabelError::MissingChildren {
+                name: self.name.clone(),
+            });
+        }
+
+        for child in self.children.iter() {
+            if !child.is_open() {
+                return Err(LabelError::ChildNotOpen {
+                    name: child.get_name().to_string(),
+                });
+            }
+            child.check_label_size(size)?;
+        }
+
+        let block_size = u64::from(self.bdev.block_len());
+
+        let mut offsets: Vec<u64> = Vec::new();
+        let mut new_size = size;
+
+        for child in self.children.iter_mut() {
+            let label = child.generate_and_write_label(size).await?;
+
+            // Append the offset of the Data partition
+            offsets.push(label.partition_offset("MayaData")?);
+
+            // Adjust size as necessary
+            new_size = min(new_size, label.partition_size("MayaData")?);
+        }
+
+        // The data partition must not have moved.
+        offsets.dedup();
+
+        if offsets.len() != 1 || offsets[0] / block_size != self.data_ent_offset
+        {
+            return Err(LabelError::DataOffsetMismatch {
+                name: self.name.clone(),
+            });
+        }
+
+        Ok(new_size / block_size)
+    }
 }
 
 struct LabelData {
@@ -1,13 +1,20 @@
 use crate::{
     bdev::{nexus::nexus_child::NexusChild, ChildState, Nexus},
+    core::MayastorEnvironment,
     persistent_store::PersistentStore,
     sleep::mayastor_sleep,
+    store::store_defs::StoreError,
 };
+use rpc::mayastor::ShareProtocolNexus;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 type ChildUri = String;
 
+/// Prefix of the keys under which the nexus information is saved in the
+/// persistent store, the key of a nexus ends in its UUID.
+pub const NEXUS_INFO_PREFIX: &str = "nexus/";
+
 /// Definition of the nexus information that gets saved in the persistent
 /// store.
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -16,6 +23,26 @@ pub struct NexusInfo {
     pub clean_shutdown: bool,
     /// Information about children.
     pub children: Vec<ChildInfo>,
+    /// Name of the nexus.
+    #[serde(default)]
+    pub name: String,
+    /// Size of the nexus in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// Node the nexus was on when the information was last saved.
+    #[serde(default)]
+    pub node: String,
+    /// Protocol the nexus is shared with, if any.
+    #[serde(default)]
+    pub share: Option<ShareProtocolNexus>,
+}
+
+impl NexusInfo {
+    /// Key under which the information of the nexus with the given UUID is
+    /// saved in the persistent store.
+    pub fn key(uuid: &str) -> String {
+        format!("{}{}", NEXUS_INFO_PREFIX, uuid)
+    }
 }
 
 /// Definition of the child information that gets saved in the persistent
@@ -26,6 +53,9 @@ pub struct ChildInfo {
     pub uuid: String,
     /// Child's state of health.
     pub healthy: bool,
+    /// URI of the child.
+    #[serde(default)]
+    pub uri: String,
 }
 
 /// Defines the type of persist operations.
@@ -34,6 +64,14 @@ pub(crate) enum PersistOp {
     Create,
     /// Update a persistent entry.
     Update((ChildUri, ChildState)),
+    /// Replace the entry of a child by that of a healthy new child.
+    Replace((ChildUri, ChildUri)),
+    /// Add an entry for a child added to the nexus.
+    Add(ChildUri),
+    /// Remove the entry of a child removed from the nexus.
+    Remove(ChildUri),
+    /// Save the protocol the nexus is shared with.
+    Share(Option<ShareProtocolNexus>),
     /// Save the clean shutdown variable.
     Shutdown,
 }
@@ -46,6 +84,11 @@ impl Nexus {
         }
 
         let mut nexus_info = self.nexus_info.lock().await;
+        // Record enough about the nexus for it to be recreated after a
+        // restart.
+        nexus_info.name = self.name.clone();
+        nexus_info.size = self.size;
+        nexus_info.node = MayastorEnvironment::global_or_default().node_name;
         match op {
             PersistOp::Create => {
                 // Initialisation of the persistent info will overwrite any
@@ -59,6 +102,7 @@ impl Nexus {
                         uuid: NexusChild::uuid(&c.name)
                             .expect("Failed to get child UUID."),
                         healthy: Self::child_healthy(&c.state()),
+                        uri: c.name.clone(),
                     };
                     nexus_info.children.push(child_info);
                 });
@@ -75,11 +119,59 @@ impl Nexus {
                     }
                 });
             }
+            PersistOp::Replace((old_uri, new_uri)) => {
+                let old = NexusChild::uuid(&old_uri)
+                    .expect("Failed to get child UUID.");
+                let new = NexusChild::uuid(&new_uri)
+                    .expect("Failed to get child UUID.");
+                // The new child takes the place of the old one in a single
+                // update, so that the nexus is never recorded with both or
+                // neither of them. The new child has an entry of its own
+                // since it was added, which must not be left behind.
+                nexus_info.children.retain(|c| c.uuid != new);
+                nexus_info.children.iter_mut().for_each(|c| {
+                    if c.uuid == old {
+                        c.uuid = new.clone();
+                        c.healthy = true;
+                        c.uri = new_uri.clone();
+                    }
+                });
+            }
+            PersistOp::Add(uri) => {
+                let uuid =
+                    NexusChild::uuid(&uri).expect("Failed to get child UUID.");
+                // A newly added child is out of sync until it is rebuilt.
+                nexus_info.children.retain(|c| c.uuid != uuid);
+                nexus_info.children.push(ChildInfo {
+                    uuid,
+                    healthy: false,
+                    uri,
+                });
+            }
+            PersistOp::Remove(uri) => {
+                if let Some(uuid) = NexusChild::uuid(&uri) {
+                    nexus_info.children.retain(|c| c.uuid != uuid);
+                }
+            }
+            PersistOp::Share(protocol) => {
+                nexus_info.share = protocol;
+            }
             PersistOp::Shutdown => {
                 // Only update the clean shutdown variable. Do not update the
                 // child state information.
                 // This should only be called when destroying a nexus.
                 nexus_info.clean_shutdown = true;
+                self.save(&nexus_info).await;
+                // Allow another node to take over the nexus.
+                let nexus_uuid = self.bdev.uuid().to_string();
+                let key = NexusInfo::key(&nexus_uuid);
+                if let Err(e) = PersistentStore::release(&key).await {
+                    error!(
+                        "Failed to release nexus {}, UUID {}: {}",
+                        self.name, nexus_uuid, e
+                    );
+                }
+                return;
             }
         }
         self.save(&nexus_info).await;
@@ -92,17 +184,28 @@ impl Nexus {
 
     // Save the nexus info to the store. This is integral to ensuring data
     // consistency across restarts of Mayastor. Therefore, keep retrying
-    // until successful.
+    // until successful, unless the nexus info is owned by another node in
+    // which case this node has been fenced off and must not overwrite it.
     // TODO: Should we give up retrying eventually?
     async fn save(&self, info: &NexusInfo) {
         let mut output_err = true;
         let nexus_uuid = self.bdev.uuid().to_string();
+        let key = NexusInfo::key(&nexus_uuid);
         loop {
-            match PersistentStore::put(&nexus_uuid, info).await {
+            match PersistentStore::put_owned(&key, info).await {
                 Ok(_) => {
                     // The state was saved successfully.
                     break;
                 }
+                Err(StoreError::NotOwner {
+                    owner, ..
+                }) => {
+                    error!(
+                        "Not persisting nexus information for nexus {}, UUID {} as it is owned by {}",
+                        self.name, nexus_uuid, owner
+                    );
+                    break;
+                }
                 Err(e) => {
                     // Output an error message on first failure. Thereafter
                     // silently retry.
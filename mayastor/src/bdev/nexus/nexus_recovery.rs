@@ -0,0 +1,130 @@
+//! Recreates the nexuses that were on this node before Mayastor restarted,
+//! from the information saved about them in the persistent store.
+//!
+//! A nexus is recreated when its information was last saved by this node and
+//! it was not shut down cleanly, that is it has not been destroyed. Only the
+//! children that were healthy are part of the recreated nexus at first, the
+//! other children are added afterwards and rebuilt from the healthy ones.
+//! Finally the nexus is shared again with the protocol it was shared with.
+//! Nexuses are recreated with the default NVMe parameters.
+
+use crate::{
+    bdev::{
+        nexus::{
+            nexus_bdev::{
+                nexus_create,
+                nexus_lookup,
+                Error,
+                RecoverNoHealthyChild,
+            },
+            nexus_persistence::{NexusInfo, NEXUS_INFO_PREFIX},
+        },
+        VerboseError,
+    },
+    core::{MayastorEnvironment, Mthread, Uuid},
+    persistent_store::PersistentStore,
+};
+
+/// Recreate the nexuses in the background on the init thread, as nexuses can
+/// only be created there.
+pub fn spawn_nexus_recovery() {
+    if let Err(e) = Mthread::get_init().spawn_local(nexus_recover()) {
+        error!("Failed to start the recovery of nexuses: {}", e);
+    }
+}
+
+/// Recreate all nexuses this node owned before it restarted, returning the
+/// number of nexuses that have been recreated.
+pub async fn nexus_recover() -> usize {
+    if !PersistentStore::enabled() {
+        warn!("Not recovering nexuses without a persistent store");
+        return 0;
+    }
+
+    let entries = match PersistentStore::get_prefix(NEXUS_INFO_PREFIX).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to list the nexuses to recover: {}", e);
+            return 0;
+        }
+    };
+
+    let node = MayastorEnvironment::global_or_default().node_name;
+    let mut recovered = 0;
+    for (key, value) in entries {
+        // the key of the nexus information ends in the UUID of the nexus
+        let uuid = key.trim_start_matches(NEXUS_INFO_PREFIX).to_string();
+        if Uuid::parse_str(&uuid).is_err() {
+            continue;
+        }
+        let info = match serde_json::from_value::<NexusInfo>(value) {
+            Ok(info) => info,
+            Err(_) => continue,
+        };
+        if info.clean_shutdown || info.node != node || info.name.is_empty() {
+            continue;
+        }
+        if nexus_lookup(&info.name).is_some() {
+            continue;
+        }
+        // another node may have taken over the nexus in the meantime
+        if let Err(e) = PersistentStore::claim(&key).await {
+            warn!("Not recovering nexus {}: {}", info.name, e);
+            continue;
+        }
+
+        let name = info.name.clone();
+        match recover(&uuid, info).await {
+            Ok(_) => {
+                info!("Recovered nexus {}, UUID {}", name, uuid);
+                recovered += 1;
+            }
+            Err(e) => error!(
+                "Failed to recover nexus {}, UUID {}: {}",
+                name,
+                uuid,
+                e.verbose()
+            ),
+        }
+    }
+    recovered
+}
+
+/// Recreate a single nexus from its persisted information.
+async fn recover(uuid: &str, info: NexusInfo) -> Result<(), Error> {
+    let (healthy, unhealthy): (Vec<_>, Vec<_>) = info
+        .children
+        .into_iter()
+        .filter(|c| !c.uri.is_empty())
+        .partition(|c| c.healthy);
+
+    if healthy.is_empty() {
+        return RecoverNoHealthyChild {
+            name: info.name,
+        }
+        .fail();
+    }
+
+    let children = healthy.into_iter().map(|c| c.uri).collect::<Vec<_>>();
+    nexus_create(&info.name, info.size, Some(uuid), &children).await?;
+
+    let nexus = nexus_lookup(&info.name).ok_or(Error::NexusNotFound {
+        name: info.name.clone(),
+    })?;
+    for child in unhealthy {
+        // a child that can no longer be added does not prevent the recovery
+        if let Err(e) = nexus.add_child(&child.uri, false).await {
+            error!(
+                "Failed to add child {} to nexus {}: {}",
+                child.uri,
+                info.name,
+                e.verbose()
+            );
+        }
+    }
+
+    if let Some(protocol) = info.share {
+        nexus.share(protocol, None).await?;
+    }
+    Ok(())
+}
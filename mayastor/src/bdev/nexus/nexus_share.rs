@@ -6,6 +6,7 @@ use rpc::mayastor::ShareProtocolNexus;
 use crate::{
     bdev::nexus::{
         nexus_bdev::{
+            AllowedHosts,
             Error,
             Nexus,
             NexusTarget,
@@ -15,6 +16,7 @@ use crate::{
             UnshareNexus,
         },
         nexus_nbd::NbdDisk,
+        nexus_persistence::PersistOp,
     },
     core::{Protocol, Share},
 };
@@ -50,17 +52,23 @@ impl Share for Nexus {
         Ok(self.share_uri().unwrap())
     }
 
+    /// share the nexus over NVMf, only allowing the hosts of its NVMe
+    /// parameters to connect
     async fn share_nvmf(
         &self,
         cntlid_range: Option<(u16, u16)>,
     ) -> Result<Self::Output, Self::Error> {
         match self.shared() {
             Some(Protocol::Off) | None => {
-                self.bdev.share_nvmf(cntlid_range).await.context(
-                    ShareNvmfNexus {
+                self.bdev
+                    .share_nvmf_hosts(
+                        cntlid_range,
+                        &self.nvme_params.allowed_hosts,
+                    )
+                    .await
+                    .context(ShareNvmfNexus {
                         name: self.name.clone(),
-                    },
-                )?;
+                    })?;
             }
             Some(Protocol::Nvmf) => {}
             Some(protocol) => {
@@ -124,7 +132,7 @@ impl Nexus {
             });
         }
 
-        match protocol {
+        let uri = match protocol {
             ShareProtocolNexus::NexusNbd => {
                 let disk = NbdDisk::create(&self.name).await.context(
                     ShareNbdNexus {
@@ -133,12 +141,12 @@ impl Nexus {
                 )?;
                 let uri = disk.as_uri();
                 self.nexus_target = Some(NexusTarget::NbdDisk(disk));
-                Ok(uri)
+                uri
             }
             ShareProtocolNexus::NexusIscsi => {
                 let uri = self.share_iscsi().await?;
                 self.nexus_target = Some(NexusTarget::NexusIscsiTarget);
-                Ok(uri)
+                uri
             }
             ShareProtocolNexus::NexusNvmf => {
                 let uri = self
@@ -148,9 +156,41 @@ impl Nexus {
                     )))
                     .await?;
                 self.nexus_target = Some(NexusTarget::NexusNvmfTarget);
-                Ok(uri)
+                uri
             }
+        };
+
+        // so that the nexus is shared again when it is recreated
+        self.persist(PersistOp::Share(Some(protocol))).await;
+        Ok(uri)
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the nexus when it
+    /// is shared over NVMf, an empty list means any host may connect
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        self.nvme_params.allowed_hosts.clone()
+    }
+
+    /// only allow the hosts with the given NQNs to connect to the nexus when
+    /// it is shared over NVMf, which takes effect immediately if the nexus is
+    /// shared already. An empty list is rejected when hosts are allowed
+    /// explicitly, as any host could connect then.
+    pub fn set_allowed_hosts(
+        &mut self,
+        hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        if hosts.is_empty() && !self.allowed_hosts().is_empty() {
+            return Err(Error::AllowAnyHost {
+                name: self.name.clone(),
+            });
         }
+        if let Some(NexusTarget::NexusNvmfTarget) = self.nexus_target {
+            self.bdev.set_allowed_hosts(&hosts).context(AllowedHosts {
+                name: self.name.clone(),
+            })?;
+        }
+        self.nvme_params.set_allowed_hosts(hosts);
+        Ok(())
     }
 
     pub async fn unshare_nexus(&mut self) -> Result<(), Error> {
@@ -166,9 +206,11 @@ impl Nexus {
             }
             None => {
                 warn!("{} was not shared", self.name);
+                return Ok(());
             }
         }
 
+        self.persist(PersistOp::Share(None)).await;
         Ok(())
     }
 
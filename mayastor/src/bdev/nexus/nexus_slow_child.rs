@@ -0,0 +1,277 @@
+//! Detection of children which still complete their IOs, but with a latency
+//! far higher than the other children of the nexus. As writes are submitted
+//! to all children, a single slow child slows down the nexus as a whole.
+//! Such a child is faulted, but kept open so that it can be probed and
+//! rebuilt once its latency is back to normal.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bdev::{
+        device_lookup,
+        nexus::{
+            nexus_bdev::{nexus_lookup, Nexus},
+            nexus_child::{ChildState, Reason},
+            nexus_persistence::PersistOp,
+        },
+        VerboseError,
+    },
+    core::{DmaBuf, Reactors},
+    rebuild::RebuildMode,
+    sleep::mayastor_sleep,
+    subsys::Config,
+};
+
+/// Interval at which the latency of the children is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Latency history of a child.
+#[derive(Debug, Default)]
+struct ChildLatency {
+    /// number of IOs completed by the child at the previous sample
+    count: u64,
+    /// total latency of those IOs in microseconds
+    total_us: u64,
+    /// since when the child has been slow or, for a faulted child, since
+    /// when it has no longer been slow
+    since: Option<Instant>,
+}
+
+impl ChildLatency {
+    /// true once the condition has held for the whole window
+    fn sustained(&mut self, condition: bool, window: Duration) -> bool {
+        if !condition {
+            self.since = None;
+            return false;
+        }
+        self.since.get_or_insert_with(Instant::now).elapsed() >= window
+    }
+}
+
+/// Look up the nexus with the given name, provided it is still the nexus
+/// with the given uuid and not another nexus created with the same name.
+fn lookup_nexus(name: &str, uuid: &str) -> Option<&'static mut Nexus> {
+    nexus_lookup(name).filter(|n| n.bdev.uuid_as_string() == uuid)
+}
+
+impl Nexus {
+    /// Monitor the latency of the children of the nexus in the background
+    /// for as long as the nexus exists. The monitor runs even while slow
+    /// child detection is disabled, as it may be enabled at runtime.
+    pub(crate) fn monitor_slow_children(&self) {
+        let name = self.name.clone();
+        let uuid = self.bdev.uuid_as_string();
+
+        Reactors::master().send_future(async move {
+            let mut latencies = HashMap::new();
+            loop {
+                if mayastor_sleep(SAMPLE_INTERVAL).await.is_err() {
+                    error!("{}: failed to wait for Mayastor sleep", name);
+                    break;
+                }
+
+                // stop when the nexus is gone
+                if Self::check_slow_children(&name, &uuid, &mut latencies)
+                    .await
+                    .is_none()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Fault the children which have been slow for too long and rebuild the
+    /// slow children which have recovered for long enough. The nexus and its
+    /// children may go away while awaiting, so the nexus is looked up again
+    /// after every await. Returns None when the nexus is gone.
+    async fn check_slow_children(
+        nexus_name: &str,
+        uuid: &str,
+        latencies: &mut HashMap<String, ChildLatency>,
+    ) -> Option<()> {
+        let config = Config::get();
+        let opts = &config.nexus_opts;
+        // the detection may be disabled, or enabled again, at runtime
+        if opts.slow_child_factor == 0 {
+            return lookup_nexus(nexus_name, uuid).map(|_| ());
+        }
+        let window = Duration::from_secs(opts.slow_child_window_secs);
+        let min_latency_us = opts.slow_child_min_latency_ms * 1000;
+
+        let open = lookup_nexus(nexus_name, uuid)?
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Open)
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+
+        // mean latency of the open children over the last interval
+        let mut means = Vec::new();
+        for name in open {
+            let device = lookup_nexus(nexus_name, uuid)?
+                .children
+                .iter()
+                .find(|c| c.name == name && c.state() == ChildState::Open)
+                .and_then(|c| c.get_device().ok())
+                .and_then(|d| device_lookup(&d.device_name()));
+            let stats = match device {
+                Some(device) => device.io_stats().await,
+                None => continue,
+            };
+            if let Ok(stats) = stats {
+                let count =
+                    stats.read_latency.count + stats.write_latency.count;
+                let total_us =
+                    stats.read_latency.total_us + stats.write_latency.total_us;
+                let latency = latencies.entry(name.clone()).or_default();
+                if count > latency.count && total_us >= latency.total_us {
+                    means.push((
+                        name,
+                        (total_us - latency.total_us) / (count - latency.count),
+                    ));
+                }
+                latency.count = count;
+                latency.total_us = total_us;
+            }
+        }
+
+        let mut slow = Vec::new();
+        for (name, mean) in means.iter() {
+            let mut peers = means
+                .iter()
+                .filter(|(n, _)| n != name)
+                .map(|(_, m)| *m)
+                .collect::<Vec<_>>();
+            peers.sort_unstable();
+            let is_slow = match peers.get(peers.len() / 2) {
+                Some(median) => {
+                    *mean >= min_latency_us
+                        && *mean > median.saturating_mul(opts.slow_child_factor)
+                }
+                None => false,
+            };
+
+            if let Some(latency) = latencies.get_mut(name) {
+                if latency.sustained(is_slow, window) {
+                    latency.since = None;
+                    slow.push(name.clone());
+                }
+            }
+        }
+
+        for name in slow {
+            warn!(
+                "{}: child {} has been too slow for {:?}, faulting it",
+                nexus_name, name, window
+            );
+            match lookup_nexus(nexus_name, uuid)?
+                .fault_child(&name, Reason::Slow)
+                .await
+            {
+                Ok(_) => {
+                    lookup_nexus(nexus_name, uuid)?
+                        .persist(PersistOp::Update((
+                            name,
+                            ChildState::Faulted(Reason::Slow),
+                        )))
+                        .await
+                }
+                Err(e) => error!(
+                    "{}: failed to fault slow child {}: {}",
+                    nexus_name,
+                    name,
+                    e.verbose()
+                ),
+            }
+        }
+
+        let mut means = means.into_iter().map(|(_, m)| m).collect::<Vec<_>>();
+        means.sort_unstable();
+        let median = means.get(means.len() / 2).copied();
+
+        let faulted = lookup_nexus(nexus_name, uuid)?
+            .children
+            .iter()
+            .filter(|c| c.state() == ChildState::Faulted(Reason::Slow))
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+
+        for name in faulted {
+            let recovered = match lookup_nexus(nexus_name, uuid)?
+                .probe_latency(&name)
+            {
+                Some(probe) => match probe.await {
+                    Some(latency) => {
+                        latency < min_latency_us
+                            || median.map_or(false, |m| {
+                                latency
+                                    <= m.saturating_mul(opts.slow_child_factor)
+                            })
+                    }
+                    None => false,
+                },
+                None => false,
+            };
+
+            let latency = latencies.entry(name.clone()).or_default();
+            if !latency.sustained(recovered, window) {
+                continue;
+            }
+            latencies.remove(&name);
+
+            let nexus = lookup_nexus(nexus_name, uuid)?;
+            let child = match nexus.get_child_by_name(&name) {
+                Ok(child)
+                    if child.state() == ChildState::Faulted(Reason::Slow) =>
+                {
+                    child
+                }
+                _ => continue,
+            };
+
+            info!(
+                "{}: slow child {} has recovered, rebuilding it",
+                nexus_name, name
+            );
+            child.set_state(ChildState::Faulted(Reason::OutOfSync));
+            if let Err(e) =
+                nexus.start_rebuild(&name, RebuildMode::Partial).await
+            {
+                error!(
+                    "{}: failed to rebuild child {}: {}",
+                    nexus_name,
+                    name,
+                    e.verbose()
+                );
+            }
+        }
+
+        Some(())
+    }
+
+    /// Returns a future timing a read of a single block from the child, in
+    /// microseconds. The future owns everything it needs, so it does not
+    /// refer to the nexus while the read is in flight.
+    fn probe_latency(
+        &self,
+        name: &str,
+    ) -> Option<impl Future<Output = Option<u64>>> {
+        let child = self.children.iter().find(|c| c.name == name)?;
+        let device = child.get_device().ok()?;
+        let handle = child.get_io_handle().ok()?;
+        let mut buf =
+            DmaBuf::new(device.block_len(), device.alignment()).ok()?;
+        let offset = self.data_ent_offset * device.block_len();
+
+        Some(async move {
+            let start = Instant::now();
+            handle.read_at(offset, &mut buf).await.ok()?;
+            Some(start.elapsed().as_micros() as u64)
+        })
+    }
+}
@@ -4,6 +4,8 @@ use std::{cmp::max, mem::size_of, os::raw::c_void, ptr::NonNull};
 
 use spdk_sys::{
     nvme_qpair_abort_reqs,
+    spdk_get_ticks,
+    spdk_get_ticks_hz,
     spdk_io_channel,
     spdk_nvme_ctrlr_alloc_io_qpair,
     spdk_nvme_ctrlr_connect_io_qpair,
@@ -395,6 +397,7 @@ pub struct IoStatsController {
     // I/O stats to the caller, inside get_io_stats().
     io_stats: BlockDeviceIoStats,
     block_size: u64,
+    ticks_hz: u64,
 }
 
 /// Top-level wrapper around device I/O statistics.
@@ -403,29 +406,39 @@ impl IoStatsController {
         Self {
             io_stats: BlockDeviceIoStats::default(),
             block_size,
+            ticks_hz: unsafe { spdk_get_ticks_hz() },
         }
     }
 
     #[inline]
-    /// Account amount of blocks and I/O operations.
+    /// Account amount of blocks and I/O operations, along with the latency
+    /// of the operation which was submitted at the given tick.
     pub fn account_block_io(
         &mut self,
         op: IoType,
         num_ops: u64,
         num_blocks: u64,
+        submitted: u64,
     ) {
+        let latency_us = unsafe { spdk_get_ticks() }.saturating_sub(submitted)
+            * 1_000_000
+            / self.ticks_hz;
+
         match op {
             IoType::Read => {
                 self.io_stats.num_read_ops += num_ops;
                 self.io_stats.bytes_read += num_blocks;
+                self.io_stats.read_latency.record(latency_us);
             }
             IoType::Write => {
                 self.io_stats.num_write_ops += num_ops;
                 self.io_stats.bytes_written += num_blocks;
+                self.io_stats.write_latency.record(latency_us);
             }
             IoType::Unmap => {
                 self.io_stats.num_unmap_ops += num_ops;
                 self.io_stats.bytes_unmapped += num_blocks;
+                self.io_stats.unmap_latency.record(latency_us);
             }
             _ => {
                 warn!("Unsupported I/O type for I/O statistics: {:?}", op);
@@ -1164,9 +1164,10 @@ pub(crate) mod transport {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
     #[allow(clippy::upper_case_acronyms)]
-    enum TransportId {
+    pub(crate) enum TransportId {
+        RDMA = 0x1,
         TCP = 0x3,
     }
 
@@ -1179,6 +1180,7 @@ pub(crate) mod transport {
     impl From<TransportId> for String {
         fn from(t: TransportId) -> Self {
             match t {
+                TransportId::RDMA => String::from("rdma"),
                 TransportId::TCP => String::from("tcp"),
             }
         }
@@ -1216,6 +1218,12 @@ pub(crate) mod transport {
             }
         }
 
+        /// the transport to connect over
+        pub(crate) fn with_transport(mut self, trid: TransportId) -> Self {
+            self.trid = trid;
+            self
+        }
+
         /// the address to connect to
         pub fn with_traddr(mut self, traddr: &str) -> Self {
             self.traddr = traddr.to_string();
@@ -1236,10 +1244,10 @@ pub(crate) mod transport {
 
         /// builder for transportID currently defaults to TCP IPv4
         pub fn build(self) -> NvmeTransportId {
-            let trtype = String::from(TransportId::TCP);
+            let trtype = String::from(self.trid);
             let mut trid = spdk_nvme_transport_id {
                 adrfam: AdressFamily::NvmfAdrfamIpv4 as u32,
-                trtype: TransportId::TCP as u32,
+                trtype: self.trid as u32,
                 ..Default::default()
             };
 
@@ -1286,6 +1294,20 @@ pub(crate) mod transport {
             assert_eq!(transport.traddr(), "127.0.0.1");
             assert_eq!(transport.subnqn(), "nqn.2021-01-01:test.nqn");
             assert_eq!(transport.svcid(), "4420");
+            assert_eq!(transport.trtype(), "tcp");
+        }
+
+        #[test]
+        fn test_rdma_transport_id() {
+            let transport = transport::Builder::new()
+                .with_transport(transport::TransportId::RDMA)
+                .with_subnqn("nqn.2021-01-01:test.nqn")
+                .with_svcid("4420")
+                .with_traddr("127.0.0.1")
+                .build();
+
+            assert_eq!(transport.trtype(), "rdma");
+            assert_eq!(transport.0.trtype, spdk_sys::SPDK_NVME_TRANSPORT_RDMA);
         }
     }
 }
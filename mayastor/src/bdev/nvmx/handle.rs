@@ -9,6 +9,7 @@ use spdk_sys::{
     self,
     iovec,
     spdk_get_io_channel,
+    spdk_get_ticks,
     spdk_io_channel,
     spdk_nvme_cpl,
     spdk_nvme_ctrlr_cmd_admin_raw,
@@ -73,6 +74,7 @@ struct NvmeIoCtx {
     op: IoType,
     num_blocks: u64,
     channel: *mut spdk_io_channel,
+    submitted: u64,
 }
 
 unsafe impl Send for NvmeIoCtx {}
@@ -237,7 +239,12 @@ fn complete_nvme_command(ctx: *mut NvmeIoCtx, cpl: *const spdk_nvme_cpl) {
     // Update I/O statistics in case the operation succeeded.
     if op_succeeded {
         let stats_controller = inner.get_io_stats_controller();
-        stats_controller.account_block_io(io_ctx.op, 1, io_ctx.num_blocks);
+        stats_controller.account_block_io(
+            io_ctx.op,
+            1,
+            io_ctx.num_blocks,
+            io_ctx.submitted,
+        );
     }
 
     // Adjust the number of active I/O.
@@ -482,6 +489,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         check_channel_for_io(IoType::Read, inner, offset_blocks, num_blocks)?;
 
         let (s, r) = oneshot::channel::<bool>();
+        let submitted = unsafe { spdk_get_ticks() };
 
         let rc = unsafe {
             spdk_nvme_ns_cmd_read(
@@ -510,6 +518,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 IoType::Read,
                 1,
                 num_blocks,
+                submitted,
             );
             Ok(buffer.len())
         } else {
@@ -555,6 +564,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         check_channel_for_io(IoType::Write, inner, offset_blocks, num_blocks)?;
 
         let (s, r) = oneshot::channel::<bool>();
+        let submitted = unsafe { spdk_get_ticks() };
 
         let rc = unsafe {
             spdk_nvme_ns_cmd_write(
@@ -583,6 +593,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 IoType::Write,
                 1,
                 num_blocks,
+                submitted,
             );
             Ok(buffer.len())
         } else {
@@ -625,6 +636,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Read,
                 num_blocks,
+                submitted: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
@@ -702,6 +714,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Write,
                 num_blocks,
+                submitted: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
@@ -813,6 +826,7 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Unmap,
                 num_blocks,
+                submitted: unsafe { spdk_get_ticks() },
             },
             offset_blocks,
             num_blocks,
@@ -9,6 +9,7 @@ pub use controller_state::NvmeControllerState;
 pub use device::{lookup_by_name, open_by_name, NvmeBlockDevice};
 pub use handle::{nvme_io_ctx_pool_init, NvmeDeviceHandle};
 pub use namespace::NvmeNamespace;
+pub use uri::host_nqn;
 pub(crate) use uri::NvmfDeviceTemplate;
 
 use crate::{
@@ -115,6 +116,6 @@ impl<'a> Default for NVMeCtlrList<'a> {
 pub static NVME_CONTROLLERS: Lazy<NVMeCtlrList> =
     Lazy::new(NVMeCtlrList::default);
 
-pub fn nvme_bdev_running_config() -> &'static NvmeBdevOpts {
-    &Config::get().nvme_bdev_opts
+pub fn nvme_bdev_running_config() -> NvmeBdevOpts {
+    Config::get().nvme_bdev_opts.clone()
 }
@@ -40,15 +40,39 @@ use crate::{
         CreateDestroy,
         GetName,
     },
-    core::poller,
+    core::{poller, MayastorEnvironment},
     ffihelper::ErrnoResult,
     nexus_uri::{self, NexusBdevError},
     subsys::Config,
 };
 
-use super::controller::transport::NvmeTransportId;
+use super::controller::transport::{NvmeTransportId, TransportId};
 
 const DEFAULT_NVMF_PORT: u16 = 8420;
+
+/// returns the NQN this node connects to NVMf targets with, which is taken
+/// from HOSTNQN or otherwise derived from MAYASTOR_NVMF_HOSTID or the name of
+/// the node, so that targets can restrict access to it
+pub fn host_nqn() -> String {
+    if let Ok(host_nqn) = std::env::var("HOSTNQN") {
+        return host_nqn;
+    }
+    match host_id() {
+        Some(uuid) => format!("nqn.2019-05.io.openebs:uuid:{}", uuid),
+        None => format!(
+            "nqn.2019-05.io.openebs:node-name:{}",
+            MayastorEnvironment::global_or_default().node_name
+        ),
+    }
+}
+
+/// the extended host identifier given by MAYASTOR_NVMF_HOSTID, if any
+fn host_id() -> Option<Uuid> {
+    std::env::var("MAYASTOR_NVMF_HOSTID")
+        .ok()
+        .and_then(|id| Uuid::parse_str(&id).ok())
+}
+
 // Callback to be called once NVMe controller is successfully created.
 extern "C" fn connect_attach_cb(
     _cb_ctx: *mut c_void,
@@ -76,18 +100,28 @@ pub struct NvmfDeviceTemplate {
     host: String,
     /// the transport service id (ie. port)
     port: u16,
+    /// the transport to connect over, TCP unless the scheme is nvmf+rdma
+    transport: TransportId,
     /// the nqn of the subsystem we want to connect to
     subnqn: String,
     /// Enable protection information checking (reftag, guard)
     prchk_flags: u32,
     /// uuid of the spdk bdev
     uuid: Option<uuid::Uuid>,
+    /// the nqn to connect with, which the target may require to be in the
+    /// list of hosts allowed to connect to the subsystem
+    hostnqn: Option<String>,
 }
 
 impl TryFrom<&Url> for NvmfDeviceTemplate {
     type Error = NexusBdevError;
 
     fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let transport = match url.scheme() {
+            "nvmf+rdma" => TransportId::RDMA,
+            _ => TransportId::TCP,
+        };
+
         let host =
             url.host_str().ok_or_else(|| NexusBdevError::UriInvalid {
                 uri: url.to_string(),
@@ -145,15 +179,19 @@ impl TryFrom<&Url> for NvmfDeviceTemplate {
             },
         )?;
 
+        let hostnqn = parameters.remove("hostnqn");
+
         Ok(NvmfDeviceTemplate {
             name: url[url::Position::BeforeHost .. url::Position::AfterPath]
                 .to_string(),
             alias: url.to_string(),
             host: host.to_string(),
             port: url.port().unwrap_or(DEFAULT_NVMF_PORT),
+            transport,
             subnqn: segments[0].to_string(),
             prchk_flags,
             uuid,
+            hostnqn,
         })
     }
 }
@@ -177,14 +215,15 @@ pub(crate) struct NvmeControllerContext<'probe> {
 impl<'probe> NvmeControllerContext<'probe> {
     pub fn new(template: &NvmfDeviceTemplate) -> NvmeControllerContext {
         let trid = controller::transport::Builder::new()
+            .with_transport(template.transport)
             .with_subnqn(&template.subnqn)
             .with_svcid(&template.port.to_string())
             .with_traddr(&template.host)
             .build();
 
         // setting the HOSTNQN allows tracking who is connected to what. These
-        // makes debugging connections easier in certain cases. If no
-        // HOSTNQN is provided.
+        // makes debugging connections easier in certain cases, and allows
+        // the target to restrict which hosts may connect.
 
         let mut opts = controller::options::Builder::new()
             .with_keep_alive_timeout_ms(
@@ -194,21 +233,13 @@ impl<'probe> NvmeControllerContext<'probe> {
                 Config::get().nvme_bdev_opts.retry_count as u8,
             );
 
-        if let Ok(ext_host_id) = std::env::var("MAYASTOR_NVMF_HOSTID") {
-            if let Ok(uuid) = Uuid::parse_str(&ext_host_id) {
-                opts = opts.with_ext_host_id(*uuid.as_bytes());
-                if std::env::var("HOSTNQN").is_err() {
-                    opts = opts.with_hostnqn(format!(
-                        "nqn.2019-05.io.openebs:uuid:{}",
-                        uuid
-                    ));
-                }
-            }
+        if let Some(uuid) = host_id() {
+            opts = opts.with_ext_host_id(*uuid.as_bytes());
         }
 
-        if let Ok(host_nqn) = std::env::var("HOSTNQN") {
-            opts = opts.with_hostnqn(host_nqn);
-        }
+        // a host nqn given as part of the uri takes precedence
+        opts = opts
+            .with_hostnqn(template.hostnqn.clone().unwrap_or_else(host_nqn));
 
         let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
         let opts = opts.build();
@@ -0,0 +1,620 @@
+//! Passthrough bdev which enforces QoS limits on the IO submitted to the bdev
+//! underneath it, using the same rate limiter as the nexus. It is used for
+//! replicas, as the QoS of SPDK has no separate read and write IOPS limits
+//! and no bursts.
+//!
+//! The QoS bdev of a replica is the namespace of the NVMe-OF subsystem of the
+//! replica while it is shared, so the limits apply to the IO of remote hosts
+//! only. It carries the UUID of the bdev underneath it, which means that the
+//! namespace looks the same whether or not it is there.
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_void, CString},
+    ptr::NonNull,
+    sync::Arc,
+};
+
+use crossbeam::atomic::AtomicCell;
+use futures::channel::oneshot;
+use merge::Merge;
+use nix::errno::Errno;
+use once_cell::sync::Lazy;
+
+use spdk_sys::{
+    spdk_bdev,
+    spdk_bdev_flush_blocks,
+    spdk_bdev_fn_table,
+    spdk_bdev_free_io,
+    spdk_bdev_io,
+    spdk_bdev_io_get_buf,
+    spdk_bdev_io_get_io_channel,
+    spdk_bdev_io_type,
+    spdk_bdev_io_type_supported,
+    spdk_bdev_module,
+    spdk_bdev_module_claim_bdev,
+    spdk_bdev_module_list_add,
+    spdk_bdev_module_release_bdev,
+    spdk_bdev_readv_blocks,
+    spdk_bdev_register,
+    spdk_bdev_reset,
+    spdk_bdev_unmap_blocks,
+    spdk_bdev_unregister,
+    spdk_bdev_write_zeroes_blocks,
+    spdk_bdev_writev_blocks,
+    spdk_get_io_channel,
+    spdk_io_channel,
+};
+
+use crate::{
+    core::{
+        poller,
+        Bdev,
+        Bio,
+        CoreError,
+        Descriptor,
+        IoChannel,
+        IoDevice,
+        IoLatency,
+        IoType,
+        QosCredits,
+        QosLimiter,
+        QosLimits,
+    },
+    ffihelper::{cb_arg, FfiResult},
+};
+
+/// name of the bdev module, which is the driver of QoS bdevs
+pub(crate) const QOS_DRIVER: &str = "qos";
+
+/// suffix of the name of a QoS bdev on top of another bdev
+pub(crate) const QOS_SUFFIX: &str = "-qos";
+
+/// Interval at which IOs held back by the QoS limits are resubmitted.
+const QOS_POLL_INTERVAL_US: u64 = 100;
+
+static QOS_MODULE: Lazy<QosModule> = Lazy::new(QosModule::new);
+
+static QOS_FN_TBL: Lazy<QosFnTable> = Lazy::new(QosFnTable::new);
+
+struct QosModule(*mut spdk_bdev_module);
+
+unsafe impl Sync for QosModule {}
+unsafe impl Send for QosModule {}
+
+impl QosModule {
+    fn new() -> Self {
+        let mut module = Box::new(spdk_bdev_module::default());
+        module.name = CString::new(QOS_DRIVER).unwrap().into_raw();
+        module.module_init = Some(Self::module_init);
+        module.get_ctx_size = Some(Self::ctx_size);
+        QosModule(Box::into_raw(module))
+    }
+
+    extern "C" fn module_init() -> i32 {
+        info!("Initializing QoS Module");
+        0
+    }
+
+    extern "C" fn ctx_size() -> i32 {
+        0
+    }
+}
+
+struct QosFnTable(spdk_bdev_fn_table);
+
+unsafe impl Sync for QosFnTable {}
+unsafe impl Send for QosFnTable {}
+
+impl QosFnTable {
+    fn new() -> Self {
+        QosFnTable(spdk_bdev_fn_table {
+            io_type_supported: Some(QosBdev::io_supported),
+            submit_request: Some(QosBdev::io_submit),
+            get_io_channel: Some(QosBdev::io_channel),
+            destruct: Some(QosBdev::destruct),
+            dump_info_json: None,
+            write_config_json: None,
+            get_spin_time: None,
+            get_module_ctx: None,
+        })
+    }
+}
+
+/// register the QoS bdev module with SPDK
+pub fn register_module() {
+    unsafe { spdk_bdev_module_list_add(QOS_MODULE.0) }
+}
+
+/// A bdev which passes all IO on to the bdev underneath it, for as far as
+/// its QoS limits allow for it.
+pub(crate) struct QosBdev {
+    bdev: Box<spdk_bdev>,
+    name: CString,
+    /// descriptor of the bdev underneath, which is claimed by the QoS bdev
+    base: Descriptor,
+    /// rate limiter, if any limits are set
+    qos: parking_lot::Mutex<Option<Arc<QosLimiter>>>,
+    /// bumped whenever the limits are set
+    qos_generation: AtomicCell<u64>,
+    io_device: Option<IoDevice>,
+}
+
+impl QosBdev {
+    /// create a QoS bdev on top of the given bdev enforcing the given limits
+    pub(crate) fn create(
+        base: &Bdev,
+        limits: QosLimits,
+    ) -> Result<Bdev, CoreError> {
+        let name = format!("{}{}", base.name(), QOS_SUFFIX);
+        let desc = base.open(true)?;
+
+        let rc = unsafe {
+            spdk_bdev_module_claim_bdev(
+                base.as_ptr(),
+                desc.as_ptr(),
+                QOS_MODULE.0,
+            )
+        };
+        if rc != 0 {
+            return Err(CoreError::SetQos {
+                source: Errno::from_i32(rc.abs()),
+                name,
+            });
+        }
+
+        let mut qos = Box::new(QosBdev {
+            bdev: Box::new(spdk_bdev::default()),
+            name: CString::new(name.clone()).unwrap(),
+            base: desc,
+            qos: parking_lot::Mutex::new(None),
+            qos_generation: AtomicCell::new(0),
+            io_device: None,
+        });
+        qos.set_limits(limits);
+
+        unsafe {
+            let b = &mut *qos.bdev;
+            let base = base.as_ptr();
+            b.name = qos.name.as_ptr() as *mut _;
+            b.product_name = qos.name.as_ptr() as *mut _;
+            b.fn_table = &QOS_FN_TBL.0;
+            b.module = QOS_MODULE.0;
+            b.blocklen = (*base).blocklen;
+            b.blockcnt = (*base).blockcnt;
+            b.required_alignment = (*base).required_alignment;
+            b.write_cache = (*base).write_cache;
+            b.uuid = (*base).uuid;
+        }
+
+        let ptr = Box::into_raw(qos);
+        let qos = unsafe { &mut *ptr };
+        qos.bdev.ctxt = ptr.cast();
+        qos.io_device = Some(IoDevice::new::<QosChannel>(
+            NonNull::new(ptr.cast()).unwrap(),
+            &name,
+            Some(QosChannel::create),
+            Some(QosChannel::destroy),
+        ));
+
+        let rc = unsafe { spdk_bdev_register(&mut *qos.bdev) };
+        if rc != 0 {
+            let qos = unsafe { Box::from_raw(ptr) };
+            unsafe {
+                spdk_bdev_module_release_bdev(qos.base.get_bdev().as_ptr())
+            };
+            return Err(CoreError::SetQos {
+                source: Errno::from_i32(rc.abs()),
+                name,
+            });
+        }
+
+        info!("created QoS bdev {} with limits {:?}", name, limits);
+        Ok(Bdev::from(&mut *qos.bdev as *mut spdk_bdev))
+    }
+
+    /// returns the QoS bdev on top of the given bdev, if any
+    pub(crate) fn lookup<'a>(base: &Bdev) -> Option<&'a QosBdev> {
+        Bdev::lookup_by_name(&format!("{}{}", base.name(), QOS_SUFFIX))
+            .filter(|b| b.driver() == QOS_DRIVER)
+            .map(|b| unsafe { &*((*b.as_ptr()).ctxt as *const QosBdev) })
+    }
+
+    /// destroy the QoS bdev on top of the given bdev, if any
+    pub(crate) async fn destroy(base: &Bdev) -> Result<(), CoreError> {
+        extern "C" fn unregister_cb(arg: *mut c_void, rc: i32) {
+            let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
+            let _ = s.send(rc);
+        }
+
+        let qos = match Self::lookup(base) {
+            Some(qos) => qos,
+            None => return Ok(()),
+        };
+        let name = qos.bdev().name();
+
+        let (s, r) = oneshot::channel::<i32>();
+        unsafe {
+            spdk_bdev_unregister(
+                qos.bdev().as_ptr(),
+                Some(unregister_cb),
+                cb_arg(s),
+            );
+        }
+
+        r.await
+            .expect("QoS bdev unregister callback is gone")
+            .to_result(|e| CoreError::SetQos {
+                source: Errno::from_i32(e.abs()),
+                name: name.clone(),
+            })?;
+
+        info!("destroyed QoS bdev {}", name);
+        Ok(())
+    }
+
+    /// returns the QoS bdev as a bdev
+    pub(crate) fn bdev(&self) -> Bdev {
+        Bdev::from(&*self.bdev as *const spdk_bdev as *mut spdk_bdev)
+    }
+
+    /// set the QoS limits, setting all limits to 0 removes them. The IO
+    /// channels pick up the new limits with the next IO they submit.
+    pub(crate) fn set_limits(&self, limits: QosLimits) {
+        *self.qos.lock() = if limits.is_unlimited() {
+            None
+        } else {
+            Some(Arc::new(QosLimiter::new(limits)))
+        };
+        self.qos_generation.fetch_add(1);
+    }
+
+    /// number of IOs which were held back by the QoS limits since they were
+    /// last set
+    pub(crate) fn throttled(&self) -> u64 {
+        self.qos
+            .lock()
+            .as_ref()
+            .map(|qos| qos.throttled())
+            .unwrap_or_default()
+    }
+
+    /// collect the latency histograms of the IO completed through all the
+    /// channels of the QoS bdev
+    pub(crate) fn latency(&self) -> oneshot::Receiver<IoLatency> {
+        let (s, r) = oneshot::channel();
+        let mut sender = Some(s);
+
+        if let Some(io_device) = &self.io_device {
+            io_device.traverse_io_channels(
+                |channel: &mut QosChannel, latency: &mut IoLatency| {
+                    latency.merge(channel.latency);
+                    0
+                },
+                move |_status, latency| {
+                    if let Some(s) = sender.take() {
+                        let _ = s.send(latency);
+                    }
+                },
+                QosChannel::from_io_channel,
+                IoLatency::default(),
+            );
+        }
+        r
+    }
+
+    unsafe fn from_raw<'a>(ctx: *mut c_void) -> &'a mut QosBdev {
+        &mut *(ctx as *mut QosBdev)
+    }
+
+    extern "C" fn io_supported(
+        ctx: *mut c_void,
+        io_type: spdk_bdev_io_type,
+    ) -> bool {
+        let qos = unsafe { Self::from_raw(ctx) };
+        match IoType::from(io_type) {
+            IoType::Read
+            | IoType::Write
+            | IoType::Flush
+            | IoType::Reset
+            | IoType::Unmap
+            | IoType::WriteZeros => unsafe {
+                spdk_bdev_io_type_supported(
+                    qos.base.get_bdev().as_ptr(),
+                    io_type,
+                )
+            },
+            _ => false,
+        }
+    }
+
+    extern "C" fn io_submit(ch: *mut spdk_io_channel, io: *mut spdk_bdev_io) {
+        QosChannel::from_io_channel(ch).submit(Bio::from(io));
+    }
+
+    extern "C" fn io_channel(ctx: *mut c_void) -> *mut spdk_io_channel {
+        unsafe { spdk_get_io_channel(ctx) }
+    }
+
+    /// called when the QoS bdev is unregistered, releases the bdev
+    /// underneath it
+    extern "C" fn destruct(ctx: *mut c_void) -> i32 {
+        let mut qos = unsafe { Box::from_raw(ctx as *mut QosBdev) };
+        qos.io_device.take();
+        unsafe {
+            spdk_bdev_module_release_bdev(qos.base.get_bdev().as_ptr());
+        }
+        0
+    }
+}
+
+/// IO channel of a QoS bdev
+struct QosChannel {
+    device: *mut QosBdev,
+    /// channel of the bdev underneath
+    base: IoChannel,
+    /// rate limiter of the QoS bdev, if any
+    qos: Option<Arc<QosLimiter>>,
+    /// generation of the limits the rate limiter belongs to
+    qos_generation: u64,
+    /// tokens this channel has taken from the rate limiter
+    qos_credits: QosCredits,
+    /// IOs held back by the rate limiter, in order of submission
+    throttled: VecDeque<Bio>,
+    /// submits the held back IOs as the rate limiter allows for it, paused
+    /// while no IOs are held back
+    poller: Option<poller::Poller<'static>>,
+    /// latency of the IOs completed through this channel
+    latency: IoLatency,
+}
+
+impl QosChannel {
+    extern "C" fn create(device: *mut c_void, ctx: *mut c_void) -> i32 {
+        let qos = unsafe { QosBdev::from_raw(device) };
+        let base = match qos.base.get_channel() {
+            Some(base) => base,
+            None => return -(Errno::ENOMEM as i32),
+        };
+
+        unsafe {
+            std::ptr::write(
+                ctx as *mut QosChannel,
+                QosChannel {
+                    device: qos,
+                    base,
+                    qos: qos.qos.lock().clone(),
+                    qos_generation: qos.qos_generation.load(),
+                    qos_credits: QosCredits::default(),
+                    throttled: VecDeque::new(),
+                    poller: None,
+                    latency: IoLatency::default(),
+                },
+            )
+        };
+        0
+    }
+
+    extern "C" fn destroy(_device: *mut c_void, ctx: *mut c_void) {
+        let channel = unsafe { &mut *(ctx as *mut QosChannel) };
+        channel.poller.take();
+        channel.throttled.drain(..).for_each(|io| io.fail());
+        unsafe { std::ptr::drop_in_place(channel) };
+    }
+
+    fn from_io_channel<'a>(ch: *mut spdk_io_channel) -> &'a mut QosChannel {
+        unsafe {
+            &mut *((ch as *mut u8).add(std::mem::size_of::<spdk_io_channel>())
+                as *mut QosChannel)
+        }
+    }
+
+    /// pick up the rate limiter of the QoS bdev when its limits have changed
+    fn refresh(&mut self) {
+        let qos = unsafe { &*self.device };
+        let generation = qos.qos_generation.load();
+        if generation != self.qos_generation {
+            self.qos_generation = generation;
+            self.qos = qos.qos.lock().clone();
+            self.qos_credits = QosCredits::default();
+        }
+    }
+
+    /// pass the IO on right away if the limits allow for it, otherwise hold
+    /// it back until they do
+    fn submit(&mut self, io: Bio) {
+        self.refresh();
+
+        let qos = match &self.qos {
+            Some(qos) => qos,
+            None => return self.forward(io),
+        };
+
+        let io_type = io.io_type();
+        if !matches!(io_type, IoType::Read | IoType::Write) {
+            return self.forward(io);
+        }
+
+        // IOs held back before this one go first
+        if self.throttled.is_empty()
+            && qos.admit(
+                &mut self.qos_credits,
+                io_type,
+                io.num_blocks() * io.block_len(),
+            )
+        {
+            return self.forward(io);
+        }
+
+        qos.throttle();
+        self.throttled.push_back(io);
+
+        if let Some(poller) = self.poller.as_mut() {
+            if self.throttled.len() == 1 {
+                poller.resume();
+            }
+        } else {
+            let channel = self as *mut QosChannel;
+            self.poller = Some(
+                poller::Builder::new()
+                    .with_name("replica_qos")
+                    .with_interval(QOS_POLL_INTERVAL_US)
+                    .with_poll_fn(move || unsafe {
+                        (*channel).submit_throttled()
+                    })
+                    .build(),
+            );
+        }
+    }
+
+    /// pass on the IOs held back, for as far as the limits allow for it
+    fn submit_throttled(&mut self) -> i32 {
+        self.refresh();
+
+        let mut submitted = 0;
+        while let Some(io) = self.throttled.front() {
+            if let Some(qos) = &self.qos {
+                let bytes = io.num_blocks() * io.block_len();
+                if !qos.admit(&mut self.qos_credits, io.io_type(), bytes) {
+                    break;
+                }
+            }
+
+            if let Some(io) = self.throttled.pop_front() {
+                self.forward(io);
+                submitted += 1;
+            }
+        }
+
+        // nothing to do until an IO is held back again
+        if self.throttled.is_empty() {
+            if let Some(poller) = self.poller.as_mut() {
+                poller.pause();
+            }
+        }
+
+        (submitted > 0) as i32
+    }
+
+    extern "C" fn get_buf_cb(
+        ch: *mut spdk_io_channel,
+        io: *mut spdk_bdev_io,
+        success: bool,
+    ) {
+        let io = Bio::from(io);
+        if success {
+            Self::from_io_channel(ch).forward_read(io);
+        } else {
+            io.no_mem();
+        }
+    }
+
+    /// complete the IO of the QoS bdev with the status of the IO of the bdev
+    /// underneath it
+    extern "C" fn completion(
+        child: *mut spdk_bdev_io,
+        success: bool,
+        parent: *mut c_void,
+    ) {
+        unsafe { spdk_bdev_free_io(child) };
+        let io = Bio::from(parent);
+        if success {
+            let (ch, submitted) = unsafe {
+                (
+                    spdk_bdev_io_get_io_channel(io.as_ptr()),
+                    (*io.as_ptr()).internal.submit_tsc,
+                )
+            };
+            Self::from_io_channel(ch)
+                .latency
+                .record(io.io_type(), submitted);
+            io.ok();
+        } else {
+            io.fail();
+        }
+    }
+
+    fn forward_read(&mut self, io: Bio) {
+        let desc = unsafe { (*self.device).base.as_ptr() };
+        let rc = unsafe {
+            spdk_bdev_readv_blocks(
+                desc,
+                self.base.as_ptr(),
+                io.iovs(),
+                io.iov_count(),
+                io.offset(),
+                io.num_blocks(),
+                Some(Self::completion),
+                io.as_ptr().cast(),
+            )
+        };
+        Self::submitted(io, rc);
+    }
+
+    /// pass the IO on to the bdev underneath
+    fn forward(&mut self, io: Bio) {
+        let desc = unsafe { (*self.device).base.as_ptr() };
+        let ch = self.base.as_ptr();
+        let cb = Some(Self::completion as _);
+        let arg = io.as_ptr().cast();
+
+        let rc = unsafe {
+            match io.io_type() {
+                IoType::Read if io.need_buf() => {
+                    spdk_bdev_io_get_buf(
+                        io.as_ptr(),
+                        Some(Self::get_buf_cb),
+                        io.num_blocks() * io.block_len(),
+                    );
+                    return;
+                }
+                IoType::Read => return self.forward_read(io),
+                IoType::Write => spdk_bdev_writev_blocks(
+                    desc,
+                    ch,
+                    io.iovs(),
+                    io.iov_count(),
+                    io.offset(),
+                    io.num_blocks(),
+                    cb,
+                    arg,
+                ),
+                IoType::Unmap => spdk_bdev_unmap_blocks(
+                    desc,
+                    ch,
+                    io.offset(),
+                    io.num_blocks(),
+                    cb,
+                    arg,
+                ),
+                IoType::WriteZeros => spdk_bdev_write_zeroes_blocks(
+                    desc,
+                    ch,
+                    io.offset(),
+                    io.num_blocks(),
+                    cb,
+                    arg,
+                ),
+                IoType::Flush => spdk_bdev_flush_blocks(
+                    desc,
+                    ch,
+                    io.offset(),
+                    io.num_blocks(),
+                    cb,
+                    arg,
+                ),
+                IoType::Reset => spdk_bdev_reset(desc, ch, cb, arg),
+                _ => -(Errno::ENOTSUP as i32),
+            }
+        };
+        Self::submitted(io, rc);
+    }
+
+    /// complete the IO right away if it could not be passed on
+    fn submitted(io: Bio, rc: i32) {
+        match rc {
+            0 => {}
+            rc if rc == -(Errno::ENOMEM as i32) => io.no_mem(),
+            _ => io.fail(),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//!
+//! methods to read and change the configuration of a running instance
+
+use super::context::Context;
+use crate::{context::OutputFormat, Error, GrpcStatus};
+use ::rpc::mayastor as rpc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use colored_json::ToColoredJson;
+use snafu::ResultExt;
+use tonic::Status;
+
+pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
+    let get = SubCommand::with_name("get").about("Show the current config");
+    let update = SubCommand::with_name("update")
+        .about("Change options of the config without restarting")
+        .arg(
+            Arg::with_name("options")
+                .required(true)
+                .index(1)
+                .help("Options to change in YAML, e.g. \"log_level: debug\""),
+        );
+
+    SubCommand::with_name("config")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("Mayastor configuration")
+        .subcommand(get)
+        .subcommand(update)
+}
+
+pub async fn handler(
+    ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    match matches.subcommand() {
+        ("get", Some(args)) => get(ctx, args).await,
+        ("update", Some(args)) => update(ctx, args).await,
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {} does not exist", cmd)))
+                .context(GrpcStatus)
+        }
+    }
+}
+
+async fn get(mut ctx: Context, _matches: &ArgMatches<'_>) -> crate::Result<()> {
+    let response = ctx
+        .client
+        .get_config(rpc::GetConfigRequest {
+            kind: rpc::ConfigKind::MayastorConfig as i32,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_config(&ctx, &response.get_ref().config);
+    Ok(())
+}
+
+async fn update(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let options = matches
+        .value_of("options")
+        .ok_or_else(|| Error::MissingValue {
+            field: "options".to_string(),
+        })?
+        .to_owned();
+
+    let response = ctx
+        .client
+        .update_config(rpc::UpdateConfigRequest {
+            kind: rpc::ConfigKind::MayastorConfig as i32,
+            data: options.into_bytes(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_config(&ctx, &response.get_ref().config);
+    Ok(())
+}
+
+/// print the YAML config as is, or converted to JSON
+fn print_config(ctx: &Context, config: &[u8]) {
+    match ctx.output {
+        OutputFormat::Json => {
+            let value: serde_json::Value =
+                serde_yaml::from_slice(config).unwrap();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&value)
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", String::from_utf8_lossy(config));
+        }
+    }
+}
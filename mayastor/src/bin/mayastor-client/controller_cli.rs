@@ -88,6 +88,9 @@ async fn controller_stats(
                     let num_write_ops = stats.num_write_ops.to_string();
                     let bytes_read = stats.bytes_read.to_string();
                     let bytes_written = stats.bytes_written.to_string();
+                    let p99 = |l: &Option<rpc::LatencyStats>| {
+                        l.as_ref().map_or(0, |l| l.p99_us).to_string()
+                    };
 
                     vec![
                         c.name.to_string(),
@@ -95,11 +98,21 @@ async fn controller_stats(
                         num_write_ops,
                         bytes_read,
                         bytes_written,
+                        p99(&stats.read_latency),
+                        p99(&stats.write_latency),
                     ]
                 })
                 .collect();
 
-            let hdr = vec!["NAME", "READS", "WRITES", "READ/B", "WRITTEN/B"];
+            let hdr = vec![
+                "NAME",
+                "READS",
+                "WRITES",
+                "READ/B",
+                "WRITTEN/B",
+                "READ P99/us",
+                "WRITE P99/us",
+            ];
             ctx.print_list(hdr, table);
         }
     }
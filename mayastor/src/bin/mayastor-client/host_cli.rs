@@ -0,0 +1,146 @@
+//!
+//! methods to manage the hosts allowed to connect to a nexus or replica
+
+use crate::{
+    context::{Context, OutputFormat},
+    Error,
+    GrpcStatus,
+};
+use ::rpc::mayastor as rpc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use colored_json::ToColoredJson;
+use snafu::ResultExt;
+use tonic::Status;
+
+pub async fn handler(
+    ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    match matches.subcommand() {
+        ("add", Some(args)) => add(ctx, args).await,
+        ("remove", Some(args)) => remove(ctx, args).await,
+        ("list", Some(args)) => list(ctx, args).await,
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {} does not exist", cmd)))
+                .context(GrpcStatus)
+        }
+    }
+}
+
+fn uuid_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("uuid")
+        .required(true)
+        .index(1)
+        .help("uuid of the nexus or replica")
+}
+
+fn nqn_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("nqn")
+        .required(true)
+        .index(2)
+        .help("NQN of the host")
+}
+
+pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
+    let add = SubCommand::with_name("add")
+        .about("allows a host to connect to a nexus or replica")
+        .arg(uuid_arg())
+        .arg(nqn_arg());
+
+    let remove = SubCommand::with_name("remove")
+        .about("no longer allows a host to connect to a nexus or replica")
+        .arg(uuid_arg())
+        .arg(nqn_arg());
+
+    let list = SubCommand::with_name("list")
+        .about("lists the hosts allowed to connect to a nexus or replica")
+        .arg(uuid_arg());
+
+    SubCommand::with_name("host")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("Hosts allowed to connect over NVMf")
+        .subcommand(add)
+        .subcommand(remove)
+        .subcommand(list)
+}
+
+fn get_value(matches: &ArgMatches<'_>, field: &str) -> crate::Result<String> {
+    Ok(matches
+        .value_of(field)
+        .ok_or_else(|| Error::MissingValue {
+            field: field.to_string(),
+        })?
+        .to_string())
+}
+
+fn print_hosts(ctx: &Context, response: &rpc::ListAllowedHostsReply) {
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(response)
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            if response.host_nqns.is_empty() {
+                ctx.v1("Any host is allowed");
+                return;
+            }
+            ctx.print_list(
+                vec!["NQN"],
+                response.host_nqns.iter().map(|h| vec![h.clone()]).collect(),
+            );
+        }
+    };
+}
+
+async fn add(mut ctx: Context, matches: &ArgMatches<'_>) -> crate::Result<()> {
+    let response = ctx
+        .client
+        .add_allowed_host(rpc::AllowedHostRequest {
+            uuid: get_value(matches, "uuid")?,
+            host_nqn: get_value(matches, "nqn")?,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_hosts(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn remove(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let response = ctx
+        .client
+        .remove_allowed_host(rpc::AllowedHostRequest {
+            uuid: get_value(matches, "uuid")?,
+            host_nqn: get_value(matches, "nqn")?,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_hosts(&ctx, response.get_ref());
+    Ok(())
+}
+
+async fn list(mut ctx: Context, matches: &ArgMatches<'_>) -> crate::Result<()> {
+    let response = ctx
+        .client
+        .list_allowed_hosts(rpc::ListAllowedHostsRequest {
+            uuid: get_value(matches, "uuid")?,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    print_hosts(&ctx, response.get_ref());
+    Ok(())
+}
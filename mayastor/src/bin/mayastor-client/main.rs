@@ -11,9 +11,11 @@ use ::rpc::mayastor::{
 };
 
 mod bdev_cli;
+mod config_cli;
 mod context;
 mod controller_cli;
 mod device_cli;
+mod host_cli;
 mod jsonrpc_cli;
 mod nexus_child_cli;
 mod nexus_cli;
@@ -22,6 +24,7 @@ mod pool_cli;
 mod rebuild_cli;
 mod replica_cli;
 mod snapshot_cli;
+mod verify_cli;
 
 type MayaClient = MayastorClient<Channel>;
 type BdevClient = BdevRpcClient<Channel>;
@@ -105,11 +108,14 @@ async fn main() -> crate::Result<()> {
         .subcommand(replica_cli::subcommands())
         .subcommand(bdev_cli::subcommands())
         .subcommand(device_cli::subcommands())
+        .subcommand(host_cli::subcommands())
         .subcommand(perf_cli::subcommands())
         .subcommand(rebuild_cli::subcommands())
+        .subcommand(verify_cli::subcommands())
         .subcommand(snapshot_cli::subcommands())
         .subcommand(jsonrpc_cli::subcommands())
         .subcommand(controller_cli::subcommands())
+        .subcommand(config_cli::subcommands())
         .get_matches();
 
     let ctx = Context::new(&matches).await.context(ContextError)?;
@@ -117,13 +123,16 @@ async fn main() -> crate::Result<()> {
     let status = match matches.subcommand() {
         ("bdev", Some(args)) => bdev_cli::handler(ctx, args).await,
         ("device", Some(args)) => device_cli::handler(ctx, args).await,
+        ("host", Some(args)) => host_cli::handler(ctx, args).await,
         ("nexus", Some(args)) => nexus_cli::handler(ctx, args).await,
         ("perf", Some(args)) => perf_cli::handler(ctx, args).await,
         ("pool", Some(args)) => pool_cli::handler(ctx, args).await,
         ("replica", Some(args)) => replica_cli::handler(ctx, args).await,
         ("rebuild", Some(args)) => rebuild_cli::handler(ctx, args).await,
+        ("verify", Some(args)) => verify_cli::handler(ctx, args).await,
         ("snapshot", Some(args)) => snapshot_cli::handler(ctx, args).await,
         ("controller", Some(args)) => controller_cli::handler(ctx, args).await,
+        ("config", Some(args)) => config_cli::handler(ctx, args).await,
         ("jsonrpc", Some(args)) => jsonrpc_cli::json_rpc_call(ctx, args).await,
         _ => panic!("Command not found"),
     };
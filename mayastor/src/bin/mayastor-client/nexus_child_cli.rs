@@ -18,6 +18,7 @@ pub async fn handler(
 ) -> crate::Result<()> {
     match matches.subcommand() {
         ("fault", Some(args)) => fault(ctx, args).await,
+        ("migrate", Some(args)) => migrate(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
                 .context(GrpcStatus)
@@ -41,6 +42,27 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uri of the child"),
         );
 
+    let migrate = SubCommand::with_name("migrate")
+        .about("move a child to a new replica, e.g. on another pool")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the nexus"),
+        )
+        .arg(
+            Arg::with_name("src_uri")
+                .required(true)
+                .index(2)
+                .help("uri of the child to migrate"),
+        )
+        .arg(
+            Arg::with_name("dst_uri")
+                .required(true)
+                .index(3)
+                .help("uri of the replica to migrate to"),
+        );
+
     SubCommand::with_name("child")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -49,6 +71,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         ])
         .about("Nexus child management")
         .subcommand(fault)
+        .subcommand(migrate)
 }
 
 async fn fault(
@@ -94,3 +117,54 @@ async fn fault(
 
     Ok(())
 }
+
+async fn migrate(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let src_uri = matches
+        .value_of("src_uri")
+        .ok_or_else(|| Error::MissingValue {
+            field: "src_uri".to_string(),
+        })?
+        .to_string();
+    let dst_uri = matches
+        .value_of("dst_uri")
+        .ok_or_else(|| Error::MissingValue {
+            field: "dst_uri".to_string(),
+        })?
+        .to_string();
+
+    let response = ctx
+        .client
+        .migrate_replica(rpc::MigrateReplicaRequest {
+            uuid,
+            src_uri,
+            dst_uri,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", response.get_ref().uri);
+        }
+    };
+
+    Ok(())
+}
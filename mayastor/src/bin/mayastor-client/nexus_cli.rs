@@ -12,6 +12,14 @@ use colored_json::ToColoredJson;
 use snafu::ResultExt;
 use tonic::{Code, Status};
 
+/// read policies as accepted on the command line
+const READ_POLICIES: &[&str] = &[
+    "round_robin",
+    "prefer_local",
+    "least_outstanding",
+    "lowest_latency",
+];
+
 pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
     let create = SubCommand::with_name("create")
         .about("Create a new nexus device")
@@ -68,6 +76,14 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .help("NVMe reservation key for children"),
         )
+        .arg(
+            Arg::with_name("read-policy")
+                .long("read-policy")
+                .value_name("POLICY")
+                .possible_values(READ_POLICIES)
+                .default_value("round_robin")
+                .help("policy used to select the child to read from"),
+        )
         .arg(
             Arg::with_name("children")
                 .required(true)
@@ -84,6 +100,21 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uuid for the nexus"),
         );
 
+    let resize = SubCommand::with_name("resize")
+        .about("grow the nexus once all of its children are large enough")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid for the nexus"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .index(2)
+                .help("size with optional unit suffix"),
+        );
+
     let publish = SubCommand::with_name("publish")
         .about("publish the nexus")
         .arg(Arg::with_name("protocol").short("p").long("protocol").value_name("PROTOCOL")
@@ -91,7 +122,10 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .arg(Arg::with_name("uuid").required(true).index(1)
             .help("uuid for the nexus"))
         .arg(Arg::with_name("key").required(false).index(2)
-            .help("crypto key to use"));
+            .help("crypto key to use"))
+        .arg(Arg::with_name("allowed-host").long("allowed-host").value_name("NQN")
+            .takes_value(true).multiple(true).number_of_values(1)
+            .help("NQN of a host allowed to connect over nvmf (default: any host)"));
 
     let unpublish = SubCommand::with_name("unpublish")
         .about("unpublish the nexus")
@@ -120,6 +154,33 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("NVMe ANA state of the nexus"),
         );
 
+    let read_policy = SubCommand::with_name("read_policy")
+        .about("set the policy used to select the child to read from")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid for the nexus"),
+        )
+        .arg(
+            Arg::with_name("policy")
+                .required(true)
+                .index(2)
+                .possible_values(READ_POLICIES)
+                .help("read policy of the nexus"),
+        );
+
+    let qos = qos_args(
+        SubCommand::with_name("qos")
+            .about("set the IO QoS limits of the nexus, 0 means unlimited")
+            .arg(
+                Arg::with_name("uuid")
+                    .required(true)
+                    .index(1)
+                    .help("uuid for the nexus"),
+            ),
+    );
+
     let add = SubCommand::with_name("add")
         .about("add a child")
         .arg(
@@ -195,11 +256,14 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(create)
         .subcommand(create_v2)
         .subcommand(destroy)
+        .subcommand(resize)
         .subcommand(publish)
         .subcommand(add)
         .subcommand(remove)
         .subcommand(unpublish)
         .subcommand(ana_state)
+        .subcommand(read_policy)
+        .subcommand(qos)
         .subcommand(list)
         .subcommand(list2)
         .subcommand(children)
@@ -214,12 +278,15 @@ pub async fn handler(
         ("create", Some(args)) => nexus_create(ctx, args).await,
         ("create2", Some(args)) => nexus_create_v2(ctx, args).await,
         ("destroy", Some(args)) => nexus_destroy(ctx, args).await,
+        ("resize", Some(args)) => nexus_resize(ctx, args).await,
         ("list", Some(args)) => nexus_list(ctx, args).await,
         ("list2", Some(args)) => nexus_list_v2(ctx, args).await,
         ("children", Some(args)) => nexus_children(ctx, args).await,
         ("publish", Some(args)) => nexus_publish(ctx, args).await,
         ("unpublish", Some(args)) => nexus_unpublish(ctx, args).await,
         ("ana_state", Some(args)) => nexus_nvme_ana_state(ctx, args).await,
+        ("read_policy", Some(args)) => nexus_read_policy(ctx, args).await,
+        ("qos", Some(args)) => nexus_qos(ctx, args).await,
         ("add", Some(args)) => nexus_add(ctx, args).await,
         ("remove", Some(args)) => nexus_remove(ctx, args).await,
         ("child", Some(args)) => nexus_child_cli::handler(ctx, args).await,
@@ -302,6 +369,7 @@ async fn nexus_create_v2(
         .unwrap_or_else(|e| e.exit());
     let resv_key = value_t!(matches.value_of("resv-key"), u64)
         .unwrap_or_else(|e| e.exit());
+    let read_policy = parse_read_policy(matches)?;
 
     let response = ctx
         .client
@@ -313,6 +381,7 @@ async fn nexus_create_v2(
             max_cntl_id,
             resv_key,
             children,
+            read_policy: read_policy.into(),
         })
         .await
         .context(GrpcStatus)?;
@@ -367,6 +436,46 @@ async fn nexus_destroy(
     Ok(())
 }
 
+async fn nexus_resize(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let size = parse_size(matches.value_of("size").ok_or_else(|| {
+        Error::MissingValue {
+            field: "size".to_string(),
+        }
+    })?)
+    .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))
+    .context(GrpcStatus)?;
+
+    let response = ctx
+        .client
+        .resize_nexus(rpc::ResizeNexusRequest {
+            uuid: uuid.clone(),
+            size: size.get_bytes() as u64,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", &uuid);
+        }
+    };
+
+    Ok(())
+}
+
 async fn nexus_list(
     mut ctx: Context,
     matches: &ArgMatches<'_>,
@@ -545,10 +654,18 @@ async fn nexus_children(
                 .iter()
                 .map(|c| {
                     let state = child_state_to_str(c.state);
-                    vec![c.uri.clone(), state.to_string()]
+                    vec![
+                        c.uri.clone(),
+                        state.to_string(),
+                        c.media_errors.to_string(),
+                        c.transport_errors.to_string(),
+                    ]
                 })
                 .collect();
-            ctx.print_list(vec!["NAME", "STATE"], table);
+            ctx.print_list(
+                vec!["NAME", "STATE", ">MEDIA_ERRORS", ">TRANSPORT_ERRORS"],
+                table,
+            );
         }
     };
 
@@ -579,12 +696,18 @@ async fn nexus_publish(
         }
     };
 
+    let allowed_hosts = matches
+        .values_of("allowed-host")
+        .map(|hosts| hosts.map(|h| h.to_string()).collect())
+        .unwrap_or_default();
+
     let response = ctx
         .client
         .publish_nexus(rpc::PublishNexusRequest {
             uuid,
             key,
             share: protocol.into(),
+            allowed_hosts,
         })
         .await
         .context(GrpcStatus)?;
@@ -699,6 +822,102 @@ async fn nexus_set_nvme_ana_state(
     Ok(())
 }
 
+async fn nexus_read_policy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+    let read_policy = parse_read_policy(matches)?;
+
+    ctx.client
+        .set_nexus_read_policy(rpc::SetNexusReadPolicyRequest {
+            uuid: uuid.clone(),
+            read_policy: read_policy.into(),
+        })
+        .await
+        .context(GrpcStatus)?;
+    ctx.v1(&uuid);
+    Ok(())
+}
+
+async fn nexus_qos(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.client
+        .set_nexus_qos(rpc::SetNexusQosRequest {
+            uuid: uuid.clone(),
+            qos: Some(parse_qos_limits(matches)),
+        })
+        .await
+        .context(GrpcStatus)?;
+    ctx.v1(&uuid);
+    Ok(())
+}
+
+/// add the QoS limit options to a subcommand
+pub(crate) fn qos_args<'a, 'b>(cmd: App<'a, 'b>) -> App<'a, 'b> {
+    [
+        ("iops", "read and write IOs per second"),
+        ("read-iops", "read IOs per second"),
+        ("write-iops", "write IOs per second"),
+        ("mbps", "read and write MiB per second"),
+        ("read-mbps", "read MiB per second"),
+        ("write-mbps", "write MiB per second"),
+        (
+            "burst-ms",
+            "milliseconds the limits may be exceeded by bursts",
+        ),
+    ]
+    .iter()
+    .fold(cmd, |cmd, &(name, help)| {
+        cmd.arg(
+            Arg::with_name(name)
+                .long(name)
+                .takes_value(true)
+                .default_value("0")
+                .help(help),
+        )
+    })
+}
+
+/// parse the QoS limit options added by `qos_args`
+pub(crate) fn parse_qos_limits(matches: &ArgMatches<'_>) -> rpc::QosLimits {
+    let limit = |name| {
+        value_t!(matches.value_of(name), u64).unwrap_or_else(|e| e.exit())
+    };
+    rpc::QosLimits {
+        iops: limit("iops"),
+        read_iops: limit("read-iops"),
+        write_iops: limit("write-iops"),
+        mbps: limit("mbps"),
+        read_mbps: limit("read-mbps"),
+        write_mbps: limit("write-mbps"),
+        burst_ms: value_t!(matches.value_of("burst-ms"), u32)
+            .unwrap_or_else(|e| e.exit()),
+    }
+}
+
+fn parse_read_policy(
+    matches: &ArgMatches<'_>,
+) -> crate::Result<rpc::NexusReadPolicy> {
+    let policy = matches
+        .value_of("read-policy")
+        .or_else(|| matches.value_of("policy"))
+        .unwrap_or("round_robin");
+    policy
+        .parse()
+        .map_err(|_| {
+            Status::new(
+                Code::InvalidArgument,
+                format!("Invalid read policy {}", policy),
+            )
+        })
+        .context(GrpcStatus)
+}
+
 async fn nexus_add(
     mut ctx: Context,
     matches: &ArgMatches<'_>,
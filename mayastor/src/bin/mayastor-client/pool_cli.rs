@@ -5,7 +5,7 @@ use crate::{
 };
 use ::rpc::mayastor as rpc;
 use byte_unit::Byte;
-use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored_json::ToColoredJson;
 use snafu::ResultExt;
 use tonic::Status;
@@ -25,6 +25,15 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .index(2)
                 .help("Disk device files"),
+        )
+        .arg(
+            Arg::with_name("overcommit")
+                .long("overcommit")
+                .takes_value(true)
+                .help(
+                    "Max size of all replicas as % of capacity (0 = unlimited), \
+                    an existing pool keeps its limit when not given",
+                ),
         );
     let destroy = SubCommand::with_name("destroy")
         .about("Destroy storage pool")
@@ -34,6 +43,20 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .index(1)
                 .help("Storage pool name"),
         );
+    let drain = SubCommand::with_name("drain")
+        .about("Stop placing replicas on a storage pool")
+        .arg(
+            Arg::with_name("pool")
+                .required(true)
+                .index(1)
+                .help("Storage pool name"),
+        )
+        .arg(
+            Arg::with_name("stop")
+                .long("stop")
+                .takes_value(false)
+                .help("Accept new replicas on the pool again"),
+        );
     SubCommand::with_name("pool")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -43,6 +66,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .about("Storage pool management")
         .subcommand(create)
         .subcommand(destroy)
+        .subcommand(drain)
         .subcommand(SubCommand::with_name("list").about("List storage pools"))
 }
 
@@ -53,6 +77,7 @@ pub async fn handler(
     match matches.subcommand() {
         ("create", Some(args)) => create(ctx, args).await,
         ("destroy", Some(args)) => destroy(ctx, args).await,
+        ("drain", Some(args)) => drain(ctx, args).await,
         ("list", Some(args)) => list(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
@@ -78,12 +103,22 @@ async fn create(
         })?
         .map(|dev| dev.to_owned())
         .collect();
+    let overcommit = if matches.is_present("overcommit") {
+        Some(rpc::PoolOvercommit {
+            percent: value_t!(matches.value_of("overcommit"), u32)
+                .unwrap_or_else(|e| e.exit()),
+        })
+    } else {
+        None
+    };
 
     let response = ctx
         .client
         .create_pool(rpc::CreatePoolRequest {
             name: name.clone(),
             disks,
+            overcommit,
+            replica_keys: Vec::new(),
         })
         .await
         .context(GrpcStatus)?;
@@ -143,6 +178,61 @@ async fn destroy(
     Ok(())
 }
 
+async fn drain(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let name = matches
+        .value_of("pool")
+        .ok_or_else(|| Error::MissingValue {
+            field: "pool".to_string(),
+        })?
+        .to_owned();
+
+    let response = ctx
+        .client
+        .drain_pool(rpc::DrainPoolRequest {
+            name,
+            stop: matches.is_present("stop"),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            let replicas = &response.get_ref().replicas;
+            if replicas.is_empty() {
+                ctx.v1("No replicas left on the pool");
+                return Ok(());
+            }
+
+            let table = replicas
+                .iter()
+                .map(|r| {
+                    let nexus = if r.nexus.is_empty() {
+                        "-".to_string()
+                    } else {
+                        r.nexus.clone()
+                    };
+                    vec![r.uuid.clone(), nexus, r.uri.clone()]
+                })
+                .collect();
+            ctx.print_list(vec!["UUID", "NEXUS", "URI"], table);
+        }
+    };
+
+    Ok(())
+}
+
 async fn list(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -177,18 +267,34 @@ async fn list(
                 .map(|p| {
                     let cap = Byte::from_bytes(p.capacity.into());
                     let used = Byte::from_bytes(p.used.into());
+                    let committed = Byte::from_bytes(p.committed.into());
                     let state = pool_state_to_str(p.state);
+                    let overcommit = if p.overcommit == 0 {
+                        "-".to_string()
+                    } else {
+                        format!("{}%", p.overcommit)
+                    };
                     vec![
                         p.name.clone(),
                         state.to_string(),
                         ctx.units(cap),
                         ctx.units(used),
+                        ctx.units(committed),
+                        overcommit,
                         p.disks.join(" "),
                     ]
                 })
                 .collect();
             ctx.print_list(
-                vec!["NAME", "STATE", ">CAPACITY", ">USED", "DISKS"],
+                vec![
+                    "NAME",
+                    "STATE",
+                    ">CAPACITY",
+                    ">USED",
+                    ">COMMITTED",
+                    ">OVERCOMMIT",
+                    "DISKS",
+                ],
                 table,
             );
         }
@@ -203,5 +309,6 @@ fn pool_state_to_str(idx: i32) -> &'static str {
         rpc::PoolState::PoolOnline => "online",
         rpc::PoolState::PoolDegraded => "degraded",
         rpc::PoolState::PoolFaulted => "faulted",
+        rpc::PoolState::PoolDraining => "draining",
     }
 }
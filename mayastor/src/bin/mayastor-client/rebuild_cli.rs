@@ -45,6 +45,13 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
                 .index(2)
                 .help("uri of child to start rebuilding"),
+        )
+        .arg(
+            Arg::with_name("partial")
+                .long("partial")
+                .required(false)
+                .takes_value(false)
+                .help("only rebuild regions written to while degraded"),
         );
 
     let stop = SubCommand::with_name("stop")
@@ -175,6 +182,7 @@ async fn start(
         .start_rebuild(rpc::StartRebuildRequest {
             uuid: uuid.clone(),
             uri: uri.clone(),
+            partial: matches.is_present("partial"),
         })
         .await
         .context(GrpcStatus)?;
@@ -424,6 +432,7 @@ async fn stats(
                     "block_size",
                     "tasks_total",
                     "tasks_active",
+                    "partial",
                 ],
                 vec![vec![
                     response.blocks_total,
@@ -436,6 +445,7 @@ async fn stats(
                 ]
                 .iter()
                 .map(|s| s.to_string())
+                .chain(std::iter::once(response.partial.to_string()))
                 .collect()],
             );
         }
@@ -1,5 +1,6 @@
 use crate::{
     context::{Context, OutputFormat},
+    nexus_cli::{parse_qos_limits, qos_args},
     parse_size,
     Error,
     GrpcStatus,
@@ -82,7 +83,29 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                         .short("t")
                         .long("thin")
                         .takes_value(false)
-                        .help("Whether replica is thin provisioned (default false)"));
+                        .help("Whether replica is thin provisioned (default false)"))
+                .arg(
+                    Arg::with_name("cipher")
+                        .long("cipher")
+                        .takes_value(true)
+                        .possible_values(&["aes_cbc", "aes_xts"])
+                        .help("Cipher to encrypt the replica with (default none)"))
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .takes_value(true)
+                        .help("Encryption key of 16 bytes"))
+                .arg(
+                    Arg::with_name("key2")
+                        .long("key2")
+                        .takes_value(true)
+                        .help("Second encryption key, for aes_xts only"))
+                .arg(
+                    Arg::with_name("key-file")
+                        .long("key-file")
+                        .takes_value(true)
+                        .conflicts_with_all(&["key", "key2"])
+                        .help("File on the mayastor node holding the encryption key(s)"));
 
     let destroy = SubCommand::with_name("destroy")
         .about("Destroy replica")
@@ -93,6 +116,21 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("Replica uuid"),
         );
 
+    let resize = SubCommand::with_name("resize")
+        .about("Grow replica")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("Replica uuid"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .index(2)
+                .help("New size with optional unit suffix"),
+        );
+
     let share = SubCommand::with_name("share").about("Share or unshare replica")
         .arg(
             Arg::with_name("name")
@@ -103,7 +141,26 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
             Arg::with_name("protocol")
                 .required(true)
                 .index(2)
-                .help("Name of a protocol (nvmf, iscsi) used for sharing or \"none\" to unshare the replica"));
+                .help("Name of a protocol (nvmf, iscsi) used for sharing or \"none\" to unshare the replica"))
+        .arg(
+            Arg::with_name("allowed-host")
+                .long("allowed-host")
+                .value_name("NQN")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("NQN of a host allowed to connect over nvmf (default: any host)"));
+
+    let qos = qos_args(
+        SubCommand::with_name("qos")
+            .about("Set the IO QoS limits of the replica, 0 means unlimited")
+            .arg(
+                Arg::with_name("uuid")
+                    .required(true)
+                    .index(1)
+                    .help("Replica uuid"),
+            ),
+    );
 
     SubCommand::with_name("replica")
         .settings(&[
@@ -115,7 +172,9 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(create)
         .subcommand(create_v2)
         .subcommand(destroy)
+        .subcommand(resize)
         .subcommand(share)
+        .subcommand(qos)
         .subcommand(SubCommand::with_name("list").about("List replicas"))
         .subcommand(SubCommand::with_name("list2").about("List replicas"))
         .subcommand(
@@ -131,9 +190,11 @@ pub async fn handler(
         ("create", Some(args)) => replica_create(ctx, args).await,
         ("create2", Some(args)) => replica_create_v2(ctx, args).await,
         ("destroy", Some(args)) => replica_destroy(ctx, args).await,
+        ("resize", Some(args)) => replica_resize(ctx, args).await,
         ("list", Some(args)) => replica_list(ctx, args).await,
         ("list2", Some(args)) => replica_list2(ctx, args).await,
         ("share", Some(args)) => replica_share(ctx, args).await,
+        ("qos", Some(args)) => replica_qos(ctx, args).await,
         ("stats", Some(args)) => replica_stat(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
@@ -229,6 +290,22 @@ async fn replica_create_v2(
     let share = parse_replica_protocol(matches.value_of("protocol"))
         .context(GrpcStatus)?;
 
+    let encryption =
+        matches
+            .value_of("cipher")
+            .map(|cipher| rpc::ReplicaEncryption {
+                cipher: match cipher {
+                    "aes_xts" => rpc::ReplicaCipher::CipherAesXts,
+                    _ => rpc::ReplicaCipher::CipherAesCbc,
+                } as i32,
+                key: matches.value_of("key").unwrap_or_default().to_owned(),
+                key2: matches.value_of("key2").unwrap_or_default().to_owned(),
+                key_file: matches
+                    .value_of("key-file")
+                    .unwrap_or_default()
+                    .to_owned(),
+            });
+
     let rq = rpc::CreateReplicaRequestV2 {
         name,
         uuid: uuid.clone(),
@@ -236,6 +313,7 @@ async fn replica_create_v2(
         thin,
         share,
         size: size.get_bytes() as u64,
+        encryption,
     };
     let response =
         ctx.client.create_replica_v2(rq).await.context(GrpcStatus)?;
@@ -295,6 +373,51 @@ async fn replica_destroy(
     Ok(())
 }
 
+async fn replica_resize(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_owned();
+    let size = parse_size(matches.value_of("size").ok_or_else(|| {
+        Error::MissingValue {
+            field: "size".to_string(),
+        }
+    })?)
+    .map_err(|s| Status::invalid_argument(format!("Bad size '{}'", s)))
+    .context(GrpcStatus)?;
+
+    let response = ctx
+        .client
+        .resize_replica(rpc::ResizeReplicaRequest {
+            uuid: uuid.clone(),
+            size: size.get_bytes() as u64,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", &uuid);
+        }
+    };
+
+    Ok(())
+}
+
 async fn replica_list(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -379,6 +502,18 @@ async fn replica_list2(
                 .map(|r| {
                     let proto = replica_protocol_to_str(r.share);
                     let size = ctx.units(Byte::from_bytes(r.size.into()));
+                    let allocated =
+                        ctx.units(Byte::from_bytes(r.allocated.into()));
+                    let cipher = match rpc::ReplicaCipher::from_i32(r.cipher) {
+                        Some(rpc::ReplicaCipher::CipherAesCbc) => "aes_cbc",
+                        Some(rpc::ReplicaCipher::CipherAesXts) => "aes_xts",
+                        _ => "none",
+                    };
+                    let encrypted = if r.locked {
+                        format!("{} (locked)", cipher)
+                    } else {
+                        cipher.to_string()
+                    };
                     vec![
                         r.pool.clone(),
                         r.name.clone(),
@@ -386,12 +521,24 @@ async fn replica_list2(
                         r.thin.to_string(),
                         proto.to_string(),
                         size,
+                        allocated,
+                        encrypted,
                         r.uri.clone(),
                     ]
                 })
                 .collect();
             ctx.print_list(
-                vec!["POOL", "NAME", "UUID", ">THIN", ">SHARE", ">SIZE", "URI"],
+                vec![
+                    "POOL",
+                    "NAME",
+                    "UUID",
+                    ">THIN",
+                    ">SHARE",
+                    ">SIZE",
+                    ">ALLOCATED",
+                    ">ENCRYPTION",
+                    "URI",
+                ],
                 table,
             );
         }
@@ -413,6 +560,10 @@ async fn replica_share(
         .share_replica(rpc::ShareReplicaRequest {
             uuid: name.clone(),
             share,
+            allowed_hosts: matches
+                .values_of("allowed-host")
+                .map(|hosts| hosts.map(|h| h.to_string()).collect())
+                .unwrap_or_default(),
         })
         .await
         .context(GrpcStatus)?;
@@ -435,6 +586,23 @@ async fn replica_share(
     Ok(())
 }
 
+async fn replica_qos(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches.value_of("uuid").unwrap().to_string();
+
+    ctx.client
+        .set_replica_qos(rpc::SetReplicaQosRequest {
+            uuid: uuid.clone(),
+            qos: Some(parse_qos_limits(matches)),
+        })
+        .await
+        .context(GrpcStatus)?;
+    ctx.v1(&uuid);
+    Ok(())
+}
+
 async fn replica_stat(
     mut ctx: Context,
     _matches: &ArgMatches<'_>,
@@ -462,8 +630,22 @@ async fn replica_stat(
                 return Ok(());
             }
 
-            let header =
-                vec!["POOL", "NAME", "RDCNT", "WRCNT", "RDBYTES", "WRBYTES"];
+            let header = vec![
+                "POOL", "NAME", "RDCNT", "WRCNT", "RDBYTES", "WRBYTES",
+                "RDLAT/us", "WRLAT/us", "RDP99/us", "WRP99/us",
+            ];
+            let mean = |l: &Option<rpc::LatencyStats>| {
+                l.as_ref()
+                    .filter(|l| l.count > 0)
+                    .map_or(0, |l| l.total_us / l.count)
+                    .to_string()
+            };
+            // replicas which are not shared have no latency histograms
+            let p99 = |l: &Option<rpc::LatencyStats>| {
+                l.as_ref()
+                    .filter(|l| !l.buckets.is_empty())
+                    .map_or_else(|| "-".to_string(), |l| l.p99_us.to_string())
+            };
             let table = replicas
                 .iter()
                 .map(|replica| {
@@ -479,6 +661,10 @@ async fn replica_stat(
                         stats.num_write_ops.to_string(),
                         read,
                         written,
+                        mean(&stats.read_latency),
+                        mean(&stats.write_latency),
+                        p99(&stats.read_latency),
+                        p99(&stats.write_latency),
                     ]
                 })
                 .collect();
@@ -489,7 +675,9 @@ async fn replica_stat(
     Ok(())
 }
 
-fn parse_replica_protocol(pcol: Option<&str>) -> Result<i32, Status> {
+pub(crate) fn parse_replica_protocol(
+    pcol: Option<&str>,
+) -> Result<i32, Status> {
     match pcol {
         None => Ok(rpc::ShareProtocolReplica::ReplicaNone as i32),
         Some("nvmf") => Ok(rpc::ShareProtocolReplica::ReplicaNvmf as i32),
@@ -3,10 +3,12 @@
 
 use crate::{
     context::{Context, OutputFormat},
+    replica_cli::parse_replica_protocol,
     Error,
     GrpcStatus,
 };
 use ::rpc::mayastor as rpc;
+use byte_unit::Byte;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored_json::ToColoredJson;
 use snafu::ResultExt;
@@ -18,6 +20,10 @@ pub async fn handler(
 ) -> crate::Result<()> {
     match matches.subcommand() {
         ("create", Some(args)) => create(ctx, args).await,
+        ("list", Some(args)) => list(ctx, args).await,
+        ("destroy", Some(args)) => destroy(ctx, args).await,
+        ("clone", Some(args)) => clone(ctx, args).await,
+        ("revert", Some(args)) => revert(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {} does not exist", cmd)))
                 .context(GrpcStatus)
@@ -27,7 +33,7 @@ pub async fn handler(
 
 pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
     let create = SubCommand::with_name("create")
-        .about("create a snapshot")
+        .about("create a consistent snapshot of all healthy nexus children")
         .arg(
             Arg::with_name("uuid")
                 .required(true)
@@ -35,6 +41,62 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("uuid of the nexus"),
         );
 
+    let list = SubCommand::with_name("list").about("list snapshots").arg(
+        Arg::with_name("replica")
+            .short("r")
+            .long("replica")
+            .takes_value(true)
+            .value_name("UUID")
+            .help("only list the snapshots of this replica"),
+    );
+
+    let destroy = SubCommand::with_name("destroy")
+        .about("destroy a snapshot")
+        .arg(
+            Arg::with_name("name")
+                .required(true)
+                .index(1)
+                .help("name of the snapshot"),
+        );
+
+    let clone = SubCommand::with_name("clone")
+        .about("create a writable replica from a snapshot")
+        .arg(
+            Arg::with_name("snapshot")
+                .required(true)
+                .index(1)
+                .help("name of the snapshot"),
+        )
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(2)
+                .help("uuid of the new replica"),
+        )
+        .arg(
+            Arg::with_name("protocol")
+                .short("p")
+                .long("protocol")
+                .takes_value(true)
+                .value_name("PROTOCOL")
+                .help("Name of a protocol (nvmf) used for sharing the replica (default none)"),
+        );
+
+    let revert = SubCommand::with_name("revert")
+        .about("revert a replica to one of its snapshots")
+        .arg(
+            Arg::with_name("uuid")
+                .required(true)
+                .index(1)
+                .help("uuid of the replica"),
+        )
+        .arg(
+            Arg::with_name("snapshot")
+                .required(true)
+                .index(2)
+                .help("name of the snapshot"),
+        );
+
     SubCommand::with_name("snapshot")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -43,6 +105,10 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         ])
         .about("Snapshot management")
         .subcommand(create)
+        .subcommand(list)
+        .subcommand(destroy)
+        .subcommand(clone)
+        .subcommand(revert)
 }
 
 async fn create(
@@ -59,7 +125,193 @@ async fn create(
     let response = ctx
         .client
         .create_snapshot(rpc::CreateSnapshotRequest {
-            uuid: uuid.clone(),
+            uuid,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            let reply = response.get_ref();
+            println!("{}", &reply.name);
+            let table = reply
+                .children
+                .iter()
+                .map(|c| vec![c.uri.clone(), c.name.clone()])
+                .collect();
+            ctx.print_list(vec!["CHILD", "SNAPSHOT"], table);
+        }
+    };
+
+    Ok(())
+}
+
+async fn list(mut ctx: Context, matches: &ArgMatches<'_>) -> crate::Result<()> {
+    let replica = matches.value_of("replica").unwrap_or_default().to_string();
+
+    let response = ctx
+        .client
+        .list_snapshots(rpc::ListSnapshotsRequest {
+            replica,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            let snapshots = &response.get_ref().snapshots;
+            if snapshots.is_empty() {
+                ctx.v1("No snapshots found");
+                return Ok(());
+            }
+
+            let table = snapshots
+                .iter()
+                .map(|s| {
+                    let size = ctx.units(Byte::from_bytes(s.size.into()));
+                    vec![
+                        s.pool.clone(),
+                        s.name.clone(),
+                        s.uuid.clone(),
+                        size,
+                        s.parent.clone(),
+                    ]
+                })
+                .collect();
+            ctx.print_list(
+                vec!["POOL", "NAME", "UUID", ">SIZE", "PARENT"],
+                table,
+            );
+        }
+    };
+
+    Ok(())
+}
+
+async fn destroy(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let name = matches
+        .value_of("name")
+        .ok_or_else(|| Error::MissingValue {
+            field: "name".to_string(),
+        })?
+        .to_string();
+
+    let response = ctx
+        .client
+        .destroy_snapshot(rpc::DestroySnapshotRequest {
+            name: name.clone(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", &name);
+        }
+    };
+
+    Ok(())
+}
+
+async fn clone(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let snapshot = matches
+        .value_of("snapshot")
+        .ok_or_else(|| Error::MissingValue {
+            field: "snapshot".to_string(),
+        })?
+        .to_string();
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let share = parse_replica_protocol(matches.value_of("protocol"))
+        .context(GrpcStatus)?;
+
+    let response = ctx
+        .client
+        .create_clone(rpc::CreateCloneRequest {
+            snapshot,
+            uuid,
+            share,
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            println!("{}", &response.get_ref().uri);
+        }
+    };
+
+    Ok(())
+}
+
+async fn revert(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string();
+    let snapshot = matches
+        .value_of("snapshot")
+        .ok_or_else(|| Error::MissingValue {
+            field: "snapshot".to_string(),
+        })?
+        .to_string();
+
+    let response = ctx
+        .client
+        .revert_replica(rpc::RevertReplicaRequest {
+            uuid,
+            snapshot,
         })
         .await
         .context(GrpcStatus)?;
@@ -75,7 +327,7 @@ async fn create(
             );
         }
         OutputFormat::Default => {
-            println!("{}", &uuid);
+            println!("{}", &response.get_ref().uri);
         }
     };
 
@@ -0,0 +1,261 @@
+//!
+//! methods to compare the children of a nexus and repair them
+
+use crate::{
+    context::{Context, OutputFormat},
+    Error,
+    GrpcStatus,
+};
+use ::rpc::mayastor as rpc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use colored_json::ToColoredJson;
+use snafu::ResultExt;
+use tonic::Status;
+
+pub async fn handler(
+    ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    match matches.subcommand() {
+        ("start", Some(args)) => start(ctx, args).await,
+        ("stop", Some(args)) => stop(ctx, args).await,
+        ("pause", Some(args)) => pause(ctx, args).await,
+        ("resume", Some(args)) => resume(ctx, args).await,
+        ("stats", Some(args)) => stats(ctx, args).await,
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {} does not exist", cmd)))
+                .context(GrpcStatus)
+        }
+    }
+}
+
+fn uuid_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("uuid")
+        .required(true)
+        .index(1)
+        .help("uuid of the nexus")
+}
+
+pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
+    let start = SubCommand::with_name("start")
+        .about("starts comparing the children of a nexus")
+        .arg(uuid_arg())
+        .arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .required(false)
+                .takes_value(false)
+                .help("overwrite mismatching blocks with the source data"),
+        )
+        .arg(
+            Arg::with_name("source")
+                .long("source")
+                .required(false)
+                .takes_value(true)
+                .value_name("URI")
+                .help("child to compare against (default: first healthy one)"),
+        );
+
+    let stop = SubCommand::with_name("stop")
+        .about("stops a verify, or discards the results of a finished one")
+        .arg(uuid_arg());
+
+    let pause = SubCommand::with_name("pause")
+        .about("pauses a verify")
+        .arg(uuid_arg());
+
+    let resume = SubCommand::with_name("resume")
+        .about("resumes a verify")
+        .arg(uuid_arg());
+
+    let stats = SubCommand::with_name("stats")
+        .about("shows the progress of a verify and the mismatches found")
+        .arg(uuid_arg());
+
+    SubCommand::with_name("verify")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("Nexus consistency check")
+        .subcommand(start)
+        .subcommand(stop)
+        .subcommand(pause)
+        .subcommand(resume)
+        .subcommand(stats)
+}
+
+fn get_uuid(matches: &ArgMatches<'_>) -> crate::Result<String> {
+    Ok(matches
+        .value_of("uuid")
+        .ok_or_else(|| Error::MissingValue {
+            field: "uuid".to_string(),
+        })?
+        .to_string())
+}
+
+fn print_json<T: serde::Serialize>(response: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(response)
+            .unwrap()
+            .to_colored_json_auto()
+            .unwrap()
+    );
+}
+
+async fn start(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = get_uuid(matches)?;
+
+    let response = ctx
+        .client
+        .start_verify(rpc::StartVerifyRequest {
+            uuid: uuid.clone(),
+            repair: matches.is_present("repair"),
+            source: matches.value_of("source").unwrap_or_default().to_string(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => print_json(response.get_ref()),
+        OutputFormat::Default => println!("{}", &uuid),
+    };
+
+    Ok(())
+}
+
+async fn stop(mut ctx: Context, matches: &ArgMatches<'_>) -> crate::Result<()> {
+    let uuid = get_uuid(matches)?;
+
+    let response = ctx
+        .client
+        .stop_verify(rpc::StopVerifyRequest {
+            uuid: uuid.clone(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => print_json(response.get_ref()),
+        OutputFormat::Default => println!("{}", &uuid),
+    };
+
+    Ok(())
+}
+
+async fn pause(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = get_uuid(matches)?;
+
+    let response = ctx
+        .client
+        .pause_verify(rpc::PauseVerifyRequest {
+            uuid: uuid.clone(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => print_json(response.get_ref()),
+        OutputFormat::Default => println!("{}", &uuid),
+    };
+
+    Ok(())
+}
+
+async fn resume(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = get_uuid(matches)?;
+
+    let response = ctx
+        .client
+        .resume_verify(rpc::ResumeVerifyRequest {
+            uuid: uuid.clone(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => print_json(response.get_ref()),
+        OutputFormat::Default => println!("{}", &uuid),
+    };
+
+    Ok(())
+}
+
+async fn stats(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let uuid = get_uuid(matches)?;
+
+    ctx.v2(&format!("Getting the verify stats of nexus {}", uuid));
+    let response = ctx
+        .client
+        .get_verify_stats(rpc::VerifyStatsRequest {
+            uuid: uuid.clone(),
+        })
+        .await
+        .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => print_json(response.get_ref()),
+        OutputFormat::Default => {
+            let response = &response.get_ref();
+            ctx.print_list(
+                vec![
+                    "state",
+                    "source",
+                    ">blocks_total",
+                    ">blocks_verified",
+                    ">progress (%)",
+                    ">blocks_mismatched",
+                    ">blocks_repaired",
+                ],
+                vec![vec![
+                    response.state.clone(),
+                    response.source.clone(),
+                    response.blocks_total.to_string(),
+                    response.blocks_verified.to_string(),
+                    response.progress.to_string(),
+                    response.blocks_mismatched.to_string(),
+                    response.blocks_repaired.to_string(),
+                ]],
+            );
+
+            if !response.mismatches.is_empty() {
+                println!();
+                ctx.print_list(
+                    vec![">offset_blks", ">num_blks", "repaired", "children"],
+                    response
+                        .mismatches
+                        .iter()
+                        .map(|m| {
+                            vec![
+                                m.offset_blks.to_string(),
+                                m.num_blks.to_string(),
+                                m.repaired.to_string(),
+                                m.children.join(","),
+                            ]
+                        })
+                        .collect(),
+                );
+            }
+
+            if !response.error.is_empty() {
+                println!("\nerror: {}", response.error);
+            }
+        }
+    };
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ use structopt::StructOpt;
 
 use git_version::git_version;
 use mayastor::{
-    bdev::util::uring,
+    bdev::{spawn_nexus_recovery, util::uring},
     core::{
         device_monitor,
         runtime,
@@ -19,6 +19,7 @@ use mayastor::{
     },
     grpc,
     logger,
+    metrics,
     persistent_store::PersistentStore,
     subsys,
     subsys::Registration,
@@ -32,6 +33,7 @@ mayastor::CPS_INIT!();
 fn start_tokio_runtime(args: &MayastorCliArgs) {
     let grpc_address = grpc::endpoint(args.grpc_endpoint.clone());
     let rpc_address = args.rpc_address.clone();
+    let metrics_address = args.metrics_endpoint.clone().map(metrics::endpoint);
     let node_name = args
         .node_name
         .clone()
@@ -39,6 +41,7 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
 
     let endpoint = args.mbus_endpoint.clone();
     let persistent_store_endpoint = args.persistent_store_endpoint.clone();
+    let recover_nexus = args.recover_nexus;
 
     Mthread::spawn_unaffinitized(move || {
         runtime::block_on(async move {
@@ -48,9 +51,13 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
                 mbus_api::message_bus_init_tokio(endpoint);
                 Registration::init(&node_name, &grpc_address.to_string());
                 futures.push(subsys::Registration::run().boxed());
+                futures.push(subsys::PoolSpaceMonitor::run(node_name).boxed());
             }
 
             PersistentStore::init(persistent_store_endpoint).await;
+            if recover_nexus {
+                spawn_nexus_recovery();
+            }
             runtime::spawn(device_monitor());
 
             futures.push(
@@ -58,6 +65,11 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
                     .boxed(),
             );
 
+            if let Some(metrics_address) = metrics_address {
+                futures
+                    .push(metrics::MetricsServer::run(metrics_address).boxed());
+            }
+
             futures::future::try_join_all(futures)
                 .await
                 .expect_err("runtime exited in the abnormal state");
@@ -27,6 +27,7 @@ use spdk_sys::{
     spdk_bdev_io_stat,
     spdk_bdev_io_type_supported,
     spdk_bdev_next,
+    spdk_bdev_notify_blockcnt_change,
     spdk_bdev_open_ext,
     spdk_uuid,
     spdk_uuid_copy,
@@ -43,6 +44,7 @@ use crate::{
         Descriptor,
         DeviceEventType,
         IoType,
+        LatencyHistogram,
         ShareIscsi,
         ShareNvmf,
         UnshareIscsi,
@@ -77,14 +79,7 @@ impl Share for Bdev {
         &self,
         cntlid_range: Option<(u16, u16)>,
     ) -> Result<Self::Output, Self::Error> {
-        let subsystem =
-            NvmfSubsystem::try_from(self.clone()).context(ShareNvmf {})?;
-        if let Some((cntlid_min, cntlid_max)) = cntlid_range {
-            subsystem
-                .set_cntlid_range(cntlid_min, cntlid_max)
-                .context(ShareNvmf {})?;
-        }
-        subsystem.start().await.context(ShareNvmf {})
+        self.share_nvmf_hosts(cntlid_range, &[]).await
     }
 
     /// unshare the bdev regardless of current active share
@@ -260,6 +255,18 @@ impl Bdev {
         }
     }
 
+    /// change the block count of a registered device, which notifies all
+    /// consumers of the device with a resize event
+    pub fn notify_block_count(&mut self, count: u64) -> Result<(), Errno> {
+        let rc =
+            unsafe { spdk_bdev_notify_blockcnt_change(self.0.as_ptr(), count) };
+        if rc == 0 {
+            Ok(())
+        } else {
+            Err(Errno::from_i32(rc.abs()))
+        }
+    }
+
     /// set the block length of the device in bytes
     pub fn set_block_len(&mut self, len: u32) {
         unsafe {
@@ -414,7 +421,17 @@ impl Bdev {
                 source: Errno::from_i32(errno),
             })
         } else {
-            // stat is populated with the stats by now
+            // stat is populated with the stats by now, SPDK only tracks the
+            // total latency of the bdev so the histograms have no buckets
+            let latency = |count: u64, ticks: u64| LatencyHistogram {
+                count,
+                total_us: if stat.ticks_rate == 0 {
+                    0
+                } else {
+                    (ticks as u128 * 1_000_000 / stat.ticks_rate as u128) as u64
+                },
+                ..Default::default()
+            };
             Ok(BlockDeviceIoStats {
                 num_read_ops: stat.num_read_ops,
                 num_write_ops: stat.num_write_ops,
@@ -422,10 +439,106 @@ impl Bdev {
                 bytes_written: stat.bytes_written,
                 num_unmap_ops: stat.num_unmap_ops,
                 bytes_unmapped: stat.bytes_unmapped,
+                read_latency: latency(
+                    stat.num_read_ops,
+                    stat.read_latency_ticks,
+                ),
+                write_latency: latency(
+                    stat.num_write_ops,
+                    stat.write_latency_ticks,
+                ),
+                unmap_latency: latency(
+                    stat.num_unmap_ops,
+                    stat.unmap_latency_ticks,
+                ),
             })
         }
     }
 
+    /// share the bdev over NVMe-OF TCP, only allowing the hosts with the
+    /// given NQNs to connect to it, or any host when none are given
+    pub async fn share_nvmf_hosts(
+        &self,
+        cntlid_range: Option<(u16, u16)>,
+        allowed_hosts: &[String],
+    ) -> Result<String, CoreError> {
+        let subsystem =
+            NvmfSubsystem::try_from(self.clone()).context(ShareNvmf {})?;
+        Self::start_nvmf_share(subsystem, cntlid_range, allowed_hosts).await
+    }
+
+    /// share the bdev over NVMe-OF TCP under its own NQN, with the given
+    /// bdev on top of it as the namespace, only allowing the hosts with the
+    /// given NQNs to connect to it, or any host when none are given
+    pub async fn share_nvmf_namespace(
+        &self,
+        namespace: &Bdev,
+        cntlid_range: Option<(u16, u16)>,
+        allowed_hosts: &[String],
+    ) -> Result<String, CoreError> {
+        let subsystem = NvmfSubsystem::new_with_uuid(&self.name(), namespace)
+            .context(ShareNvmf {})?;
+        Self::start_nvmf_share(subsystem, cntlid_range, allowed_hosts).await
+    }
+
+    async fn start_nvmf_share(
+        subsystem: NvmfSubsystem,
+        cntlid_range: Option<(u16, u16)>,
+        allowed_hosts: &[String],
+    ) -> Result<String, CoreError> {
+        if let Some((cntlid_min, cntlid_max)) = cntlid_range {
+            subsystem
+                .set_cntlid_range(cntlid_min, cntlid_max)
+                .context(ShareNvmf {})?;
+        }
+        if let Err(e) = subsystem.set_allowed_hosts(allowed_hosts) {
+            subsystem.destroy();
+            return Err(e).context(ShareNvmf {});
+        }
+        subsystem.start().await.context(ShareNvmf {})
+    }
+
+    /// stop sharing the bdev over NVMe-OF under its own NQN, whichever bdev
+    /// is the namespace
+    pub async fn unshare_nvmf_namespace(&self) -> Result<(), CoreError> {
+        if let Some(subsystem) = NvmfSubsystem::nqn_lookup(&self.name()) {
+            subsystem.stop().await.context(UnshareNvmf {})?;
+            subsystem.destroy();
+        }
+        Ok(())
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the bdev when it
+    /// is shared over NVMe-OF, an empty list means any host may connect
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        match NvmfSubsystem::nqn_lookup(&self.name()) {
+            Some(subsystem) if !subsystem.allows_any() => {
+                subsystem.allowed_hosts()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// change the hosts allowed to connect to the bdev when it is shared over
+    /// NVMe-OF, which is a no-op when it is not
+    pub fn set_allowed_hosts(&self, hosts: &[String]) -> Result<(), CoreError> {
+        match NvmfSubsystem::nqn_lookup(&self.name()) {
+            Some(subsystem) => {
+                subsystem.set_allowed_hosts(hosts).context(ShareNvmf {})
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// allow any host to connect to the bdev when it is shared over NVMe-OF,
+    /// which is a no-op when it is not
+    pub fn allow_any_host(&self) -> Result<(), CoreError> {
+        match NvmfSubsystem::nqn_lookup(&self.name()) {
+            Some(subsystem) => subsystem.allow_any_host().context(ShareNvmf {}),
+            None => Ok(()),
+        }
+    }
+
     /// returns the first bdev in the list
     pub fn bdev_first() -> Option<Bdev> {
         Self::from_ptr(unsafe { spdk_bdev_first() })
@@ -18,9 +18,128 @@ pub struct BlockDeviceIoStats {
     pub num_unmap_ops: u64,
     #[merge(strategy = merge::num::saturating_add)]
     pub bytes_unmapped: u64,
+    pub read_latency: LatencyHistogram,
+    pub write_latency: LatencyHistogram,
+    pub unmap_latency: LatencyHistogram,
 }
 
-use spdk_sys::iovec;
+impl BlockDeviceIoStats {
+    /// replace the latencies of the stats, which for SPDK bdevs only have a
+    /// total, by the histograms a device keeps in its own IO path
+    pub fn with_latency(mut self, latency: IoLatency) -> Self {
+        self.read_latency = latency.read;
+        self.write_latency = latency.write;
+        self.unmap_latency = latency.unmap;
+        self
+    }
+}
+
+/// Latency histograms of the read, write and unmap operations of a device,
+/// kept per channel by devices which complete the I/O themselves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Merge)]
+pub struct IoLatency {
+    pub read: LatencyHistogram,
+    pub write: LatencyHistogram,
+    pub unmap: LatencyHistogram,
+}
+
+impl IoLatency {
+    /// account an I/O operation of the given type which was submitted at
+    /// the given tick and has completed successfully
+    #[inline]
+    pub fn record(&mut self, op: IoType, submitted: u64) {
+        let histogram = match op {
+            IoType::Read => &mut self.read,
+            IoType::Write => &mut self.write,
+            IoType::Unmap => &mut self.unmap,
+            _ => return,
+        };
+        let (now, hz) = unsafe { (spdk_get_ticks(), spdk_get_ticks_hz()) };
+        histogram.record(now.saturating_sub(submitted) * 1_000_000 / hz);
+    }
+}
+
+/// Number of buckets of a latency histogram.
+pub const LATENCY_BUCKETS: usize = 32;
+
+/// Histogram of I/O latencies with log-scale buckets: bucket n counts the
+/// I/O operations which took less than 2^n microseconds (and not less than
+/// 2^(n-1)), the last bucket also counts all slower operations.
+/// Devices which only track the total latency leave the buckets empty.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LatencyHistogram {
+    /// number of I/O operations accounted
+    pub count: u64,
+    /// sum of the latencies in microseconds
+    pub total_us: u64,
+    /// number of I/O operations per bucket
+    pub buckets: [u64; LATENCY_BUCKETS],
+}
+
+impl Merge for LatencyHistogram {
+    fn merge(&mut self, other: Self) {
+        self.count = self.count.saturating_add(other.count);
+        self.total_us = self.total_us.saturating_add(other.total_us);
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *b = b.saturating_add(*o);
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// account an I/O operation which took the given number of microseconds
+    #[inline]
+    pub fn record(&mut self, latency_us: u64) {
+        let bucket = (64 - latency_us.leading_zeros()) as usize;
+        self.buckets[bucket.min(LATENCY_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total_us += latency_us;
+    }
+
+    /// upper bound in microseconds of the given bucket, None for the last
+    /// bucket which has no upper bound
+    pub fn bucket_bound(bucket: usize) -> Option<u64> {
+        if bucket < LATENCY_BUCKETS - 1 {
+            Some(1 << bucket)
+        } else {
+            None
+        }
+    }
+
+    /// mean latency in microseconds
+    pub fn mean_us(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total_us / self.count
+        }
+    }
+
+    /// latency in microseconds below which the given fraction (0.0 - 1.0)
+    /// of the I/O operations completed, rounded up to the bound of the
+    /// bucket it falls in. None if the buckets are not tracked.
+    pub fn percentile(&self, fraction: f64) -> Option<u64> {
+        let total = self.buckets.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        let target = ((total as f64) * fraction).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Some(
+                    Self::bucket_bound(bucket)
+                        .unwrap_or(1 << (LATENCY_BUCKETS - 1)),
+                );
+            }
+        }
+        None
+    }
+}
+
+use spdk_sys::{iovec, spdk_get_ticks, spdk_get_ticks_hz};
 
 /*
  * Core trait that represents a block device.
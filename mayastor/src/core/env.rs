@@ -45,8 +45,9 @@ use crate::{
     },
     grpc,
     logger,
+    metrics,
     persistent_store::PersistentStore,
-    subsys::{self, Config, PoolConfig},
+    subsys::{self, Config, PoolConfig, ResourceConfig},
     target::iscsi,
 };
 
@@ -113,6 +114,9 @@ pub struct MayastorCliArgs {
     #[structopt(short = "P")]
     /// Path to pool config file.
     pub pool_config: Option<String>,
+    #[structopt(short = "R")]
+    /// Path to the config file of the replicas and nexuses.
+    pub resource_config: Option<String>,
     #[structopt(long = "huge-dir")]
     /// Path to hugedir.
     pub hugedir: Option<String>,
@@ -124,14 +128,23 @@ pub struct MayastorCliArgs {
     /// it supersedes the core mask (-m) argument.
     pub core_list: Option<String>,
     #[structopt(short = "p")]
-    /// Endpoint of the persistent store.
+    /// Endpoint of the persistent store: an etcd endpoint, file:///<path> to
+    /// keep it in a local file or memory:// to keep it in memory.
     pub persistent_store_endpoint: Option<String>,
+    #[structopt(long = "recover-nexus")]
+    /// Recreate the nexuses that were on this node before it restarted from
+    /// the persistent store.
+    pub recover_nexus: bool,
     #[structopt(long = "bdev-pool-size", default_value = "65535")]
     /// Number of entries in memory pool for bdev I/O contexts
     pub bdev_io_ctx_pool_size: u64,
     #[structopt(long = "nvme-ctl-pool-size", default_value = "65535")]
     /// Number of entries in memory pool for NVMe controller I/O contexts
     pub nvme_ctl_io_ctx_pool_size: u64,
+    #[structopt(long = "metrics-endpoint")]
+    /// IP address and port (optional) for the Prometheus metrics endpoint.
+    /// The metrics are not exported when not specified.
+    pub metrics_endpoint: Option<String>,
 }
 
 /// Mayastor features.
@@ -159,6 +172,7 @@ impl Default for MayastorCliArgs {
             grpc_endpoint: grpc::default_endpoint().to_string(),
             mbus_endpoint: None,
             persistent_store_endpoint: None,
+            recover_nexus: false,
             node_name: None,
             env_context: None,
             reactor_mask: "0x1".into(),
@@ -168,10 +182,12 @@ impl Default for MayastorCliArgs {
             log_components: vec![],
             mayastor_config: None,
             pool_config: None,
+            resource_config: None,
             hugedir: None,
             core_list: None,
             bdev_io_ctx_pool_size: 65535,
             nvme_ctl_io_ctx_pool_size: 65535,
+            metrics_endpoint: None,
         }
     }
 }
@@ -223,9 +239,12 @@ pub struct MayastorEnvironment {
     pub node_name: String,
     pub mbus_endpoint: Option<String>,
     pub grpc_endpoint: Option<std::net::SocketAddr>,
+    pub metrics_endpoint: Option<std::net::SocketAddr>,
     persistent_store_endpoint: Option<String>,
+    recover_nexus: bool,
     mayastor_config: Option<String>,
     pool_config: Option<String>,
+    resource_config: Option<String>,
     delay_subsystem_init: bool,
     enable_coredump: bool,
     env_context: Option<String>,
@@ -261,9 +280,12 @@ impl Default for MayastorEnvironment {
             node_name: "mayastor-node".into(),
             mbus_endpoint: None,
             grpc_endpoint: None,
+            metrics_endpoint: None,
             persistent_store_endpoint: None,
+            recover_nexus: false,
             mayastor_config: None,
             pool_config: None,
+            resource_config: None,
             delay_subsystem_init: false,
             enable_coredump: true,
             env_context: None,
@@ -371,11 +393,14 @@ impl MayastorEnvironment {
     pub fn new(args: MayastorCliArgs) -> Self {
         Self {
             grpc_endpoint: Some(grpc::endpoint(args.grpc_endpoint)),
+            metrics_endpoint: args.metrics_endpoint.map(metrics::endpoint),
             mbus_endpoint: subsys::mbus_endpoint(args.mbus_endpoint),
             persistent_store_endpoint: args.persistent_store_endpoint,
+            recover_nexus: args.recover_nexus,
             node_name: args.node_name.unwrap_or_else(|| "mayastor-node".into()),
             mayastor_config: args.mayastor_config,
             pool_config: args.pool_config,
+            resource_config: args.resource_config,
             log_component: args.log_components,
             mem_size: args.mem_size,
             no_pci: args.no_pci,
@@ -661,6 +686,22 @@ impl MayastorEnvironment {
         None
     }
 
+    /// load the config file of the replicas and nexuses.
+    fn load_resource_config(&self) -> Option<ResourceConfig> {
+        if let Some(file) = &self.resource_config {
+            info!("loading resource config file {}", file);
+            match ResourceConfig::load(file) {
+                Ok(config) => {
+                    return Some(config);
+                }
+                Err(error) => {
+                    warn!("failed to load resource configuration: {}", error);
+                }
+            }
+        }
+        None
+    }
+
     /// initialize the core, call this before all else
     pub fn init(mut self) -> Self {
         // setup the logger as soon as possible
@@ -669,6 +710,7 @@ impl MayastorEnvironment {
         self.load_yaml_config();
 
         let pool_config = self.load_pool_config();
+        let resource_config = self.load_resource_config();
 
         // bootstrap DPDK and its magic
         self.initialize_eal();
@@ -734,6 +776,11 @@ impl MayastorEnvironment {
             config.import_pools();
         }
 
+        // then the replicas and nexuses on top of them
+        if let Some(config) = resource_config {
+            config.import_resources();
+        }
+
         self
     }
 
@@ -754,14 +801,19 @@ impl MayastorEnvironment {
     {
         type FutureResult = Result<(), ()>;
         let grpc_endpoint = self.grpc_endpoint;
+        let metrics_endpoint = self.metrics_endpoint;
         let rpc_addr = self.rpc_addr.clone();
         let persistent_store_endpoint = self.persistent_store_endpoint.clone();
+        let recover_nexus = self.recover_nexus;
         let ms = self.init();
 
         let rt = Builder::new_current_thread().enable_all().build().unwrap();
 
         rt.block_on(async {
             PersistentStore::init(persistent_store_endpoint).await;
+            if recover_nexus {
+                nexus::nexus_recovery::spawn_nexus_recovery();
+            }
             let master = Reactors::current();
             master.send_future(async { f() });
             let mut futures: Vec<
@@ -773,6 +825,11 @@ impl MayastorEnvironment {
                     rpc_addr,
                 )));
             }
+            if let Some(metrics_endpoint) = metrics_endpoint {
+                futures.push(Box::pin(metrics::MetricsServer::run(
+                    metrics_endpoint,
+                )));
+            }
             futures.push(Box::pin(subsys::Registration::run()));
             futures.push(Box::pin(master));
             let _out = future::try_join_all(futures).await;
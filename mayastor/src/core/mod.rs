@@ -20,9 +20,12 @@ pub use block_device::{
     DeviceTimeoutAction,
     IoCompletionCallback,
     IoCompletionCallbackArg,
+    IoLatency,
+    LatencyHistogram,
     LbaRangeController,
     OpCompletionCallback,
     OpCompletionCallbackArg,
+    LATENCY_BUCKETS,
 };
 pub use channel::IoChannel;
 pub use cpu_cores::{Core, Cores};
@@ -48,6 +51,7 @@ pub use nvme::{
     NvmeCommandStatus,
     NvmeStatus,
 };
+pub use qos::{QosCredits, QosLimiter, QosLimits};
 pub use reactor::{Reactor, ReactorState, Reactors, REACTOR_LIST};
 pub use runtime::spawn;
 pub use share::{Protocol, Share};
@@ -69,6 +73,7 @@ pub mod io_driver;
 pub mod mempool;
 mod nvme;
 pub mod poller;
+mod qos;
 mod reactor;
 pub mod runtime;
 mod share;
@@ -203,6 +208,11 @@ pub enum CoreError {
     },
     #[snafu(display("No devices available for I/O"))]
     NoDevicesAvailable {},
+    #[snafu(display("Failed to set the QoS limits of {}: {}", name, source))]
+    SetQos {
+        source: Errno,
+        name: String,
+    },
 }
 
 // Generic I/O completion status for block devices, which supports per-protocol
@@ -225,6 +225,7 @@ pub mod nvme_admin_opc {
     // pub const GET_FEATURES: u8 = 0x0a;
     // Vendor-specific
     pub const CREATE_SNAPSHOT: u8 = 0xc0;
+    pub const DESTROY_SNAPSHOT: u8 = 0xc1;
 }
 
 /// NVM command set opcodes, from nvme_spec.h
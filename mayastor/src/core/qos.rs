@@ -0,0 +1,244 @@
+//! Rate limiting (QoS) of block device IO.
+//!
+//! The limits are enforced by means of token buckets, one per limit, which
+//! are refilled at the configured rate. An IO is admitted as long as none of
+//! the buckets it draws from is in debt, after which its cost is deducted.
+//! Allowing the buckets to go into debt means that a single large IO is
+//! never starved by a small bandwidth limit.
+//!
+//! Tokens which are not used accumulate up to `burst_ms` worth of the rate,
+//! which allows for short bursts above the limits after a quiet period.
+//!
+//! IO is submitted on many cores at once, so the buckets are lock free and
+//! every IO channel spends the tokens it has taken from them as its own
+//! credits, rather than contending on the shared buckets for every IO.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use spdk_sys::{spdk_get_ticks, spdk_get_ticks_hz};
+
+use crate::core::IoType;
+
+const MIB: u64 = 1024 * 1024;
+
+/// QoS limits of a block device, a limit of 0 means unlimited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QosLimits {
+    /// read and write IOs per second
+    pub iops: u64,
+    /// read IOs per second
+    pub read_iops: u64,
+    /// write IOs per second
+    pub write_iops: u64,
+    /// read and write MiB per second
+    pub mbps: u64,
+    /// read MiB per second
+    pub read_mbps: u64,
+    /// write MiB per second
+    pub write_mbps: u64,
+    /// how long, in milliseconds, the limits may be exceeded after IO has
+    /// been below the limits for a while
+    pub burst_ms: u32,
+}
+
+impl QosLimits {
+    /// returns true if none of the limits are set
+    pub fn is_unlimited(&self) -> bool {
+        self.iops == 0
+            && self.read_iops == 0
+            && self.write_iops == 0
+            && self.mbps == 0
+            && self.read_mbps == 0
+            && self.write_mbps == 0
+    }
+}
+
+/// A single token bucket, shared by all the IO channels of a device. The
+/// channels do not take their tokens one IO at a time but in batches which
+/// they keep as credits, so that the shared state is only touched about once
+/// per millisecond worth of IO on every channel.
+#[derive(Debug)]
+struct Bucket {
+    /// tokens added per second
+    rate: u64,
+    /// max number of tokens
+    capacity: i64,
+    /// number of tokens a channel takes at a time
+    batch: i64,
+    /// available tokens, negative when in debt
+    tokens: AtomicI64,
+    /// ticks at which the bucket was last refilled
+    last: AtomicU64,
+}
+
+impl Bucket {
+    fn new(rate: u64, burst_ms: u32, now: u64) -> Option<Self> {
+        if rate == 0 {
+            return None;
+        }
+
+        let capacity = std::cmp::max(rate * burst_ms as u64 / 1000, 1) as i64;
+        Some(Self {
+            rate,
+            capacity,
+            batch: std::cmp::max(rate / 1000, 1) as i64,
+            tokens: AtomicI64::new(capacity),
+            last: AtomicU64::new(now),
+        })
+    }
+
+    /// add the tokens for the ticks elapsed since the last refill, of
+    /// channels racing to refill the bucket only one adds the tokens
+    fn refill(&self, now: u64, hz: u64) {
+        let last = self.last.load(Ordering::Acquire);
+        let elapsed = now.saturating_sub(last) as u128;
+        let added = elapsed * self.rate as u128 / hz as u128;
+        if added == 0 {
+            return;
+        }
+
+        // keep the fraction of a token which has not been added yet, unless
+        // the bucket fills up anyway
+        let (added, next) = if added >= self.capacity as u128 {
+            (self.capacity, now)
+        } else {
+            let ticks = added * hz as u128 / self.rate as u128;
+            (added as i64, last + ticks as u64)
+        };
+
+        if self
+            .last
+            .compare_exchange(last, next, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            let capacity = self.capacity;
+            let _ = self.tokens.fetch_update(
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                |tokens| Some(std::cmp::min(tokens + added, capacity)),
+            );
+        }
+    }
+
+    /// returns true if an IO of the given cost may go ahead as far as this
+    /// bucket is concerned
+    fn allows(&self, credits: i64, cost: i64, now: u64, hz: u64) -> bool {
+        if credits >= cost {
+            return true;
+        }
+        self.refill(now, hz);
+        self.tokens.load(Ordering::Acquire) >= 0
+    }
+
+    /// deduct the cost of an IO from the credits of a channel, taking a new
+    /// batch of tokens from the bucket when the credits do not cover it
+    fn take(&self, credits: &mut i64, cost: i64) {
+        if *credits < cost {
+            let batch = std::cmp::max(cost - *credits, self.batch);
+            self.tokens.fetch_sub(batch, Ordering::AcqRel);
+            *credits += batch;
+        }
+        *credits -= cost;
+    }
+}
+
+const IOPS: usize = 0;
+const READ_IOPS: usize = 1;
+const WRITE_IOPS: usize = 2;
+const BPS: usize = 3;
+const READ_BPS: usize = 4;
+const WRITE_BPS: usize = 5;
+const BUCKETS: usize = 6;
+
+/// Tokens taken from the buckets of a limiter by a single IO channel, which
+/// it spends without synchronising with the other channels. Credits are only
+/// valid for the limiter they were taken from.
+#[derive(Debug, Default)]
+pub struct QosCredits([i64; BUCKETS]);
+
+/// Rate limiter of the IO submitted to a block device, shared by all the IO
+/// channels of the device.
+#[derive(Debug)]
+pub struct QosLimiter {
+    limits: QosLimits,
+    hz: u64,
+    buckets: [Option<Bucket>; BUCKETS],
+    /// number of IOs which had to wait before being submitted
+    throttled: AtomicU64,
+}
+
+impl QosLimiter {
+    /// create a new limiter enforcing the given limits
+    pub fn new(limits: QosLimits) -> Self {
+        let hz = unsafe { spdk_get_ticks_hz() };
+        let now = unsafe { spdk_get_ticks() };
+        let bucket = |rate| Bucket::new(rate, limits.burst_ms, now);
+
+        Self {
+            limits,
+            hz,
+            buckets: [
+                bucket(limits.iops),
+                bucket(limits.read_iops),
+                bucket(limits.write_iops),
+                bucket(limits.mbps * MIB),
+                bucket(limits.read_mbps * MIB),
+                bucket(limits.write_mbps * MIB),
+            ],
+            throttled: AtomicU64::new(0),
+        }
+    }
+
+    /// the limits enforced by this limiter
+    pub fn limits(&self) -> QosLimits {
+        self.limits
+    }
+
+    /// number of IOs which had to wait for the limits to allow for them
+    pub fn throttled(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// account for an IO which has been held back
+    pub fn throttle(&self) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// returns true if an IO of the given type and size may be submitted now
+    /// by the channel owning the credits, in which case its cost is deducted
+    /// from them. Only reads and writes are subject to the limits.
+    pub fn admit(
+        &self,
+        credits: &mut QosCredits,
+        io_type: IoType,
+        bytes: u64,
+    ) -> bool {
+        let (iops, bps) = match io_type {
+            IoType::Read => (READ_IOPS, READ_BPS),
+            IoType::Write => (WRITE_IOPS, WRITE_BPS),
+            _ => return true,
+        };
+
+        let now = unsafe { spdk_get_ticks() };
+        let bytes = bytes as i64;
+        let limited = [(IOPS, 1), (iops, 1), (BPS, bytes), (bps, bytes)];
+
+        let admit = limited.iter().all(|&(i, cost)| match &self.buckets[i] {
+            Some(bucket) => bucket.allows(credits.0[i], cost, now, self.hz),
+            None => true,
+        });
+
+        if admit {
+            for &(i, cost) in limited.iter() {
+                if let Some(bucket) = &self.buckets[i] {
+                    bucket.take(&mut credits.0[i], cost);
+                }
+            }
+        }
+
+        admit
+    }
+}
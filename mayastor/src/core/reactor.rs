@@ -36,6 +36,7 @@ use std::{
     os::raw::c_void,
     pin::Pin,
     slice::Iter,
+    sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
 
@@ -50,6 +51,8 @@ use spdk_sys::{
     spdk_cpuset_get_cpu,
     spdk_env_thread_launch_pinned,
     spdk_env_thread_wait_all,
+    spdk_get_ticks,
+    spdk_get_ticks_hz,
     spdk_thread,
     spdk_thread_get_cpumask,
     spdk_thread_lib_init_ext,
@@ -110,6 +113,10 @@ pub struct Reactor {
     /// through FFI
     sx: Sender<Pin<Box<dyn Future<Output = ()> + 'static>>>,
     rx: Receiver<Pin<Box<dyn Future<Output = ()> + 'static>>>,
+    /// ticks spent polling threads which had work to do
+    busy_ticks: AtomicU64,
+    /// ticks spent polling threads which had nothing to do
+    idle_ticks: AtomicU64,
 }
 
 thread_local! {
@@ -277,6 +284,8 @@ impl Reactor {
             flags: Cell::new(ReactorState::Init),
             sx,
             rx,
+            busy_ticks: AtomicU64::new(0),
+            idle_ticks: AtomicU64::new(0),
         }
     }
 
@@ -419,6 +428,18 @@ impl Reactor {
         self.lcore
     }
 
+    /// returns the time spent polling threads which had work to do, and
+    /// which had nothing to do, since the reactor started
+    pub fn utilisation(&self) -> (Duration, Duration) {
+        let hz = unsafe { spdk_get_ticks_hz() };
+        let to_duration =
+            |ticks: u64| Duration::from_secs_f64(ticks as f64 / hz as f64);
+        (
+            to_duration(self.busy_ticks.load(Ordering::Relaxed)),
+            to_duration(self.idle_ticks.load(Ordering::Relaxed)),
+        )
+    }
+
     /// poll this reactor to complete any work that is pending
     pub fn poll_reactor(&self) {
         loop {
@@ -454,9 +475,14 @@ impl Reactor {
         self.receive_futures();
         self.run_futures();
         let threads = self.threads.borrow();
-        threads.iter().for_each(|t| {
-            t.poll();
-        });
+        let start = unsafe { spdk_get_ticks() };
+        let busy = threads.iter().fold(false, |busy, t| t.poll() || busy);
+        let ticks = unsafe { spdk_get_ticks() } - start;
+        if busy {
+            self.busy_ticks.fetch_add(ticks, Ordering::Relaxed);
+        } else {
+            self.idle_ticks.fetch_add(ticks, Ordering::Relaxed);
+        }
 
         drop(threads);
         while let Ok(i) = self.incoming.pop() {
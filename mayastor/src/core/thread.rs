@@ -83,9 +83,10 @@ impl Mthread {
         out
     }
 
+    /// poll the thread once, returns true if it did any work
     #[inline]
-    pub fn poll(&self) {
-        let _ = unsafe { spdk_thread_poll(self.0.as_ptr(), 0, 0) };
+    pub fn poll(&self) -> bool {
+        unsafe { spdk_thread_poll(self.0.as_ptr(), 0, 0) > 0 }
     }
 
     #[inline]
@@ -60,6 +60,9 @@ impl From<BlockDeviceIoStats> for rpc::NvmeControllerIoStats {
             bytes_written: b.bytes_written,
             num_unmap_ops: b.num_unmap_ops,
             bytes_unmapped: b.bytes_unmapped,
+            read_latency: Some(b.read_latency.into()),
+            write_latency: Some(b.write_latency.into()),
+            unmap_latency: Some(b.unmap_latency.into()),
         }
     }
 }
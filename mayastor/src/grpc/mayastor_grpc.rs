@@ -10,7 +10,8 @@
 
 use crate::{
     bdev::{
-        nexus::{instances, nexus_bdev},
+        host_nqn,
+        nexus::{instances, nexus_bdev, nexus_child::NexusChild},
         nexus_create,
         nexus_create_v2,
         Reason,
@@ -19,17 +20,21 @@ use crate::{
         Bdev,
         BlockDeviceIoStats,
         CoreError,
+        LatencyHistogram,
         MayastorFeatures,
         Protocol,
+        QosLimits as CoreQosLimits,
         Share,
     },
     grpc::{
         controller_grpc::{controller_stats, list_controllers},
         mayastor_grpc::nexus_bdev::NexusNvmeParams,
         nexus_grpc::{
+            name_to_uuid,
             nexus_add_child,
             nexus_destroy,
             nexus_lookup,
+            read_policy_from_i32,
             uuid_to_name,
         },
         rpc_submit,
@@ -38,9 +43,10 @@ use crate::{
         Serializer,
     },
     host::{blk_device, resource},
-    lvs::{Error as LvsError, Lvol, Lvs},
+    lvs::{EncryptionKey, Error as LvsError, Lvol, Lvs},
     nexus_uri::NexusBdevError,
-    subsys::PoolConfig,
+    rebuild::RebuildMode,
+    subsys::{Config, ConfigError, PoolConfig, ResourceConfig},
 };
 use futures::FutureExt;
 use nix::errno::Errno;
@@ -143,6 +149,59 @@ impl From<LvsError> for Status {
             LvsError::ReplicaShareProtocol {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            LvsError::RepResize {
+                source, ..
+            } => match source {
+                Errno::ENOSPC => Status::resource_exhausted(e.to_string()),
+                Errno::EINVAL => Status::invalid_argument(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            },
+            LvsError::PoolOvercommit {
+                ..
+            } => Status::resource_exhausted(e.to_string()),
+            LvsError::PoolDraining {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            LvsError::LvolQos {
+                source:
+                    CoreError::SetQos {
+                        source, ..
+                    },
+                ..
+            } if source == Errno::EINVAL || source == Errno::EOPNOTSUPP => {
+                Status::invalid_argument(e.to_string())
+            }
+
+            LvsError::LvolHosts {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+
+            LvsError::AllowAnyHost {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+
+            LvsError::RepDestroy {
+                source: Errno::EBUSY,
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            LvsError::RepRevert {
+                source, ..
+            } => match source {
+                Errno::EBUSY => Status::failed_precondition(e.to_string()),
+                _ => Status::invalid_argument(e.to_string()),
+            },
+            LvsError::NotASnapshot {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            LvsError::InvalidKey {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            LvsError::Locked {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            LvsError::RepExists {
+                ..
+            } => Status::already_exists(e.to_string()),
 
             LvsError::Destroy {
                 source, ..
@@ -158,6 +217,17 @@ impl From<LvsError> for Status {
     }
 }
 
+impl From<ConfigError> for Status {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            ConfigError::RestartRequired {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            _ => Status::invalid_argument(e.to_string()),
+        }
+    }
+}
+
 impl From<Protocol> for i32 {
     fn from(p: Protocol) -> Self {
         match p {
@@ -173,9 +243,15 @@ impl From<Lvs> for Pool {
         Self {
             name: l.name().into(),
             disks: vec![l.base_bdev().bdev_uri().unwrap_or_else(|| "".into())],
-            state: PoolState::PoolOnline.into(),
+            state: if l.draining() {
+                PoolState::PoolDraining.into()
+            } else {
+                PoolState::PoolOnline.into()
+            },
             capacity: l.capacity(),
             used: l.used(),
+            committed: l.committed(),
+            overcommit: l.overcommit(),
         }
     }
 }
@@ -187,6 +263,55 @@ impl From<BlockDeviceIoStats> for Stats {
             num_write_ops: b.num_write_ops,
             bytes_read: b.bytes_read,
             bytes_written: b.bytes_written,
+            read_latency: Some(b.read_latency.into()),
+            write_latency: Some(b.write_latency.into()),
+        }
+    }
+}
+
+impl From<LatencyHistogram> for LatencyStats {
+    fn from(h: LatencyHistogram) -> Self {
+        let percentile = |fraction| h.percentile(fraction).unwrap_or_default();
+        Self {
+            count: h.count,
+            total_us: h.total_us,
+            p50_us: percentile(0.5),
+            p90_us: percentile(0.9),
+            p99_us: percentile(0.99),
+            p999_us: percentile(0.999),
+            buckets: if h.buckets.iter().any(|b| *b > 0) {
+                h.buckets.to_vec()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+impl From<CoreQosLimits> for QosLimits {
+    fn from(q: CoreQosLimits) -> Self {
+        Self {
+            iops: q.iops,
+            read_iops: q.read_iops,
+            write_iops: q.write_iops,
+            mbps: q.mbps,
+            read_mbps: q.read_mbps,
+            write_mbps: q.write_mbps,
+            burst_ms: q.burst_ms,
+        }
+    }
+}
+
+impl From<QosLimits> for CoreQosLimits {
+    fn from(q: QosLimits) -> Self {
+        Self {
+            iops: q.iops,
+            read_iops: q.read_iops,
+            write_iops: q.write_iops,
+            mbps: q.mbps,
+            read_mbps: q.read_mbps,
+            write_mbps: q.write_mbps,
+            burst_ms: q.burst_ms,
         }
     }
 }
@@ -199,9 +324,43 @@ impl From<Lvol> for Replica {
             thin: l.is_thin(),
             size: l.size(),
             share: l.shared().unwrap().into(),
-            uri: l.share_uri().unwrap(),
+            uri: l.share_uri().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Lvol> for DrainingReplica {
+    fn from(l: Lvol) -> Self {
+        let name = l.name();
+        Self {
+            nexus: instances()
+                .iter()
+                .find(|n| n.children.iter().any(|c| is_replica(c, &name)))
+                .map(|n| name_to_uuid(&n.name).to_string())
+                .unwrap_or_default(),
+            uuid: name,
+            uri: l.share_uri().unwrap_or_default(),
+        }
+    }
+}
+
+/// returns true if the nexus child is the local or a remote replica with the
+/// given name, remote replicas are identified by the last segment of their
+/// URI which is either the name or an NQN ending in it
+fn is_replica(child: &NexusChild, name: &str) -> bool {
+    if let Ok(device) = child.get_device() {
+        if device.device_name() == name {
+            return true;
         }
     }
+
+    url::Url::parse(child.get_name())
+        .ok()
+        .and_then(|u| {
+            u.path_segments()
+                .and_then(|s| s.last().map(|s| s.to_string()))
+        })
+        .map_or(false, |s| s == name || s.ends_with(&format!(":{}", name)))
 }
 
 impl From<Lvol> for ReplicaV2 {
@@ -213,11 +372,103 @@ impl From<Lvol> for ReplicaV2 {
             thin: l.is_thin(),
             size: l.size(),
             share: l.shared().unwrap().into(),
-            uri: l.share_uri().unwrap(),
+            uri: l.share_uri().unwrap_or_default(),
+            allocated: l.allocated(),
+            qos: Some(l.qos_limits().into()),
+            cipher: l
+                .cipher()
+                .map_or(ReplicaCipher::CipherNone, ReplicaCipher::from)
+                as i32,
+            locked: l.is_locked(),
+            allowed_hosts: l.allowed_hosts().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Lvol> for Snapshot {
+    fn from(l: Lvol) -> Self {
+        Self {
+            name: l.name(),
+            uuid: l.uuid(),
+            pool: l.pool(),
+            size: l.size(),
+            parent: l.parent_snapshot().map(|p| p.name()).unwrap_or_default(),
         }
     }
 }
 
+/// only the mayastor config can be read or changed over gRPC, the states of
+/// the nexus children are kept in the persistent store
+fn check_config_kind(kind: i32) -> Result<(), ConfigError> {
+    match ConfigKind::from_i32(kind) {
+        Some(ConfigKind::MayastorConfig) => Ok(()),
+        _ => Err(ConfigError::InvalidConfig {
+            msg: format!("unsupported config kind {}", kind),
+        }),
+    }
+}
+
+/// the current config in YAML
+fn config_reply(config: &Config) -> Result<GetConfigReply, ConfigError> {
+    serde_yaml::to_string(&config.refresh())
+        .map(|config| GetConfigReply {
+            config: config.into_bytes(),
+        })
+        .map_err(|e| ConfigError::InvalidConfig {
+            msg: e.to_string(),
+        })
+}
+
+/// lookup the lvol with the given name
+fn lvol_lookup(name: &str) -> Result<Lvol, LvsError> {
+    match Bdev::lookup_by_name(name) {
+        Some(bdev) => Lvol::try_from(bdev),
+        None => Err(LvsError::InvalidBdev {
+            source: NexusBdevError::BdevNotFound {
+                name: name.to_string(),
+            },
+            name: name.to_string(),
+        }),
+    }
+}
+
+/// lookup the replica with the given uuid, for calls which accept either a
+/// nexus or a replica
+fn replica_lookup(uuid: &str) -> Result<Lvol, Status> {
+    lvol_lookup(uuid).map_err(|_| {
+        Status::not_found(format!("nexus or replica {} not found", uuid))
+    })
+}
+
+/// the hosts allowed to connect to the nexus or replica with the given uuid,
+/// any host may connect when the list is empty
+fn allowed_hosts(uuid: &str) -> Result<Vec<String>, Status> {
+    if let Ok(nexus) = nexus_lookup(uuid) {
+        return Ok(nexus.allowed_hosts());
+    }
+    Ok(replica_lookup(uuid)?.allowed_hosts()?)
+}
+
+/// change the hosts allowed to connect to the nexus or replica with the given
+/// uuid and return them
+async fn update_allowed_hosts(
+    uuid: &str,
+    update: impl FnOnce(&mut Vec<String>),
+) -> Result<Vec<String>, Status> {
+    if let Ok(nexus) = nexus_lookup(uuid) {
+        let mut hosts = nexus.allowed_hosts();
+        update(&mut hosts);
+        nexus.set_allowed_hosts(hosts)?;
+        return Ok(nexus.allowed_hosts());
+    }
+
+    let lvol = replica_lookup(uuid)?;
+    let mut hosts = lvol.allowed_hosts()?;
+    update(&mut hosts);
+    lvol.set_allowed_hosts(hosts).await?;
+    Ok(lvol.allowed_hosts()?)
+}
+
 impl From<MayastorFeatures> for rpc::mayastor::MayastorFeatures {
     fn from(f: MayastorFeatures) -> Self {
         Self {
@@ -277,6 +528,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                         config.export().await;
 
                         pool.destroy().await?;
+                        ResourceConfig::capture().export().await;
                     }
                     Ok(Null {})
                 })?;
@@ -315,6 +567,57 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[named]
+    async fn drain_pool(
+        &self,
+        request: Request<DrainPoolRequest>,
+    ) -> GrpcResult<DrainPoolReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit::<_, _, LvsError>(async move {
+                    let pool = match Lvs::lookup(&args.name) {
+                        Some(pool) => pool,
+                        None => {
+                            return Err(LvsError::Invalid {
+                                source: Errno::ENOENT,
+                                msg: format!("pool {} not found", args.name),
+                            })
+                        }
+                    };
+
+                    pool.set_draining(!args.stop).await?;
+                    // Capture current pool config and export to file.
+                    PoolConfig::capture().export().await;
+                    ResourceConfig::capture().export().await;
+
+                    let replicas = pool
+                        .lvols()
+                        .map(|lvols| {
+                            lvols
+                                .filter(|lvol| !lvol.is_snapshot())
+                                .map(DrainingReplica::from)
+                                .collect()
+                        })
+                        .unwrap_or_default();
+
+                    Ok(DrainPoolReply {
+                        pool: Some(Pool::from(pool)),
+                        replicas,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn create_replica(
         &self,
@@ -353,6 +656,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     match lvol.share_nvmf(None).await {
                         Ok(s) => {
                             debug!("created and shared {} as {}", lvol, s);
+                            ResourceConfig::capture().export().await;
                             Ok(Replica::from(lvol))
                         }
                         Err(e) => {
@@ -368,6 +672,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                 }
                 Ok(lvol) => {
                     debug!("created lvol {}", lvol);
+                    ResourceConfig::capture().export().await;
                     Ok(Replica::from(lvol))
                 }
                 Err(e) => Err(e),
@@ -386,67 +691,104 @@ impl mayastor_server::Mayastor for MayastorSvc {
         &self,
         request: Request<CreateReplicaRequestV2>,
     ) -> GrpcResult<ReplicaV2> {
-        self.locked(GrpcClientContext::new(&request, function_name!()), async move {
-        let rx = rpc_submit(async move {
-            let args = request.into_inner();
-
-            let lvs = match Lvs::lookup(&args.pool) {
-                Some(lvs) => lvs,
-                None => {
-                    return Err(LvsError::Invalid {
-                        source: Errno::ENOSYS,
-                        msg: format!("Pool {} not found", args.pool),
-                    })
-                }
-            };
-
-            if let Some(b) = Bdev::lookup_by_name(&args.name) {
-                let lvol = Lvol::try_from(b)?;
-                return Ok(ReplicaV2::from(lvol));
-            }
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let rx = rpc_submit(async move {
+                    let args = request.into_inner();
 
-            if !matches!(
-                Protocol::try_from(args.share)?,
-                Protocol::Off | Protocol::Nvmf
-            ) {
-                return Err(LvsError::ReplicaShareProtocol {
-                    value: args.share,
-                });
-            }
+                    let lvs = match Lvs::lookup(&args.pool) {
+                        Some(lvs) => lvs,
+                        None => {
+                            return Err(LvsError::Invalid {
+                                source: Errno::ENOSYS,
+                                msg: format!("Pool {} not found", args.pool),
+                            })
+                        }
+                    };
+
+                    if !matches!(
+                        Protocol::try_from(args.share)?,
+                        Protocol::Off | Protocol::Nvmf
+                    ) {
+                        return Err(LvsError::ReplicaShareProtocol {
+                            value: args.share,
+                        });
+                    }
 
-            match lvs.create_lvol(&args.name, args.size, Some(&args.uuid), false).await {
-                Ok(lvol)
-                    if Protocol::try_from(args.share)? == Protocol::Nvmf =>
-                {
-                    match lvol.share_nvmf(None).await {
-                        Ok(s) => {
-                            debug!("created and shared {} as {}", lvol, s);
-                            Ok(ReplicaV2::from(lvol))
+                    let key = match &args.encryption {
+                        Some(spec) => {
+                            EncryptionKey::from_rpc(&args.name, spec)?
                         }
-                        Err(e) => {
+                        None => None,
+                    };
+
+                    if let Some(b) = Bdev::lookup_by_name(&args.name) {
+                        let lvol = Lvol::try_from(b)?;
+                        // an encrypted replica is locked after importing its
+                        // pool until it is opened with its key
+                        if let (true, Some(key)) = (lvol.is_locked(), &key) {
+                            lvol.open_encrypted(key).await?;
+                            if Protocol::try_from(args.share)? == Protocol::Nvmf
+                            {
+                                lvol.share_nvmf(None).await?;
+                            }
+                        }
+                        return Ok(ReplicaV2::from(lvol));
+                    }
+
+                    let lvol = lvs
+                        .create_lvol(
+                            &args.name,
+                            args.size,
+                            Some(&args.uuid),
+                            false,
+                        )
+                        .await?;
+
+                    if let Some(key) = &key {
+                        if let Err(e) = lvol.encrypt(key).await {
                             debug!(
-                                "failed to share created lvol {}: {} (destroying)",
+                                "failed to encrypt created lvol {}: {} (destroying)",
                                 lvol,
                                 e.to_string()
                             );
                             let _ = lvol.destroy().await;
-                            Err(e)
+                            return Err(e);
                         }
                     }
-                }
-                Ok(lvol) => {
-                    debug!("created lvol {}", lvol);
-                    Ok(ReplicaV2::from(lvol))
-                }
-                Err(e) => Err(e),
-            }
-        })?;
 
-        rx.await
-            .map_err(|_| Status::cancelled("cancelled"))?
-            .map_err(Status::from)
-            .map(Response::new)
-        }).await
+                    if Protocol::try_from(args.share)? == Protocol::Nvmf {
+                        match lvol.share_nvmf(None).await {
+                            Ok(s) => {
+                                debug!("created and shared {} as {}", lvol, s);
+                                ResourceConfig::capture().export().await;
+                                Ok(ReplicaV2::from(lvol))
+                            }
+                            Err(e) => {
+                                debug!(
+                                    "failed to share created lvol {}: {} (destroying)",
+                                    lvol,
+                                    e.to_string()
+                                );
+                                let _ = lvol.destroy().await;
+                                Err(e)
+                            }
+                        }
+                    } else {
+                        debug!("created lvol {}", lvol);
+                        ResourceConfig::capture().export().await;
+                        Ok(ReplicaV2::from(lvol))
+                    }
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
     }
 
     #[named]
@@ -460,6 +802,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                 if let Some(bdev) = Bdev::lookup_by_name(&args.uuid) {
                     let lvol = Lvol::try_from(bdev)?;
                     lvol.destroy().await?;
+                    ResourceConfig::capture().export().await;
                 }
                 Ok(Null {})
             })?;
@@ -472,6 +815,38 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[named]
+    async fn resize_replica(
+        &self,
+        request: Request<ResizeReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                match Bdev::lookup_by_name(&args.uuid) {
+                    Some(bdev) => {
+                        let lvol = Lvol::try_from(bdev)?;
+                        lvol.resize(args.size).await?;
+                        ResourceConfig::capture().export().await;
+                        Ok(Replica::from(lvol))
+                    }
+                    None => Err(LvsError::InvalidBdev {
+                        source: NexusBdevError::BdevNotFound {
+                            name: args.uuid.clone(),
+                        },
+                        name: args.uuid,
+                    }),
+                }
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
     #[named]
     async fn list_replicas(
         &self,
@@ -545,7 +920,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
 
             let mut replicas = Vec::new();
             for l in lvols {
-                let stats = l.as_bdev().stats().await;
+                let stats = l.stats().await;
                 if stats.is_err() {
                     error!("failed to get stats for lvol: {}", l);
                 }
@@ -554,6 +929,8 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     uuid: l.name(),
                     pool: l.pool(),
                     stats: stats.ok().map(Stats::from),
+                    qos: Some(l.qos_limits().into()),
+                    throttled: l.qos_throttled(),
                 });
             }
 
@@ -568,6 +945,32 @@ impl mayastor_server::Mayastor for MayastorSvc {
             .map(Response::new)
     }
 
+    #[named]
+    async fn set_replica_qos(
+        &self,
+        request: Request<SetReplicaQosRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                let rx = rpc_submit::<_, _, LvsError>(async move {
+                    let limits =
+                        args.qos.map(CoreQosLimits::from).unwrap_or_default();
+                    lvol_lookup(&args.uuid)?.set_qos_limits(limits).await?;
+                    info!("Set replica {} QoS limits {:?}", args.uuid, limits);
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn share_replica(
         &self,
@@ -582,6 +985,12 @@ impl mayastor_server::Mayastor for MayastorSvc {
                         Some(bdev) => {
                             let lvol = Lvol::try_from(bdev)?;
 
+                            if Protocol::try_from(args.share)? == Protocol::Nvmf
+                            {
+                                lvol.set_allowed_hosts(args.allowed_hosts)
+                                    .await?;
+                            }
+
                             // if we are already shared ...
                             if lvol.shared()
                                 == Some(Protocol::try_from(args.share)?)
@@ -607,6 +1016,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                                     });
                                 }
                             }
+                            ResourceConfig::capture().export().await;
 
                             Ok(ShareReplicaReply {
                                 uri: lvol.share_uri().unwrap(),
@@ -652,6 +1062,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     .await?;
                     let nexus = nexus_lookup(&uuid)?;
                     info!("Created nexus {}", uuid);
+                    ResourceConfig::capture().export().await;
                     Ok(nexus.to_grpc())
                 })?;
                 rx.await
@@ -673,6 +1084,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
             async move {
                 let args = request.into_inner();
                 let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    let read_policy = read_policy_from_i32(args.read_policy)?;
                     nexus_create_v2(
                         &args.name,
                         args.size,
@@ -681,14 +1093,44 @@ impl mayastor_server::Mayastor for MayastorSvc {
                             min_cntlid: args.min_cntl_id as u16,
                             max_cntlid: args.max_cntl_id as u16,
                             resv_key: args.resv_key,
+                            ..Default::default()
                         },
                         &args.children,
                     )
                     .await?;
                     let nexus = nexus_lookup(&args.name)?;
+                    nexus.set_read_policy(read_policy);
                     info!("Created nexus {}", &args.name);
+                    ResourceConfig::capture().export().await;
+                    Ok(nexus.to_grpc())
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn resize_nexus(
+        &self,
+        request: Request<ResizeNexusRequest>,
+    ) -> GrpcResult<Nexus> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    let nexus = nexus_lookup(&args.uuid)?;
+                    nexus.resize(args.size).await?;
+                    info!("Resized nexus {} to {} bytes", args.uuid, args.size);
+                    ResourceConfig::capture().export().await;
                     Ok(nexus.to_grpc())
                 })?;
+
                 rx.await
                     .map_err(|_| Status::cancelled("cancelled"))?
                     .map_err(Status::from)
@@ -710,6 +1152,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     let args = request.into_inner();
                     trace!("{:?}", args);
                     nexus_destroy(&args.uuid).await?;
+                    ResourceConfig::capture().export().await;
                     Ok(Null {})
                 })?;
 
@@ -785,6 +1228,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
             debug!("Adding child {} to nexus {} ...", args.uri, uuid);
             let child = nexus_add_child(args).await?;
             info!("Added child to nexus {}", uuid);
+            ResourceConfig::capture().export().await;
             Ok(child)
         })?;
 
@@ -805,6 +1249,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
             debug!("Removing child {} from nexus {} ...", args.uri, uuid);
             nexus_lookup(&args.uuid)?.remove_child(&args.uri).await?;
             info!("Removed child from nexus {}", uuid);
+            ResourceConfig::capture().export().await;
             Ok(Null {})
         })?;
 
@@ -837,18 +1282,60 @@ impl mayastor_server::Mayastor for MayastorSvc {
             .map(Response::new)
     }
 
-    async fn publish_nexus(
+    async fn set_nexus_read_policy(
         &self,
-        request: Request<PublishNexusRequest>,
-    ) -> GrpcResult<PublishNexusReply> {
-        let rx = rpc_submit::<_, _, nexus_bdev::Error>(async {
+        request: Request<SetNexusReadPolicyRequest>,
+    ) -> GrpcResult<Null> {
+        let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
             let args = request.into_inner();
             trace!("{:?}", args);
-            let uuid = args.uuid.clone();
-            debug!("Publishing nexus {} ...", uuid);
+            let read_policy = read_policy_from_i32(args.read_policy)?;
+            nexus_lookup(&args.uuid)?.set_read_policy(read_policy);
+            info!(
+                "Set nexus {} read policy {}",
+                args.uuid,
+                read_policy.to_string()
+            );
+            Ok(Null {})
+        })?;
 
-            if !args.key.is_empty() && args.key.len() != 16 {
-                return Err(nexus_bdev::Error::InvalidKey {});
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    async fn set_nexus_qos(
+        &self,
+        request: Request<SetNexusQosRequest>,
+    ) -> GrpcResult<Null> {
+        let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let limits = args.qos.map(CoreQosLimits::from).unwrap_or_default();
+            nexus_lookup(&args.uuid)?.set_qos_limits(limits);
+            info!("Set nexus {} QoS limits {:?}", args.uuid, limits);
+            Ok(Null {})
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    async fn publish_nexus(
+        &self,
+        request: Request<PublishNexusRequest>,
+    ) -> GrpcResult<PublishNexusReply> {
+        let rx = rpc_submit::<_, _, nexus_bdev::Error>(async {
+            let args = request.into_inner();
+            trace!("{:?}", args);
+            let uuid = args.uuid.clone();
+            debug!("Publishing nexus {} ...", uuid);
+
+            if !args.key.is_empty() && args.key.len() != 16 {
+                return Err(nexus_bdev::Error::InvalidKey {});
             }
 
             let key: Option<String> = if args.key.is_empty() {
@@ -867,10 +1354,17 @@ impl mayastor_server::Mayastor for MayastorSvc {
                 }
             };
 
-            let device_uri =
-                nexus_lookup(&args.uuid)?.share(share_protocol, key).await?;
+            let nexus = nexus_lookup(&args.uuid)?;
+            if share_protocol == ShareProtocolNexus::NexusNvmf
+                && !args.allowed_hosts.is_empty()
+            {
+                nexus.set_allowed_hosts(args.allowed_hosts)?;
+            }
+
+            let device_uri = nexus.share(share_protocol, key).await?;
 
             info!("Published nexus {} under {}", uuid, device_uri);
+            ResourceConfig::capture().export().await;
             Ok(PublishNexusReply {
                 device_uri,
             })
@@ -892,6 +1386,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
             debug!("Unpublishing nexus {} ...", uuid);
             nexus_lookup(&args.uuid)?.unshare_nexus().await?;
             info!("Unpublished nexus {}", uuid);
+            ResourceConfig::capture().export().await;
             Ok(Null {})
         })?;
 
@@ -953,6 +1448,93 @@ impl mayastor_server::Mayastor for MayastorSvc {
             .map(Response::new)
     }
 
+    #[named]
+    async fn add_allowed_host(
+        &self,
+        request: Request<AllowedHostRequest>,
+    ) -> GrpcResult<ListAllowedHostsReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                if args.host_nqn.is_empty() {
+                    return Err(Status::invalid_argument("empty host NQN"));
+                }
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    let host_nqns = update_allowed_hosts(&args.uuid, |hosts| {
+                        if !hosts.contains(&args.host_nqn) {
+                            hosts.push(args.host_nqn.clone());
+                        }
+                    })
+                    .await?;
+                    info!("Allowed host {} on {}", args.host_nqn, args.uuid);
+                    Ok(ListAllowedHostsReply {
+                        host_nqns,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn remove_allowed_host(
+        &self,
+        request: Request<AllowedHostRequest>,
+    ) -> GrpcResult<ListAllowedHostsReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    let host_nqns = update_allowed_hosts(&args.uuid, |hosts| {
+                        hosts.retain(|h| h != &args.host_nqn)
+                    })
+                    .await?;
+                    info!("Disallowed host {} on {}", args.host_nqn, args.uuid);
+                    Ok(ListAllowedHostsReply {
+                        host_nqns,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn list_allowed_hosts(
+        &self,
+        request: Request<ListAllowedHostsRequest>,
+    ) -> GrpcResult<ListAllowedHostsReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    Ok(ListAllowedHostsReply {
+                        host_nqns: allowed_hosts(&args.uuid)?,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn child_operation(
         &self,
@@ -990,6 +1572,34 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[named]
+    async fn migrate_replica(
+        &self,
+        request: Request<MigrateReplicaRequest>,
+    ) -> GrpcResult<Child> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    let nexus = nexus_lookup(&args.uuid)?;
+                    nexus.migrate_child(&args.src_uri, &args.dst_uri).await?;
+                    ResourceConfig::capture().export().await;
+                    nexus_lookup(&args.uuid)?
+                        .get_child_by_name(&args.dst_uri)
+                        .map(|ch| ch.to_grpc())
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn start_rebuild(
         &self,
@@ -1001,8 +1611,13 @@ impl mayastor_server::Mayastor for MayastorSvc {
                 let args = request.into_inner();
                 trace!("{:?}", args);
                 let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    let mode = if args.partial {
+                        RebuildMode::Partial
+                    } else {
+                        RebuildMode::Full
+                    };
                     nexus_lookup(&args.uuid)?
-                        .start_rebuild(&args.uri)
+                        .start_rebuild(&args.uri, mode)
                         .await
                         .map(|_| {})?;
                     Ok(Null {})
@@ -1157,6 +1772,130 @@ impl mayastor_server::Mayastor for MayastorSvc {
         .await
     }
 
+    #[named]
+    async fn start_verify(
+        &self,
+        request: Request<StartVerifyRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    let source =
+                        Some(args.source.as_str()).filter(|s| !s.is_empty());
+                    nexus_lookup(&args.uuid)?
+                        .start_verify(args.repair, source)
+                        .await
+                        .map(|_| {})?;
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn stop_verify(
+        &self,
+        request: Request<StopVerifyRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    nexus_lookup(&args.uuid)?.stop_verify().await?;
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn pause_verify(
+        &self,
+        request: Request<PauseVerifyRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    nexus_lookup(&args.uuid)?.pause_verify().await?;
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn resume_verify(
+        &self,
+        request: Request<ResumeVerifyRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    nexus_lookup(&args.uuid)?.resume_verify().await?;
+                    Ok(Null {})
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn get_verify_stats(
+        &self,
+        request: Request<VerifyStatsRequest>,
+    ) -> GrpcResult<VerifyStatsReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                trace!("{:?}", args);
+                let rx = rpc_submit::<_, _, nexus_bdev::Error>(async move {
+                    nexus_lookup(&args.uuid)?.get_verify_stats().await
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     async fn create_snapshot(
         &self,
         request: Request<CreateSnapshotRequest>,
@@ -1167,6 +1906,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
             debug!("Creating snapshot on nexus {} ...", uuid);
             let reply = nexus_lookup(&args.uuid)?.create_snapshot().await?;
             info!("Created snapshot on nexus {}", uuid);
+            ResourceConfig::capture().export().await;
             trace!("{:?}", reply);
             Ok(reply)
         })?;
@@ -1177,6 +1917,141 @@ impl mayastor_server::Mayastor for MayastorSvc {
             .map(Response::new)
     }
 
+    #[named]
+    async fn list_snapshots(
+        &self,
+        request: Request<ListSnapshotsRequest>,
+    ) -> GrpcResult<ListSnapshotsReply> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                let snapshots = if args.replica.is_empty() {
+                    match Bdev::bdev_first() {
+                        Some(bdev) => bdev
+                            .into_iter()
+                            .filter(|b| b.driver() == "lvol")
+                            .map(|b| Lvol::try_from(b).unwrap())
+                            .filter(|l| l.is_snapshot())
+                            .map(Snapshot::from)
+                            .collect(),
+                        None => Vec::new(),
+                    }
+                } else {
+                    lvol_lookup(&args.replica)?
+                        .snapshots()
+                        .into_iter()
+                        .map(Snapshot::from)
+                        .collect()
+                };
+
+                Ok(ListSnapshotsReply {
+                    snapshots,
+                })
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
+    #[named]
+    async fn destroy_snapshot(
+        &self,
+        request: Request<DestroySnapshotRequest>,
+    ) -> GrpcResult<Null> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                if let Some(bdev) = Bdev::lookup_by_name(&args.name) {
+                    let lvol = Lvol::try_from(bdev)?;
+                    if !lvol.is_snapshot() {
+                        return Err(LvsError::NotASnapshot {
+                            source: Errno::EINVAL,
+                            name: args.name,
+                        });
+                    }
+                    lvol.destroy().await?;
+                }
+                Ok(Null {})
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
+    #[named]
+    async fn create_clone(
+        &self,
+        request: Request<CreateCloneRequest>,
+    ) -> GrpcResult<Replica> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                if !matches!(
+                    Protocol::try_from(args.share)?,
+                    Protocol::Off | Protocol::Nvmf
+                ) {
+                    return Err(LvsError::ReplicaShareProtocol {
+                        value: args.share,
+                    });
+                }
+
+                let snapshot = lvol_lookup(&args.snapshot)?;
+                let lvol = snapshot.create_clone(&args.uuid).await?;
+
+                if Protocol::try_from(args.share)? == Protocol::Nvmf {
+                    if let Err(e) = lvol.share_nvmf(None).await {
+                        debug!(
+                            "failed to share clone {}: {} (destroying)",
+                            lvol,
+                            e.to_string()
+                        );
+                        let _ = lvol.destroy().await;
+                        return Err(e);
+                    }
+                }
+
+                ResourceConfig::capture().export().await;
+                Ok(Replica::from(lvol))
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
+    #[named]
+    async fn revert_replica(
+        &self,
+        request: Request<RevertReplicaRequest>,
+    ) -> GrpcResult<Replica> {
+        self.locked(GrpcClientContext::new(&request, function_name!()), async {
+            let args = request.into_inner();
+            let rx = rpc_submit::<_, _, LvsError>(async move {
+                let snapshot = lvol_lookup(&args.snapshot)?;
+                let lvol = lvol_lookup(&args.uuid)?.revert(&snapshot).await?;
+                ResourceConfig::capture().export().await;
+                Ok(Replica::from(lvol))
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
     async fn list_block_devices(
         &self,
         request: Request<ListBlockDevicesRequest>,
@@ -1228,8 +2103,55 @@ impl mayastor_server::Mayastor for MayastorSvc {
             )
             .to_string(),
             supported_features: Some(features),
+            host_nqn: host_nqn(),
         };
 
         Ok(Response::new(reply))
     }
+
+    #[named]
+    async fn get_config(
+        &self,
+        request: Request<GetConfigRequest>,
+    ) -> GrpcResult<GetConfigReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                let rx = rpc_submit::<_, _, ConfigError>(async move {
+                    check_config_kind(args.kind)?;
+                    config_reply(&Config::get())
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn update_config(
+        &self,
+        request: Request<UpdateConfigRequest>,
+    ) -> GrpcResult<GetConfigReply> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                let rx = rpc_submit::<_, _, ConfigError>(async move {
+                    check_config_kind(args.kind)?;
+                    config_reply(&Config::update(&args.data)?)
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
 }
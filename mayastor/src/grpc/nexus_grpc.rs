@@ -7,7 +7,7 @@ use uuid::Uuid;
 use crate::{
     bdev::nexus::{
         instances,
-        nexus_bdev::{Error, Nexus, NexusStatus},
+        nexus_bdev::{Error, Nexus, NexusReadPolicy, NexusStatus},
         nexus_child::{ChildState, NexusChild, Reason},
     },
     core::{Protocol, Share},
@@ -26,6 +26,8 @@ impl From<ChildState> for rpc::ChildState {
             ChildState::Closed => rpc::ChildState::ChildDegraded,
             ChildState::Faulted(reason) => match reason {
                 Reason::OutOfSync => rpc::ChildState::ChildDegraded,
+                // slow children are rebuilt once they have recovered
+                Reason::Slow => rpc::ChildState::ChildDegraded,
                 _ => rpc::ChildState::ChildFaulted,
             },
         }
@@ -41,16 +43,65 @@ impl From<NexusStatus> for rpc::NexusState {
     }
 }
 
+impl From<NexusReadPolicy> for rpc::NexusReadPolicy {
+    fn from(policy: NexusReadPolicy) -> Self {
+        match policy {
+            NexusReadPolicy::RoundRobin => {
+                rpc::NexusReadPolicy::NexusReadRoundRobin
+            }
+            NexusReadPolicy::PreferLocal => {
+                rpc::NexusReadPolicy::NexusReadPreferLocal
+            }
+            NexusReadPolicy::LeastOutstanding => {
+                rpc::NexusReadPolicy::NexusReadLeastOutstanding
+            }
+            NexusReadPolicy::LowestLatency => {
+                rpc::NexusReadPolicy::NexusReadLowestLatency
+            }
+        }
+    }
+}
+impl From<rpc::NexusReadPolicy> for NexusReadPolicy {
+    fn from(policy: rpc::NexusReadPolicy) -> Self {
+        match policy {
+            rpc::NexusReadPolicy::NexusReadRoundRobin => {
+                NexusReadPolicy::RoundRobin
+            }
+            rpc::NexusReadPolicy::NexusReadPreferLocal => {
+                NexusReadPolicy::PreferLocal
+            }
+            rpc::NexusReadPolicy::NexusReadLeastOutstanding => {
+                NexusReadPolicy::LeastOutstanding
+            }
+            rpc::NexusReadPolicy::NexusReadLowestLatency => {
+                NexusReadPolicy::LowestLatency
+            }
+        }
+    }
+}
+
+/// Convert the raw read policy value of a request into a read policy.
+pub fn read_policy_from_i32(value: i32) -> Result<NexusReadPolicy, Error> {
+    rpc::NexusReadPolicy::from_i32(value)
+        .map(NexusReadPolicy::from)
+        .ok_or(Error::InvalidReadPolicy {
+            policy_value: value,
+        })
+}
+
 impl NexusChild {
     /// Convert nexus child object to grpc representation.
     ///
     /// We cannot use From trait because it is not value to value conversion.
     /// All we have is a reference to a child.
     pub fn to_grpc(&self) -> rpc::Child {
+        let (media_errors, transport_errors) = self.io_errors();
         rpc::Child {
             uri: self.get_name().to_string(),
             state: rpc::ChildState::from(self.state()) as i32,
             rebuild_progress: self.get_rebuild_progress(),
+            media_errors,
+            transport_errors,
         }
     }
 }
@@ -98,6 +149,10 @@ impl Nexus {
                 .collect::<Vec<_>>(),
             rebuilds: RebuildJob::count() as u32,
             ana_state: ana_state as i32,
+            read_policy: rpc::NexusReadPolicy::from(self.read_policy()) as i32,
+            qos: Some(self.qos_limits().into()),
+            throttled: self.qos_throttled(),
+            allowed_hosts: self.allowed_hosts(),
         }
     }
 }
@@ -107,7 +162,7 @@ impl Nexus {
 /// This function never fails which means that if there is a nexus with
 /// unconventional name that likely means it was not created using nexus
 /// rpc api, we return the whole name without modifications as it is.
-fn name_to_uuid(name: &str) -> &str {
+pub(crate) fn name_to_uuid(name: &str) -> &str {
     if let Some(stripped) = name.strip_prefix("nexus-") {
         stripped
     } else {
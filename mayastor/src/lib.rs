@@ -19,6 +19,7 @@ pub mod host;
 pub mod jsonrpc;
 pub mod logger;
 pub mod lvs;
+pub mod metrics;
 pub mod nexus_uri;
 pub mod persistent_store;
 pub mod pool;
@@ -28,6 +29,7 @@ mod sleep;
 pub mod store;
 pub mod subsys;
 pub mod target;
+pub mod verify;
 #[macro_export]
 macro_rules! CPS_INIT {
     () => {
@@ -40,4 +42,5 @@ macro_rules! CPS_INIT {
 pub extern "C" fn cps_init() {
     subsys::register_subsystem();
     bdev::nexus::register_module();
+    bdev::qos::register_module();
 }
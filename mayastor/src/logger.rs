@@ -1,16 +1,19 @@
 use std::{ffi::CStr, os::raw::c_char, path::Path};
 
 use ansi_term::{Colour, Style};
+use once_cell::sync::OnceCell;
 
 use tracing_core::{event::Event, Metadata};
 use tracing_log::{LogTracer, NormalizeEvent};
 use tracing_subscriber::{
     fmt::{
-        format::{FmtSpan, FormatEvent, FormatFields},
+        format::{DefaultFields, FmtSpan, FormatEvent, FormatFields},
         FmtContext,
         FormattedFields,
+        Formatter,
     },
     registry::LookupSpan,
+    reload,
     EnvFilter,
 };
 
@@ -251,6 +254,11 @@ where
     }
 }
 
+/// Handle to change the log level filter once the logger is running.
+static FILTER_HANDLE: OnceCell<
+    reload::Handle<EnvFilter, Formatter<DefaultFields, CustomFormat>>,
+> = OnceCell::new();
+
 /// This function configures the logging format. The loglevel is also processed
 /// here i.e `RUST_LOG=mayastor=TRACE` will print all trace!() and higher
 /// messages to the console.
@@ -273,11 +281,21 @@ pub fn init(level: &str) {
         .with_span_events(FmtSpan::FULL)
         .event_format(format);
 
-    let subscriber = match EnvFilter::try_from_default_env() {
-        Ok(filter) => builder.with_env_filter(filter).finish(),
-        Err(_) => builder.with_env_filter(level).finish(),
-    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(level));
+    let builder = builder.with_env_filter(filter).with_filter_reloading();
+    let _ = FILTER_HANDLE.set(builder.reload_handle());
 
-    tracing::subscriber::set_global_default(subscriber)
+    tracing::subscriber::set_global_default(builder.finish())
         .expect("failed to set default subscriber");
 }
+
+/// Change the log level of the running logger, the level uses the same syntax
+/// as `RUST_LOG` i.e. `info,mayastor=debug`.
+pub fn set_level(level: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(level).map_err(|e| e.to_string())?;
+    match FILTER_HANDLE.get() {
+        Some(handle) => handle.reload(filter).map_err(|e| e.to_string()),
+        None => Err("logger is not initialised".into()),
+    }
+}
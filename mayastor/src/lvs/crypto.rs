@@ -0,0 +1,325 @@
+//! Data-at-rest encryption of lvols by means of the SPDK crypto bdev, which
+//! is layered on top of the lvol and encrypts all data written through it.
+//!
+//! The keys are only ever held in memory. The lvol records the cipher it is
+//! encrypted with as a property, so once a pool has been imported its
+//! encrypted lvols are known, but they remain locked, i.e. they can not be
+//! shared, until they have been opened with their key again. Along with the
+//! cipher, a check value derived from the key with a random salt is stored,
+//! so that opening the lvol with the wrong key fails instead of exposing
+//! garbage and corrupting the data written through it.
+//!
+//! The check value is derived with PBKDF2-HMAC-SHA256 from libcrypto, which
+//! is linked in for SPDK already. It is stored along with the name of the
+//! algorithm and its iteration count, so that both can be changed without
+//! breaking the lvols encrypted before.
+
+use std::{
+    fmt,
+    fs,
+    os::raw::{c_char, c_int, c_uchar},
+    str::FromStr,
+};
+
+use rpc::mayastor::{ReplicaCipher, ReplicaEncryption};
+
+use crate::lvs::Error;
+
+/// length in bytes of the key, and of the second key of AES-XTS
+const KEY_LENGTH: usize = 16;
+
+/// length in bytes of the salt of the key check value
+const SALT_LENGTH: usize = 16;
+
+/// length in bytes of the key check value
+const CHECK_LENGTH: usize = 32;
+
+/// name of the algorithm deriving the key check value
+const KEY_CHECK_ALGORITHM: &str = "pbkdf2-sha256";
+
+/// number of PBKDF2 iterations deriving the key check value from the key,
+/// which makes guessing the key from the check value stored on disk
+/// expensive
+const KEY_CHECK_ITERATIONS: u32 = 600_000;
+
+/// separator of the algorithm, the iteration count, the salt and the check
+/// value in the key check property
+const KEY_CHECK_SEPARATOR: char = ':';
+
+/// opaque libcrypto message digest
+#[repr(C)]
+struct EvpMd {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn EVP_sha256() -> *const EvpMd;
+    fn PKCS5_PBKDF2_HMAC(
+        pass: *const c_char,
+        passlen: c_int,
+        salt: *const c_uchar,
+        saltlen: c_int,
+        iter: c_int,
+        digest: *const EvpMd,
+        keylen: c_int,
+        out: *mut c_uchar,
+    ) -> c_int;
+}
+
+/// cipher an lvol is encrypted with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cipher {
+    /// AES in CBC mode, using the AES-NI multi-buffer driver
+    AesCbc,
+    /// AES in XTS mode, which requires an Intel QAT device
+    AesXts,
+}
+
+impl Cipher {
+    /// name of the DPDK crypto driver implementing the cipher
+    pub(crate) fn driver(&self) -> &'static str {
+        match self {
+            Cipher::AesCbc => "crypto_aesni_mb",
+            Cipher::AesXts => "crypto_qat",
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Cipher::AesCbc => "AES_CBC",
+            Cipher::AesXts => "AES_XTS",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AES_CBC" => Ok(Cipher::AesCbc),
+            "AES_XTS" => Ok(Cipher::AesXts),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<Cipher> for ReplicaCipher {
+    fn from(c: Cipher) -> Self {
+        match c {
+            Cipher::AesCbc => ReplicaCipher::CipherAesCbc,
+            Cipher::AesXts => ReplicaCipher::CipherAesXts,
+        }
+    }
+}
+
+/// cipher and key(s) to encrypt an lvol with
+#[derive(Clone)]
+pub struct EncryptionKey {
+    cipher: Cipher,
+    key: String,
+    key2: Option<String>,
+}
+
+/// the keys must never end up in the logs
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKey")
+            .field("cipher", &self.cipher)
+            .finish()
+    }
+}
+
+impl EncryptionKey {
+    /// create a new key for the lvol with the given name, AES-XTS requires a
+    /// second key whereas AES-CBC does not take one
+    pub fn new(
+        name: &str,
+        cipher: Cipher,
+        key: &str,
+        key2: Option<&str>,
+    ) -> Result<Self, Error> {
+        let invalid = |msg: String| Error::InvalidKey {
+            name: name.to_string(),
+            msg,
+        };
+
+        if key.len() != KEY_LENGTH {
+            return Err(invalid(format!(
+                "the key must be {} bytes long",
+                KEY_LENGTH
+            )));
+        }
+
+        match (cipher, key2) {
+            (Cipher::AesCbc, Some(_)) => {
+                Err(invalid(format!("{} takes a single key", cipher)))
+            }
+            (Cipher::AesXts, None) => {
+                Err(invalid(format!("{} requires a second key", cipher)))
+            }
+            (_, Some(key2)) if key2.len() != KEY_LENGTH => Err(invalid(
+                format!("the second key must be {} bytes long", KEY_LENGTH),
+            )),
+            _ => Ok(Self {
+                cipher,
+                key: key.to_string(),
+                key2: key2.map(String::from),
+            }),
+        }
+    }
+
+    /// parse the encryption of the replica with the given name as received
+    /// over gRPC, returns None when the replica is not to be encrypted
+    pub fn from_rpc(
+        name: &str,
+        spec: &ReplicaEncryption,
+    ) -> Result<Option<Self>, Error> {
+        let cipher = match ReplicaCipher::from_i32(spec.cipher) {
+            Some(ReplicaCipher::CipherNone) => return Ok(None),
+            Some(ReplicaCipher::CipherAesCbc) => Cipher::AesCbc,
+            Some(ReplicaCipher::CipherAesXts) => Cipher::AesXts,
+            None => {
+                return Err(Error::InvalidKey {
+                    name: name.to_string(),
+                    msg: format!("invalid cipher {}", spec.cipher),
+                })
+            }
+        };
+
+        if spec.key_file.is_empty() {
+            let key2 = Some(spec.key2.as_str()).filter(|k| !k.is_empty());
+            return Self::new(name, cipher, &spec.key, key2).map(Some);
+        }
+
+        let keys = fs::read_to_string(&spec.key_file).map_err(|e| {
+            Error::InvalidKey {
+                name: name.to_string(),
+                msg: format!("failed to read {}: {}", spec.key_file, e),
+            }
+        })?;
+        let mut lines = keys.lines();
+        let key = lines.next().unwrap_or_default();
+        let key2 = lines.next().filter(|k| !k.is_empty());
+        Self::new(name, cipher, key, key2).map(Some)
+    }
+
+    /// derive the check value of the key(s) from the given salt with the
+    /// given number of PBKDF2 iterations, returns None if libcrypto fails
+    fn check_value(
+        &self,
+        salt: &[u8],
+        iterations: u32,
+    ) -> Option<[u8; CHECK_LENGTH]> {
+        let mut pass = self.key.clone();
+        pass.push_str(self.key2.as_deref().unwrap_or_default());
+        let mut value = [0u8; CHECK_LENGTH];
+
+        let rc = unsafe {
+            PKCS5_PBKDF2_HMAC(
+                pass.as_ptr() as *const c_char,
+                pass.len() as c_int,
+                salt.as_ptr(),
+                salt.len() as c_int,
+                iterations as c_int,
+                EVP_sha256(),
+                value.len() as c_int,
+                value.as_mut_ptr(),
+            )
+        };
+        if rc == 1 {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// create a key check to store with an lvol encrypted with this key,
+    /// made up of the algorithm, its iteration count, a random salt and the
+    /// check value derived from them, for the lvol with the given name
+    pub(crate) fn key_check(&self, name: &str) -> Result<String, Error> {
+        let salt: [u8; SALT_LENGTH] = rand::random();
+        let value =
+            self.check_value(&salt, KEY_CHECK_ITERATIONS)
+                .ok_or_else(|| Error::InvalidKey {
+                    name: name.to_string(),
+                    msg: "failed to derive the key check value".to_string(),
+                })?;
+        Ok([
+            KEY_CHECK_ALGORITHM.to_string(),
+            KEY_CHECK_ITERATIONS.to_string(),
+            to_hex(&salt),
+            to_hex(&value),
+        ]
+        .join(&KEY_CHECK_SEPARATOR.to_string()))
+    }
+
+    /// returns true if this is the key the key check was created with, a key
+    /// check of an unknown algorithm never matches
+    pub(crate) fn verify(&self, key_check: &str) -> bool {
+        let parts = key_check.split(KEY_CHECK_SEPARATOR).collect::<Vec<_>>();
+        let (iterations, salt, check) = match parts.as_slice() {
+            [algorithm, iterations, salt, check]
+                if *algorithm == KEY_CHECK_ALGORITHM =>
+            {
+                (
+                    iterations.parse::<u32>().ok(),
+                    from_hex(salt),
+                    from_hex(check),
+                )
+            }
+            _ => return false,
+        };
+        match (iterations, salt, check) {
+            (Some(iterations), Some(salt), Some(check))
+                if iterations > 0 && iterations <= c_int::MAX as u32 =>
+            {
+                let value = match self.check_value(&salt, iterations) {
+                    Some(value) => value,
+                    None => return false,
+                };
+                // compare all bytes, so the time taken does not tell how
+                // much of the check value matched
+                check.len() == value.len()
+                    && check
+                        .iter()
+                        .zip(value.iter())
+                        .fold(0, |d, (a, b)| d | (a ^ b))
+                        == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// the cipher to encrypt with
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub(crate) fn key2(&self) -> Option<&str> {
+        self.key2.as_deref()
+    }
+}
+
+/// encode bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// decode hex into bytes, returns None if it is not valid hex
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0 .. hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i .. i + 2)?, 16).ok())
+        .collect()
+}
@@ -38,6 +38,61 @@ pub enum Error {
     #[snafu(display("failed to destroy lvol {}", name))]
     RepDestroy { source: Errno, name: String },
 
+    #[snafu(display(
+        "lvol {} would exceed the overcommit limit of {}% of pool {}",
+        name,
+        percent,
+        pool
+    ))]
+    PoolOvercommit {
+        source: Errno,
+        name: String,
+        pool: String,
+        percent: u32,
+    },
+
+    #[snafu(display(
+        "errno: {} failed to access the properties of pool {}",
+        source,
+        name
+    ))]
+    PoolProperty { source: Errno, name: String },
+
+    #[snafu(display(
+        "pool {} is draining and does not accept new lvol {}",
+        pool,
+        name
+    ))]
+    PoolDraining {
+        source: Errno,
+        name: String,
+        pool: String,
+    },
+
+    #[snafu(display("errno: {} failed to resize lvol {}", source, name))]
+    RepResize { source: Errno, name: String },
+
+    #[snafu(display(
+        "errno: {} failed to revert lvol {} to snapshot {}",
+        source,
+        name,
+        snapshot
+    ))]
+    RepRevert {
+        source: Errno,
+        snapshot: String,
+        name: String,
+    },
+
+    #[snafu(display("errno: {} failed to create snapshot {}", source, name))]
+    SnapCreate { source: Errno, name: String },
+
+    #[snafu(display("errno: {} failed to create clone {}", source, name))]
+    CloneCreate { source: Errno, name: String },
+
+    #[snafu(display("lvol {} is not a snapshot", name))]
+    NotASnapshot { source: Errno, name: String },
+
     #[snafu(display("bdev {} is not a lvol", name))]
     NotALvol { source: Errno, name: String },
 
@@ -47,6 +102,45 @@ pub enum Error {
     #[snafu(display("failed to unshare lvol {}", name))]
     LvolUnShare { source: CoreError, name: String },
 
+    #[snafu(display(
+        "failed to set the QoS limits of lvol {}: {}",
+        name,
+        source
+    ))]
+    LvolQos { source: CoreError, name: String },
+
+    #[snafu(display("invalid encryption key for lvol {}: {}", name, msg))]
+    InvalidKey { name: String, msg: String },
+
+    #[snafu(display(
+        "errno: {} failed to open encrypted lvol {}",
+        source,
+        name
+    ))]
+    CryptoOpen { source: Errno, name: String },
+
+    #[snafu(display(
+        "errno: {} failed to close encrypted lvol {}",
+        source,
+        name
+    ))]
+    CryptoClose { source: Errno, name: String },
+
+    #[snafu(display(
+        "encrypted lvol {} has not been opened with its key",
+        name
+    ))]
+    Locked { name: String },
+
+    #[snafu(display("failed to set the allowed hosts of lvol {}", name))]
+    LvolHosts { source: CoreError, name: String },
+
+    #[snafu(display(
+        "removing all allowed hosts of lvol {} would allow any host",
+        name
+    ))]
+    AllowAnyHost { name: String },
+
     #[snafu(display(
         "failed to get property {} ({}) from {}",
         prop,
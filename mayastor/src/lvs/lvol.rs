@@ -12,21 +12,43 @@ use nix::errno::Errno;
 use pin_utils::core_reexport::fmt::Formatter;
 
 use spdk_sys::{
+    create_crypto_disk,
+    delete_crypto_disk,
+    lvol_get_num_allocated_clusters,
+    lvol_set_snapshot_xattr,
+    spdk_blob_get_parent_snapshot,
     spdk_blob_get_xattr_value,
+    spdk_blob_id,
     spdk_blob_is_read_only,
     spdk_blob_is_snapshot,
     spdk_blob_set_xattr,
     spdk_blob_sync_md,
+    spdk_bs_get_cluster_size,
     spdk_lvol,
+    vbdev_lvol_create_clone,
     vbdev_lvol_create_snapshot,
     vbdev_lvol_destroy,
     vbdev_lvol_get_from_bdev,
+    vbdev_lvol_rename,
+    vbdev_lvol_resize,
     LVS_CLEAR_WITH_UNMAP,
 };
 
 use crate::{
-    bdev::nexus::nexus_bdev::Nexus,
-    core::{Bdev, CoreError, Mthread, Protocol, Share},
+    bdev::{
+        nexus::nexus_bdev::Nexus,
+        qos::{QosBdev, QOS_DRIVER, QOS_SUFFIX},
+        VerboseError,
+    },
+    core::{
+        Bdev,
+        BlockDeviceIoStats,
+        CoreError,
+        Mthread,
+        Protocol,
+        QosLimits,
+        Share,
+    },
     ffihelper::{
         cb_arg,
         errno_result_from_i32,
@@ -35,36 +57,79 @@ use crate::{
         FfiResult,
         IntoCString,
     },
-    lvs::{error::Error, lvs_pool::Lvs},
-    subsys::NvmfReq,
+    lvs::{error::Error, lvs_pool::Lvs, Cipher, EncryptionKey},
+    subsys::{NvmfError, NvmfReq},
+    target::nvmf,
 };
 
+/// SPDK_BLOBID_INVALID, which bindgen can not translate as it is a cast
+const BLOBID_INVALID: spdk_blob_id = spdk_blob_id::MAX;
+
+/// suffix of the name of the crypto bdev on top of an encrypted lvol
+const CRYPTO_SUFFIX: &str = "-crypt";
+
+/// suffix of the name of the clone replacing an lvol which is reverted,
+/// until it takes over the name of the lvol
+const REVERT_SUFFIX: &str = "-revert";
+
+/// suffix the name of an lvol which is reverted is renamed to, until its
+/// replacement has taken over its name and it is destroyed
+const REVERTED_SUFFIX: &str = "-reverted";
+
+/// separator of the host NQNs in the allowed hosts property
+const HOSTS_SEPARATOR: char = '\n';
+
 /// properties we allow for being set on the lvol, this information is stored on
 /// disk
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[non_exhaustive]
 pub enum PropValue {
     Shared(bool),
+    Encrypted(Cipher),
+    KeyCheck(String),
+    AllowedHosts(Vec<String>),
+    Qos(QosLimits),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 #[non_exhaustive]
 pub enum PropName {
     Shared,
+    Encrypted,
+    KeyCheck,
+    AllowedHosts,
+    Qos,
 }
 
-impl From<PropValue> for PropName {
-    fn from(v: PropValue) -> Self {
+impl From<&PropValue> for PropName {
+    fn from(v: &PropValue) -> Self {
         match v {
             PropValue::Shared(_) => Self::Shared,
+            PropValue::Encrypted(_) => Self::Encrypted,
+            PropValue::KeyCheck(_) => Self::KeyCheck,
+            PropValue::AllowedHosts(_) => Self::AllowedHosts,
+            PropValue::Qos(_) => Self::Qos,
         }
     }
 }
 
+/// split the value of the allowed hosts property into the host NQNs
+fn split_hosts(value: &str) -> Vec<String> {
+    value
+        .split(HOSTS_SEPARATOR)
+        .filter(|h| !h.is_empty())
+        .map(String::from)
+        .collect()
+}
+
 impl Display for PropName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = match self {
             PropName::Shared => "shared",
+            PropName::Encrypted => "encryption",
+            PropName::KeyCheck => "key_check",
+            PropName::AllowedHosts => "allowed_hosts",
+            PropName::Qos => "qos",
         };
         write!(f, "{}", name)
     }
@@ -120,17 +185,22 @@ impl Share for Lvol {
         })
     }
 
-    /// share the lvol as a nvmf target
+    /// share the lvol as a nvmf target, which only the allowed hosts of the
+    /// lvol may connect to
     async fn share_nvmf(
         &self,
         cntlid_range: Option<(u16, u16)>,
     ) -> Result<Self::Output, Self::Error> {
-        let share =
-            self.as_bdev().share_nvmf(cntlid_range).await.map_err(|e| {
-                Error::LvolShare {
-                    source: e,
-                    name: self.name(),
-                }
+        let bdev = self.share_bdev().ok_or_else(|| Error::Locked {
+            name: self.name(),
+        })?;
+        let hosts = self.allowed_hosts()?;
+        let share = self
+            .start_share(&bdev, cntlid_range, &hosts)
+            .await
+            .map_err(|e| Error::LvolShare {
+                source: e,
+                name: self.name(),
             })?;
 
         self.set(PropValue::Shared(true)).await?;
@@ -140,9 +210,11 @@ impl Share for Lvol {
 
     /// unshare the nvmf target
     async fn unshare(&self) -> Result<Self::Output, Self::Error> {
+        let bdev = self.share_bdev().ok_or_else(|| Error::Locked {
+            name: self.name(),
+        })?;
         let share =
-            self.as_bdev()
-                .unshare()
+            Self::stop_share(&bdev)
                 .await
                 .map_err(|e| Error::LvolUnShare {
                     source: e,
@@ -154,9 +226,16 @@ impl Share for Lvol {
         Ok(share)
     }
 
-    /// return the protocol this bdev is shared under
+    /// return the protocol this bdev is shared under, a locked lvol can not
+    /// be shared
     fn shared(&self) -> Option<Protocol> {
-        self.as_bdev().shared()
+        match self.share_bdev() {
+            Some(bdev) => match QosBdev::lookup(&bdev) {
+                Some(qos) => qos.bdev().shared(),
+                None => bdev.shared(),
+            },
+            None => Some(Protocol::Off),
+        }
     }
 
     /// returns the share URI this lvol is shared as
@@ -164,7 +243,11 @@ impl Share for Lvol {
     /// uniquely identify a replica as the replica UUID is currently set to its
     /// name, which is *NOT* unique and in MOAC's use case, is the volume UUID
     fn share_uri(&self) -> Option<String> {
-        let uri_no_uuid = self.as_bdev().share_uri();
+        let uri_no_uuid =
+            self.share_bdev().and_then(|b| match QosBdev::lookup(&b) {
+                Some(_) => nvmf::get_uri(&b.name()),
+                None => b.share_uri(),
+            });
         uri_no_uuid.map(|uri| format!("{}?uuid={}", uri, self.uuid()))
     }
 
@@ -197,11 +280,96 @@ impl Lvol {
     pub(crate) fn as_bdev(&self) -> Bdev {
         Bdev::from(unsafe { self.0.as_ref().bdev })
     }
+
+    /// returns the bdev through which the lvol is shared, which for an
+    /// encrypted lvol is the crypto bdev on top of it, or None when the
+    /// encrypted lvol has not been opened with its key
+    pub(crate) fn share_bdev(&self) -> Option<Bdev> {
+        match self.cipher() {
+            None => Some(self.as_bdev()),
+            Some(_) => self.crypto_bdev(),
+        }
+    }
+
+    /// returns the lvol which is exposed through the given bdev, which is
+    /// either the bdev of the lvol itself or the crypto bdev on top of an
+    /// encrypted lvol, or the QoS bdev on top of either of them
+    pub fn from_share_bdev(bdev: Bdev) -> Result<Lvol, Error> {
+        let bdev = bdev
+            .name()
+            .strip_suffix(QOS_SUFFIX)
+            .filter(|_| bdev.driver() == QOS_DRIVER)
+            .and_then(Bdev::lookup_by_name)
+            .unwrap_or(bdev);
+        let base = bdev
+            .name()
+            .strip_suffix(CRYPTO_SUFFIX)
+            .filter(|_| bdev.driver() == "crypto")
+            .and_then(Bdev::lookup_by_name);
+        Lvol::try_from(base.unwrap_or(bdev))
+    }
+
+    /// returns the crypto bdev of an opened encrypted lvol
+    fn crypto_bdev(&self) -> Option<Bdev> {
+        Bdev::lookup_by_name(&format!("{}{}", self.name(), CRYPTO_SUFFIX))
+    }
+
+    /// share the share bdev of the lvol over NVMe-OF under its own NQN, with
+    /// a QoS bdev on top of it as the namespace which enforces the QoS
+    /// limits of the lvol
+    async fn start_share(
+        &self,
+        bdev: &Bdev,
+        cntlid_range: Option<(u16, u16)>,
+        hosts: &[String],
+    ) -> Result<String, CoreError> {
+        if bdev.is_claimed() {
+            return Err(CoreError::ShareNvmf {
+                source: NvmfError::CreateTarget {
+                    msg: "already shared".to_string(),
+                },
+            });
+        }
+
+        let qos = QosBdev::create(bdev, self.qos_limits())?;
+        match bdev.share_nvmf_namespace(&qos, cntlid_range, hosts).await {
+            Ok(share) => Ok(share),
+            Err(e) => {
+                if let Err(e) = QosBdev::destroy(bdev).await {
+                    error!("{}", e.to_string());
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// stop sharing the given share bdev of an lvol and destroy the QoS bdev
+    /// on top of it, which unlike `unshare` does not persist that the lvol
+    /// is no longer shared
+    pub(crate) async fn stop_share(bdev: &Bdev) -> Result<String, CoreError> {
+        if QosBdev::lookup(bdev).is_none() {
+            return bdev.unshare().await;
+        }
+        bdev.unshare_nvmf_namespace().await?;
+        QosBdev::destroy(bdev).await?;
+        Ok(bdev.name())
+    }
+
     /// return the size of the lvol in bytes
     pub fn size(&self) -> u64 {
         self.as_bdev().size_in_bytes()
     }
 
+    /// returns the number of bytes allocated to the lvol on the pool, which
+    /// for thin provisioned lvols can be less than the size of the lvol
+    pub fn allocated(&self) -> u64 {
+        unsafe {
+            let lvol = self.0.as_ptr();
+            spdk_bs_get_cluster_size((*(*lvol).lvol_store).blobstore)
+                * lvol_get_num_allocated_clusters(lvol)
+        }
+    }
+
     /// returns the name of the bdev
     pub fn name(&self) -> String {
         self.as_bdev().name()
@@ -214,11 +382,17 @@ impl Lvol {
 
     /// returns the pool of the lvol
     pub fn pool(&self) -> String {
-        unsafe {
-            Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store))
-                .name()
-                .to_string()
-        }
+        self.lvs().name().to_string()
+    }
+
+    /// returns the lvol store the lvol lives on
+    pub(crate) fn lvs(&self) -> Lvs {
+        unsafe { Lvs(NonNull::new_unchecked(self.0.as_ref().lvol_store)) }
+    }
+
+    /// returns the id of the blob backing the lvol
+    fn blob_id(&self) -> spdk_blob_id {
+        unsafe { self.0.as_ref().blob_id }
     }
 
     // wipe the first MB if unmap is not supported on failure the operation
@@ -263,6 +437,242 @@ impl Lvol {
         Ok(())
     }
 
+    /// returns the QoS limits of the lvol
+    pub fn qos_limits(&self) -> QosLimits {
+        self.get_xattr(PropName::Qos)
+            .ok()
+            .and_then(|limits| serde_json::from_str(&limits).ok())
+            .unwrap_or_default()
+    }
+
+    /// number of IOs which were held back by the QoS limits of the lvol since
+    /// they were last set or the lvol was shared
+    pub fn qos_throttled(&self) -> u64 {
+        self.share_bdev()
+            .and_then(|bdev| QosBdev::lookup(&bdev).map(|qos| qos.throttled()))
+            .unwrap_or_default()
+    }
+
+    /// IO stats of the lvol. While the lvol is shared, the latencies are
+    /// those of the IO submitted over NVMe-OF, with histograms, otherwise
+    /// they are the totals kept by SPDK.
+    pub async fn stats(&self) -> Result<BlockDeviceIoStats, CoreError> {
+        let latency = self
+            .share_bdev()
+            .and_then(|bdev| QosBdev::lookup(&bdev).map(|qos| qos.latency()));
+        let stats = self.as_bdev().stats().await?;
+        Ok(match latency {
+            Some(latency) => {
+                stats.with_latency(latency.await.unwrap_or_default())
+            }
+            None => stats,
+        })
+    }
+
+    /// set the QoS limits of the lvol, which apply to all IO submitted to it
+    /// over NVMe-OF. Setting all limits to 0 removes them. The limits are
+    /// stored on disk, so they apply again when the lvol is shared after the
+    /// pool has been imported.
+    pub async fn set_qos_limits(&self, limits: QosLimits) -> Result<(), Error> {
+        if self.qos_limits() == limits {
+            return Ok(());
+        }
+        self.set(PropValue::Qos(limits)).await?;
+        if let Some(qos) = self.share_bdev().as_ref().and_then(QosBdev::lookup)
+        {
+            qos.set_limits(limits);
+        }
+        info!("{}: QoS limits set to {:?}", self, limits);
+        Ok(())
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the lvol when it
+    /// is shared, an empty list means any host may connect
+    pub fn allowed_hosts(&self) -> Result<Vec<String>, Error> {
+        match self.get_xattr(PropName::AllowedHosts) {
+            Ok(hosts) => Ok(split_hosts(&hosts)),
+            Err(Error::GetProperty {
+                source: Errno::ENOENT,
+                ..
+            }) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// only allow the hosts with the given NQNs to connect to the lvol. The
+    /// list is stored on disk so that it is applied again when the lvol is
+    /// shared after an import. An empty list is rejected when hosts are
+    /// allowed explicitly, as any host could connect then.
+    pub async fn set_allowed_hosts(
+        &self,
+        hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        if self.allowed_hosts()? == hosts {
+            return Ok(());
+        }
+        if hosts.is_empty() {
+            return Err(Error::AllowAnyHost {
+                name: self.name(),
+            });
+        }
+        if let Some(bdev) = self.share_bdev() {
+            bdev.set_allowed_hosts(&hosts)
+                .map_err(|e| Error::LvolHosts {
+                    source: e,
+                    name: self.name(),
+                })?;
+        }
+        self.set(PropValue::AllowedHosts(hosts)).await
+    }
+
+    /// allow any host to connect to the lvol again, forgetting the hosts
+    /// which were allowed explicitly
+    pub async fn allow_any_host(&self) -> Result<(), Error> {
+        if self.allowed_hosts()?.is_empty() {
+            return Ok(());
+        }
+        if let Some(bdev) = self.share_bdev() {
+            bdev.allow_any_host().map_err(|e| Error::LvolHosts {
+                source: e,
+                name: self.name(),
+            })?;
+        }
+        self.set(PropValue::AllowedHosts(Vec::new())).await
+    }
+
+    /// returns the cipher the lvol is encrypted with, if any
+    pub fn cipher(&self) -> Option<Cipher> {
+        self.get_xattr(PropName::Encrypted)
+            .ok()
+            .and_then(|c| c.parse().ok())
+    }
+
+    /// returns true if the lvol is encrypted but has not been opened with
+    /// its key yet
+    pub fn is_locked(&self) -> bool {
+        self.cipher().is_some() && self.crypto_bdev().is_none()
+    }
+
+    /// encrypt a newly created lvol with the given key, the cipher and a
+    /// check value of the key are stored on disk but the key is not
+    pub async fn encrypt(&self, key: &EncryptionKey) -> Result<(), Error> {
+        if self.cipher().is_none() {
+            let check = key.key_check(&self.name())?;
+            self.set(PropValue::KeyCheck(check)).await?;
+            self.set(PropValue::Encrypted(key.cipher())).await?;
+        }
+        self.open_encrypted(key).await
+    }
+
+    /// open an encrypted lvol with its key by creating the crypto bdev on top
+    /// of it, through which all IO to the lvol must go
+    pub async fn open_encrypted(
+        &self,
+        key: &EncryptionKey,
+    ) -> Result<(), Error> {
+        match self.cipher() {
+            Some(cipher) if cipher == key.cipher() => {}
+            Some(cipher) => {
+                return Err(Error::InvalidKey {
+                    name: self.name(),
+                    msg: format!("the lvol is encrypted with {}", cipher),
+                })
+            }
+            None => {
+                return Err(Error::InvalidKey {
+                    name: self.name(),
+                    msg: "the lvol is not encrypted".to_string(),
+                })
+            }
+        }
+
+        match self.get_xattr(PropName::KeyCheck) {
+            Ok(check) if !key.verify(&check) => {
+                return Err(Error::InvalidKey {
+                    name: self.name(),
+                    msg: "the key does not match".to_string(),
+                })
+            }
+            Ok(_) => {}
+            // lvols encrypted before the key check was introduced get one
+            // the first time they are opened
+            Err(_) => {
+                let check = key.key_check(&self.name())?;
+                self.set(PropValue::KeyCheck(check)).await?
+            }
+        }
+
+        if self.crypto_bdev().is_some() {
+            return Ok(());
+        }
+
+        let base = self.name().into_cstring();
+        let crypto = format!("{}{}", self.name(), CRYPTO_SUFFIX).into_cstring();
+        let driver = key.cipher().driver().into_cstring();
+        let cipher = key.cipher().to_string().into_cstring();
+        let key1 = key.key().into_cstring();
+        let key2 = key.key2().map(|k| k.into_cstring());
+
+        unsafe {
+            create_crypto_disk(
+                base.as_ptr(),
+                crypto.as_ptr(),
+                driver.as_ptr(),
+                key1.as_ptr(),
+                cipher.as_ptr(),
+                key2.as_ref().map_or(std::ptr::null(), |k| k.as_ptr()),
+            )
+        }
+        .to_result(|e| Error::CryptoOpen {
+            source: Errno::from_i32(e.abs()),
+            name: self.name(),
+        })?;
+
+        info!("opened encrypted lvol {}", self);
+        Ok(())
+    }
+
+    /// close an encrypted lvol by destroying the crypto bdev on top of it,
+    /// after which the lvol is locked again
+    pub(crate) async fn close_encrypted(&self) -> Result<(), Error> {
+        extern "C" fn close_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        let bdev = match self.crypto_bdev() {
+            Some(bdev) => bdev,
+            None => return Ok(()),
+        };
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            delete_crypto_disk(bdev.as_ptr(), Some(close_cb), cb_arg(s));
+        }
+
+        r.await
+            .expect("crypto bdev delete callback is gone")
+            .to_result(|e| Error::CryptoClose {
+                source: Errno::from_i32(e),
+                name: self.name(),
+            })?;
+
+        info!("closed encrypted lvol {}", self);
+        Ok(())
+    }
+
+    /// mark a snapshot or clone as encrypted the same way as the lvol it was
+    /// created from, as its data is encrypted with the same key
+    async fn inherit_encryption(&self, origin: &Lvol) -> Result<(), Error> {
+        for prop in &[PropName::KeyCheck, PropName::Encrypted] {
+            if let Ok(value) = origin.get_xattr(*prop) {
+                self.set_xattr(*prop, &value).await?;
+            }
+        }
+        Ok(())
+    }
+
     /// returns a boolean indicating if the lvol is thin provisioned
     pub fn is_thin(&self) -> bool {
         unsafe { self.0.as_ref().thin_provision }
@@ -288,7 +698,11 @@ impl Lvol {
 
         // we must always unshare before destroying bdev
         let _ = self.unshare().await;
-        self.wipe_super().await?;
+        self.close_encrypted().await?;
+        // the data of a snapshot is still referenced by its clones
+        if !self.is_snapshot() {
+            self.wipe_super().await?;
+        }
 
         let name = self.name();
 
@@ -308,6 +722,278 @@ impl Lvol {
         Ok(name)
     }
 
+    /// grow the lvol to the given size in bytes, consumers of the lvol bdev
+    /// are notified by means of a resize event
+    pub async fn resize(&self, size: u64) -> Result<(), Error> {
+        extern "C" fn resize_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        if size < self.size() {
+            return Err(Error::RepResize {
+                source: Errno::EINVAL,
+                name: self.name(),
+            });
+        }
+
+        self.lvs()
+            .check_overcommit(&self.name(), size - self.size())?;
+
+        let (s, r) = pair::<i32>();
+        unsafe {
+            vbdev_lvol_resize(self.0.as_ptr(), size, Some(resize_cb), cb_arg(s))
+        };
+
+        r.await
+            .expect("lvol resize callback is gone")
+            .to_result(|e| Error::RepResize {
+                source: Errno::from_i32(e),
+                name: self.name(),
+            })?;
+
+        info!("Resized {} to {} bytes", self.name(), self.size());
+        Ok(())
+    }
+
+    /// returns the snapshot this lvol depends on, i.e. the most recent
+    /// snapshot taken of it or the snapshot it was cloned from
+    pub fn parent_snapshot(&self) -> Option<Lvol> {
+        let parent = unsafe {
+            spdk_blob_get_parent_snapshot(
+                (*self.0.as_ref().lvol_store).blobstore,
+                self.blob_id(),
+            )
+        };
+
+        if parent == BLOBID_INVALID {
+            return None;
+        }
+
+        self.lvs().lvols()?.find(|l| l.blob_id() == parent)
+    }
+
+    /// returns the chain of snapshots this lvol depends on, newest first
+    pub fn snapshots(&self) -> Vec<Lvol> {
+        let mut snapshots = Vec::new();
+        let mut parent = self.parent_snapshot();
+        while let Some(snapshot) = parent {
+            parent = snapshot.parent_snapshot();
+            snapshots.push(snapshot);
+        }
+        snapshots
+    }
+
+    /// destroy the snapshot of this lvol with the given name, a snapshot
+    /// which does not exist is not an error
+    pub async fn destroy_snapshot(
+        &self,
+        snapshot_name: &str,
+    ) -> Result<(), Error> {
+        match self
+            .snapshots()
+            .into_iter()
+            .find(|s| s.name() == snapshot_name)
+        {
+            Some(snapshot) => snapshot.destroy().await.map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// create a writable, thin provisioned clone of this snapshot
+    pub async fn create_clone(&self, clone_name: &str) -> Result<Lvol, Error> {
+        if !self.is_snapshot() {
+            return Err(Error::NotASnapshot {
+                source: Errno::EINVAL,
+                name: self.name(),
+            });
+        }
+
+        if Bdev::lookup_by_name(clone_name).is_some() {
+            return Err(Error::RepExists {
+                source: Errno::EEXIST,
+                name: clone_name.to_string(),
+            });
+        }
+
+        self.lvs().check_draining(clone_name)?;
+        self.lvs().check_overcommit(clone_name, self.size())?;
+
+        self.clone_snapshot(clone_name).await
+    }
+
+    /// create a clone of this snapshot without checking whether the pool
+    /// accepts a new lvol
+    async fn clone_snapshot(&self, clone_name: &str) -> Result<Lvol, Error> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
+        let cname = clone_name.into_cstring();
+        unsafe {
+            vbdev_lvol_create_clone(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                Some(Lvol::lvol_cb),
+                cb_arg(s),
+            )
+        };
+
+        let clone = r
+            .await
+            .expect("lvol clone callback dropped")
+            .map_err(|e| Error::CloneCreate {
+                source: e,
+                name: clone_name.to_string(),
+            })
+            .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
+
+        if let Err(e) = clone.inherit_encryption(self).await {
+            let _ = clone.destroy().await;
+            return Err(e);
+        }
+
+        info!("created clone {} of snapshot {}", clone, self);
+        Ok(clone)
+    }
+
+    /// rename the lvol, the bdev of the lvol is renamed along with it
+    async fn rename(&self, name: &str) -> Result<(), Errno> {
+        extern "C" fn rename_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).unwrap();
+        }
+
+        let cname = name.into_cstring();
+        let (s, r) = pair::<i32>();
+        unsafe {
+            vbdev_lvol_rename(
+                self.0.as_ptr(),
+                cname.as_ptr(),
+                Some(rename_cb),
+                cb_arg(s),
+            )
+        };
+
+        r.await
+            .expect("lvol rename callback is gone")
+            .to_result(Errno::from_i32)
+    }
+
+    /// revert the lvol to one of its snapshots, discarding all writes made
+    /// since the snapshot was taken. The lvol is replaced by a clone of the
+    /// snapshot with the same name, allowed hosts and QoS limits, which is
+    /// shared again if the original was. The clone is created under a
+    /// temporary name, and the lvol is moved out of its way and only
+    /// destroyed once the clone has taken over its name, so that the lvol is
+    /// kept when any of that fails. Note that the UUID of the lvol changes as
+    /// a result.
+    pub async fn revert(self, snapshot: &Lvol) -> Result<Lvol, Error> {
+        // the clone is encrypted as well, but the key to open it is not at
+        // hand
+        if self.cipher().is_some() {
+            return Err(Error::RepRevert {
+                source: Errno::EOPNOTSUPP,
+                snapshot: snapshot.name(),
+                name: self.name(),
+            });
+        }
+
+        if !self
+            .snapshots()
+            .iter()
+            .any(|s| s.blob_id() == snapshot.blob_id())
+        {
+            return Err(Error::RepRevert {
+                source: Errno::EINVAL,
+                snapshot: snapshot.name(),
+                name: self.name(),
+            });
+        }
+
+        // when shared, the claim is held by the nvmf target, anything else
+        // (i.e. a local nexus) is still using the lvol
+        let shared = self.shared();
+        if self.as_bdev().is_claimed() && shared != Some(Protocol::Nvmf) {
+            return Err(Error::RepRevert {
+                source: Errno::EBUSY,
+                snapshot: snapshot.name(),
+                name: self.name(),
+            });
+        }
+
+        let name = self.name();
+        let hosts = self.allowed_hosts()?;
+        let qos = self.qos_limits();
+
+        // the clone replaces the lvol, so it is created on a draining pool as
+        // well and does not count towards the overcommit limit
+        let lvol = snapshot
+            .clone_snapshot(&format!("{}{}", name, REVERT_SUFFIX))
+            .await?;
+        let mut result = lvol.set_allowed_hosts(hosts).await;
+        if result.is_ok() && qos != QosLimits::default() {
+            result = lvol.set_qos_limits(qos).await;
+        }
+        if let Err(e) = result {
+            let _ = lvol.destroy().await;
+            return Err(e);
+        }
+
+        let revert_error = |source| Error::RepRevert {
+            source,
+            snapshot: snapshot.name(),
+            name: name.clone(),
+        };
+
+        // the nvmf subsystem is named after the lvol, so it is unshared
+        // before it is moved out of the way of its replacement
+        if shared == Some(Protocol::Nvmf) {
+            if let Err(e) = self.unshare().await {
+                let _ = lvol.destroy().await;
+                return Err(e);
+            }
+        }
+
+        let result =
+            match self.rename(&format!("{}{}", name, REVERTED_SUFFIX)).await {
+                Ok(()) => lvol.rename(&name).await.map_err(|e| (e, true)),
+                Err(e) => Err((e, false)),
+            };
+        if let Err((e, moved)) = result {
+            if moved {
+                if let Err(e) = self.rename(&name).await {
+                    error!("failed to restore the name of {}: {}", self, e);
+                }
+            }
+            if shared == Some(Protocol::Nvmf) {
+                if let Err(e) = self.share_nvmf(None).await {
+                    error!("failed to share {} again: {}", self, e.verbose());
+                }
+            }
+            let _ = lvol.destroy().await;
+            return Err(revert_error(e));
+        }
+
+        // the lvol is only destroyed once its replacement is complete, should
+        // that fail the revert has taken place regardless and the lvol is
+        // left behind under its temporary name
+        let original = self.name();
+        if let Err(e) = self.destroy().await {
+            error!(
+                "failed to destroy {} after reverting it: {}",
+                original,
+                e.verbose()
+            );
+        }
+
+        if shared == Some(Protocol::Nvmf) {
+            lvol.share_nvmf(None).await?;
+        }
+
+        info!("reverted {} to snapshot {}", lvol, snapshot.name());
+        Ok(lvol)
+    }
+
     /// callback executed after synchronizing the lvols metadata
     extern "C" fn blob_sync_cb(sender_ptr: *mut c_void, errno: i32) {
         let sender =
@@ -317,9 +1003,6 @@ impl Lvol {
 
     /// write the property prop on to the lvol which is stored on disk
     pub async fn set(&self, prop: PropValue) -> Result<(), Error> {
-        let blob = unsafe { self.0.as_ref().blob };
-        assert!(!blob.is_null());
-
         if self.is_snapshot() {
             warn!("ignoring set property on snapshot {}", self.name());
             return Ok(());
@@ -327,27 +1010,70 @@ impl Lvol {
         if self.is_read_only() {
             warn!("{} is read-only", self.name());
         }
-        match prop {
+        let value = match &prop {
             PropValue::Shared(val) => {
-                let name = PropName::from(prop).to_string().into_cstring();
-                let value = if val { "true" } else { "false" }.into_cstring();
-                unsafe {
-                    spdk_blob_set_xattr(
-                        blob,
-                        name.as_ptr(),
-                        value.as_bytes_with_nul().as_ptr() as *const _,
-                        value.as_bytes_with_nul().len() as u16,
-                    )
-                }
-                .to_result(|e| Error::SetProperty {
-                    source: Errno::from_i32(e),
-                    prop: prop.into(),
-                    name: self.name(),
-                })?;
+                if *val { "true" } else { "false" }.to_string()
+            }
+            PropValue::Encrypted(cipher) => cipher.to_string(),
+            PropValue::KeyCheck(check) => check.clone(),
+            PropValue::AllowedHosts(hosts) => {
+                hosts.join(&HOSTS_SEPARATOR.to_string())
             }
+            PropValue::Qos(limits) => serde_json::to_string(limits).unwrap(),
         };
 
+        self.set_xattr(PropName::from(&prop), &value).await
+    }
+
+    /// write the raw value of a property to the blob of this lvol, which
+    /// unlike `set` also updates the (read-only) metadata of snapshots
+    async fn set_xattr(
+        &self,
+        prop: PropName,
+        value: &str,
+    ) -> Result<(), Error> {
+        let blob = unsafe { self.0.as_ref().blob };
+        assert!(!blob.is_null());
+
+        let value = value.into_cstring();
+        let name = prop.to_string().into_cstring();
         let (s, r) = pair::<i32>();
+
+        if self.is_snapshot() {
+            unsafe {
+                lvol_set_snapshot_xattr(
+                    self.0.as_ptr(),
+                    name.as_ptr(),
+                    value.as_bytes_with_nul().as_ptr() as *const _,
+                    value.as_bytes_with_nul().len() as u16,
+                    Some(Self::blob_sync_cb),
+                    cb_arg(s),
+                )
+            };
+
+            return r.await.expect("sync callback is gone").to_result(|e| {
+                Error::SetProperty {
+                    source: Errno::from_i32(e),
+                    prop,
+                    name: self.name(),
+                }
+            });
+        }
+
+        unsafe {
+            spdk_blob_set_xattr(
+                blob,
+                name.as_ptr(),
+                value.as_bytes_with_nul().as_ptr() as *const _,
+                value.as_bytes_with_nul().len() as u16,
+            )
+        }
+        .to_result(|e| Error::SetProperty {
+            source: Errno::from_i32(e),
+            prop,
+            name: self.name(),
+        })?;
+
         unsafe {
             spdk_blob_sync_md(blob, Some(Self::blob_sync_cb), cb_arg(s));
         };
@@ -364,37 +1090,60 @@ impl Lvol {
 
     /// get/read a property from this lvol from disk
     pub async fn get(&self, prop: PropName) -> Result<PropValue, Error> {
-        let blob = unsafe { self.0.as_ref().blob };
-        assert!(!blob.is_null());
+        let value = self.get_xattr(prop)?;
+        let invalid = || Error::Property {
+            source: Errno::EINVAL,
+            name: self.name(),
+        };
 
         match prop {
-            PropName::Shared => {
-                let name = prop.to_string().into_cstring();
-                let mut value: *const libc::c_char =
-                    std::ptr::null::<libc::c_char>();
-                let mut value_len: u64 = 0;
-                unsafe {
-                    spdk_blob_get_xattr_value(
-                        blob,
-                        name.as_ptr(),
-                        &mut value as *mut *const c_char as *mut *const c_void,
-                        &mut value_len,
-                    )
-                }
-                .to_result(|e| Error::GetProperty {
-                    source: Errno::from_i32(e),
-                    prop,
-                    name: self.name(),
-                })?;
-                match unsafe { CStr::from_ptr(value).to_str() } {
-                    Ok("true") => Ok(PropValue::Shared(true)),
-                    Ok("false") => Ok(PropValue::Shared(false)),
-                    _ => Err(Error::Property {
-                        source: Errno::EINVAL,
-                        name: self.name(),
-                    }),
-                }
+            PropName::Shared => match value.as_str() {
+                "true" => Ok(PropValue::Shared(true)),
+                "false" => Ok(PropValue::Shared(false)),
+                _ => Err(invalid()),
+            },
+            PropName::Encrypted => value
+                .parse()
+                .map(PropValue::Encrypted)
+                .map_err(|_| invalid()),
+            PropName::KeyCheck => Ok(PropValue::KeyCheck(value)),
+            PropName::AllowedHosts => {
+                Ok(PropValue::AllowedHosts(split_hosts(&value)))
             }
+            PropName::Qos => serde_json::from_str(&value)
+                .map(PropValue::Qos)
+                .map_err(|_| invalid()),
+        }
+    }
+
+    /// read the raw value of a property from the blob of this lvol
+    fn get_xattr(&self, prop: PropName) -> Result<String, Error> {
+        let blob = unsafe { self.0.as_ref().blob };
+        assert!(!blob.is_null());
+
+        let name = prop.to_string().into_cstring();
+        let mut value: *const libc::c_char = std::ptr::null::<libc::c_char>();
+        let mut value_len: u64 = 0;
+        unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                name.as_ptr(),
+                &mut value as *mut *const c_char as *mut *const c_void,
+                &mut value_len,
+            )
+        }
+        .to_result(|e| Error::GetProperty {
+            source: Errno::from_i32(e),
+            prop,
+            name: self.name(),
+        })?;
+
+        match unsafe { CStr::from_ptr(value).to_str() } {
+            Ok(value) => Ok(value.to_string()),
+            Err(_) => Err(Error::Property {
+                source: Errno::EINVAL,
+                name: self.name(),
+            }),
         }
     }
 
@@ -404,80 +1153,76 @@ impl Lvol {
         format!("{}-snap-{}", base_name, snapshot_time)
     }
 
-    /// Create a snapshot
+    /// Create a snapshot and complete the NVMf request once it has been
+    /// created
     pub async fn create_snapshot(
         &self,
         nvmf_req: &NvmfReq,
         snapshot_name: &str,
     ) {
-        extern "C" fn snapshot_done_cb(
-            nvmf_req_ptr: *mut c_void,
-            _lvol_ptr: *mut spdk_lvol,
-            errno: i32,
-        ) {
-            let nvmf_req = NvmfReq::from(nvmf_req_ptr);
-            let mut rsp = nvmf_req.response();
-            let nvme_status = rsp.status();
-
-            nvme_status.set_sct(0); // SPDK_NVME_SCT_GENERIC
-            nvme_status.set_sc(match errno {
-                0 => 0,
-                _ => {
-                    error!("vbdev_lvol_create_snapshot errno {}", errno);
-                    0x06 // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
-                }
-            });
+        info!("Creating snapshot {} on {}", snapshot_name, &self);
+        let success = self
+            .snapshot(snapshot_name)
+            .await
+            .map_err(|e| error!("{}", e))
+            .is_ok();
+        nvmf_req.complete(success);
+    }
 
-            // From nvmf_bdev_ctrlr_complete_cmd
-            unsafe {
-                spdk_sys::spdk_nvmf_request_complete(nvmf_req.0.as_ptr());
-            }
+    /// Create a snapshot and wait for it to be created, must be called
+    /// from the master core
+    pub async fn snapshot(&self, snapshot_name: &str) -> Result<Lvol, Error> {
+        if Bdev::lookup_by_name(snapshot_name).is_some() {
+            return Err(Error::RepExists {
+                source: Errno::EEXIST,
+                name: snapshot_name.to_string(),
+            });
         }
 
+        let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
         let c_snapshot_name = snapshot_name.into_cstring();
         unsafe {
             vbdev_lvol_create_snapshot(
                 self.0.as_ptr(),
                 c_snapshot_name.as_ptr(),
-                Some(snapshot_done_cb),
-                nvmf_req.0.as_ptr().cast(),
+                Some(Lvol::lvol_cb),
+                cb_arg(s),
             )
         };
 
-        info!("Creating snapshot {} on {}", snapshot_name, &self);
+        let snapshot = r
+            .await
+            .expect("lvol snapshot callback dropped")
+            .map_err(|e| Error::SnapCreate {
+                source: e,
+                name: snapshot_name.to_string(),
+            })
+            .map(|lvol| Lvol(NonNull::new(lvol).unwrap()))?;
+
+        if let Err(e) = snapshot.inherit_encryption(self).await {
+            let _ = snapshot.destroy().await;
+            return Err(e);
+        }
+
+        info!("Created snapshot {} of {}", snapshot_name, self);
+        Ok(snapshot)
     }
 
-    /// Create snapshot for local replica
+    /// Create snapshot for local replica, completing the IO once it has
+    /// been created
     pub async fn create_snapshot_local(
         &self,
         io: *mut spdk_sys::spdk_bdev_io,
         snapshot_name: &str,
     ) {
-        extern "C" fn snapshot_done_cb(
-            bio_ptr: *mut c_void,
-            _lvol_ptr: *mut spdk_lvol,
-            errno: i32,
-        ) {
-            if errno != 0 {
-                error!("vbdev_lvol_create_snapshot errno {}", errno);
-            }
-            // Must complete IO on thread IO was submitted from
-            Mthread::from(unsafe {
-                spdk_sys::spdk_bdev_io_get_thread(bio_ptr.cast())
-            })
-            .with(|| Nexus::io_completion_local(errno == 0, bio_ptr));
-        }
-
-        let c_snapshot_name = snapshot_name.into_cstring();
-        unsafe {
-            vbdev_lvol_create_snapshot(
-                self.0.as_ptr(),
-                c_snapshot_name.as_ptr(),
-                Some(snapshot_done_cb),
-                io.cast(),
-            )
-        };
-
         info!("Creating snapshot {} on {}", snapshot_name, &self);
+        let success = self
+            .snapshot(snapshot_name)
+            .await
+            .map_err(|e| error!("{}", e))
+            .is_ok();
+        // Must complete IO on thread IO was submitted from
+        Mthread::from(unsafe { spdk_sys::spdk_bdev_io_get_thread(io) })
+            .with(|| Nexus::io_completion_local(success, io.cast()));
     }
 }
@@ -1,14 +1,29 @@
-use std::{convert::TryFrom, fmt::Debug, os::raw::c_void, ptr::NonNull};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    ffi::CStr,
+    fmt::Debug,
+    os::raw::{c_char, c_void},
+    ptr::NonNull,
+};
 
 use futures::channel::oneshot;
 use nix::errno::Errno;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use pin_utils::core_reexport::fmt::Formatter;
 
-use rpc::mayastor::CreatePoolRequest;
+use rpc::mayastor::{CreatePoolRequest, ReplicaKey};
 use spdk_sys::{
     lvol_store_bdev,
+    spdk_blob,
+    spdk_blob_close,
+    spdk_blob_get_xattr_value,
+    spdk_blob_set_xattr,
+    spdk_blob_sync_md,
     spdk_bs_free_cluster_count,
     spdk_bs_get_cluster_size,
+    spdk_bs_open_blob,
     spdk_bs_total_data_cluster_count,
     spdk_lvol,
     spdk_lvol_store,
@@ -32,10 +47,29 @@ use crate::{
     bdev::Uri,
     core::{Bdev, IoType, Share, Uuid},
     ffihelper::{cb_arg, pair, AsStr, ErrnoResult, FfiResult, IntoCString},
-    lvs::{Error, Lvol, PropName, PropValue},
+    lvs::{EncryptionKey, Error, Lvol, PropName, PropValue},
     nexus_uri::{bdev_destroy, NexusBdevError},
 };
 
+/// name of the xattr of the super blob of a pool which holds its overcommit
+/// limit
+const OVERCOMMIT_XATTR: &str = "overcommit";
+
+/// name of the xattr of the super blob of a pool which tells whether the pool
+/// is being drained
+const DRAINING_XATTR: &str = "draining";
+
+/// overcommit limits of the pools keyed by pool name, as a percentage of the
+/// capacity of the pool that may be handed out to replicas, as read from the
+/// pools when they are imported
+static POOL_OVERCOMMIT: Lazy<Mutex<HashMap<String, u32>>> =
+    Lazy::new(Default::default);
+
+/// names of the pools that are being drained and no longer accept new lvols,
+/// as read from the pools when they are imported
+static POOL_DRAINING: Lazy<Mutex<HashSet<String>>> =
+    Lazy::new(Default::default);
+
 impl From<*mut spdk_lvol_store> for Lvs {
     fn from(p: *mut spdk_lvol_store) -> Self {
         Lvs(NonNull::new(p).unwrap())
@@ -174,6 +208,279 @@ impl Lvs {
         self.capacity() - self.available()
     }
 
+    /// returns the sum of the sizes of all lvols on the store, which for thin
+    /// provisioned lvols can exceed the capacity of the store. Snapshots are
+    /// not included as they share their clusters with the lvols.
+    pub fn committed(&self) -> u64 {
+        self.lvols()
+            .map(|lvols| {
+                lvols.filter(|l| !l.is_snapshot()).map(|l| l.size()).sum()
+            })
+            .unwrap_or_default()
+    }
+
+    /// returns the overcommit limit of the store as a percentage of its
+    /// capacity, 0 means that there is no limit
+    pub fn overcommit(&self) -> u32 {
+        POOL_OVERCOMMIT
+            .lock()
+            .get(self.name())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// set the overcommit limit of the store as a percentage of its capacity,
+    /// 0 removes the limit. The limit is kept in the super blob of the store
+    /// so that it is still in place after the store has been imported again.
+    pub async fn set_overcommit(&self, percent: u32) -> Result<(), Error> {
+        self.set_super_xattr(OVERCOMMIT_XATTR, &percent.to_string())
+            .await?;
+        self.cache_overcommit(percent);
+        Ok(())
+    }
+
+    /// read the overcommit limit of the store from its super blob
+    async fn load_overcommit(&self) -> Result<(), Error> {
+        // stores which never had a limit set have no such xattr at all
+        let percent = match self.get_super_xattr(OVERCOMMIT_XATTR).await? {
+            Some(value) => value.parse().ok(),
+            None => Some(0),
+        };
+
+        match percent {
+            Some(percent) => {
+                self.cache_overcommit(percent);
+                Ok(())
+            }
+            None => Err(Error::PoolProperty {
+                source: Errno::EINVAL,
+                name: self.name().to_string(),
+            }),
+        }
+    }
+
+    /// keep the overcommit limit of the store at hand for the checks made
+    /// whenever an lvol is created or grows
+    fn cache_overcommit(&self, percent: u32) {
+        let mut limits = POOL_OVERCOMMIT.lock();
+        if percent == 0 {
+            limits.remove(self.name());
+        } else {
+            limits.insert(self.name().to_string(), percent);
+        }
+    }
+
+    /// set an xattr of the super blob of the store, which holds the
+    /// properties of the store itself
+    async fn set_super_xattr(
+        &self,
+        name: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        let blob = self.open_super_blob().await?;
+        let name = name.into_cstring();
+        let value = value.into_cstring();
+        let (s, r) = pair::<i32>();
+
+        let errno = unsafe {
+            spdk_blob_set_xattr(
+                blob,
+                name.as_ptr(),
+                value.as_bytes_with_nul().as_ptr() as *const _,
+                value.as_bytes_with_nul().len() as u16,
+            )
+        };
+        let errno = if errno == 0 {
+            unsafe {
+                spdk_blob_sync_md(blob, Some(Self::lvs_op_cb), cb_arg(s));
+            }
+            r.await.expect("callback gone while syncing the super blob")
+        } else {
+            errno
+        };
+
+        self.close_super_blob(blob).await?;
+        errno.to_result(|e| Error::PoolProperty {
+            source: Errno::from_i32(e.abs()),
+            name: self.name().to_string(),
+        })
+    }
+
+    /// get an xattr of the super blob of the store, returns None if the
+    /// store does not have it
+    async fn get_super_xattr(
+        &self,
+        name: &str,
+    ) -> Result<Option<String>, Error> {
+        let blob = self.open_super_blob().await?;
+        let name = name.into_cstring();
+        let mut value: *const c_void = std::ptr::null();
+        let mut value_len: u64 = 0;
+
+        let errno = unsafe {
+            spdk_blob_get_xattr_value(
+                blob,
+                name.as_ptr(),
+                &mut value,
+                &mut value_len,
+            )
+        };
+        let value = if errno == 0 {
+            unsafe { CStr::from_ptr(value as *const c_char) }
+                .to_str()
+                .map(String::from)
+                .map_err(|_| Errno::EINVAL)
+                .map(Some)
+        } else {
+            Ok(None)
+        };
+
+        self.close_super_blob(blob).await?;
+        value.map_err(|source| Error::PoolProperty {
+            source,
+            name: self.name().to_string(),
+        })
+    }
+
+    /// callback when the super blob of the store has been opened
+    extern "C" fn super_blob_cb(
+        sender_ptr: *mut c_void,
+        blob: *mut spdk_blob,
+        errno: i32,
+    ) {
+        let sender = unsafe {
+            Box::from_raw(
+                sender_ptr as *mut oneshot::Sender<ErrnoResult<*mut spdk_blob>>,
+            )
+        };
+
+        if errno == 0 {
+            sender.send(Ok(blob)).expect("receiver gone");
+        } else {
+            sender
+                .send(Err(Errno::from_i32(errno.abs())))
+                .expect("receiver gone");
+        }
+    }
+
+    /// open the super blob of the store, which holds the properties of the
+    /// store itself
+    async fn open_super_blob(&self) -> Result<*mut spdk_blob, Error> {
+        let (s, r) = pair::<ErrnoResult<*mut spdk_blob>>();
+        unsafe {
+            let lvs = self.0.as_ref();
+            spdk_bs_open_blob(
+                lvs.blobstore,
+                lvs.super_blob_id,
+                Some(Self::super_blob_cb),
+                cb_arg(s),
+            );
+        }
+
+        r.await
+            .expect("callback gone while opening the super blob")
+            .map_err(|source| Error::PoolProperty {
+                source,
+                name: self.name().to_string(),
+            })
+    }
+
+    /// close the super blob of the store again
+    async fn close_super_blob(
+        &self,
+        blob: *mut spdk_blob,
+    ) -> Result<(), Error> {
+        let (s, r) = pair::<i32>();
+        unsafe {
+            spdk_blob_close(blob, Some(Self::lvs_op_cb), cb_arg(s));
+        }
+
+        r.await
+            .expect("callback gone while closing the super blob")
+            .to_result(|e| Error::PoolProperty {
+                source: Errno::from_i32(e.abs()),
+                name: self.name().to_string(),
+            })
+    }
+
+    /// verify that committing another `size` bytes to the lvol with the given
+    /// name stays within the overcommit limit of the store
+    pub(crate) fn check_overcommit(
+        &self,
+        name: &str,
+        size: u64,
+    ) -> Result<(), Error> {
+        let percent = self.overcommit();
+        if percent == 0 {
+            return Ok(());
+        }
+
+        let limit = self.capacity() as u128 * percent as u128 / 100;
+        let committed = self.committed() as u128 + size as u128;
+        if committed > limit {
+            return Err(Error::PoolOvercommit {
+                source: Errno::ENOSPC,
+                name: name.to_string(),
+                pool: self.name().to_string(),
+                percent,
+            });
+        }
+        Ok(())
+    }
+
+    /// returns true if the store is being drained
+    pub fn draining(&self) -> bool {
+        POOL_DRAINING.lock().contains(self.name())
+    }
+
+    /// mark the store as draining, which stops it from accepting new lvols,
+    /// or as accepting lvols again. The mark is kept in the super blob of the
+    /// store so that it is still in place after the store has been imported
+    /// again.
+    pub async fn set_draining(&self, draining: bool) -> Result<(), Error> {
+        self.set_super_xattr(DRAINING_XATTR, &draining.to_string())
+            .await?;
+        self.cache_draining(draining);
+        Ok(())
+    }
+
+    /// read whether the store is being drained from its super blob
+    async fn load_draining(&self) -> Result<(), Error> {
+        // stores which were never drained have no such xattr at all
+        let draining = match self.get_super_xattr(DRAINING_XATTR).await? {
+            Some(value) => value.parse().map_err(|_| Error::PoolProperty {
+                source: Errno::EINVAL,
+                name: self.name().to_string(),
+            })?,
+            None => false,
+        };
+        self.cache_draining(draining);
+        Ok(())
+    }
+
+    /// keep the draining mark of the store at hand for the checks made
+    /// whenever an lvol is created
+    fn cache_draining(&self, draining: bool) {
+        let mut pools = POOL_DRAINING.lock();
+        if draining {
+            pools.insert(self.name().to_string());
+        } else {
+            pools.remove(self.name());
+        }
+    }
+
+    /// verify that the store accepts a new lvol with the given name
+    pub(crate) fn check_draining(&self, name: &str) -> Result<(), Error> {
+        if self.draining() {
+            return Err(Error::PoolDraining {
+                source: Errno::EROFS,
+                name: name.to_string(),
+                pool: self.name().to_string(),
+            });
+        }
+        Ok(())
+    }
+
     /// returns the base bdev of this lvs
     pub fn base_bdev(&self) -> Bdev {
         Bdev::from(unsafe {
@@ -324,6 +631,10 @@ impl Lvs {
 
         if let Some(pool) = Self::lookup(&args.name) {
             return if pool.base_bdev().name() == parsed.get_name() {
+                if let Some(overcommit) = args.overcommit {
+                    pool.set_overcommit(overcommit.percent).await?;
+                }
+                pool.open_encrypted(&args.replica_keys).await?;
                 Ok(pool)
             } else {
                 Err(Error::PoolCreate {
@@ -346,7 +657,7 @@ impl Lvs {
             Ok(name) => Ok(name),
         }?;
 
-        match Self::import(&args.name, &bdev).await {
+        let pool = match Self::import(&args.name, &bdev).await {
             Ok(pool) => Ok(pool),
             Err(Error::Import {
                 source,
@@ -381,7 +692,15 @@ impl Lvs {
             }
             // some other error, bubble it back up
             Err(e) => Err(e),
+        }?;
+
+        pool.load_overcommit().await?;
+        pool.load_draining().await?;
+        if let Some(overcommit) = args.overcommit {
+            pool.set_overcommit(overcommit.percent).await?;
         }
+        pool.open_encrypted(&args.replica_keys).await?;
+        Ok(pool)
     }
 
     /// export the given lvl
@@ -404,6 +723,8 @@ impl Lvs {
             })?;
 
         info!("pool {} exported successfully", pool);
+        POOL_OVERCOMMIT.lock().remove(&pool);
+        POOL_DRAINING.lock().remove(&pool);
         bdev_destroy(&base_bdev.bdev_uri().unwrap())
             .await
             .map_err(|e| Error::Destroy {
@@ -413,14 +734,23 @@ impl Lvs {
         Ok(())
     }
 
-    /// unshare all lvols prior to export or destroy
+    /// unshare all lvols prior to export or destroy, and close the encrypted
+    /// ones
     async fn unshare_all(&self) {
         for l in self.lvols().unwrap() {
             // notice we dont use the unshare impl of the bdev
             // here. we do this to avoid the on disk persistence
-            let bdev = l.as_bdev();
-            if let Err(e) = bdev.unshare().await {
-                error!("failed to unshare lvol {} error {}", l, e.to_string())
+            if let Some(bdev) = l.share_bdev() {
+                if let Err(e) = Lvol::stop_share(&bdev).await {
+                    error!(
+                        "failed to unshare lvol {} error {}",
+                        l,
+                        e.to_string()
+                    )
+                }
+            }
+            if let Err(e) = l.close_encrypted().await {
+                error!("{}", e.to_string());
             }
         }
     }
@@ -432,6 +762,12 @@ impl Lvs {
             for l in lvols {
                 if let Ok(prop) = l.get(PropName::Shared).await {
                     match prop {
+                        PropValue::Shared(true) if l.is_locked() => {
+                            info!(
+                                "{} is encrypted, it is shared once opened",
+                                l.name()
+                            )
+                        }
                         PropValue::Shared(true) => {
                             if let Err(e) = l.share_nvmf(None).await {
                                 error!(
@@ -444,12 +780,52 @@ impl Lvs {
                         PropValue::Shared(false) => {
                             debug!("{} not shared on disk", l.name())
                         }
+                        _ => {}
                     }
                 }
             }
         }
     }
 
+    /// open the encrypted lvols for which a key is given, sharing those that
+    /// have the shared property set
+    async fn open_encrypted(&self, keys: &[ReplicaKey]) -> Result<(), Error> {
+        for rk in keys {
+            let lvol = self
+                .lvols()
+                .and_then(|mut lvols| lvols.find(|l| l.name() == rk.name))
+                .ok_or_else(|| Error::Invalid {
+                    source: Errno::ENOENT,
+                    msg: format!(
+                        "replica {} not found on pool {}",
+                        rk.name,
+                        self.name()
+                    ),
+                })?;
+
+            let key = match &rk.encryption {
+                Some(spec) => EncryptionKey::from_rpc(&rk.name, spec)?,
+                None => None,
+            }
+            .ok_or_else(|| Error::InvalidKey {
+                name: rk.name.clone(),
+                msg: "no key given".to_string(),
+            })?;
+
+            if !lvol.is_locked() {
+                continue;
+            }
+
+            lvol.open_encrypted(&key).await?;
+            if let Ok(PropValue::Shared(true)) =
+                lvol.get(PropName::Shared).await
+            {
+                lvol.share_nvmf(None).await?;
+            }
+        }
+        Ok(())
+    }
+
     /// destroys the given pool deleting the on disk super blob before doing so,
     /// un share all targets
     pub async fn destroy(self) -> Result<(), Error> {
@@ -477,6 +853,8 @@ impl Lvs {
             })?;
 
         info!("pool {} destroyed successfully", pool);
+        POOL_OVERCOMMIT.lock().remove(&pool);
+        POOL_DRAINING.lock().remove(&pool);
 
         bdev_destroy(&base_bdev.bdev_uri().unwrap())
             .await
@@ -529,6 +907,9 @@ impl Lvs {
             });
         };
 
+        self.check_draining(name)?;
+        self.check_overcommit(name, size)?;
+
         let (s, r) = pair::<ErrnoResult<*mut spdk_lvol>>();
 
         let cname = name.into_cstring();
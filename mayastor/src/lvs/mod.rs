@@ -1,7 +1,9 @@
+pub use crypto::{Cipher, EncryptionKey};
 pub use error::Error;
 pub use lvol::{Lvol, PropName, PropValue};
 pub use lvs_pool::Lvs;
 
+mod crypto;
 mod error;
 mod lvol;
 mod lvs_pool;
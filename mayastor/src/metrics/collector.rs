@@ -0,0 +1,379 @@
+//! Collection of the metrics exported by mayastor. This must run on the
+//! init thread as it walks the nexus, pool and controller instances.
+
+use std::convert::TryFrom;
+
+use futures::channel::oneshot;
+
+use crate::{
+    bdev::{
+        device_lookup,
+        nexus::instances,
+        nexus_lookup,
+        ChildState,
+        NexusStatus,
+        NVME_CONTROLLERS,
+    },
+    core::{Bdev, BlockDeviceIoStats, CoreError, Reactors},
+    ffihelper::{cb_arg, done_cb},
+    lvs::{Lvol, Lvs},
+    metrics::encoder::{encode, Family},
+    rebuild::{ClientOperations, RebuildJob},
+};
+
+/// the IO counters and latencies of a block device
+struct IoFamilies {
+    reads: Family,
+    writes: Family,
+    bytes_read: Family,
+    bytes_written: Family,
+    unmaps: Family,
+    bytes_unmapped: Family,
+    read_latency: Family,
+    write_latency: Family,
+    unmap_latency: Family,
+}
+
+impl IoFamilies {
+    fn new(prefix: &str) -> Self {
+        Self {
+            reads: Family::counter(
+                &format!("{}_reads_total", prefix),
+                "Number of read operations",
+            ),
+            writes: Family::counter(
+                &format!("{}_writes_total", prefix),
+                "Number of write operations",
+            ),
+            bytes_read: Family::counter(
+                &format!("{}_read_bytes_total", prefix),
+                "Number of bytes read",
+            ),
+            bytes_written: Family::counter(
+                &format!("{}_written_bytes_total", prefix),
+                "Number of bytes written",
+            ),
+            unmaps: Family::counter(
+                &format!("{}_unmaps_total", prefix),
+                "Number of unmap operations",
+            ),
+            bytes_unmapped: Family::counter(
+                &format!("{}_unmapped_bytes_total", prefix),
+                "Number of bytes unmapped",
+            ),
+            read_latency: Family::histogram(
+                &format!("{}_read_latency_seconds", prefix),
+                "Latency of the read operations",
+            ),
+            write_latency: Family::histogram(
+                &format!("{}_write_latency_seconds", prefix),
+                "Latency of the write operations",
+            ),
+            unmap_latency: Family::histogram(
+                &format!("{}_unmap_latency_seconds", prefix),
+                "Latency of the unmap operations",
+            ),
+        }
+    }
+
+    fn add(&mut self, labels: &[(&'static str, &str)], s: &BlockDeviceIoStats) {
+        self.reads.sample(labels, s.num_read_ops as f64);
+        self.writes.sample(labels, s.num_write_ops as f64);
+        self.bytes_read.sample(labels, s.bytes_read as f64);
+        self.bytes_written.sample(labels, s.bytes_written as f64);
+        self.unmaps.sample(labels, s.num_unmap_ops as f64);
+        self.bytes_unmapped.sample(labels, s.bytes_unmapped as f64);
+        self.read_latency.histogram_sample(labels, &s.read_latency);
+        self.write_latency
+            .histogram_sample(labels, &s.write_latency);
+        self.unmap_latency
+            .histogram_sample(labels, &s.unmap_latency);
+    }
+
+    fn into_families(self) -> Vec<Family> {
+        vec![
+            self.reads,
+            self.writes,
+            self.bytes_read,
+            self.bytes_written,
+            self.unmaps,
+            self.bytes_unmapped,
+            self.read_latency,
+            self.write_latency,
+            self.unmap_latency,
+        ]
+    }
+}
+
+fn nexus_status(status: NexusStatus) -> &'static str {
+    match status {
+        NexusStatus::Faulted => "faulted",
+        NexusStatus::Degraded => "degraded",
+        NexusStatus::Online => "online",
+    }
+}
+
+fn child_state(state: ChildState) -> &'static str {
+    match state {
+        ChildState::Init => "init",
+        ChildState::ConfigInvalid => "config_invalid",
+        ChildState::Open => "open",
+        ChildState::Destroying => "destroying",
+        ChildState::Closed => "closed",
+        ChildState::Faulted(_) => "faulted",
+    }
+}
+
+async fn nexus_metrics(families: &mut Vec<Family>) {
+    let mut status = Family::gauge(
+        "mayastor_nexus_status",
+        "Status of the nexus, set to 1 for the current status",
+    );
+    let mut size =
+        Family::gauge("mayastor_nexus_size_bytes", "Size of the nexus");
+    let mut io = IoFamilies::new("mayastor_nexus");
+    let mut state = Family::gauge(
+        "mayastor_nexus_child_state",
+        "State of the nexus child, set to 1 for the current state",
+    );
+    let mut child_io = IoFamilies::new("mayastor_nexus_child");
+    let mut child_errors = Family::counter(
+        "mayastor_nexus_child_io_errors_total",
+        "Number of failed IOs of the nexus child by kind of error",
+    );
+    let mut blocks_total = Family::gauge(
+        "mayastor_rebuild_total_blocks",
+        "Number of blocks to rebuild",
+    );
+    let mut blocks_recovered = Family::gauge(
+        "mayastor_rebuild_recovered_blocks",
+        "Number of blocks rebuilt so far",
+    );
+    let mut progress = Family::gauge(
+        "mayastor_rebuild_progress_percent",
+        "Progress of the rebuild in percent",
+    );
+
+    let names = instances()
+        .iter()
+        .map(|n| n.name.clone())
+        .collect::<Vec<_>>();
+
+    // the nexus, and its children, may go away while awaiting their stats,
+    // so what is needed afterwards is copied out first and the nexus is
+    // looked up again after every await
+    for name in names {
+        let nexus = match nexus_lookup(&name) {
+            Some(nexus) => nexus,
+            None => continue,
+        };
+        let labels = [("nexus", name.as_str())];
+
+        status.sample(
+            &[
+                ("nexus", name.as_str()),
+                ("status", nexus_status(nexus.status())),
+            ],
+            1.0,
+        );
+        size.sample(&labels, nexus.size as f64);
+
+        let mut devices = Vec::new();
+        for child in nexus.children.iter() {
+            let labels =
+                [("nexus", name.as_str()), ("child", child.name.as_str())];
+
+            state.sample(
+                &[
+                    ("nexus", name.as_str()),
+                    ("child", child.name.as_str()),
+                    ("state", child_state(child.state())),
+                ],
+                1.0,
+            );
+
+            let (media, transport) = child.io_errors();
+            for &(kind, errors) in
+                [("media", media), ("transport", transport)].iter()
+            {
+                child_errors.sample(
+                    &[
+                        ("nexus", name.as_str()),
+                        ("child", child.name.as_str()),
+                        ("kind", kind),
+                    ],
+                    errors as f64,
+                );
+            }
+
+            if let Ok(job) = RebuildJob::lookup(&child.name) {
+                let stats = job.as_client().stats();
+                blocks_total.sample(&labels, stats.blocks_total as f64);
+                blocks_recovered.sample(&labels, stats.blocks_recovered as f64);
+                progress.sample(&labels, stats.progress as f64);
+            }
+
+            if let Ok(device) = child.get_device() {
+                devices.push((child.name.clone(), device.device_name()));
+            }
+        }
+
+        // the latency histograms are kept by the channels of the nexus
+        let latency = nexus.latency();
+        let bdev = nexus.bdev.clone();
+        match bdev.stats().await {
+            Ok(stats) => io.add(
+                &labels,
+                &stats.with_latency(latency.await.unwrap_or_default()),
+            ),
+            Err(e) => error!("{}: failed to get stats: {}", name, e),
+        }
+
+        for (child, device_name) in devices {
+            // skip children which have been removed, or whose device has
+            // been closed, in the meantime
+            let device = match nexus_lookup(&name).and_then(|nexus| {
+                nexus
+                    .children
+                    .iter()
+                    .find(|c| c.name == child)
+                    .and_then(|c| c.get_device().ok())
+                    .filter(|d| d.device_name() == device_name)
+                    .and_then(|_| device_lookup(&device_name))
+            }) {
+                Some(device) => device,
+                None => continue,
+            };
+
+            match device.io_stats().await {
+                Ok(stats) => child_io.add(
+                    &[("nexus", name.as_str()), ("child", child.as_str())],
+                    &stats,
+                ),
+                Err(e) => error!("{}: failed to get stats: {}", child, e),
+            }
+        }
+    }
+
+    families.extend(vec![status, size]);
+    families.extend(io.into_families());
+    families.push(state);
+    families.extend(child_io.into_families());
+    families.push(child_errors);
+    families.extend(vec![blocks_total, blocks_recovered, progress]);
+}
+
+async fn pool_metrics(families: &mut Vec<Family>) {
+    let mut capacity =
+        Family::gauge("mayastor_pool_capacity_bytes", "Capacity of the pool");
+    let mut used =
+        Family::gauge("mayastor_pool_used_bytes", "Space used in the pool");
+    let mut committed = Family::gauge(
+        "mayastor_pool_committed_bytes",
+        "Space committed to the replicas of the pool",
+    );
+    let mut size =
+        Family::gauge("mayastor_replica_size_bytes", "Size of the replica");
+    let mut allocated = Family::gauge(
+        "mayastor_replica_allocated_bytes",
+        "Space allocated to the replica",
+    );
+    let mut io = IoFamilies::new("mayastor_replica");
+
+    // replicas may be destroyed while awaiting their stats, so their bdevs
+    // are looked up again rather than walking the pools across the awaits
+    let mut replicas = Vec::new();
+    for pool in Lvs::iter() {
+        let labels = [("pool", pool.name())];
+        capacity.sample(&labels, pool.capacity() as f64);
+        used.sample(&labels, pool.used() as f64);
+        committed.sample(&labels, pool.committed() as f64);
+
+        if let Some(lvols) = pool.lvols() {
+            for lvol in lvols {
+                let uuid = lvol.uuid();
+                let labels =
+                    [("pool", pool.name()), ("replica", uuid.as_str())];
+                size.sample(&labels, lvol.size() as f64);
+                allocated.sample(&labels, lvol.allocated() as f64);
+                replicas.push((pool.name().to_string(), uuid, lvol.name()));
+            }
+        }
+    }
+
+    for (pool, uuid, name) in replicas {
+        let lvol = match Bdev::lookup_by_name(&name)
+            .and_then(|bdev| Lvol::try_from(bdev).ok())
+        {
+            Some(lvol) => lvol,
+            None => continue,
+        };
+        match lvol.stats().await {
+            Ok(stats) => io.add(
+                &[("pool", pool.as_str()), ("replica", uuid.as_str())],
+                &stats,
+            ),
+            Err(e) => error!("{}: failed to get stats: {}", uuid, e),
+        }
+    }
+
+    families.extend(vec![capacity, used, committed, size, allocated]);
+    families.extend(io.into_families());
+}
+
+async fn controller_metrics(families: &mut Vec<Family>) {
+    let mut io = IoFamilies::new("mayastor_nvme_controller");
+
+    for name in NVME_CONTROLLERS.controllers() {
+        if let Some(ctrlr) = NVME_CONTROLLERS.lookup_by_name(&name) {
+            let (s, r) =
+                oneshot::channel::<Result<BlockDeviceIoStats, CoreError>>();
+
+            if ctrlr
+                .lock()
+                .get_io_stats(|stats, ch| done_cb(ch, stats), cb_arg(s))
+                .is_err()
+            {
+                continue;
+            }
+
+            if let Ok(Ok(stats)) = r.await {
+                io.add(&[("controller", name.as_str())], &stats);
+            }
+        }
+    }
+
+    families.extend(io.into_families());
+}
+
+fn reactor_metrics(families: &mut Vec<Family>) {
+    let mut busy = Family::counter(
+        "mayastor_reactor_busy_seconds_total",
+        "Time the reactor spent polling threads which had work to do",
+    );
+    let mut idle = Family::counter(
+        "mayastor_reactor_idle_seconds_total",
+        "Time the reactor spent polling threads which had nothing to do",
+    );
+
+    for reactor in Reactors::iter() {
+        let core = reactor.core().to_string();
+        let (b, i) = reactor.utilisation();
+        busy.sample(&[("core", core.as_str())], b.as_secs_f64());
+        idle.sample(&[("core", core.as_str())], i.as_secs_f64());
+    }
+
+    families.extend(vec![busy, idle]);
+}
+
+/// collect all metrics and encode them in the text exposition format
+pub async fn collect() -> String {
+    let mut families = Vec::new();
+
+    nexus_metrics(&mut families).await;
+    pool_metrics(&mut families).await;
+    controller_metrics(&mut families).await;
+    reactor_metrics(&mut families);
+
+    encode(&families)
+}
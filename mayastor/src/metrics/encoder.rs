@@ -0,0 +1,178 @@
+//! Encoding of metric families using the Prometheus text exposition format,
+//! see https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::fmt::Write;
+
+use crate::core::{LatencyHistogram, LATENCY_BUCKETS};
+
+/// content type of the encoded metrics
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// the type of a metric family
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+/// a single sample of a metric family
+#[derive(Debug)]
+struct Sample {
+    suffix: &'static str,
+    labels: Vec<(&'static str, String)>,
+    value: f64,
+}
+
+/// a named group of samples sharing the same help text and type
+#[derive(Debug)]
+pub struct Family {
+    name: String,
+    help: &'static str,
+    kind: MetricType,
+    samples: Vec<Sample>,
+}
+
+impl Family {
+    pub fn new(name: &str, help: &'static str, kind: MetricType) -> Self {
+        Self {
+            name: name.to_string(),
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    /// new counter family
+    pub fn counter(name: &str, help: &'static str) -> Self {
+        Self::new(name, help, MetricType::Counter)
+    }
+
+    /// new gauge family
+    pub fn gauge(name: &str, help: &'static str) -> Self {
+        Self::new(name, help, MetricType::Gauge)
+    }
+
+    /// new histogram family
+    pub fn histogram(name: &str, help: &'static str) -> Self {
+        Self::new(name, help, MetricType::Histogram)
+    }
+
+    /// name of the family
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// add a sample with the given labels to the family
+    pub fn sample(&mut self, labels: &[(&'static str, &str)], value: f64) {
+        self.samples.push(Sample {
+            suffix: "",
+            labels: labels.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+            value,
+        });
+    }
+
+    /// add the buckets, sum and count of a latency histogram in seconds to
+    /// the family, devices which do not keep the buckets only get the
+    /// catch-all bucket
+    pub fn histogram_sample(
+        &mut self,
+        labels: &[(&'static str, &str)],
+        h: &LatencyHistogram,
+    ) {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| (*k, v.to_string()))
+            .collect::<Vec<_>>();
+        let mut push = |suffix, labels, value| {
+            self.samples.push(Sample {
+                suffix,
+                labels,
+                value,
+            })
+        };
+
+        if h.buckets.iter().any(|b| *b > 0) {
+            let mut cumulative = 0;
+            for (bucket, count) in
+                h.buckets.iter().enumerate().take(LATENCY_BUCKETS - 1)
+            {
+                cumulative += count;
+                let bound = LatencyHistogram::bucket_bound(bucket).unwrap();
+                let mut labels = labels.clone();
+                labels.push(("le", (bound as f64 / 1e6).to_string()));
+                push("_bucket", labels, cumulative as f64);
+            }
+        }
+
+        let mut inf = labels.clone();
+        inf.push(("le", "+Inf".into()));
+        push("_bucket", inf, h.count as f64);
+        push("_sum", labels.clone(), h.total_us as f64 / 1e6);
+        push("_count", labels, h.count as f64);
+    }
+
+    fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind.as_str());
+        for sample in &self.samples {
+            out.push_str(&self.name);
+            out.push_str(sample.suffix);
+            if !sample.labels.is_empty() {
+                out.push('{');
+                for (i, (key, value)) in sample.labels.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "{}=\"{}\"", key, escape(value));
+                }
+                out.push('}');
+            }
+            let _ = writeln!(out, " {}", format_value(sample.value));
+        }
+    }
+}
+
+/// escape a label value as required by the text format
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.into()
+    } else {
+        value.to_string()
+    }
+}
+
+/// encode the families, skipping those without any samples
+pub fn encode(families: &[Family]) -> String {
+    let mut out = String::new();
+    families
+        .iter()
+        .filter(|f| !f.samples.is_empty())
+        .for_each(|f| f.encode(&mut out));
+    out
+}
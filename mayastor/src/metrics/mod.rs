@@ -0,0 +1,82 @@
+//! HTTP endpoint exporting the IO statistics and state of mayastor in the
+//! Prometheus text format.
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+
+use crate::core::Mthread;
+
+/// Metrics collection
+mod collector;
+/// Prometheus text format encoder
+pub mod encoder;
+
+pub use collector::collect;
+
+/// default port of the metrics endpoint
+pub const DEFAULT_PORT: u16 = 9502;
+
+/// If endpoint is missing a port number then add the default one.
+pub fn endpoint(endpoint: String) -> SocketAddr {
+    (if endpoint.contains(':') {
+        endpoint
+    } else {
+        format!("{}:{}", endpoint, DEFAULT_PORT)
+    })
+    .parse()
+    .expect("Invalid metrics endpoint")
+}
+
+async fn serve(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let metrics = match Mthread::get_init().spawn_local(collect()) {
+        Ok(rx) => rx.await.ok(),
+        Err(_) => None,
+    };
+
+    Ok(match metrics {
+        Some(metrics) => Response::builder()
+            .header(CONTENT_TYPE, encoder::CONTENT_TYPE)
+            .body(Body::from(metrics))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::empty())
+            .unwrap(),
+    })
+}
+
+pub struct MetricsServer;
+
+impl MetricsServer {
+    pub async fn run(endpoint: SocketAddr) -> Result<(), ()> {
+        info!("Metrics server configured at address {}", endpoint);
+        let svc = Server::bind(&endpoint).serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(serve))
+        }));
+
+        match svc.await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Metrics server failed with error: {}", e);
+                Err(())
+            }
+        }
+    }
+}
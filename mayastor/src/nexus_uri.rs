@@ -130,7 +130,7 @@ impl std::cmp::PartialEq<url::Url> for &Bdev {
             Ok(device) if device.get_name() == self.name() => {
                 self.driver()
                     == match uri.scheme() {
-                        "nvmf" | "pcie" => "nvme",
+                        "nvmf" | "nvmf+tcp" | "nvmf+rdma" | "pcie" => "nvme",
                         scheme => scheme,
                     }
             }
@@ -145,7 +145,7 @@ impl std::cmp::PartialEq<url::Url> for Bdev {
             Ok(device) if device.get_name() == self.name() => {
                 self.driver()
                     == match uri.scheme() {
-                        "nvmf" | "pcie" => "nvme",
+                        "nvmf" | "nvmf+tcp" | "nvmf+rdma" | "pcie" => "nvme",
                         scheme => scheme,
                     }
             }
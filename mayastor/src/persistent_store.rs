@@ -1,24 +1,40 @@
 //! The persistent store is used to save information that is required by
 //! Mayastor across restarts.
 //!
-//! etcd is used as the backing store and is interacted with through the use of
-//! the etcd-client crate. This crate has a dependency on the tokio async
-//! runtime.
+//! The backing store is selected by the endpoint given at startup:
+//! - `memory://` keeps the entries in memory, which is only useful for testing
+//! - `file:///path/to/file` keeps the entries in a local file, for single node
+//!   deployments without an etcd cluster
+//! - anything else is taken to be an etcd endpoint, which is interacted with
+//!   through the use of the etcd-client crate. This crate has a dependency on
+//!   the tokio async runtime.
+//!
+//! Entries can be owned by a single node at a time. Ownership is recorded in
+//! an entry of its own, which is put under a lease that is kept alive for as
+//! long as this instance is connected to the store. When an instance goes
+//! away, its lease expires and another node can take over ownership.
 use crate::{
-    core,
+    core::{self, MayastorEnvironment},
     store::{
         etcd::Etcd,
+        file::FileStore,
+        memory::MemoryStore,
         store_defs::{
             DeleteWait,
             GetWait,
+            InvalidEndpoint,
+            LeaseId,
+            OpWait,
             PutWait,
             Store,
             StoreError,
             StoreKey,
             StoreValue,
+            WatchStream,
         },
     },
 };
+use async_trait::async_trait;
 use futures::channel::oneshot;
 use once_cell::sync::OnceCell;
 use serde_json::Value;
@@ -26,16 +42,194 @@ use snafu::ResultExt;
 use std::{future::Future, sync::Mutex, time::Duration};
 
 static DEFAULT_PORT: &str = "2379";
+static MEMORY_SCHEME: &str = "memory://";
+static FILE_SCHEME: &str = "file://";
 static STORE_OP_TIMEOUT: Duration = Duration::from_secs(30);
+static LEASE_TTL: Duration = Duration::from_secs(10);
+static LEASE_REFRESH: Duration = Duration::from_secs(3);
+static OWNER_PREFIX: &str = "owner/";
 static PERSISTENT_STORE: OnceCell<Option<Mutex<PersistentStore>>> =
     OnceCell::new();
 
+/// The stores that can back the persistent store.
+#[derive(Clone, Debug)]
+enum BackingStore {
+    Etcd(Etcd),
+    File(FileStore),
+    Memory(MemoryStore),
+}
+
+impl BackingStore {
+    /// Open the backing store named by the endpoint.
+    async fn new(endpoint: &str) -> Result<BackingStore, StoreError> {
+        if endpoint == MEMORY_SCHEME {
+            Ok(Self::Memory(MemoryStore::new()))
+        } else if let Some(path) = endpoint.strip_prefix(FILE_SCHEME) {
+            if path.is_empty() {
+                return InvalidEndpoint {
+                    endpoint,
+                }
+                .fail();
+            }
+            Ok(Self::File(FileStore::new(path)?))
+        } else {
+            Ok(Self::Etcd(Etcd::new(endpoint).await?))
+        }
+    }
+
+    /// Only etcd is accessed over the network and can lose its connection,
+    /// the entries of the other stores would be lost by reopening them.
+    fn reconnectable(&self) -> bool {
+        matches!(self, Self::Etcd(_))
+    }
+}
+
+#[async_trait]
+impl Store for BackingStore {
+    async fn put_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+    ) -> Result<(), StoreError> {
+        match self {
+            Self::Etcd(s) => s.put_kv(key, value).await,
+            Self::File(s) => s.put_kv(key, value).await,
+            Self::Memory(s) => s.put_kv(key, value).await,
+        }
+    }
+
+    async fn get_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<Value, StoreError> {
+        match self {
+            Self::Etcd(s) => s.get_kv(key).await,
+            Self::File(s) => s.get_kv(key).await,
+            Self::Memory(s) => s.get_kv(key).await,
+        }
+    }
+
+    async fn delete_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<(), StoreError> {
+        match self {
+            Self::Etcd(s) => s.delete_kv(key).await,
+            Self::File(s) => s.delete_kv(key).await,
+            Self::Memory(s) => s.delete_kv(key).await,
+        }
+    }
+
+    async fn get_values_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError> {
+        match self {
+            Self::Etcd(s) => s.get_values_prefix(prefix).await,
+            Self::File(s) => s.get_values_prefix(prefix).await,
+            Self::Memory(s) => s.get_values_prefix(prefix).await,
+        }
+    }
+
+    async fn watch_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<WatchStream, StoreError> {
+        match self {
+            Self::Etcd(s) => s.watch_prefix(prefix).await,
+            Self::File(s) => s.watch_prefix(prefix).await,
+            Self::Memory(s) => s.watch_prefix(prefix).await,
+        }
+    }
+
+    async fn compare_and_swap<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        expected: Option<&Value>,
+        value: &V,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError> {
+        match self {
+            Self::Etcd(s) => {
+                s.compare_and_swap(key, expected, value, lease).await
+            }
+            Self::File(s) => {
+                s.compare_and_swap(key, expected, value, lease).await
+            }
+            Self::Memory(s) => {
+                s.compare_and_swap(key, expected, value, lease).await
+            }
+        }
+    }
+
+    async fn put_owned_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        match self {
+            Self::Etcd(s) => {
+                s.put_owned_kv(key, value, owner_key, owner, lease).await
+            }
+            Self::File(s) => {
+                s.put_owned_kv(key, value, owner_key, owner, lease).await
+            }
+            Self::Memory(s) => {
+                s.put_owned_kv(key, value, owner_key, owner, lease).await
+            }
+        }
+    }
+
+    async fn grant_lease(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<LeaseId, StoreError> {
+        match self {
+            Self::Etcd(s) => s.grant_lease(ttl).await,
+            Self::File(s) => s.grant_lease(ttl).await,
+            Self::Memory(s) => s.grant_lease(ttl).await,
+        }
+    }
+
+    async fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError> {
+        match self {
+            Self::Etcd(s) => s.keep_alive_lease(lease).await,
+            Self::File(s) => s.keep_alive_lease(lease).await,
+            Self::Memory(s) => s.keep_alive_lease(lease).await,
+        }
+    }
+
+    async fn revoke_lease(&mut self, lease: LeaseId) -> Result<(), StoreError> {
+        match self {
+            Self::Etcd(s) => s.revoke_lease(lease).await,
+            Self::File(s) => s.revoke_lease(lease).await,
+            Self::Memory(s) => s.revoke_lease(lease).await,
+        }
+    }
+
+    async fn online(&mut self) -> bool {
+        match self {
+            Self::Etcd(s) => s.online().await,
+            Self::File(s) => s.online().await,
+            Self::Memory(s) => s.online().await,
+        }
+    }
+}
+
 /// Persistent store
 pub struct PersistentStore {
     /// Backing store used for persistence.
-    store: Etcd,
+    store: BackingStore,
     /// Endpoint of the backing store.
     endpoint: String,
+    /// Lease under which the ownership of entries is recorded.
+    lease: LeaseId,
 }
 
 impl PersistentStore {
@@ -53,42 +247,91 @@ impl PersistentStore {
 
         // An endpoint has been provided, initialise the persistent store.
         let endpoint = Self::format_endpoint(&endpoint.unwrap());
-        let store = Self::connect_to_backing_store(&endpoint.clone()).await;
+        let mut store = Self::connect_to_backing_store(&endpoint.clone()).await;
+        let lease = Self::grant_lease(&mut store).await;
         PERSISTENT_STORE.get_or_init(|| {
             Some(Mutex::new(PersistentStore {
                 store,
                 endpoint,
+                lease,
             }))
         });
+        core::runtime::spawn(Self::keep_lease_alive());
+    }
+
+    /// Grant the lease under which ownership is recorded, retrying until
+    /// successful for the same reason that connecting is.
+    async fn grant_lease(store: &mut BackingStore) -> LeaseId {
+        let mut output_err = true;
+        loop {
+            match store.grant_lease(LEASE_TTL).await {
+                Ok(lease) => return lease,
+                Err(e) => {
+                    if output_err {
+                        error!("Failed to grant lease: {}. Retrying...", e);
+                        output_err = false;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    /// Keep the lease alive for as long as Mayastor runs. Should the lease
+    /// expire regardless, all entries owned by this instance may have been
+    /// taken over by other nodes and a new lease is granted.
+    async fn keep_lease_alive() {
+        loop {
+            tokio::time::sleep(LEASE_REFRESH).await;
+            let lease = Self::lease();
+            let mut store = Self::backing_store();
+            match store.keep_alive_lease(lease).await {
+                Ok(_) => {}
+                Err(StoreError::LeaseExpired {
+                    ..
+                }) => {
+                    error!("Lease {} of the persistent store expired", lease);
+                    let lease = Self::grant_lease(&mut store).await;
+                    Self::persistent_store().lock().unwrap().lease = lease;
+                }
+                Err(e) => {
+                    warn!("Failed to keep lease {} alive: {}", lease, e);
+                }
+            }
+        }
     }
 
-    /// Adds the default port to the endpoint if one isn't already specified.
+    /// Adds the default port to an etcd endpoint if one isn't already
+    /// specified.
     fn format_endpoint(endpoint: &str) -> String {
+        if endpoint == MEMORY_SCHEME || endpoint.starts_with(FILE_SCHEME) {
+            return endpoint.to_string();
+        }
         match endpoint.contains(':') {
             true => endpoint.to_string(),
             false => format!("{}:{}", endpoint, DEFAULT_PORT),
         }
     }
 
-    /// Connect to etcd as the backing store.
+    /// Connect to the backing store.
     /// A connection to the store will be attempted continuously until
     /// successful. This is necessary as the backing store is essential to the
     /// operation of Mayastor across restarts.
-    async fn connect_to_backing_store(endpoint: &str) -> Etcd {
+    async fn connect_to_backing_store(endpoint: &str) -> BackingStore {
         let mut output_err = true;
         loop {
-            match Etcd::new(endpoint).await {
+            match BackingStore::new(endpoint).await {
                 Ok(store) => {
-                    info!("Connected to etcd on endpoint {}", endpoint);
+                    info!("Connected to store on endpoint {}", endpoint);
                     return store;
                 }
-                Err(_) => {
+                Err(e) => {
                     if output_err {
                         // Only output the error on first failure to prevent
                         // flooding the logs.
                         error!(
-                            "Failed to connect to etcd on endpoint {}. Retrying...",
-                            endpoint
+                            "Failed to connect to store on endpoint {}: {}. Retrying...",
+                            endpoint, e
                         );
                         output_err = false;
                     }
@@ -175,6 +418,189 @@ impl PersistentStore {
         })?
     }
 
+    /// Retrieve all entries whose keys start with the given prefix.
+    pub async fn get_prefix(
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError> {
+        let prefix_string = prefix.to_string();
+        let rx = Self::execute_store_op(async move {
+            Self::backing_store()
+                .get_values_prefix(&prefix_string)
+                .await
+        });
+        rx.await.context(OpWait {
+            op: "get prefix",
+            key: prefix.to_string(),
+        })?
+    }
+
+    /// Watch all entries whose keys start with the given prefix.
+    pub async fn watch(prefix: &str) -> Result<WatchStream, StoreError> {
+        let prefix_string = prefix.to_string();
+        let rx = Self::execute_store_op(async move {
+            Self::backing_store().watch_prefix(&prefix_string).await
+        });
+        rx.await.context(OpWait {
+            op: "watch",
+            key: prefix.to_string(),
+        })?
+    }
+
+    /// Put a key-value in the store if the current value is `expected`, or if
+    /// there is no value when `expected` is `None`. Returns false if the value
+    /// did not match.
+    pub async fn compare_and_swap(
+        key: &impl StoreKey,
+        expected: Option<Value>,
+        value: &impl StoreValue,
+    ) -> Result<bool, StoreError> {
+        let put_value = serde_json::to_value(value)
+            .expect("Failed to convert value to a serde_json value");
+        let key_string = key.to_string();
+        let rx = Self::execute_store_op(async move {
+            Self::backing_store()
+                .compare_and_swap(
+                    &key_string,
+                    expected.as_ref(),
+                    &put_value,
+                    None,
+                )
+                .await
+        });
+        rx.await.context(OpWait {
+            op: "compare and swap",
+            key: key.to_string(),
+        })?
+    }
+
+    /// Claim ownership of the entry with the given key for this node. Fails
+    /// if the entry is owned by another node.
+    pub async fn claim(key: &impl StoreKey) -> Result<(), StoreError> {
+        let owner_key = Self::owner_key(key);
+        let rx = Self::execute_store_op(async move {
+            let mut store = Self::backing_store();
+            let node = Self::node();
+            let owner = Self::owner(&mut store, &owner_key).await?;
+            if let Some(owner) = owner.as_ref().filter(|o| *o != &node) {
+                return Err(StoreError::NotOwner {
+                    key: owner_key,
+                    owner: owner.as_str().unwrap_or_default().to_string(),
+                });
+            }
+
+            // (re)attach the ownership to the current lease
+            let lease = Self::lease();
+            if store
+                .compare_and_swap(
+                    &owner_key,
+                    owner.as_ref(),
+                    &node,
+                    Some(lease),
+                )
+                .await?
+            {
+                Ok(())
+            } else {
+                // another node got there first
+                let owner = Self::owner(&mut store, &owner_key).await?;
+                Err(StoreError::NotOwner {
+                    key: owner_key,
+                    owner: owner
+                        .as_ref()
+                        .and_then(|o| o.as_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+            }
+        });
+        rx.await.context(OpWait {
+            op: "claim",
+            key: key.to_string(),
+        })?
+    }
+
+    /// Give up ownership of the entry with the given key, if this node owns
+    /// it.
+    pub async fn release(key: &impl StoreKey) -> Result<(), StoreError> {
+        let owner_key = Self::owner_key(key);
+        let rx = Self::execute_store_op(async move {
+            let mut store = Self::backing_store();
+            if Self::owner(&mut store, &owner_key).await? == Some(Self::node())
+            {
+                store.delete_kv(&owner_key).await?;
+            }
+            Ok(())
+        });
+        rx.await.context(OpWait {
+            op: "release",
+            key: key.to_string(),
+        })?
+    }
+
+    /// Put a key-value in the store after claiming ownership of it, so that
+    /// an entry owned by another node is never overwritten. The put itself
+    /// is conditional on the ownership, should another node take over in
+    /// between it fails.
+    pub async fn put_owned(
+        key: &impl StoreKey,
+        value: &impl StoreValue,
+    ) -> Result<(), StoreError> {
+        Self::claim(key).await?;
+
+        let put_value = serde_json::to_value(value)
+            .expect("Failed to convert value to a serde_json value");
+        let key_string = key.to_string();
+        let owner_key = Self::owner_key(key);
+        let rx = Self::execute_store_op(async move {
+            let mut store = Self::backing_store();
+            let node = Self::node();
+            let lease = Self::lease();
+            if store
+                .put_owned_kv(&key_string, &put_value, &owner_key, &node, lease)
+                .await?
+            {
+                return Ok(());
+            }
+            let owner = Self::owner(&mut store, &owner_key).await?;
+            Err(StoreError::NotOwner {
+                key: owner_key,
+                owner: owner
+                    .as_ref()
+                    .and_then(|o| o.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        });
+        rx.await.context(OpWait {
+            op: "put owned",
+            key: key.to_string(),
+        })?
+    }
+
+    /// The owner of an entry as recorded under the given owner key.
+    async fn owner(
+        store: &mut BackingStore,
+        owner_key: &str,
+    ) -> Result<Option<Value>, StoreError> {
+        match store.get_kv(&owner_key).await {
+            Ok(owner) => Ok(Some(owner)),
+            Err(StoreError::MissingEntry {
+                ..
+            }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The key under which the owner of the given key is recorded.
+    fn owner_key(key: &impl StoreKey) -> String {
+        format!("{}{}", OWNER_PREFIX, key.to_string())
+    }
+
+    /// The value recorded for the entries owned by this node.
+    fn node() -> Value {
+        Value::String(MayastorEnvironment::global_or_default().node_name)
+    }
+
     /// Executes a future representing a store operation (i.e. put, get, delete)
     /// on the tokio runtime.
     /// A channel is returned which is signalled when the operation completes.
@@ -224,10 +650,15 @@ impl PersistentStore {
     }
 
     /// Get an instance of the backing store.
-    fn backing_store() -> Etcd {
+    fn backing_store() -> BackingStore {
         Self::persistent_store().lock().unwrap().store.clone()
     }
 
+    /// Get the current lease of this instance.
+    fn lease() -> LeaseId {
+        Self::persistent_store().lock().unwrap().lease
+    }
+
     /// Get the endpoint of the backing store.
     fn endpoint() -> String {
         Self::persistent_store().lock().unwrap().endpoint.clone()
@@ -236,6 +667,9 @@ impl PersistentStore {
     /// Reconnects to the backing store and replaces the old connection with the
     /// new connection.
     async fn reconnect() {
+        if !Self::backing_store().reconnectable() {
+            return;
+        }
         warn!("Attempting to reconnect to persistent store....");
         let persistent_store = Self::persistent_store();
         let backing_store =
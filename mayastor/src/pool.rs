@@ -16,7 +16,7 @@ use spdk_sys::{
     vbdev_lvol_store_next,
 };
 
-use crate::core::Bdev;
+use crate::{core::Bdev, lvs::Lvs};
 
 /// Structure representing a pool which comprises lvol store and
 /// underlying bdev.
@@ -72,6 +72,21 @@ impl Pool {
             spdk_bs_free_cluster_count(lvs.blobstore) * cluster_size
         }
     }
+
+    /// Get the sum of the sizes of all replicas on the pool in bytes.
+    pub fn get_committed(&self) -> u64 {
+        Lvs::from(self.lvs_ptr).committed()
+    }
+
+    /// Get the overcommit limit of the pool as a percentage of its capacity.
+    pub fn get_overcommit(&self) -> u32 {
+        Lvs::from(self.lvs_ptr).overcommit()
+    }
+
+    /// Return true if the pool does not accept new replicas.
+    pub fn get_draining(&self) -> bool {
+        Lvs::from(self.lvs_ptr).draining()
+    }
 }
 
 /// Iterator over available storage pools.
@@ -125,9 +140,15 @@ impl From<Pool> for rpc::Pool {
                     + &pool.get_base_bdev().name(),
             ],
             // TODO: figure out how to detect state of pool
-            state: rpc::PoolState::PoolOnline as i32,
+            state: if pool.get_draining() {
+                rpc::PoolState::PoolDraining as i32
+            } else {
+                rpc::PoolState::PoolOnline as i32
+            },
             capacity: pool.get_capacity(),
             used: pool.get_capacity() - pool.get_free(),
+            committed: pool.get_committed(),
+            overcommit: pool.get_overcommit(),
         }
     }
 }
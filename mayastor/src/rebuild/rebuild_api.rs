@@ -1,13 +1,13 @@
 #![warn(missing_docs)]
 
-use std::fmt;
+use std::{fmt, sync::Arc};
 
 use crossbeam::channel::{Receiver, Sender};
 use futures::channel::oneshot;
 use snafu::Snafu;
 
 use crate::{
-    bdev::VerboseError,
+    bdev::{nexus::nexus_dirty_log::DirtyLog, VerboseError},
     core::{BlockDeviceDescriptor, CoreError, Descriptor, DmaError},
     nexus_uri::NexusBdevError,
 };
@@ -106,6 +106,25 @@ impl fmt::Display for RebuildState {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// which segments of the destination are copied by a rebuild job
+pub enum RebuildMode {
+    /// copy the entire range
+    Full,
+    /// only copy the segments written to while the destination was out of
+    /// the IO path, falls back to a full rebuild if that is unknown
+    Partial,
+}
+
+impl fmt::Display for RebuildMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RebuildMode::Full => write!(f, "full"),
+            RebuildMode::Partial => write!(f, "partial"),
+        }
+    }
+}
+
 /// A rebuild job is responsible for managing a rebuild (copy) which reads
 /// from source_hdl and writes into destination_hdl from specified start to end
 pub struct RebuildJob {
@@ -121,6 +140,8 @@ pub struct RebuildJob {
     pub(super) range: std::ops::Range<u64>,
     pub(super) next: u64,
     pub(super) segment_size_blks: u64,
+    /// segments to copy for a partial rebuild, all of them if None
+    pub(super) dirty_log: Option<Arc<DirtyLog>>,
     pub(super) task_pool: RebuildTasks,
     pub(super) notify_fn: fn(String, String) -> (),
     /// channel used to signal rebuild update
@@ -143,6 +164,7 @@ impl fmt::Debug for RebuildJob {
             .field("nexus", &self.nexus)
             .field("source", &self.source)
             .field("destination", &self.destination)
+            .field("partial", &self.dirty_log.is_some())
             .finish()
     }
 }
@@ -163,6 +185,8 @@ pub struct RebuildStats {
     pub tasks_total: u64,
     /// number of current active tasks
     pub tasks_active: u64,
+    /// only the segments written to while degraded are rebuilt
+    pub partial: bool,
 }
 
 /// Public facing operations on a Rebuild Job
@@ -192,15 +216,18 @@ impl RebuildJob {
     /// Creates a new RebuildJob which rebuilds from source URI to target URI
     /// from start to end (of the data partition); notify_fn callback is called
     /// when the rebuild state is updated - with the nexus and destination
-    /// URI as arguments
+    /// URI as arguments. When a dirty log is given, only the segments marked
+    /// in it are rebuilt.
     pub fn create<'a>(
         nexus: &str,
         source: &str,
         destination: &'a str,
         range: std::ops::Range<u64>,
+        dirty_log: Option<Arc<DirtyLog>>,
         notify_fn: fn(String, String) -> (),
     ) -> Result<&'a mut Self, RebuildError> {
-        Self::new(nexus, source, destination, range, notify_fn)?.store()?;
+        Self::new(nexus, source, destination, range, dirty_log, notify_fn)?
+            .store()?;
 
         Self::lookup(destination)
     }
@@ -1,6 +1,6 @@
 #![warn(missing_docs)]
 
-use std::{cell::UnsafeCell, collections::HashMap};
+use std::{cell::UnsafeCell, collections::HashMap, sync::Arc};
 
 use crossbeam::channel::unbounded;
 use futures::{
@@ -13,7 +13,7 @@ use snafu::ResultExt;
 use spdk_sys::{spdk_get_thread, SPDK_BDEV_LARGE_BUF_MAX_SIZE};
 
 use crate::{
-    bdev::{device_open, VerboseError},
+    bdev::{device_open, nexus::nexus_dirty_log::DirtyLog, VerboseError},
     core::{
         Bdev,
         BlockDevice,
@@ -116,6 +116,7 @@ impl RebuildJob {
         source: &str,
         destination: &str,
         range: std::ops::Range<u64>,
+        dirty_log: Option<Arc<DirtyLog>>,
         notify_fn: fn(String, String) -> (),
     ) -> Result<Self, RebuildError> {
         let src_descriptor = device_open(
@@ -196,6 +197,7 @@ impl RebuildJob {
             range,
             block_size,
             segment_size_blks,
+            dirty_log,
             task_pool: tasks,
             notify_fn,
             notify_chan: unbounded::<RebuildState>(),
@@ -212,6 +214,10 @@ impl RebuildJob {
     // until the bdev is fully rebuilt
     async fn run(&mut self) {
         self.start_all_tasks();
+        if self.task_pool.active == 0 {
+            // a partial rebuild with nothing to copy
+            self.complete();
+        }
         while self.task_pool.active > 0 {
             match self.await_one_task().await {
                 Some(r) => match r.error {
@@ -247,6 +253,31 @@ impl RebuildJob {
         self.reconcile();
     }
 
+    /// Returns the first block at or after `blk` which needs to be rebuilt.
+    /// For a partial rebuild, segments that were not written to while the
+    /// destination was out of the IO path are skipped.
+    fn next_block(&self, blk: u64) -> Option<u64> {
+        if blk >= self.range.end {
+            return None;
+        }
+        match self.dirty_log.as_ref() {
+            None => Some(blk),
+            Some(log) => log
+                .next_dirty(blk - self.range.start)
+                .map(|b| b + self.range.start)
+                .filter(|b| *b < self.range.end),
+        }
+    }
+
+    /// Total number of blocks this job has to copy.
+    fn blocks_to_copy(&self) -> u64 {
+        let blocks = self.range.end - self.range.start;
+        match self.dirty_log.as_ref() {
+            None => blocks,
+            Some(log) => std::cmp::min(log.dirty_blocks(), blocks),
+        }
+    }
+
     /// Return the size of the segment to be copied.
     fn get_segment_size_blks(&self, blk: u64) -> u64 {
         // Adjust the segments size for the last segment
@@ -492,7 +523,7 @@ impl std::fmt::Display for RebuildOperation {
 
 impl ClientOperations for RebuildJob {
     fn stats(&self) -> RebuildStats {
-        let blocks_total = self.range.end - self.range.start;
+        let blocks_total = self.blocks_to_copy();
 
         // segment size may not be aligned to the total size
         let blocks_recovered = std::cmp::min(
@@ -500,11 +531,16 @@ impl ClientOperations for RebuildJob {
             blocks_total,
         );
 
-        let progress = (blocks_recovered * 100) / blocks_total;
+        let progress = if blocks_total == 0 {
+            100
+        } else {
+            (blocks_recovered * 100) / blocks_total
+        };
 
         info!(
             "State: {}, Src: {}, Dst: {}, range: {:?}, next: {}, \
-             block_size: {}, segment_sz: {}, recovered_blks: {}, progress: {}%",
+             block_size: {}, segment_sz: {}, partial: {}, total_blks: {}, \
+             recovered_blks: {}, progress: {}%",
             self.state(),
             self.source,
             self.destination,
@@ -512,6 +548,8 @@ impl ClientOperations for RebuildJob {
             self.next,
             self.block_size,
             self.segment_size_blks,
+            self.dirty_log.is_some(),
+            blocks_total,
             blocks_recovered,
             progress,
         );
@@ -524,6 +562,7 @@ impl ClientOperations for RebuildJob {
             block_size: self.block_size,
             tasks_total: self.task_pool.total as u64,
             tasks_active: self.task_pool.active as u64,
+            partial: self.dirty_log.is_some(),
         }
     }
 
@@ -641,14 +680,9 @@ impl RebuildJob {
     /// Sends one segment worth of data in a reactor future and notifies the
     /// management channel. Returns the next segment offset to rebuild, if any
     fn send_segment_task(&self, id: usize) -> Option<u64> {
-        if self.next >= self.range.end {
-            None
-        } else {
-            let blk = self.next;
-            let next = std::cmp::min(
-                self.next + self.segment_size_blks,
-                self.range.end,
-            );
+        if let Some(blk) = self.next_block(self.next) {
+            let next =
+                std::cmp::min(blk + self.segment_size_blks, self.range.end);
             let name = self.destination.clone();
 
             Reactors::current().send_future(async move {
@@ -667,6 +701,8 @@ impl RebuildJob {
             });
 
             Some(next)
+        } else {
+            None
         }
     }
 }
@@ -1,27 +1,69 @@
 //! Implementation of an etcd key-value store.
 
 use crate::store::store_defs::{
+    CompareAndSwap,
     Connect,
     Delete,
     DeserialiseValue,
     Get,
+    KeyString,
+    Lease,
+    LeaseId,
     Put,
     SerialiseValue,
     Store,
     StoreError,
-    StoreError::MissingEntry,
+    StoreError::{LeaseExpired, MissingEntry},
     StoreKey,
     StoreValue,
     ValueString,
+    Watch,
+    WatchEvent,
+    WatchStream,
 };
 use async_trait::async_trait;
-use etcd_client::Client;
+use etcd_client::{
+    Client,
+    Compare,
+    CompareOp,
+    EventType,
+    GetOptions,
+    KeyValue,
+    LeaseKeepAliveStream,
+    LeaseKeeper,
+    PutOptions,
+    Txn,
+    TxnOp,
+    WatchOptions,
+};
+use futures::channel::mpsc;
 use serde_json::Value;
 use snafu::ResultExt;
+use std::{convert::TryFrom, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
 
-/// etcd client
+/// etcd client, clones share the keep-alive stream of the lease
 #[derive(Clone)]
-pub struct Etcd(Client);
+pub struct Etcd(Client, Arc<Mutex<Option<LeaseKeepAlive>>>);
+
+/// keep-alive stream of a lease, which is opened once and reused for every
+/// refresh of the lease
+struct LeaseKeepAlive {
+    lease: LeaseId,
+    keeper: LeaseKeeper,
+    stream: LeaseKeepAliveStream,
+}
+
+impl LeaseKeepAlive {
+    /// refresh the lease, returns the TTL etcd reports for it
+    async fn refresh(&mut self) -> Result<i64, StoreError> {
+        self.keeper.keep_alive().await.context(Lease {})?;
+        match self.stream.message().await.context(Lease {})? {
+            Some(resp) => Ok(resp.ttl()),
+            None => Ok(0),
+        }
+    }
+}
 
 impl std::fmt::Debug for Etcd {
     fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -36,8 +78,16 @@ impl Etcd {
             Client::connect([endpoint], None)
                 .await
                 .context(Connect {})?,
+            Arc::new(Mutex::new(None)),
         ))
     }
+
+    /// Deserialise the value of an etcd key-value pair.
+    fn value(kv: &KeyValue) -> Result<Value, StoreError> {
+        serde_json::from_slice(kv.value()).context(DeserialiseValue {
+            value: kv.value_str().context(ValueString {})?,
+        })
+    }
 }
 
 #[async_trait]
@@ -68,11 +118,7 @@ impl Store for Etcd {
             key: key.to_string(),
         })?;
         match resp.kvs().first() {
-            Some(kv) => Ok(serde_json::from_slice(kv.value()).context(
-                DeserialiseValue {
-                    value: kv.value_str().context(ValueString {})?,
-                },
-            )?),
+            Some(kv) => Self::value(kv),
             None => Err(MissingEntry {
                 key: key.to_string(),
             }),
@@ -90,6 +136,205 @@ impl Store for Etcd {
         Ok(())
     }
 
+    /// 'Get' all entries whose keys start with the given prefix from etcd.
+    async fn get_values_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError> {
+        let resp = self
+            .0
+            .get(prefix, Some(GetOptions::new().with_prefix()))
+            .await
+            .context(Get {
+                key: prefix.to_string(),
+            })?;
+        resp.kvs()
+            .iter()
+            .map(|kv| {
+                Ok((
+                    kv.key_str().context(KeyString {})?.to_string(),
+                    Self::value(kv)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// 'Watch' all entries whose keys start with the given prefix in etcd.
+    /// The events are forwarded until either the receiving end of the stream
+    /// is dropped or etcd cancels the watch.
+    async fn watch_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<WatchStream, StoreError> {
+        let (watcher, mut stream) = self
+            .0
+            .watch(prefix, Some(WatchOptions::new().with_prefix()))
+            .await
+            .context(Watch {
+                key: prefix.to_string(),
+            })?;
+
+        let (sender, receiver) = mpsc::unbounded();
+        let prefix = prefix.to_string();
+        tokio::spawn(async move {
+            // the watch is cancelled when the watcher is dropped
+            let _watcher = watcher;
+            loop {
+                let resp = match stream.message().await {
+                    Ok(Some(resp)) => resp,
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to watch prefix {}: {}", prefix, e);
+                        break;
+                    }
+                };
+                for event in resp.events() {
+                    let kv = match event.kv() {
+                        Some(kv) => kv,
+                        None => continue,
+                    };
+                    let key = match kv.key_str() {
+                        Ok(key) => key.to_string(),
+                        Err(_) => continue,
+                    };
+                    let event = match event.event_type() {
+                        EventType::Put => match Self::value(kv) {
+                            Ok(value) => WatchEvent::Put(key, value),
+                            Err(e) => {
+                                error!("Ignoring update of key {}: {}", key, e);
+                                continue;
+                            }
+                        },
+                        EventType::Delete => WatchEvent::Delete(key),
+                    };
+                    if sender.unbounded_send(event).is_err() {
+                        return;
+                    }
+                }
+                if resp.canceled() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// 'Compare and swap' the entry with the given key in etcd. The swap is
+    /// made conditional on the revision of the entry that has been compared,
+    /// so that it fails if the entry changes in between.
+    async fn compare_and_swap<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        expected: Option<&Value>,
+        value: &V,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError> {
+        let resp = self.0.get(key.to_string(), None).await.context(Get {
+            key: key.to_string(),
+        })?;
+        let compare = match (resp.kvs().first(), expected) {
+            (None, None) => {
+                Compare::version(key.to_string(), CompareOp::Equal, 0)
+            }
+            (Some(kv), Some(expected)) if &Self::value(kv)? == expected => {
+                Compare::mod_revision(
+                    key.to_string(),
+                    CompareOp::Equal,
+                    kv.mod_revision(),
+                )
+            }
+            _ => return Ok(false),
+        };
+
+        let vec_value = serde_json::to_vec(value).context(SerialiseValue)?;
+        let options = lease.map(|lease| PutOptions::new().with_lease(lease));
+        let txn = Txn::new().when(vec![compare]).and_then(vec![TxnOp::put(
+            key.to_string(),
+            vec_value,
+            options,
+        )]);
+        let resp = self.0.txn(txn).await.context(CompareAndSwap {
+            key: key.to_string(),
+        })?;
+        Ok(resp.succeeded())
+    }
+
+    /// 'Put' a key-value pair into etcd in a transaction which compares the
+    /// value and the lease of the owner entry.
+    async fn put_owned_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        let vec_owner = serde_json::to_vec(owner).context(SerialiseValue)?;
+        let vec_value = serde_json::to_vec(value).context(SerialiseValue)?;
+        let txn = Txn::new()
+            .when(vec![
+                Compare::value(owner_key, CompareOp::Equal, vec_owner),
+                Compare::lease(owner_key, CompareOp::Equal, lease),
+            ])
+            .and_then(vec![TxnOp::put(key.to_string(), vec_value, None)]);
+        let resp = self.0.txn(txn).await.context(Put {
+            key: key.to_string(),
+            value: serde_json::to_string(value).unwrap(),
+        })?;
+        Ok(resp.succeeded())
+    }
+
+    /// Grant a lease in etcd.
+    async fn grant_lease(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<LeaseId, StoreError> {
+        let ttl = i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX).max(1);
+        let resp = self.0.lease_grant(ttl, None).await.context(Lease {})?;
+        Ok(resp.id())
+    }
+
+    /// Keep a lease alive in etcd, which reports a TTL of 0 for leases that
+    /// have expired. The keep-alive stream of the lease is opened the first
+    /// time and reused afterwards, it is only opened again once it failed.
+    async fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError> {
+        let mut keep_alive = self.1.lock().await;
+        let mut current = match keep_alive.take() {
+            Some(current) if current.lease == lease => current,
+            _ => {
+                let (keeper, stream) =
+                    self.0.lease_keep_alive(lease).await.context(Lease {})?;
+                LeaseKeepAlive {
+                    lease,
+                    keeper,
+                    stream,
+                }
+            }
+        };
+
+        if current.refresh().await? > 0 {
+            *keep_alive = Some(current);
+            Ok(())
+        } else {
+            Err(LeaseExpired {
+                lease,
+            })
+        }
+    }
+
+    /// Revoke a lease in etcd.
+    async fn revoke_lease(&mut self, lease: LeaseId) -> Result<(), StoreError> {
+        self.0.lease_revoke(lease).await.context(Lease {})?;
+        let mut keep_alive = self.1.lock().await;
+        if keep_alive.as_ref().map_or(false, |k| k.lease == lease) {
+            *keep_alive = None;
+        }
+        Ok(())
+    }
+
     async fn online(&mut self) -> bool {
         self.0.status().await.is_ok()
     }
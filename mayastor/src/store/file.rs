@@ -0,0 +1,195 @@
+//! Implementation of a key-value store kept in a local file, for single node
+//! deployments that do not run etcd.
+//!
+//! All entries are held in memory and the whole store is written out to the
+//! file on every change. The file is replaced atomically so that a crash
+//! while saving leaves the previous contents intact. Entries put under a lease
+//! are not written to the file, as the lease cannot outlive this process.
+
+use crate::store::{
+    local::{Entries, LocalStore},
+    store_defs::{
+        File,
+        FileContents,
+        LeaseId,
+        SerialiseValue,
+        Store,
+        StoreError,
+        StoreError::MissingEntry,
+        StoreKey,
+        StoreValue,
+        WatchStream,
+    },
+};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::Value;
+use snafu::ResultExt;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// file backed store, clones share the same entries and file
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    path: Arc<PathBuf>,
+    store: Arc<Mutex<LocalStore>>,
+}
+
+impl FileStore {
+    /// Open the store kept in the given file, which is created on the first
+    /// change if it does not exist yet
+    pub fn new(path: impl AsRef<Path>) -> Result<FileStore, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read(&path) {
+            Ok(data) if data.is_empty() => Entries::new(),
+            Ok(data) => {
+                serde_json::from_slice(&data).context(FileContents {
+                    path: path.display().to_string(),
+                })?
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Entries::new(),
+            Err(e) => {
+                return Err(e).context(File {
+                    path: path.display().to_string(),
+                })
+            }
+        };
+
+        Ok(Self {
+            path: Arc::new(path),
+            store: Arc::new(Mutex::new(LocalStore::new(entries))),
+        })
+    }
+
+    /// write the entries to a temporary file and move it into place, a
+    /// failed write leaves the entries in memory ahead of the file until the
+    /// next change is saved
+    fn save(&self, store: &LocalStore) -> Result<(), StoreError> {
+        let data = serde_json::to_vec_pretty(&store.durable())
+            .context(SerialiseValue)?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, self.path.as_ref()))
+            .context(File {
+                path: self.path.display().to_string(),
+            })
+    }
+}
+
+#[async_trait]
+impl Store for FileStore {
+    /// 'Put' a key-value pair into the store.
+    async fn put_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+    ) -> Result<(), StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        let mut store = self.store.lock();
+        store.put(&key.to_string(), value, None)?;
+        self.save(&store)
+    }
+
+    /// 'Get' the value for the given key from the store.
+    async fn get_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<Value, StoreError> {
+        self.store.lock().get(&key.to_string()).ok_or(MissingEntry {
+            key: key.to_string(),
+        })
+    }
+
+    /// 'Delete' the entry with the given key from the store.
+    async fn delete_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<(), StoreError> {
+        let mut store = self.store.lock();
+        if store.delete(&key.to_string()) {
+            self.save(&store)?;
+        }
+        Ok(())
+    }
+
+    async fn get_values_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError> {
+        Ok(self.store.lock().get_prefix(prefix))
+    }
+
+    async fn watch_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<WatchStream, StoreError> {
+        Ok(self.store.lock().watch(prefix))
+    }
+
+    async fn compare_and_swap<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        expected: Option<&Value>,
+        value: &V,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        let mut store = self.store.lock();
+        let swapped =
+            store.compare_and_swap(&key.to_string(), expected, value, lease)?;
+        if swapped {
+            self.save(&store)?;
+        }
+        Ok(swapped)
+    }
+
+    async fn put_owned_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        let mut store = self.store.lock();
+        let put = store.put_owned(
+            &key.to_string(),
+            value,
+            owner_key,
+            owner,
+            lease,
+        )?;
+        if put {
+            self.save(&store)?;
+        }
+        Ok(put)
+    }
+
+    async fn grant_lease(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<LeaseId, StoreError> {
+        Ok(self.store.lock().grant_lease(ttl))
+    }
+
+    async fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError> {
+        self.store.lock().keep_alive_lease(lease)
+    }
+
+    async fn revoke_lease(&mut self, lease: LeaseId) -> Result<(), StoreError> {
+        self.store.lock().revoke_lease(lease).map(|_| ())
+    }
+
+    async fn online(&mut self) -> bool {
+        true
+    }
+}
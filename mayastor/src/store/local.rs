@@ -0,0 +1,251 @@
+//! Entries, leases and watches of the stores that are kept by Mayastor itself
+//! rather than by an external service, see `memory.rs` and `file.rs`.
+//!
+//! Leases are expired lazily, that is the entries put under a lease are only
+//! deleted, and their watchers notified, by the first operation on the store
+//! after the lease has expired.
+
+use crate::store::store_defs::{
+    LeaseId,
+    StoreError,
+    StoreError::LeaseExpired,
+    WatchEvent,
+    WatchStream,
+};
+use futures::channel::mpsc::{self, UnboundedSender};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// entries of the store keyed by their key
+pub(crate) type Entries = BTreeMap<String, Value>;
+
+/// a lease and the keys of the entries that have been put under it
+#[derive(Debug)]
+struct Lease {
+    ttl: Duration,
+    deadline: Instant,
+    keys: HashSet<String>,
+}
+
+/// the state of a local store
+#[derive(Debug, Default)]
+pub(crate) struct LocalStore {
+    entries: Entries,
+    leases: HashMap<LeaseId, Lease>,
+    next_lease: LeaseId,
+    watchers: Vec<(String, UnboundedSender<WatchEvent>)>,
+}
+
+impl LocalStore {
+    /// a local store holding the given entries
+    pub(crate) fn new(entries: Entries) -> Self {
+        Self {
+            entries,
+            next_lease: 1,
+            ..Default::default()
+        }
+    }
+
+    /// the entries that are not put under a lease, and so must outlive the
+    /// process that put them
+    pub(crate) fn durable(&self) -> Entries {
+        let leased = self
+            .leases
+            .values()
+            .flat_map(|l| l.keys.iter())
+            .collect::<HashSet<_>>();
+        self.entries
+            .iter()
+            .filter(|(k, _)| !leased.contains(k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub(crate) fn get(&mut self, key: &str) -> Option<Value> {
+        self.expire();
+        self.entries.get(key).cloned()
+    }
+
+    pub(crate) fn get_prefix(&mut self, prefix: &str) -> Vec<(String, Value)> {
+        self.expire();
+        self.entries
+            .range(prefix.to_string() ..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    pub(crate) fn put(
+        &mut self,
+        key: &str,
+        value: Value,
+        lease: Option<LeaseId>,
+    ) -> Result<(), StoreError> {
+        self.expire();
+        if let Some(lease) = lease {
+            if !self.leases.contains_key(&lease) {
+                return Err(LeaseExpired {
+                    lease,
+                });
+            }
+        }
+
+        for l in self.leases.values_mut() {
+            l.keys.remove(key);
+        }
+        if let Some(lease) = lease.and_then(|l| self.leases.get_mut(&l)) {
+            lease.keys.insert(key.to_string());
+        }
+
+        self.entries.insert(key.to_string(), value.clone());
+        self.notify(WatchEvent::Put(key.to_string(), value));
+        Ok(())
+    }
+
+    /// delete the entry with the given key, returns true if it existed
+    pub(crate) fn delete(&mut self, key: &str) -> bool {
+        self.expire();
+        self.remove(key)
+    }
+
+    pub(crate) fn compare_and_swap(
+        &mut self,
+        key: &str,
+        expected: Option<&Value>,
+        value: Value,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError> {
+        self.expire();
+        if self.entries.get(key) != expected {
+            return Ok(false);
+        }
+        self.put(key, value, lease)?;
+        Ok(true)
+    }
+
+    /// put the entry if `owner_key` holds `owner` under the given lease,
+    /// returns false if it does not
+    pub(crate) fn put_owned(
+        &mut self,
+        key: &str,
+        value: Value,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        self.expire();
+        let owned = self.entries.get(owner_key) == Some(owner)
+            && self
+                .leases
+                .get(&lease)
+                .map_or(false, |l| l.keys.contains(owner_key));
+        if !owned {
+            return Ok(false);
+        }
+        self.put(key, value, None)?;
+        Ok(true)
+    }
+
+    pub(crate) fn watch(&mut self, prefix: &str) -> WatchStream {
+        let (sender, receiver) = mpsc::unbounded();
+        self.watchers.push((prefix.to_string(), sender));
+        receiver
+    }
+
+    pub(crate) fn grant_lease(&mut self, ttl: Duration) -> LeaseId {
+        self.expire();
+        let id = self.next_lease;
+        self.next_lease += 1;
+        self.leases.insert(
+            id,
+            Lease {
+                ttl,
+                deadline: Instant::now() + ttl,
+                keys: HashSet::new(),
+            },
+        );
+        id
+    }
+
+    pub(crate) fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError> {
+        self.expire();
+        match self.leases.get_mut(&lease) {
+            Some(l) => {
+                l.deadline = Instant::now() + l.ttl;
+                Ok(())
+            }
+            None => Err(LeaseExpired {
+                lease,
+            }),
+        }
+    }
+
+    /// revoke a lease, returns true if any entries have been deleted
+    pub(crate) fn revoke_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        self.expire();
+        match self.leases.remove(&lease) {
+            Some(l) => {
+                Ok(l.keys.iter().fold(false, |d, k| self.remove(k) || d))
+            }
+            None => Err(LeaseExpired {
+                lease,
+            }),
+        }
+    }
+
+    /// delete the entries of all leases that have expired, returns true if
+    /// any entries have been deleted
+    pub(crate) fn expire(&mut self) -> bool {
+        let now = Instant::now();
+        let expired = self
+            .leases
+            .iter()
+            .filter(|(_, l)| l.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let mut deleted = false;
+        for id in expired {
+            if let Some(l) = self.leases.remove(&id) {
+                for key in l.keys {
+                    deleted |= self.remove(&key);
+                }
+            }
+        }
+        deleted
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        for l in self.leases.values_mut() {
+            l.keys.remove(key);
+        }
+        if self.entries.remove(key).is_some() {
+            self.notify(WatchEvent::Delete(key.to_string()));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// send the event to the watchers of the key, dropping the watchers that
+    /// have gone away
+    fn notify(&mut self, event: WatchEvent) {
+        let key = match &event {
+            WatchEvent::Put(key, _) => key.clone(),
+            WatchEvent::Delete(key) => key.clone(),
+        };
+        self.watchers.retain(|(prefix, sender)| {
+            !key.starts_with(prefix.as_str())
+                || sender.unbounded_send(event.clone()).is_ok()
+        });
+    }
+}
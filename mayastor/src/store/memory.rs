@@ -0,0 +1,133 @@
+//! Implementation of an in-memory key-value store.
+//!
+//! The entries do not survive a restart of Mayastor, which makes this store
+//! only suitable for testing.
+
+use crate::store::{
+    local::LocalStore,
+    store_defs::{
+        LeaseId,
+        SerialiseValue,
+        Store,
+        StoreError,
+        StoreError::MissingEntry,
+        StoreKey,
+        StoreValue,
+        WatchStream,
+    },
+};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::Value;
+use snafu::ResultExt;
+use std::{sync::Arc, time::Duration};
+
+/// in-memory store, clones share the same entries
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(Arc<Mutex<LocalStore>>);
+
+impl MemoryStore {
+    /// Create a new, empty in-memory store
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(LocalStore::new(Default::default()))))
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    /// 'Put' a key-value pair into the store.
+    async fn put_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+    ) -> Result<(), StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        self.0.lock().put(&key.to_string(), value, None)
+    }
+
+    /// 'Get' the value for the given key from the store.
+    async fn get_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<Value, StoreError> {
+        self.0.lock().get(&key.to_string()).ok_or(MissingEntry {
+            key: key.to_string(),
+        })
+    }
+
+    /// 'Delete' the entry with the given key from the store.
+    async fn delete_kv<K: StoreKey>(
+        &mut self,
+        key: &K,
+    ) -> Result<(), StoreError> {
+        self.0.lock().delete(&key.to_string());
+        Ok(())
+    }
+
+    async fn get_values_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError> {
+        Ok(self.0.lock().get_prefix(prefix))
+    }
+
+    async fn watch_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<WatchStream, StoreError> {
+        Ok(self.0.lock().watch(prefix))
+    }
+
+    async fn compare_and_swap<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        expected: Option<&Value>,
+        value: &V,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        self.0
+            .lock()
+            .compare_and_swap(&key.to_string(), expected, value, lease)
+    }
+
+    async fn put_owned_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError> {
+        let value = serde_json::to_value(value).context(SerialiseValue)?;
+        self.0.lock().put_owned(
+            &key.to_string(),
+            value,
+            owner_key,
+            owner,
+            lease,
+        )
+    }
+
+    async fn grant_lease(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<LeaseId, StoreError> {
+        Ok(self.0.lock().grant_lease(ttl))
+    }
+
+    async fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError> {
+        self.0.lock().keep_alive_lease(lease)
+    }
+
+    async fn revoke_lease(&mut self, lease: LeaseId) -> Result<(), StoreError> {
+        self.0.lock().revoke_lease(lease).map(|_| ())
+    }
+
+    async fn online(&mut self) -> bool {
+        true
+    }
+}
@@ -1,2 +1,5 @@
 pub mod etcd;
+pub mod file;
+mod local;
+pub mod memory;
 pub mod store_defs;
@@ -2,8 +2,10 @@
 
 use async_trait::async_trait;
 use etcd_client::Error;
+use futures::channel::mpsc::UnboundedReceiver;
 use serde_json::{Error as SerdeError, Value};
 use snafu::Snafu;
+use std::time::Duration;
 
 /// Definition of errors that can be returned from the key-value store.
 #[derive(Debug, Snafu)]
@@ -77,6 +79,22 @@ pub enum StoreError {
         source
     ))]
     Watch { key: String, source: Error },
+    /// Failed to 'compare and swap' an entry in the store.
+    #[snafu(display(
+        "Failed to 'compare and swap' entry with key {}. Error {}",
+        key,
+        source
+    ))]
+    CompareAndSwap { key: String, source: Error },
+    /// Failed to grant, refresh or revoke a lease.
+    #[snafu(display("Failed to access lease. Error {}", source))]
+    Lease { source: Error },
+    /// The lease has expired or has been revoked.
+    #[snafu(display("Lease {} has expired.", lease))]
+    LeaseExpired { lease: LeaseId },
+    /// The entry is owned by another node.
+    #[snafu(display("Entry with key {} is owned by {}.", key, owner))]
+    NotOwner { key: String, owner: String },
     /// Empty key.
     #[snafu(display("Failed to get key as string. Error {}", source))]
     KeyString { source: Error },
@@ -93,6 +111,37 @@ pub enum StoreError {
     /// Failed to serialise value.
     #[snafu(display("Failed to serialise value. Error {}", source))]
     SerialiseValue { source: SerdeError },
+    /// Failed to read or write the file of a file backed store.
+    #[snafu(display(
+        "Failed to access store file {}. Error {}",
+        path,
+        source
+    ))]
+    File {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The file of a file backed store does not hold valid entries.
+    #[snafu(display(
+        "Invalid contents of store file {}. Error {}",
+        path,
+        source
+    ))]
+    FileContents { path: String, source: SerdeError },
+    /// The endpoint does not name a supported store.
+    #[snafu(display("Invalid store endpoint {}", endpoint))]
+    InvalidEndpoint { endpoint: String },
+    /// Failed to wait for an operation.
+    #[snafu(display(
+        "Failed to wait for '{}' operation to complete for key {}.",
+        op,
+        key,
+    ))]
+    OpWait {
+        op: String,
+        key: String,
+        source: futures::channel::oneshot::Canceled,
+    },
     /// Operation timed out.
     #[snafu(display("Store operation timed out.",))]
     OpTimeout {},
@@ -105,6 +154,23 @@ impl<T> StoreKey for T where T: Sync + ToString + std::fmt::Debug {}
 pub trait StoreValue: Sync + serde::Serialize + std::fmt::Debug {}
 impl<T> StoreValue for T where T: Sync + serde::Serialize + std::fmt::Debug {}
 
+/// Identifier of a lease, entries put under a lease are deleted once the lease
+/// expires or is revoked
+pub type LeaseId = i64;
+
+/// Change to an entry of the store reported by a watch
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchEvent {
+    /// the entry with the given key has been put
+    Put(String, Value),
+    /// the entry with the given key has been deleted
+    Delete(String),
+}
+
+/// Stream of the changes to the watched entries, the stream ends when the
+/// watch is cancelled by the store
+pub type WatchStream = UnboundedReceiver<WatchEvent>;
+
 /// Trait defining the operations that can be performed on a key-value store.
 #[async_trait]
 pub trait Store: Sync + Send + Clone {
@@ -127,6 +193,58 @@ pub trait Store: Sync + Send + Clone {
         key: &K,
     ) -> Result<(), StoreError>;
 
+    /// Get all entries whose keys start with the given prefix.
+    async fn get_values_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, Value)>, StoreError>;
+
+    /// Watch all entries whose keys start with the given prefix.
+    async fn watch_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<WatchStream, StoreError>;
+
+    /// Put entry into the store if its current value is `expected`, where
+    /// `None` means that the entry must not exist. Returns false, without
+    /// changing the entry, if the value did not match. The entry is deleted
+    /// when the given lease expires.
+    async fn compare_and_swap<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        expected: Option<&Value>,
+        value: &V,
+        lease: Option<LeaseId>,
+    ) -> Result<bool, StoreError>;
+
+    /// Put entry into the store if the entry with the key `owner_key` has the
+    /// value `owner` and has been put under the given lease, which is checked
+    /// atomically with the put. Returns false, without changing the entry,
+    /// if the owner did not match.
+    async fn put_owned_kv<K: StoreKey, V: StoreValue>(
+        &mut self,
+        key: &K,
+        value: &V,
+        owner_key: &str,
+        owner: &Value,
+        lease: LeaseId,
+    ) -> Result<bool, StoreError>;
+
+    /// Grant a lease that expires after `ttl` unless it is kept alive.
+    async fn grant_lease(
+        &mut self,
+        ttl: Duration,
+    ) -> Result<LeaseId, StoreError>;
+
+    /// Keep a lease alive for another period of its `ttl`.
+    async fn keep_alive_lease(
+        &mut self,
+        lease: LeaseId,
+    ) -> Result<(), StoreError>;
+
+    /// Revoke a lease, deleting all entries put under it.
+    async fn revoke_lease(&mut self, lease: LeaseId) -> Result<(), StoreError>;
+
     /// Identify whether or not the store is online.
     async fn online(&mut self) -> bool;
 }
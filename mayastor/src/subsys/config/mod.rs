@@ -6,11 +6,13 @@
 //! spell out the YAML spec for a given sub component. Serde will fill
 //! in the default when missing, which are defined within the individual
 //! options.
-use std::{fmt::Display, fs, io::Write, path::Path};
+use std::{fmt::Display, fs, io::Write, path::Path, sync::Arc};
 
 use futures::FutureExt;
 use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use snafu::Snafu;
 use spdk_sys::{
     spdk_json_write_ctx,
@@ -22,29 +24,62 @@ use spdk_sys::{
 
 use crate::{
     jsonrpc::{jsonrpc_register, Code, RpcErrorCode},
+    logger,
     subsys::config::opts::{
         BdevOpts,
         GetOpts,
         IscsiTgtOpts,
         NexusOpts,
         NvmeBdevOpts,
+        NvmfRdmaTransportOpts,
         NvmfTgtConfig,
     },
 };
 
 #[derive(Debug, Clone, Snafu)]
-pub enum Error {}
+#[snafu(visibility = "pub(crate)")]
+pub enum Error {
+    #[snafu(display("Invalid configuration: {}", msg))]
+    InvalidConfig { msg: String },
+    #[snafu(display(
+        "Options {} can only be changed by restarting mayastor",
+        options
+    ))]
+    RestartRequired { options: String },
+    #[snafu(display("Failed to set log level {}: {}", level, msg))]
+    LogLevel { level: String, msg: String },
+}
 
 impl RpcErrorCode for Error {
     fn rpc_error_code(&self) -> Code {
-        Code::InternalError
+        Code::InvalidParams
     }
 }
 
+/// Options which can be changed while mayastor is running, changing any
+/// other option requires a restart. The NVMe options apply to the NVMe
+/// controllers that are created afterwards.
+const RUNTIME_OPTIONS: &[&str] = &[
+    "nvme_bdev_opts.action_on_timeout",
+    "nvme_bdev_opts.timeout_us",
+    "nvme_bdev_opts.keep_alive_timeout_ms",
+    "nvme_bdev_opts.retry_count",
+    "nexus_opts.slow_child_factor",
+    "nexus_opts.slow_child_min_latency_ms",
+    "nexus_opts.slow_child_window_secs",
+    "nexus_opts.child_transport_error_limit",
+    "nexus_opts.child_media_error_limit",
+    "nexus_opts.child_error_window_secs",
+    "log_level",
+];
+
 pub(crate) mod opts;
 pub(crate) mod pool;
+pub(crate) mod resource;
 
-pub static CONFIG: OnceCell<Config> = OnceCell::new();
+/// The running config. An update replaces it as a whole, those who got the
+/// previous config keep it for as long as they hold on to it.
+static CONFIG: OnceCell<RwLock<Arc<Config>>> = OnceCell::new();
 
 pub struct ConfigSubsystem(pub *mut spdk_subsystem);
 
@@ -86,7 +121,7 @@ impl ConfigSubsystem {
     }
 
     extern "C" fn config(w: *mut spdk_json_write_ctx) {
-        let data = match serde_json::to_string(Config::get()) {
+        let data = match serde_json::to_string(&*Config::get()) {
             Ok(it) => it,
             _ => return,
         };
@@ -122,6 +157,9 @@ pub struct Config {
     /// these options are not set/copied but are applied
     /// on target creation.
     pub nvmf_tcp_tgt_conf: NvmfTgtConfig,
+    /// options of the RDMA transport, which the target listens on in
+    /// addition to TCP when enabled
+    pub nvmf_rdma_tgt_conf: NvmfRdmaTransportOpts,
     /// generic iSCSI options
     pub iscsi_tgt_conf: IscsiTgtOpts,
     /// options specific to NVMe bdev types
@@ -130,6 +168,9 @@ pub struct Config {
     pub bdev_opts: BdevOpts,
     /// nexus specific options
     pub nexus_opts: NexusOpts,
+    /// log level filter, using the same syntax as RUST_LOG
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
 }
 
 impl Default for Config {
@@ -137,27 +178,33 @@ impl Default for Config {
         Self {
             source: None,
             nvmf_tcp_tgt_conf: Default::default(),
+            nvmf_rdma_tgt_conf: Default::default(),
             iscsi_tgt_conf: Default::default(),
             nvme_bdev_opts: Default::default(),
             bdev_opts: Default::default(),
             nexus_opts: Default::default(),
+            log_level: None,
         }
     }
 }
 
 impl Config {
-    /// initialize the config by executing F and return a reference to the
-    /// initialized data
-    pub fn get_or_init<F>(f: F) -> &'static Self
+    /// initialize the config by executing F and return the initialized
+    /// data
+    pub fn get_or_init<F>(f: F) -> Arc<Self>
     where
         F: FnOnce() -> Config,
     {
-        CONFIG.get_or_init(f)
+        CONFIG
+            .get_or_init(|| RwLock::new(Arc::new(f())))
+            .read()
+            .clone()
     }
 
-    /// mostly similar as above, but we do not need to pass a closure
-    pub fn get() -> &'static Self {
-        CONFIG.get().unwrap()
+    /// mostly similar as above, but we do not need to pass a closure. Returns
+    /// the config as last updated at runtime.
+    pub fn get() -> Arc<Self> {
+        CONFIG.get().unwrap().read().clone()
     }
 
     /// read the config file from disk. If the config file is empty, return the
@@ -201,10 +248,12 @@ impl Config {
         Config {
             source: self.source.clone(),
             nvmf_tcp_tgt_conf: self.nvmf_tcp_tgt_conf.get(),
+            nvmf_rdma_tgt_conf: self.nvmf_rdma_tgt_conf.get(),
             iscsi_tgt_conf: self.iscsi_tgt_conf.get(),
             nvme_bdev_opts: self.nvme_bdev_opts.get(),
             bdev_opts: self.bdev_opts.get(),
             nexus_opts: self.nexus_opts.get(),
+            log_level: self.log_level.clone(),
         }
     }
 
@@ -236,6 +285,133 @@ impl Config {
 
         // no way to validate this
         self.iscsi_tgt_conf.set();
+
+        if let Some(level) = &self.log_level {
+            if let Err(error) = logger::set_level(level) {
+                warn!("failed to set log level {}: {}", level, error);
+            }
+        }
         debug!("{:#?}", self);
     }
+
+    /// update the running config with the options given in YAML (or JSON),
+    /// options which are not given keep their current value. The update is
+    /// rejected as a whole when it changes an option that requires a restart.
+    pub fn update(data: &[u8]) -> Result<Arc<Self>, Error> {
+        let update: Value = if data.iter().all(u8::is_ascii_whitespace) {
+            Value::Null
+        } else {
+            serde_yaml::from_slice(data).map_err(|e| Error::InvalidConfig {
+                msg: e.to_string(),
+            })?
+        };
+        if !update.is_object() && !update.is_null() {
+            return Err(Error::InvalidConfig {
+                msg: "expected a mapping of options".into(),
+            });
+        }
+
+        let current = Config::get();
+        let old = to_value(&*current)?;
+        let mut new = old.clone();
+        merge(&mut new, update);
+        let config: Config =
+            serde_json::from_value(new).map_err(|e| Error::InvalidConfig {
+                msg: e.to_string(),
+            })?;
+
+        // compare what the update deserialises to, so that values which are
+        // merely spelled differently do not count as changes
+        let mut changed = Vec::new();
+        changed_options("", &old, &to_value(&config)?, &mut changed);
+        if changed.is_empty() {
+            return Ok(current);
+        }
+
+        let restart = changed
+            .iter()
+            .filter(|option| !RUNTIME_OPTIONS.contains(&option.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !restart.is_empty() {
+            return Err(Error::RestartRequired {
+                options: restart.join(", "),
+            });
+        }
+
+        if let (true, Some(level)) = (
+            changed.iter().any(|option| option == "log_level"),
+            &config.log_level,
+        ) {
+            logger::set_level(level).map_err(|msg| Error::LogLevel {
+                level: level.clone(),
+                msg,
+            })?;
+        }
+
+        // our NVMe bdevs use the config directly, but keep SPDK in sync for
+        // anything that queries it
+        if changed
+            .iter()
+            .any(|option| option.starts_with("nvme_bdev_opts."))
+            && !config.nvme_bdev_opts.set()
+        {
+            warn!("failed to update the SPDK NVMe bdev options");
+        }
+
+        info!(
+            "Updated Mayastor configuration settings: {}",
+            changed.join(", ")
+        );
+        let config = Arc::new(config);
+        *CONFIG.get().unwrap().write() = config.clone();
+        Ok(config)
+    }
+}
+
+/// serialise a config for comparing and merging options
+fn to_value(config: &Config) -> Result<Value, Error> {
+    serde_json::to_value(config).map_err(|e| Error::InvalidConfig {
+        msg: e.to_string(),
+    })
+}
+
+/// merge the options given in update into target, recursing into mappings
+fn merge(target: &mut Value, update: Value) {
+    match (target, update) {
+        (Value::Object(target), Value::Object(update)) => {
+            for (key, value) in update {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (_, Value::Null) => {}
+        (target, update) => *target = update,
+    }
+}
+
+/// collect the dotted names of the options whose value differs between old
+/// and new
+fn changed_options(
+    prefix: &str,
+    old: &Value,
+    new: &Value,
+    changed: &mut Vec<String>,
+) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, value) in new {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match old.get(key) {
+                    Some(old) => changed_options(&name, old, value, changed),
+                    None => changed.push(name),
+                }
+            }
+        }
+        (old, new) if old != new => changed.push(prefix.to_string()),
+        _ => {}
+    }
 }
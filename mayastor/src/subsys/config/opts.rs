@@ -51,6 +51,22 @@ pub struct NexusOpts {
     pub iscsi_nexus_port: u16,
     /// Port for replica target portal
     pub iscsi_replica_port: u16,
+    /// a child is slow when its mean IO latency is this many times that of
+    /// the other children of the nexus, 0 disables the slow child detection
+    pub slow_child_factor: u64,
+    /// the mean IO latency of a slow child must be at least this high
+    pub slow_child_min_latency_ms: u64,
+    /// a child is faulted once it has been slow for this long and rebuilt
+    /// once it has no longer been slow for this long
+    pub slow_child_window_secs: u64,
+    /// a child is retired once it has had this many transport errors within
+    /// the error window, the IOs which failed before that are retried
+    pub child_transport_error_limit: u64,
+    /// a child is retired once it has had this many media errors within the
+    /// error window
+    pub child_media_error_limit: u64,
+    /// window over which the IO errors of a child are counted
+    pub child_error_window_secs: u64,
 }
 
 /// Default nvmf port used for replicas.
@@ -73,6 +89,12 @@ impl Default for NexusOpts {
             iscsi_enable: true,
             iscsi_nexus_port: ISCSI_PORT_NEXUS,
             iscsi_replica_port: ISCSI_PORT_REPLICA,
+            slow_child_factor: 10,
+            slow_child_min_latency_ms: 1000,
+            slow_child_window_secs: 30,
+            child_transport_error_limit: 3,
+            child_media_error_limit: 1,
+            child_error_window_secs: 10,
         }
     }
 }
@@ -214,8 +236,82 @@ impl From<NvmfTcpTransportOpts> for spdk_nvmf_transport_opts {
     }
 }
 
+/// Settings for the RDMA transport, which the target listens on in addition
+/// to TCP when enabled
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NvmfRdmaTransportOpts {
+    /// listen on RDMA, this requires an RDMA capable device such as a
+    /// soft-RoCE (rxe) link on the interface of the target address
+    pub enable: bool,
+    /// max queue depth
+    max_queue_depth: u16,
+    /// max qpairs per controller
+    max_qpairs_per_ctrl: u16,
+    /// encapsulated data size
+    in_capsule_data_size: u32,
+    /// max IO size
+    max_io_size: u32,
+    /// IO unit size
+    io_unit_size: u32,
+    /// max admin queue depth per admin queue
+    max_aq_depth: u32,
+    /// num of shared buffers
+    num_shared_buf: u32,
+    /// cache size
+    buf_cache_size: u32,
+    /// dif
+    dif_insert_or_strip: bool,
+    /// abort execution timeout
+    abort_timeout_sec: u32,
+}
+
+impl Default for NvmfRdmaTransportOpts {
+    fn default() -> Self {
+        Self {
+            enable: try_from_env("NVMF_RDMA_ENABLE", false),
+            max_queue_depth: try_from_env("NVMF_RDMA_MAX_QUEUE_DEPTH", 128),
+            in_capsule_data_size: 4096,
+            max_io_size: 131_072,
+            io_unit_size: 8192,
+            max_qpairs_per_ctrl: 32,
+            num_shared_buf: try_from_env("NVMF_RDMA_NUM_SHARED_BUF", 4095),
+            buf_cache_size: try_from_env("NVMF_RDMA_BUF_CACHE_SIZE", 32),
+            dif_insert_or_strip: false,
+            max_aq_depth: 128,
+            abort_timeout_sec: 1,
+        }
+    }
+}
+
+impl GetOpts for NvmfRdmaTransportOpts {
+    fn get(&self) -> Self {
+        *self
+    }
+}
+
+impl From<NvmfRdmaTransportOpts> for spdk_nvmf_transport_opts {
+    fn from(o: NvmfRdmaTransportOpts) -> Self {
+        Self {
+            max_queue_depth: o.max_queue_depth,
+            max_qpairs_per_ctrlr: o.max_qpairs_per_ctrl,
+            in_capsule_data_size: o.in_capsule_data_size,
+            max_io_size: o.max_io_size,
+            io_unit_size: o.io_unit_size,
+            max_aq_depth: o.max_aq_depth,
+            num_shared_buffers: o.num_shared_buf,
+            buf_cache_size: o.buf_cache_size,
+            dif_insert_or_strip: o.dif_insert_or_strip,
+            abort_timeout_sec: o.abort_timeout_sec,
+            association_timeout: 120000,
+            transport_specific: std::ptr::null(),
+            opts_size: std::mem::size_of::<spdk_nvmf_transport_opts>() as u64,
+        }
+    }
+}
+
 /// generic settings for the NVMe bdev (all our replicas)
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct NvmeBdevOpts {
     /// action take on timeout
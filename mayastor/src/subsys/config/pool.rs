@@ -14,7 +14,7 @@ use crate::{
     replica::ShareType,
 };
 
-use rpc::mayastor::CreatePoolRequest;
+use rpc::mayastor::{CreatePoolRequest, PoolOvercommit};
 
 static CONFIG_FILE: OnceCell<String> = OnceCell::new();
 
@@ -130,13 +130,27 @@ impl PoolConfig {
         if let Some(pools) = self.pools.as_ref() {
             for pool in pools.iter() {
                 info!("creating pool {}", pool.name);
-                if let Err(error) = create_pool(pool.into()).await {
-                    error!(
-                        "failed to create pool {}: {}",
-                        pool.name,
-                        error.verbose()
-                    );
-                    failures += 1;
+                match create_pool(pool.into()).await {
+                    Ok(_) if pool.draining => {
+                        if let Some(lvs) = Lvs::lookup(&pool.name) {
+                            if let Err(error) = lvs.set_draining(true).await {
+                                error!(
+                                    "failed to drain pool {}: {}",
+                                    pool.name,
+                                    error.verbose()
+                                );
+                            }
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        error!(
+                            "failed to create pool {}: {}",
+                            pool.name,
+                            error.verbose()
+                        );
+                        failures += 1;
+                    }
                 }
             }
         }
@@ -166,6 +180,13 @@ struct Pool {
     name: String,
     /// bdevs to create outside of the nexus control
     disks: Vec<String>,
+    /// overcommit limit as a percentage of the capacity, 0 for no limit, the
+    /// limit stored on the pool is kept when it is not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overcommit: Option<u32>,
+    /// the pool does not accept new replicas
+    #[serde(default)]
+    draining: bool,
     /// list of replicas (not required, informational only)
     #[serde(skip_serializing)]
     replicas: Option<Vec<Replica>>,
@@ -177,6 +198,11 @@ impl From<&Pool> for CreatePoolRequest {
         Self {
             name: pool.name.clone(),
             disks: pool.disks.clone(),
+            overcommit: pool.overcommit.map(|percent| PoolOvercommit {
+                percent,
+            }),
+            // keys of encrypted replicas are never stored in the config
+            replica_keys: Vec::new(),
         }
     }
 }
@@ -188,6 +214,8 @@ impl From<SpdkPool> for Pool {
         Self {
             name: pool.get_name().to_string(),
             disks: vec![base.bdev_uri().unwrap_or_else(|| base.name())],
+            overcommit: Some(pool.get_overcommit()),
+            draining: pool.get_draining(),
             replicas: None,
         }
     }
@@ -0,0 +1,382 @@
+//! Declarative configuration of the replicas and nexuses of a node, kept
+//! alongside the pool configuration so that a standalone node can recreate
+//! its volumes without a control plane.
+//!
+//! Resources are applied after the pools have been created or imported.
+//! Applying the configuration is idempotent: a replica or nexus that already
+//! exists is left as is, other than sharing it when it is not shared yet.
+
+use std::{convert::TryFrom, fmt::Display, fs, path::Path, sync::Mutex};
+
+use futures::channel::oneshot;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bdev::{
+        nexus::instances,
+        nexus_create,
+        nexus_lookup,
+        ChildState,
+        Nexus,
+        VerboseError,
+    },
+    core::{runtime, Bdev, Cores, Mthread, Protocol, Reactor, Share},
+    lvs::{Lvol, Lvs},
+    replica::ShareType,
+};
+
+use rpc::mayastor::ShareProtocolNexus;
+
+static CONFIG_FILE: OnceCell<String> = OnceCell::new();
+
+/// Initialise the config file location
+fn init_config_file<P>(file: P)
+where
+    P: AsRef<Path> + Display + ToString,
+{
+    CONFIG_FILE.get_or_init(|| file.to_string());
+}
+
+/// Return the config file location
+fn get_config_file() -> Option<&'static String> {
+    CONFIG_FILE.get()
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceConfig {
+    replicas: Option<Vec<Replica>>,
+    nexuses: Option<Vec<NexusConfig>>,
+}
+
+impl ResourceConfig {
+    /// Load resource configuration from a file
+    pub fn load<P>(file: P) -> Result<ResourceConfig, serde_yaml::Error>
+    where
+        P: AsRef<Path> + Display + ToString,
+    {
+        init_config_file(&file);
+
+        let bytes = fs::read(&file).unwrap_or_default();
+
+        if bytes.is_empty() {
+            return Ok(ResourceConfig::default());
+        }
+
+        serde_yaml::from_slice(&bytes)
+    }
+
+    /// Write this resource configuration to a file
+    fn write<P>(&self, file: P) -> Result<(), std::io::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let config = serde_yaml::to_string(&self).map_err(|error| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("serialization error: {}", error),
+            )
+        })?;
+
+        fs::write(&file, config.as_bytes())
+    }
+
+    /// Export current resource configuration
+    pub async fn export(self) {
+        static MUTEX: Lazy<Mutex<u32>> = Lazy::new(|| Mutex::new(0));
+
+        if let Some(file) = get_config_file() {
+            debug!("saving resource configuration");
+
+            let (sender, receiver) = oneshot::channel::<()>();
+
+            runtime::spawn(async move {
+                let future = runtime::spawn_blocking(move || {
+                    let _guard = MUTEX.lock().unwrap();
+                    if let Err(error) = self.write(file) {
+                        error!(
+                            "error saving resource configuration: {}",
+                            error
+                        );
+                    } else {
+                        info!("resource configuration saved to {}", file);
+                    }
+                });
+
+                if let Err(error) = future.await {
+                    error!("error joining thread: {}", error);
+                }
+
+                let future = Mthread::get_init().spawn_local(async move {
+                    if sender.send(()).is_err() {
+                        error!("error sending completion");
+                    }
+                });
+
+                if let Err(error) = future.unwrap().await {
+                    error!("cancelled completion: {}", error);
+                }
+            });
+
+            if let Err(error) = receiver.await {
+                error!("cancelled completion: {}", error);
+            }
+        }
+    }
+
+    /// Capture current resource configuration
+    pub fn capture() -> ResourceConfig {
+        let replicas = Lvs::iter()
+            .filter_map(|lvs| lvs.lvols())
+            .flatten()
+            .filter(|lvol| !lvol.is_snapshot())
+            .map(Replica::from)
+            .collect();
+        let nexuses = instances()
+            .iter()
+            .map(|nexus| NexusConfig::from(nexus.as_ref()))
+            .collect();
+        ResourceConfig {
+            replicas: Some(replicas),
+            nexuses: Some(nexuses),
+        }
+    }
+
+    /// Create the replicas specified in this configuration that do not exist
+    /// yet and share them if needed
+    async fn create_replicas(&self) -> usize {
+        let mut failures = 0;
+        if let Some(replicas) = self.replicas.as_ref() {
+            for replica in replicas.iter() {
+                if let Err(error) = replica.apply().await {
+                    error!(
+                        "failed to create replica {}: {}",
+                        replica.name, error
+                    );
+                    failures += 1;
+                }
+            }
+        }
+        failures
+    }
+
+    /// Create the nexuses specified in this configuration that do not exist
+    /// yet and share them if needed
+    async fn create_nexuses(&self) -> usize {
+        let mut failures = 0;
+        if let Some(nexuses) = self.nexuses.as_ref() {
+            for nexus in nexuses.iter() {
+                if let Err(error) = nexus.apply().await {
+                    error!("failed to create nexus {}: {}", nexus.name, error);
+                    failures += 1;
+                }
+            }
+        }
+        failures
+    }
+
+    /// Create the replicas and then the nexuses specified in this
+    /// configuration, returning the number of failures
+    pub async fn apply(&self) -> usize {
+        self.create_replicas().await + self.create_nexuses().await
+    }
+
+    /// Import replicas and nexuses, must be called after the pools have been
+    /// imported
+    pub fn import_resources(self) {
+        assert_eq!(Cores::current(), Cores::first());
+        Reactor::block_on(async move {
+            let errors = self.apply().await;
+            if errors != 0 {
+                warn!(
+                    "Not all resources were imported successfully ({} errors)",
+                    errors
+                );
+            }
+        });
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+/// Replicas that we create on a pool and optionally share via `ShareType`
+struct Replica {
+    /// name of the replica
+    name: String,
+    /// uuid of the replica
+    uuid: String,
+    /// name of the pool the replica is created on
+    pool: String,
+    /// size of the replica in bytes
+    size: u64,
+    /// the replica is thin provisioned
+    #[serde(default)]
+    thin: bool,
+    /// share type if shared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    share: Option<ShareType>,
+}
+
+impl Replica {
+    /// Create the replica if it does not exist and share it as configured
+    async fn apply(&self) -> Result<(), String> {
+        let lvol = match Bdev::lookup_by_name(&self.name) {
+            Some(bdev) => Lvol::try_from(bdev).map_err(|e| e.verbose())?,
+            None => {
+                let lvs = Lvs::lookup(&self.pool)
+                    .ok_or_else(|| format!("pool {} not found", self.pool))?;
+                info!("creating replica {}", self.name);
+                let uuid = if self.uuid.is_empty() {
+                    None
+                } else {
+                    Some(self.uuid.as_str())
+                };
+                lvs.create_lvol(&self.name, self.size, uuid, self.thin)
+                    .await
+                    .map_err(|e| e.verbose())?
+            }
+        };
+
+        // the keys of encrypted replicas are never stored in the config, so
+        // a locked replica is left for a client to open
+        if lvol.is_locked() {
+            warn!("replica {} is locked, not sharing it", self.name);
+            return Ok(());
+        }
+
+        match self.share {
+            Some(ShareType::Nvmf) if lvol.shared() != Some(Protocol::Nvmf) => {
+                lvol.share_nvmf(None).await.map_err(|e| e.verbose())?;
+            }
+            Some(ShareType::Iscsi) => {
+                return Err("replicas cannot be shared over iSCSI".into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Convert an Lvol into a Replica
+impl From<Lvol> for Replica {
+    fn from(lvol: Lvol) -> Self {
+        let share = match lvol.shared() {
+            Some(Protocol::Nvmf) => Some(ShareType::Nvmf),
+            Some(Protocol::Iscsi) => Some(ShareType::Iscsi),
+            _ => None,
+        };
+        Self {
+            name: lvol.name(),
+            uuid: lvol.uuid(),
+            pool: lvol.pool(),
+            size: lvol.size(),
+            thin: lvol.is_thin(),
+            share,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+/// Nexuses that we create from a list of children and optionally publish
+struct NexusConfig {
+    /// name of the nexus
+    name: String,
+    /// uuid of the nexus
+    uuid: String,
+    /// size of the nexus in bytes
+    size: u64,
+    /// children of the nexus
+    children: Vec<NexusChildConfig>,
+    /// protocol the nexus is published with, if published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    share: Option<ShareProtocolNexus>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+/// Child of a nexus
+struct NexusChildConfig {
+    /// URI of the child
+    uri: String,
+    /// the child was in sync with the other children, if not it is added to
+    /// the nexus after it has been created and rebuilt
+    #[serde(default = "default_healthy")]
+    healthy: bool,
+}
+
+fn default_healthy() -> bool {
+    true
+}
+
+impl NexusConfig {
+    /// Create the nexus if it does not exist and publish it as configured.
+    /// The nexus is created from the healthy children, the other children
+    /// are added afterwards and rebuilt from the healthy ones.
+    async fn apply(&self) -> Result<(), String> {
+        if nexus_lookup(&self.name).is_none() {
+            let (healthy, unhealthy): (Vec<_>, Vec<_>) =
+                self.children.iter().partition(|c| c.healthy);
+            if healthy.is_empty() {
+                return Err(format!(
+                    "nexus {} has no healthy child",
+                    self.name
+                ));
+            }
+
+            info!("creating nexus {}", self.name);
+            let uuid = if self.uuid.is_empty() {
+                None
+            } else {
+                Some(self.uuid.as_str())
+            };
+            let children =
+                healthy.iter().map(|c| c.uri.clone()).collect::<Vec<_>>();
+            nexus_create(&self.name, self.size, uuid, &children)
+                .await
+                .map_err(|e| e.verbose())?;
+
+            let nexus = nexus_lookup(&self.name)
+                .ok_or_else(|| format!("nexus {} not found", self.name))?;
+            for child in unhealthy {
+                // a child that can no longer be added does not prevent the
+                // nexus from being created
+                if let Err(e) = nexus.add_child(&child.uri, false).await {
+                    error!(
+                        "failed to add child {} to nexus {}: {}",
+                        child.uri,
+                        self.name,
+                        e.verbose()
+                    );
+                }
+            }
+        }
+
+        let nexus = nexus_lookup(&self.name)
+            .ok_or_else(|| format!("nexus {} not found", self.name))?;
+        if let Some(protocol) = self.share {
+            if nexus.nexus_target.is_none() {
+                nexus.share(protocol, None).await.map_err(|e| e.verbose())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert a Nexus into a NexusConfig
+impl From<&Nexus> for NexusConfig {
+    fn from(nexus: &Nexus) -> Self {
+        Self {
+            name: nexus.name.clone(),
+            uuid: nexus.bdev.uuid_as_string(),
+            size: nexus.size,
+            children: nexus
+                .children
+                .iter()
+                .map(|child| NexusChildConfig {
+                    uri: child.get_name().to_string(),
+                    healthy: child.state() == ChildState::Open,
+                })
+                .collect(),
+            share: nexus.nexus_target.as_ref().map(ShareProtocolNexus::from),
+        }
+    }
+}
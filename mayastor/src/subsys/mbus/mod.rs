@@ -3,8 +3,10 @@
 //! It is designed to make sending events to control plane easy in the future.
 //!
 //! A Registration subsystem is used to keep moac in the loop
-//! about the lifecycle of mayastor instances.
+//! about the lifecycle of mayastor instances, and a pool space monitor
+//! warns the control plane about pools which are running low on space.
 
+pub mod pool_space;
 pub mod registration;
 
 use crate::core::MayastorEnvironment;
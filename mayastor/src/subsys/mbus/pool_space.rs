@@ -0,0 +1,138 @@
+//! Pool space monitor which lets the control plane know when a pool is
+//! running low on space. With thin provisioned replicas the sum of the
+//! replica sizes can exceed the capacity of the pool, in which case writes
+//! fail once the pool is full, so the control plane should act before that.
+//!
+//! The pools are checked every `CHECK_INTERVAL_SEC` and a `PoolLowSpace`
+//! message is published when the used capacity of a pool crosses
+//! `LOW_SPACE_PERCENT`. The message is sent again only after the usage has
+//! dropped below the threshold in the meantime. Both can be overridden by
+//! the `MAYASTOR_POOL_CHECK_INTERVAL_SEC` and
+//! `MAYASTOR_POOL_LOW_SPACE_PERCENT` environment variables.
+
+use std::{collections::HashSet, env, time::Duration};
+
+use mbus_api::{v0::*, *};
+
+use crate::{core::Mthread, lvs::Lvs};
+
+/// How often the pools are checked
+const CHECK_INTERVAL_SEC: Duration = Duration::from_secs(10);
+/// Percentage of the pool capacity in use at which a pool is low on space
+const LOW_SPACE_PERCENT: u64 = 90;
+
+/// Space accounting of a single pool
+#[derive(Debug)]
+struct PoolSpace {
+    name: String,
+    capacity: u64,
+    used: u64,
+    committed: u64,
+}
+
+pub struct PoolSpaceMonitor {
+    /// Id of the node that mayastor is running on
+    node: NodeId,
+    /// how often the pools are checked
+    interval: Duration,
+    /// usage threshold as a percentage of the pool capacity
+    threshold: u64,
+    /// pools which are currently low on space
+    low: HashSet<String>,
+}
+
+impl PoolSpaceMonitor {
+    /// runner which periodically checks the space of all pools
+    pub async fn run(node: String) -> Result<(), ()> {
+        Self::new(&NodeId::from(node)).run_loop().await;
+        Ok(())
+    }
+
+    fn new(node: &NodeId) -> Self {
+        Self {
+            node: node.to_owned(),
+            interval: match env::var("MAYASTOR_POOL_CHECK_INTERVAL_SEC")
+                .map(|v| v.parse::<u64>())
+            {
+                Ok(Ok(num)) => Duration::from_secs(num),
+                _ => CHECK_INTERVAL_SEC,
+            },
+            threshold: match env::var("MAYASTOR_POOL_LOW_SPACE_PERCENT")
+                .map(|v| v.parse::<u64>())
+            {
+                Ok(Ok(num)) => num,
+                _ => LOW_SPACE_PERCENT,
+            },
+            low: HashSet::new(),
+        }
+    }
+
+    async fn run_loop(&mut self) {
+        loop {
+            tokio::time::sleep(self.interval).await;
+            let pools = Self::pools().await;
+            self.low
+                .retain(|name| pools.iter().any(|p| &p.name == name));
+            for pool in pools {
+                self.check(pool).await;
+            }
+        }
+    }
+
+    /// collect the space accounting of all pools, pools can only be accessed
+    /// from the init thread
+    async fn pools() -> Vec<PoolSpace> {
+        let rx = Mthread::get_init().spawn_local(async {
+            Lvs::iter()
+                .map(|lvs| PoolSpace {
+                    name: lvs.name().to_string(),
+                    capacity: lvs.capacity(),
+                    used: lvs.used(),
+                    committed: lvs.committed(),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        match rx {
+            Ok(rx) => rx.await.unwrap_or_default(),
+            Err(error) => {
+                error!("failed to collect pool space: {}", error);
+                Vec::new()
+            }
+        }
+    }
+
+    /// publish a low space message when the pool crosses the threshold
+    async fn check(&mut self, pool: PoolSpace) {
+        let low = pool.capacity > 0
+            && pool.used as u128 * 100
+                >= pool.capacity as u128 * self.threshold as u128;
+
+        if !low {
+            self.low.remove(&pool.name);
+            return;
+        }
+
+        if !self.low.insert(pool.name.clone()) {
+            return;
+        }
+
+        warn!(
+            "pool {} is low on space, {} of {} bytes used, {} bytes committed",
+            pool.name, pool.used, pool.capacity, pool.committed
+        );
+
+        let payload = PoolLowSpace {
+            node: self.node.clone(),
+            id: PoolId::from(pool.name.as_str()),
+            capacity: pool.capacity,
+            used: pool.used,
+            committed: pool.committed,
+        };
+        if let Err(error) = payload.publish().await {
+            error!("failed to publish low space of {}: {:?}", pool.name, error);
+            // try again on the next check
+            self.low.remove(&pool.name);
+        }
+    }
+}
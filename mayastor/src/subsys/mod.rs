@@ -2,14 +2,18 @@
 //! Main file to register additional subsystems
 
 pub use config::{
-    opts::{NexusOpts, NvmeBdevOpts},
+    opts::{NexusOpts, NvmeBdevOpts, NvmfRdmaTransportOpts},
     pool::PoolConfig,
+    resource::ResourceConfig,
     Config,
     ConfigSubsystem,
+    Error as ConfigError,
 };
 pub use nvmf::{
     create_snapshot,
+    set_snapshot_id,
     set_snapshot_time,
+    snapshot_time,
     Error as NvmfError,
     NvmeCpl,
     NvmfReq,
@@ -26,6 +30,7 @@ use spdk_sys::{
 pub use mbus::{
     mbus_endpoint,
     message_bus_init,
+    pool_space::PoolSpaceMonitor,
     registration::Registration,
     MessageBusSubsystem,
 };
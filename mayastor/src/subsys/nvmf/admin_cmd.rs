@@ -1,7 +1,6 @@
 //! Handlers for custom NVMe Admin commands
 
 use std::{
-    convert::TryFrom,
     ffi::c_void,
     ptr::NonNull,
     time::{SystemTime, UNIX_EPOCH},
@@ -14,12 +13,11 @@ use spdk_sys::{
     spdk_nvme_cmd,
     spdk_nvme_cpl,
     spdk_nvme_status,
-    spdk_nvmf_bdev_ctrlr_nvme_passthru_admin,
     spdk_nvmf_request,
 };
 
 use crate::{
-    bdev::nexus::nexus_module,
+    bdev::{nexus::nexus_module, nexus_lookup},
     core::{nvme_admin_opc, Bdev, Reactors},
     lvs::Lvol,
 };
@@ -47,6 +45,24 @@ impl NvmfReq {
             .unwrap(),
         )
     }
+
+    /// Complete the request with a generic success or internal device
+    /// error status
+    pub(crate) fn complete(&self, success: bool) {
+        let mut rsp = self.response();
+        let nvme_status = rsp.status();
+
+        nvme_status.set_sct(0); // SPDK_NVME_SCT_GENERIC
+        nvme_status.set_sc(if success {
+            0
+        } else {
+            0x06 // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+        });
+
+        unsafe {
+            spdk_sys::spdk_nvmf_request_complete(self.0.as_ptr());
+        }
+    }
 }
 
 impl From<*mut c_void> for NvmfReq {
@@ -55,37 +71,53 @@ impl From<*mut c_void> for NvmfReq {
     }
 }
 
-/// Set the snapshot time in an spdk_nvme_cmd struct to the current time
-/// Returns seconds since Unix epoch
-pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
-    // encode snapshot time in cdw10/11
-    let now = SystemTime::now()
+/// Returns the current time, in seconds since Unix epoch, to be used as
+/// snapshot id
+pub fn snapshot_time() -> u64 {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_secs();
+        .as_secs()
+}
+
+/// Encode the snapshot id in an spdk_nvme_cmd struct
+pub fn set_snapshot_id(cmd: &mut spdk_nvme_cmd, id: u64) {
+    // encode snapshot id in cdw10/11
     unsafe {
-        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = now as u32;
-        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (now >> 32) as u32;
+        *spdk_sys::nvme_cmd_cdw10_get(&mut *cmd) = id as u32;
+        *spdk_sys::nvme_cmd_cdw11_get(&mut *cmd) = (id >> 32) as u32;
     }
-    now as u64
 }
 
-/// NVMf custom command handler for opcode c0h
-/// Called from nvmf_ctrlr_process_admin_cmd
-/// Return: <0 for any error, caller handles it as unsupported opcode
-extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
-    debug!("nvmf_create_snapshot_hdlr {:?}", req);
+/// Returns the snapshot id encoded in an spdk_nvme_cmd struct
+pub fn snapshot_id(cmd: &spdk_nvme_cmd) -> u64 {
+    unsafe {
+        spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
+            | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32
+    }
+}
 
+/// Set the snapshot time in an spdk_nvme_cmd struct to the current time
+/// Returns seconds since Unix epoch
+pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
+    let now = snapshot_time();
+    set_snapshot_id(cmd, now);
+    now
+}
+
+/// Returns the bdev of the only namespace of the subsystem the request was
+/// sent to, if it has exactly one
+fn request_bdev(req: *mut spdk_nvmf_request) -> Option<Bdev> {
     let subsys = unsafe { spdk_sys::spdk_nvmf_request_get_subsystem(req) };
     if subsys.is_null() {
         debug!("subsystem is null");
-        return -1;
+        return None;
     }
 
     /* Only process this request if it has exactly one namespace */
     if unsafe { spdk_sys::spdk_nvmf_subsystem_get_max_nsid(subsys) } != 1 {
         debug!("multiple namespaces");
-        return -1;
+        return None;
     }
 
     /* Forward to first namespace if it supports NVME admin commands */
@@ -100,25 +132,49 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     if rc != 0 {
         /* No bdev found for this namespace. Continue. */
         debug!("no bdev found");
-        return -1;
+        return None;
     }
 
-    let bd = Bdev::from(bdev);
+    Some(Bdev::from(bdev))
+}
+
+/// NVMf custom command handler for opcode c0h
+/// Called from nvmf_ctrlr_process_admin_cmd
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_create_snapshot_hdlr {:?}", req);
+
+    let bd = match request_bdev(req) {
+        Some(bd) => bd,
+        None => return -1,
+    };
+
     if bd.driver() == nexus_module::NEXUS_NAME {
-        // Received command on a published Nexus
-        set_snapshot_time(unsafe {
+        // Received command on a published Nexus, snapshot all its children
+        // at the same point in time. The subsystem can not be paused from
+        // within one of its own admin commands, so rely on the nexus
+        // draining the writes.
+        let snapshot_id = set_snapshot_time(unsafe {
             &mut *spdk_sys::spdk_nvmf_request_get_cmd(req)
         });
-        unsafe {
-            spdk_nvmf_bdev_ctrlr_nvme_passthru_admin(bdev, desc, ch, req, None)
-        }
-    } else if let Ok(lvol) = Lvol::try_from(bd) {
+        let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+        let name = bd.name();
+        Reactors::master().send_future(async move {
+            let success = match nexus_lookup(&name) {
+                Some(nexus) => nexus
+                    .snapshot_children(snapshot_id)
+                    .await
+                    .map_err(|e| error!("{}: {}", name, e))
+                    .is_ok(),
+                None => false,
+            };
+            nvmf_req.complete(success);
+        });
+        1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+    } else if let Ok(lvol) = Lvol::from_share_bdev(bd) {
         // Received command on a shared replica (lvol)
         let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
-        let snapshot_time = unsafe {
-            spdk_sys::nvme_cmd_cdw10_get_val(cmd) as u64
-                | (spdk_sys::nvme_cmd_cdw11_get_val(cmd) as u64) << 32
-        };
+        let snapshot_time = snapshot_id(unsafe { &*cmd });
         let snapshot_name =
             Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
         let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
@@ -133,28 +189,60 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     }
 }
 
+/// NVMf custom command handler for opcode c1h, which destroys the snapshot
+/// with the given id of a shared replica (lvol)
+/// Called from nvmf_ctrlr_process_admin_cmd
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_destroy_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    debug!("nvmf_destroy_snapshot_hdlr {:?}", req);
+
+    let lvol = match request_bdev(req).map(Lvol::from_share_bdev) {
+        Some(Ok(lvol)) => lvol,
+        _ => {
+            debug!("unsupported bdev driver");
+            return -1;
+        }
+    };
+
+    let cmd = unsafe { spdk_sys::spdk_nvmf_request_get_cmd(req) };
+    let snapshot_name =
+        Lvol::format_snapshot_name(&lvol.name(), snapshot_id(unsafe { &*cmd }));
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let success = lvol
+            .destroy_snapshot(&snapshot_name)
+            .await
+            .map_err(|e| error!("{}", e))
+            .is_ok();
+        nvmf_req.complete(success);
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
 pub fn create_snapshot(
     lvol: Lvol,
     cmd: &spdk_sys::spdk_nvme_cmd,
     io: *mut spdk_sys::spdk_bdev_io,
 ) {
-    let snapshot_time = unsafe {
-        spdk_sys::nvme_cmd_cdw10_get_val(&*cmd) as u64
-            | (spdk_sys::nvme_cmd_cdw11_get_val(&*cmd) as u64) << 32
-    };
-    let snapshot_name = Lvol::format_snapshot_name(&lvol.name(), snapshot_time);
+    let snapshot_name =
+        Lvol::format_snapshot_name(&lvol.name(), snapshot_id(cmd));
     // Blobfs operations must be on md_thread
     Reactors::master().send_future(async move {
         lvol.create_snapshot_local(io, &snapshot_name).await;
     });
 }
 
-/// Register custom NVMe admin command handler
+/// Register custom NVMe admin command handlers
 pub fn setup_create_snapshot_hdlr() {
     unsafe {
         spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
             nvme_admin_opc::CREATE_SNAPSHOT,
             Some(nvmf_create_snapshot_hdlr),
         );
+        spdk_sys::spdk_nvmf_set_custom_admin_cmd_hdlr(
+            nvme_admin_opc::DESTROY_SNAPSHOT,
+            Some(nvmf_destroy_snapshot_hdlr),
+        );
     }
 }
@@ -3,8 +3,9 @@
 //! but also, if desired a nexus device. A target makes use of
 //! several transports, what transports that exactly is -- is flexible.
 //!
-//! In our case we currently only deal with TCP. We create two transports
-//! one for the frontend (nexus) and one for the backend (replica)
+//! In our case we deal with TCP and, when enabled in the config, RDMA. For
+//! each of them we listen on two ports, one for the frontend (nexus) and one
+//! for the backend (replica)
 //!
 //! As connections come on, we randomly schedule them across cores by putting
 //! the qpair in a poll group that is allocated during reactor start.
@@ -13,7 +14,14 @@ use std::cell::RefCell;
 use nix::errno::Errno;
 use snafu::Snafu;
 
-pub use admin_cmd::{create_snapshot, set_snapshot_time, NvmeCpl, NvmfReq};
+pub use admin_cmd::{
+    create_snapshot,
+    set_snapshot_id,
+    set_snapshot_time,
+    snapshot_time,
+    NvmeCpl,
+    NvmfReq,
+};
 use poll_groups::PollGroup;
 use spdk_sys::{
     spdk_subsystem,
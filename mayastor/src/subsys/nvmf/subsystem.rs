@@ -14,21 +14,26 @@ use spdk_sys::{
     nvmf_subsystem_set_ana_state,
     nvmf_subsystem_set_cntlid_range,
     spdk_bdev_nvme_opts,
+    spdk_nvmf_host_get_nqn,
     spdk_nvmf_ns_get_bdev,
     spdk_nvmf_ns_opts,
     spdk_nvmf_subsystem,
+    spdk_nvmf_subsystem_add_host,
     spdk_nvmf_subsystem_add_listener,
     spdk_nvmf_subsystem_add_ns_ext,
     spdk_nvmf_subsystem_create,
     spdk_nvmf_subsystem_destroy,
     spdk_nvmf_subsystem_get_first,
+    spdk_nvmf_subsystem_get_first_host,
     spdk_nvmf_subsystem_get_first_listener,
     spdk_nvmf_subsystem_get_first_ns,
     spdk_nvmf_subsystem_get_next,
+    spdk_nvmf_subsystem_get_next_host,
     spdk_nvmf_subsystem_get_next_listener,
     spdk_nvmf_subsystem_get_nqn,
     spdk_nvmf_subsystem_listener_get_trid,
     spdk_nvmf_subsystem_pause,
+    spdk_nvmf_subsystem_remove_host,
     spdk_nvmf_subsystem_resume,
     spdk_nvmf_subsystem_set_allow_any_host,
     spdk_nvmf_subsystem_set_ana_reporting,
@@ -45,7 +50,11 @@ use crate::{
     core::{Bdev, Reactors},
     ffihelper::{cb_arg, AsStr, FfiResult, IntoCString},
     subsys::{
-        nvmf::{transport::TransportId, Error, NVMF_TGT},
+        nvmf::{
+            transport::{TransportId, TransportType},
+            Error,
+            NVMF_TGT,
+        },
         Config,
     },
 };
@@ -107,6 +116,7 @@ impl Debug for NvmfSubsystem {
                     "allow_any_host",
                     &self.0.as_ref().flags.allow_any_host(),
                 )
+                .field("allowed_hosts", &self.allowed_hosts())
                 .field("ana_reporting", &self.0.as_ref().flags.ana_reporting())
                 .field("listeners", &self.listeners_to_vec())
                 .finish()
@@ -245,6 +255,87 @@ impl NvmfSubsystem {
         };
     }
 
+    /// returns true if any host may connect to the subsystem
+    pub fn allows_any(&self) -> bool {
+        unsafe { self.0.as_ref().flags.allow_any_host() }
+    }
+
+    /// add the host with the given NQN to the hosts allowed to connect to
+    /// the subsystem, which only takes effect when not any host is allowed
+    pub fn allow_host(&self, host_nqn: &str) -> Result<(), Error> {
+        let cnqn = host_nqn.into_cstring();
+        unsafe { spdk_nvmf_subsystem_add_host(self.0.as_ptr(), cnqn.as_ptr()) }
+            .to_result(|e| Error::Subsystem {
+                source: Errno::from_i32(e),
+                nqn: self.get_nqn(),
+                msg: format!("failed to allow host {}", host_nqn),
+            })
+    }
+
+    /// remove the host with the given NQN from the allowed hosts, hosts
+    /// which are connected already remain connected
+    pub fn disallow_host(&self, host_nqn: &str) -> Result<(), Error> {
+        let cnqn = host_nqn.into_cstring();
+        unsafe {
+            spdk_nvmf_subsystem_remove_host(self.0.as_ptr(), cnqn.as_ptr())
+        }
+        .to_result(|e| Error::Subsystem {
+            source: Errno::from_i32(e),
+            nqn: self.get_nqn(),
+            msg: format!("failed to disallow host {}", host_nqn),
+        })
+    }
+
+    /// returns the NQNs of the hosts allowed to connect to the subsystem
+    pub fn allowed_hosts(&self) -> Vec<String> {
+        let mut hosts = Vec::new();
+        unsafe {
+            let mut host = spdk_nvmf_subsystem_get_first_host(self.0.as_ptr());
+            while !host.is_null() {
+                hosts.push(spdk_nvmf_host_get_nqn(host).as_str().to_string());
+                host = spdk_nvmf_subsystem_get_next_host(self.0.as_ptr(), host);
+            }
+        }
+        hosts
+    }
+
+    /// only allow the hosts with the given NQNs to connect to the subsystem.
+    /// An empty list leaves a subsystem which allows any host as it is, but
+    /// is rejected for a restricted one as that would allow any host to
+    /// connect, which has to be asked for with `allow_any_host`.
+    pub fn set_allowed_hosts(&self, hosts: &[String]) -> Result<(), Error> {
+        if hosts.is_empty() {
+            if self.allows_any() {
+                return Ok(());
+            }
+            return Err(Error::Subsystem {
+                source: Errno::EPERM,
+                nqn: self.get_nqn(),
+                msg: "removing all allowed hosts would allow any host".into(),
+            });
+        }
+        for host in hosts {
+            self.allow_host(host)?;
+        }
+        for host in self.allowed_hosts() {
+            if !hosts.contains(&host) {
+                self.disallow_host(&host)?;
+            }
+        }
+        self.allow_any(false);
+        Ok(())
+    }
+
+    /// allow any host to connect to the subsystem, removing the hosts which
+    /// were allowed explicitly
+    pub fn allow_any_host(&self) -> Result<(), Error> {
+        for host in self.allowed_hosts() {
+            self.disallow_host(&host)?;
+        }
+        self.allow_any(true);
+        Ok(())
+    }
+
     /// enable Asymmetric Namespace Access (ANA) reporting
     pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
         match std::env::var("NEXUS_NVMF_ANA_ENABLE") {
@@ -301,26 +392,33 @@ impl NvmfSubsystem {
 
         let cfg = Config::get();
 
-        // dont yet enable both ports, IOW just add one transportID now
-
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-
-        let (s, r) = oneshot::channel::<i32>();
-        unsafe {
-            spdk_nvmf_subsystem_add_listener(
-                self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                Some(listen_cb),
-                cb_arg(s),
+        // dont yet enable both ports, IOW just add the replica port of each
+        // transport now. TCP comes first so that it is the endpoint the
+        // subsystem is shared with
+        for transport in TransportType::enabled() {
+            let trid_replica = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_replica_port,
             );
-        }
 
-        r.await.expect("listener callback gone").to_result(|e| {
-            Error::Transport {
-                source: Errno::from_i32(e),
-                msg: "Failed to add listener".to_string(),
+            let (s, r) = oneshot::channel::<i32>();
+            unsafe {
+                spdk_nvmf_subsystem_add_listener(
+                    self.0.as_ptr(),
+                    trid_replica.as_ptr(),
+                    Some(listen_cb),
+                    cb_arg(s),
+                );
             }
-        })
+
+            r.await.expect("listener callback gone").to_result(|e| {
+                Error::Transport {
+                    source: Errno::from_i32(e),
+                    msg: format!("Failed to add {:?} listener", transport),
+                }
+            })?;
+        }
+        Ok(())
     }
 
     /// start the subsystem previously created -- note that we destroy it on
@@ -532,27 +630,34 @@ impl NvmfSubsystem {
             s.send(status).unwrap();
         }
         let cfg = Config::get();
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
 
-        let (s, r) = oneshot::channel::<i32>();
-
-        unsafe {
-            nvmf_subsystem_set_ana_state(
-                self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                ana_state,
-                Some(set_ana_state_cb),
-                cb_arg(s),
+        for transport in TransportType::enabled() {
+            let trid_replica = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_replica_port,
             );
-        }
 
-        r.await
-            .expect("Cancellation is not supported")
-            .to_result(|e| Error::Subsystem {
-                source: Errno::from_i32(-e),
-                nqn: self.get_nqn(),
-                msg: "failed to set_ana_state of the subsystem".to_string(),
-            })
+            let (s, r) = oneshot::channel::<i32>();
+
+            unsafe {
+                nvmf_subsystem_set_ana_state(
+                    self.0.as_ptr(),
+                    trid_replica.as_ptr(),
+                    ana_state,
+                    Some(set_ana_state_cb),
+                    cb_arg(s),
+                );
+            }
+
+            r.await
+                .expect("Cancellation is not supported")
+                .to_result(|e| Error::Subsystem {
+                    source: Errno::from_i32(-e),
+                    nqn: self.get_nqn(),
+                    msg: "failed to set_ana_state of the subsystem".to_string(),
+                })?;
+        }
+        Ok(())
     }
 
     /// destroy all subsystems associated with our target, subsystems must be in
@@ -596,7 +701,11 @@ impl NvmfSubsystem {
 
     /// lookup a subsystem by its UUID
     pub fn nqn_lookup(uuid: &str) -> Option<NvmfSubsystem> {
-        let nqn = gen_nqn(uuid);
+        Self::lookup(&gen_nqn(uuid))
+    }
+
+    /// lookup the subsystem with the given NQN
+    pub fn lookup(nqn: &str) -> Option<NvmfSubsystem> {
         NvmfSubsystem::first()
             .unwrap()
             .into_iter()
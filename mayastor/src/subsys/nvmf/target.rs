@@ -37,7 +37,7 @@ use crate::{
             poll_groups::PollGroup,
             subsystem::NvmfSubsystem,
             transport,
-            transport::{get_ipv4_address, TransportId},
+            transport::{get_ipv4_address, TransportId, TransportType},
             Error,
             NVMF_PGS,
         },
@@ -183,10 +183,17 @@ impl Target {
         };
     }
 
-    /// add the transport to the target
+    /// add the enabled transports to the target
     fn add_transport(&self) {
         Reactors::master().send_future(async {
-            let result = transport::add_tcp_transport().await;
+            let mut result = Ok(());
+            for t in TransportType::enabled() {
+                result = transport::add_transport(t).await;
+                if let Err(e) = &result {
+                    error!("{}", e);
+                    break;
+                }
+            }
             NVMF_TGT.with(|t| {
                 if result.is_err() {
                     t.borrow_mut().next_state = TargetState::Invalid;
@@ -253,11 +260,10 @@ impl Target {
         0
     }
 
-    /// Listen for incoming connections by default we only listen on the replica
-    /// port
+    /// Listen for incoming connections on the nexus and the replica port of
+    /// every enabled transport
     fn listen(&mut self) -> Result<()> {
         let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
         let mut opts = spdk_nvmf_listen_opts::default();
         unsafe {
             spdk_nvmf_listen_opts_init(
@@ -265,40 +271,51 @@ impl Target {
                 std::mem::size_of::<spdk_nvmf_listen_opts>() as u64,
             );
         }
-        let rc = unsafe {
-            spdk_nvmf_tgt_listen_ext(
-                self.tgt.as_ptr(),
-                trid_nexus.as_ptr(),
-                &mut opts,
-            )
-        };
 
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to back target".into(),
-            });
-        }
+        for transport in TransportType::enabled() {
+            let trid_nexus = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_nexus_port,
+            );
+            let rc = unsafe {
+                spdk_nvmf_tgt_listen_ext(
+                    self.tgt.as_ptr(),
+                    trid_nexus.as_ptr(),
+                    &mut opts,
+                )
+            };
 
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-        let rc = unsafe {
-            spdk_nvmf_tgt_listen_ext(
-                self.tgt.as_ptr(),
-                trid_replica.as_ptr(),
-                &mut opts,
-            )
-        };
+            if rc != 0 {
+                return Err(Error::CreateTarget {
+                    msg: format!("failed to back target over {:?}", transport),
+                });
+            }
 
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to front target".into(),
-            });
+            let trid_replica = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_replica_port,
+            );
+            let rc = unsafe {
+                spdk_nvmf_tgt_listen_ext(
+                    self.tgt.as_ptr(),
+                    trid_replica.as_ptr(),
+                    &mut opts,
+                )
+            };
+
+            if rc != 0 {
+                return Err(Error::CreateTarget {
+                    msg: format!("failed to front target over {:?}", transport),
+                });
+            }
+            info!(
+                "nvmf target listening on {:?} {}:({},{})",
+                transport,
+                get_ipv4_address().unwrap(),
+                trid_nexus.trsvcid.as_str(),
+                trid_replica.trsvcid.as_str(),
+            );
         }
-        info!(
-            "nvmf target listening on {}:({},{})",
-            get_ipv4_address().unwrap(),
-            trid_nexus.trsvcid.as_str(),
-            trid_replica.trsvcid.as_str(),
-        );
         self.next_state();
         Ok(())
     }
@@ -404,16 +421,30 @@ impl Target {
         unsafe { spdk_poller_unregister(&mut self.acceptor_poller.as_ptr()) };
 
         let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
+        for transport in TransportType::enabled() {
+            let trid_nexus = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_nexus_port,
+            );
+            let trid_replica = TransportId::with_transport(
+                transport,
+                cfg.nexus_opts.nvmf_replica_port,
+            );
 
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_replica.as_ptr())
-        };
+            unsafe {
+                spdk_nvmf_tgt_stop_listen(
+                    self.tgt.as_ptr(),
+                    trid_replica.as_ptr(),
+                )
+            };
 
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_nexus.as_ptr())
-        };
+            unsafe {
+                spdk_nvmf_tgt_stop_listen(
+                    self.tgt.as_ptr(),
+                    trid_nexus.as_ptr(),
+                )
+            };
+        }
 
         unsafe {
             spdk_nvmf_tgt_destroy(
@@ -13,8 +13,11 @@ use once_cell::sync::Lazy;
 
 use spdk_sys::{
     spdk_nvme_transport_id,
+    spdk_nvme_transport_type,
     spdk_nvmf_tgt_add_transport,
     spdk_nvmf_transport_create,
+    spdk_nvmf_transport_opts,
+    SPDK_NVME_TRANSPORT_RDMA,
     SPDK_NVME_TRANSPORT_TCP,
     SPDK_NVMF_ADRFAM_IPV4,
     SPDK_NVMF_TRSVCID_MAX_LEN,
@@ -38,16 +41,59 @@ use crate::{
 static TCP_TRANSPORT: Lazy<CString> =
     Lazy::new(|| CString::new("TCP").unwrap());
 
-pub async fn add_tcp_transport() -> Result<(), Error> {
-    let cfg = Config::get();
-    let mut opts = cfg.nvmf_tcp_tgt_conf.opts.into();
-    let transport = unsafe {
-        spdk_nvmf_transport_create(TCP_TRANSPORT.as_ptr(), &mut opts)
+static RDMA_TRANSPORT: Lazy<CString> =
+    Lazy::new(|| CString::new("RDMA").unwrap());
+
+/// The transports the target can listen on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportType {
+    Tcp,
+    Rdma,
+}
+
+impl TransportType {
+    /// the transports enabled in the config, TCP always comes first
+    pub fn enabled() -> Vec<TransportType> {
+        let mut transports = vec![TransportType::Tcp];
+        if Config::get().nvmf_rdma_tgt_conf.enable {
+            transports.push(TransportType::Rdma);
+        }
+        transports
+    }
+
+    fn name(self) -> &'static CString {
+        match self {
+            TransportType::Tcp => &TCP_TRANSPORT,
+            TransportType::Rdma => &RDMA_TRANSPORT,
+        }
+    }
+
+    fn trtype(self) -> spdk_nvme_transport_type {
+        match self {
+            TransportType::Tcp => SPDK_NVME_TRANSPORT_TCP,
+            TransportType::Rdma => SPDK_NVME_TRANSPORT_RDMA,
+        }
+    }
+
+    fn opts(self) -> spdk_nvmf_transport_opts {
+        let cfg = Config::get();
+        match self {
+            TransportType::Tcp => cfg.nvmf_tcp_tgt_conf.opts.into(),
+            TransportType::Rdma => cfg.nvmf_rdma_tgt_conf.into(),
+        }
+    }
+}
+
+/// create the transport and add it to the target
+pub async fn add_transport(transport: TransportType) -> Result<(), Error> {
+    let mut opts = transport.opts();
+    let transport_ptr = unsafe {
+        spdk_nvmf_transport_create(transport.name().as_ptr(), &mut opts)
     };
 
-    transport.to_result(|_| Error::Transport {
+    transport_ptr.to_result(|_| Error::Transport {
         source: Errno::UnknownErrno,
-        msg: "failed to create transport".into(),
+        msg: format!("failed to create {:?} transport", transport),
     })?;
 
     let (s, r) = oneshot::channel::<ErrnoResult<()>>();
@@ -55,16 +101,19 @@ pub async fn add_tcp_transport() -> Result<(), Error> {
         NVMF_TGT.with(|t| {
             spdk_nvmf_tgt_add_transport(
                 t.borrow().tgt.as_ptr(),
-                transport,
+                transport_ptr,
                 Some(done_errno_cb),
                 cb_arg(s),
             );
         })
     };
 
-    let _result = r.await.unwrap();
+    r.await.unwrap().map_err(|source| Error::Transport {
+        source,
+        msg: format!("failed to add {:?} transport", transport),
+    })?;
 
-    debug!("Added TCP nvmf transport");
+    debug!("Added {:?} nvmf transport", transport);
     Ok(())
 }
 
@@ -84,11 +133,17 @@ impl DerefMut for TransportId {
 }
 
 impl TransportId {
+    /// the TCP transport ID of the given port
     pub fn new(port: u16) -> Self {
+        Self::with_transport(TransportType::Tcp, port)
+    }
+
+    /// the transport ID of the given transport and port
+    pub fn with_transport(transport: TransportType, port: u16) -> Self {
         let address = get_ipv4_address().unwrap();
 
         let mut trid = spdk_nvme_transport_id {
-            trtype: SPDK_NVME_TRANSPORT_TCP,
+            trtype: transport.trtype(),
             adrfam: SPDK_NVMF_ADRFAM_IPV4,
             ..Default::default()
         };
@@ -101,9 +156,9 @@ impl TransportId {
 
         unsafe {
             copy_nonoverlapping(
-                TCP_TRANSPORT.as_ptr(),
+                transport.name().as_ptr(),
                 &mut trid.trstring[0],
-                trid.trstring.len(),
+                transport.name().as_bytes().len(),
             );
             copy_nonoverlapping(
                 c_addr.as_ptr(),
@@ -126,9 +181,15 @@ impl TransportId {
 
 impl Display for TransportId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let scheme = if self.0.trtype == SPDK_NVME_TRANSPORT_RDMA {
+            "nvmf+rdma"
+        } else {
+            "nvmf"
+        };
         write!(
             f,
-            "nvmf://{}:{}",
+            "{}://{}:{}",
+            scheme,
             self.0.traddr.as_str(),
             self.0.trsvcid.as_str()
         )
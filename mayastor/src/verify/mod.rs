@@ -0,0 +1,6 @@
+/// Verify api module
+mod verify_api;
+/// Verify implementation module
+mod verify_impl;
+
+pub use verify_api::*;
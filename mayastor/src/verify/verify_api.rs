@@ -0,0 +1,274 @@
+#![warn(missing_docs)]
+
+use std::fmt;
+
+use crossbeam::channel::{Receiver, Sender};
+use futures::channel::oneshot;
+use snafu::Snafu;
+
+use crate::{
+    bdev::VerboseError,
+    core::{BlockDeviceDescriptor, CoreError, Descriptor, DmaError},
+    nexus_uri::NexusBdevError,
+};
+
+use super::verify_impl::*;
+
+#[derive(Debug, Snafu, Clone)]
+#[snafu(visibility = "pub(crate)")]
+#[allow(missing_docs)]
+/// Various verify errors when interacting with a verify job or encountered
+/// while comparing the children
+pub enum VerifyError {
+    #[snafu(display("Failed to allocate buffer for the verify reads"))]
+    NoVerifyBuffer { source: DmaError },
+    #[snafu(display("Failed to validate verify job creation parameters"))]
+    InvalidParameters {},
+    #[snafu(display("Nexus {} needs 2 healthy children to verify", job))]
+    NotEnoughChildren { job: String },
+    #[snafu(display("Failed to get a handle for bdev {}", bdev))]
+    NoBdevHandle { source: CoreError, bdev: String },
+    #[snafu(display("Bdev {} not found", bdev))]
+    BdevNotFound { source: CoreError, bdev: String },
+    #[snafu(display("Read IO failed for bdev {}", bdev))]
+    ReadIoError { source: CoreError, bdev: String },
+    #[snafu(display("Write IO failed for bdev {}", bdev))]
+    WriteIoError { source: CoreError, bdev: String },
+    #[snafu(display("Failed to find verify job {}", job))]
+    JobNotFound { job: String },
+    #[snafu(display("Job {} already exists", job))]
+    JobAlreadyExists { job: String },
+    #[snafu(display(
+        "{} operation failed because current verify state is {}.",
+        operation,
+        state,
+    ))]
+    OpError { operation: String, state: String },
+    #[snafu(display("Existing pending state {}", state,))]
+    StatePending { state: String },
+    #[snafu(display(
+        "Failed to lock LBA range for blk {}, len {}, with error: {}",
+        blk,
+        len,
+        source,
+    ))]
+    RangeLockError {
+        blk: u64,
+        len: u64,
+        source: nix::errno::Errno,
+    },
+    #[snafu(display(
+        "Failed to unlock LBA range for blk {}, len {}, with error: {}",
+        blk,
+        len,
+        source,
+    ))]
+    RangeUnLockError {
+        blk: u64,
+        len: u64,
+        source: nix::errno::Errno,
+    },
+    #[snafu(display("Failed to get bdev name from URI {}", uri))]
+    BdevInvalidUri { source: NexusBdevError, uri: String },
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// allowed states for a verify job
+pub enum VerifyState {
+    /// Init when the job is newly created
+    Init,
+    /// Running when the job is comparing the children
+    Running,
+    /// Stopped when the job is halted as requested through stop
+    Stopped,
+    /// Paused when the job is paused as requested through pause
+    Paused,
+    /// Failed when reading from or repairing a child failed
+    Failed,
+    /// Completed when all blocks have been compared
+    Completed,
+}
+
+impl fmt::Display for VerifyState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyState::Init => write!(f, "init"),
+            VerifyState::Running => write!(f, "running"),
+            VerifyState::Stopped => write!(f, "stopped"),
+            VerifyState::Paused => write!(f, "paused"),
+            VerifyState::Failed => write!(f, "failed"),
+            VerifyState::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+/// A range of blocks which does not hold the same data on all children
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyMismatch {
+    /// first block of the range, relative to the start of the nexus
+    pub offset_blks: u64,
+    /// number of blocks in the range
+    pub num_blks: u64,
+    /// children whose data differs from the data of the reference child
+    pub children: Vec<String>,
+    /// the range has been repaired by copying the reference data
+    pub repaired: bool,
+}
+
+/// A verify job reads the same segments from all healthy children of a nexus
+/// and compares them block by block against the reference child, which is
+/// the first child. Mismatching ranges are recorded and, if requested,
+/// repaired by writing the data of the reference child to the others.
+pub struct VerifyJob {
+    /// name of the nexus associated with the verify job
+    pub nexus: String,
+    /// descriptor for the nexus
+    pub(super) nexus_descriptor: Descriptor,
+    /// URIs of the children being compared, starting with the reference
+    pub children: Vec<String>,
+    /// repair mismatching ranges from the reference child
+    pub repair: bool,
+    pub(super) block_size: u64,
+    pub(super) range: std::ops::Range<u64>,
+    pub(super) next: u64,
+    pub(super) segment_size_blks: u64,
+    pub(super) task_pool: VerifyTasks,
+    pub(super) notify_fn: fn(String) -> (),
+    /// channel used to signal verify update
+    pub notify_chan: (Sender<VerifyState>, Receiver<VerifyState>),
+    /// current state of the verify job
+    pub(super) states: VerifyStates,
+    /// channel list which allows the await of the verify job
+    pub(super) complete_chan: Vec<oneshot::Sender<VerifyState>>,
+    /// ranges found to differ so far
+    pub(super) mismatches: Vec<VerifyMismatch>,
+    /// verify error, if any
+    pub error: Option<VerifyError>,
+
+    // Pre-opened descriptors of the children, in the order of `children`
+    pub(super) descriptors: Vec<Box<dyn BlockDeviceDescriptor>>,
+}
+
+impl fmt::Debug for VerifyJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifyJob")
+            .field("nexus", &self.nexus)
+            .field("children", &self.children)
+            .field("repair", &self.repair)
+            .finish()
+    }
+}
+
+/// verify statistics
+pub struct VerifyStats {
+    /// total number of blocks to compare
+    pub blocks_total: u64,
+    /// number of blocks compared
+    pub blocks_verified: u64,
+    /// verify progress in %
+    pub progress: u64,
+    /// granularity of each comparison in blocks
+    pub segment_size_blks: u64,
+    /// size in bytes of each block
+    pub block_size: u64,
+    /// total number of concurrent verify tasks
+    pub tasks_total: u64,
+    /// number of current active tasks
+    pub tasks_active: u64,
+    /// number of blocks which differ between the children
+    pub blocks_mismatched: u64,
+    /// number of mismatching blocks which have been repaired
+    pub blocks_repaired: u64,
+}
+
+/// Public facing operations on a Verify Job
+pub trait VerifyOperations {
+    /// Collects statistics from the job
+    fn stats(&self) -> VerifyStats;
+    /// Schedules the job to start in a future and returns a complete channel
+    /// which can be waited on
+    fn start(&mut self) -> Result<oneshot::Receiver<VerifyState>, VerifyError>;
+    /// Stops the job which then triggers the completion hooks
+    fn stop(&mut self) -> Result<(), VerifyError>;
+    /// pauses the job which can then be later resumed
+    fn pause(&mut self) -> Result<(), VerifyError>;
+    /// Resumes a previously paused job
+    fn resume(&mut self) -> Result<(), VerifyError>;
+    /// Forcefully terminates the job, overriding any pending client operation
+    /// returns an async channel which can be used to await for termination
+    fn terminate(&mut self) -> oneshot::Receiver<VerifyState>;
+}
+
+impl VerifyJob {
+    /// Creates a new VerifyJob for the nexus which compares the given
+    /// children, the first of which is the reference, over the range of
+    /// blocks of the data partition. notify_fn is called with the name of the
+    /// nexus when the state of the job changes.
+    pub fn create<'a>(
+        nexus: &'a str,
+        children: &[String],
+        range: std::ops::Range<u64>,
+        repair: bool,
+        notify_fn: fn(String) -> (),
+    ) -> Result<&'a mut Self, VerifyError> {
+        Self::new(nexus, children, range, repair, notify_fn)?.store()?;
+
+        Self::lookup(nexus)
+    }
+
+    /// Lookup the verify job of a nexus and return it
+    pub fn lookup(nexus: &str) -> Result<&mut Self, VerifyError> {
+        if let Some(job) = Self::get_instances().get_mut(nexus) {
+            Ok(job)
+        } else {
+            Err(VerifyError::JobNotFound {
+                job: nexus.to_owned(),
+            })
+        }
+    }
+
+    /// Lookup the verify job of a nexus then remove and return it
+    pub fn remove(nexus: &str) -> Result<Self, VerifyError> {
+        match Self::get_instances().remove(nexus) {
+            Some(job) => Ok(*job),
+            None => Err(VerifyError::JobNotFound {
+                job: nexus.to_owned(),
+            }),
+        }
+    }
+
+    /// Number of verify job instances
+    pub fn count() -> usize {
+        Self::get_instances().len()
+    }
+
+    /// State of the verify job
+    pub fn state(&self) -> VerifyState {
+        self.states.current
+    }
+
+    /// Ranges found to differ between the children so far
+    pub fn mismatches(&self) -> &[VerifyMismatch] {
+        &self.mismatches
+    }
+
+    /// Error description
+    pub fn error_desc(&self) -> String {
+        match self.error.as_ref() {
+            Some(e) => e.verbose(),
+            _ => "".to_string(),
+        }
+    }
+
+    /// VerifyOperations trait
+    pub fn as_client(&mut self) -> &mut impl VerifyOperations {
+        self
+    }
+}
+
+impl VerifyState {
+    /// Final update for a verify job
+    pub fn done(self) -> bool {
+        matches!(self, Self::Stopped | Self::Failed | Self::Completed)
+    }
+}
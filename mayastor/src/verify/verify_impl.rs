@@ -0,0 +1,831 @@
+#![warn(missing_docs)]
+
+use std::{cell::UnsafeCell, collections::HashMap};
+
+use crossbeam::channel::unbounded;
+use futures::{
+    channel::{mpsc, oneshot},
+    future::try_join_all,
+    StreamExt,
+};
+use once_cell::sync::OnceCell;
+use snafu::ResultExt;
+
+use spdk_sys::spdk_get_thread;
+
+use crate::{
+    bdev::{device_open, VerboseError},
+    core::{
+        Bdev,
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        DmaBuf,
+        RangeContext,
+        Reactors,
+    },
+    nexus_uri::bdev_get_name,
+    rebuild::{rebuild_impl::Within, SEGMENT_SIZE},
+};
+
+use super::verify_api::*;
+
+/// Global list of verify jobs, keyed by nexus name
+pub(super) struct VerifyInstances {
+    inner: UnsafeCell<HashMap<String, Box<VerifyJob>>>,
+}
+
+unsafe impl Sync for VerifyInstances {}
+unsafe impl Send for VerifyInstances {}
+
+/// Result returned by each segment task worker to the management task
+#[derive(Debug, Clone)]
+struct TaskResult {
+    /// first block of the segment that was verified
+    blk: u64,
+    /// id of the task
+    id: usize,
+    /// encountered error, if any
+    error: Option<VerifyError>,
+}
+
+/// Number of concurrent verify tasks per job, each of which reads a segment
+/// from every child
+const SEGMENT_TASKS: usize = 4;
+
+/// Each verify task needs a buffer per child to read the segment into
+#[derive(Debug)]
+struct VerifyTask {
+    buffers: Vec<DmaBuf>,
+    sender: mpsc::Sender<TaskResult>,
+}
+
+/// Pool of verify tasks and progress tracking
+#[derive(Debug)]
+pub(super) struct VerifyTasks {
+    tasks: Vec<VerifyTask>,
+
+    channel: (mpsc::Sender<TaskResult>, mpsc::Receiver<TaskResult>),
+    active: usize,
+    total: usize,
+
+    segments_done: u64,
+}
+
+impl VerifyJob {
+    /// Stores a verify job in the verify job list
+    pub(super) fn store(self) -> Result<(), VerifyError> {
+        let verify_list = Self::get_instances();
+
+        if verify_list.contains_key(&self.nexus) {
+            Err(VerifyError::JobAlreadyExists {
+                job: self.nexus,
+            })
+        } else {
+            let _ = verify_list.insert(self.nexus.clone(), Box::new(self));
+            Ok(())
+        }
+    }
+
+    /// Returns a new verify job based on the parameters
+    pub(super) fn new(
+        nexus: &str,
+        children: &[String],
+        range: std::ops::Range<u64>,
+        repair: bool,
+        notify_fn: fn(String) -> (),
+    ) -> Result<Self, VerifyError> {
+        if children.len() < 2 {
+            return Err(VerifyError::NotEnoughChildren {
+                job: nexus.to_string(),
+            });
+        }
+
+        let mut descriptors = Vec::with_capacity(children.len());
+        for child in children {
+            let descriptor = device_open(
+                &bdev_get_name(child).context(BdevInvalidUri {
+                    uri: child.to_string(),
+                })?,
+                repair,
+            )
+            .map_err(|e| VerifyError::BdevNotFound {
+                source: e,
+                bdev: child.to_string(),
+            })?;
+            descriptors.push(descriptor);
+        }
+
+        let handles = descriptors
+            .iter()
+            .map(|d| Self::get_io_handle(&**d))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !Self::validate(&handles, &range) {
+            return Err(VerifyError::InvalidParameters {});
+        }
+
+        // validation passed, the block size is the same for all children
+        let block_size = handles[0].get_device().block_len();
+        let segment_size_blks = SEGMENT_SIZE / block_size;
+
+        let mut tasks = VerifyTasks {
+            tasks: Vec::new(),
+            channel: mpsc::channel(0),
+            active: 0,
+            total: SEGMENT_TASKS,
+            segments_done: 0,
+        };
+
+        for _ in 0 .. tasks.total {
+            let buffers = handles
+                .iter()
+                .map(|h| h.dma_malloc(segment_size_blks * block_size))
+                .collect::<Result<Vec<_>, _>>()
+                .context(NoVerifyBuffer {})?;
+            tasks.tasks.push(VerifyTask {
+                buffers,
+                sender: tasks.channel.0.clone(),
+            });
+        }
+
+        let nexus_descriptor =
+            Bdev::open_by_name(nexus, false).context(BdevNotFound {
+                bdev: nexus.to_string(),
+            })?;
+
+        Ok(Self {
+            nexus: nexus.to_string(),
+            nexus_descriptor,
+            children: children.to_vec(),
+            repair,
+            next: range.start,
+            range,
+            block_size,
+            segment_size_blks,
+            task_pool: tasks,
+            notify_fn,
+            notify_chan: unbounded::<VerifyState>(),
+            states: Default::default(),
+            complete_chan: Vec::new(),
+            mismatches: Vec::new(),
+            error: None,
+            descriptors,
+        })
+    }
+
+    /// Runs the management task which kicks off the segment tasks and awaits
+    /// their completion, starting a task for the next segment each time one
+    /// completes until all segments have been verified
+    async fn run(&mut self) {
+        self.start_all_tasks();
+        while self.task_pool.active > 0 {
+            match self.await_one_task().await {
+                Some(r) => match r.error {
+                    None => match self.states.pending {
+                        None | Some(VerifyState::Running) => {
+                            self.start_task_by_id(r.id);
+                        }
+                        _ => {
+                            self.await_all_tasks().await;
+                            break;
+                        }
+                    },
+                    Some(e) => {
+                        error!(
+                            "Failed to verify segment id {} block {} of nexus {} with error: {}",
+                            r.id, r.blk, self.nexus, e
+                        );
+                        self.fail();
+                        self.await_all_tasks().await;
+                        self.error = Some(e);
+                        break;
+                    }
+                },
+                None => {
+                    error!(
+                        "Out of place termination with potentially {} active tasks",
+                        self.task_pool.active
+                    );
+                    let _ = self.terminate();
+                    break;
+                }
+            }
+        }
+        self.reconcile();
+    }
+
+    /// Return the size of the segment starting at `blk`
+    fn get_segment_size_blks(&self, blk: u64) -> u64 {
+        // Adjust the segments size for the last segment
+        if (blk + self.segment_size_blks) > self.range.end {
+            return self.range.end - blk;
+        }
+        self.segment_size_blks
+    }
+
+    /// Verifies one segment with the LBA range locked on the nexus, so that
+    /// writes in flight can not make the children appear to differ.
+    ///
+    /// # Safety
+    ///
+    /// The RangeContext is referenced as a raw pointer by the lock and
+    /// unlock functions, it lives on the stack until both have completed.
+    async fn locked_verify_one(
+        &mut self,
+        id: usize,
+        blk: u64,
+    ) -> Result<(), VerifyError> {
+        let len = self.get_segment_size_blks(blk);
+        // the range is locked on the nexus, which has no metadata partition
+        let mut ctx = RangeContext::new(blk - self.range.start, len);
+        let ch = self
+            .nexus_descriptor
+            .get_channel()
+            .expect("Failed to get nexus channel");
+
+        self.nexus_descriptor
+            .lock_lba_range(&mut ctx, &ch)
+            .await
+            .context(RangeLockError {
+                blk,
+                len,
+            })?;
+
+        let result = self.verify_one(id, blk).await;
+
+        self.nexus_descriptor
+            .unlock_lba_range(&mut ctx, &ch)
+            .await
+            .context(RangeUnLockError {
+                blk,
+                len,
+            })?;
+
+        result
+    }
+
+    /// Reads one segment from all children and compares them block by block
+    /// against the reference child, repairing the mismatches if requested.
+    async fn verify_one(
+        &mut self,
+        id: usize,
+        blk: u64,
+    ) -> Result<(), VerifyError> {
+        let len = self.get_segment_size_blks(blk);
+        let block_size = self.block_size as usize;
+        let handles = self
+            .descriptors
+            .iter()
+            .map(|d| Self::get_io_handle(&**d))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut last_buffers: Vec<DmaBuf>;
+        let buffers = if len == self.segment_size_blks {
+            &mut self.task_pool.tasks[id].buffers
+        } else {
+            last_buffers = handles
+                .iter()
+                .map(|h| h.dma_malloc(len * self.block_size))
+                .collect::<Result<Vec<_>, _>>()
+                .context(NoVerifyBuffer {})?;
+            &mut last_buffers
+        };
+
+        let offset = blk * self.block_size;
+        try_join_all(
+            handles
+                .iter()
+                .zip(buffers.iter_mut())
+                .zip(self.children.iter())
+                .map(|((hdl, buf), child)| async move {
+                    hdl.read_at(offset, buf).await.context(ReadIoError {
+                        bdev: child.clone(),
+                    })
+                }),
+        )
+        .await?;
+
+        // compare block by block, coalescing consecutive blocks which differ
+        // on the same set of children into a single range
+        let reference = buffers[0].as_slice();
+        let mut ranges: Vec<(u64, u64, Vec<usize>)> = Vec::new();
+        for b in 0 .. len as usize {
+            let block = b * block_size .. (b + 1) * block_size;
+            let differing = (1 .. buffers.len())
+                .filter(|i| {
+                    buffers[*i].as_slice()[block.clone()]
+                        != reference[block.clone()]
+                })
+                .collect::<Vec<_>>();
+
+            if differing.is_empty() {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some((start, num, children))
+                    if *start + *num == b as u64 && *children == differing =>
+                {
+                    *num += 1;
+                }
+                _ => ranges.push((b as u64, 1, differing)),
+            }
+        }
+
+        for (start, num, differing) in ranges {
+            let mut mismatch = VerifyMismatch {
+                offset_blks: blk + start - self.range.start,
+                num_blks: num,
+                children: differing
+                    .iter()
+                    .map(|i| self.children[*i].clone())
+                    .collect(),
+                repaired: false,
+            };
+
+            warn!(
+                "nexus {}: blocks {}..{} differ on children {:?}",
+                self.nexus,
+                mismatch.offset_blks,
+                mismatch.offset_blks + num,
+                mismatch.children
+            );
+
+            if self.repair {
+                let mut data = handles[0]
+                    .dma_malloc(num * self.block_size)
+                    .context(NoVerifyBuffer {})?;
+                let src = start as usize * block_size
+                    .. (start + num) as usize * block_size;
+                data.as_mut_slice()
+                    .copy_from_slice(&buffers[0].as_slice()[src]);
+
+                for i in differing {
+                    handles[i]
+                        .write_at(offset + start * self.block_size, &data)
+                        .await
+                        .context(WriteIoError {
+                            bdev: self.children[i].clone(),
+                        })?;
+                }
+                mismatch.repaired = true;
+            }
+
+            self.mismatches.push(mismatch);
+        }
+
+        Ok(())
+    }
+
+    fn get_io_handle(
+        descriptor: &dyn BlockDeviceDescriptor,
+    ) -> Result<Box<dyn BlockDeviceHandle>, VerifyError> {
+        descriptor
+            .get_io_handle()
+            .map_err(|e| VerifyError::NoBdevHandle {
+                source: e,
+                bdev: descriptor.get_device().device_name(),
+            })
+    }
+
+    /// Calls the job's registered notify fn callback and notify sender channel
+    fn notify(&mut self) {
+        (self.notify_fn)(self.nexus.clone());
+        if let Err(e) = self.notify_chan.0.send(self.state()) {
+            error!(
+                "Verify job of nexus {} failed to send complete via the unbound channel with err {}",
+                self.nexus, e
+            );
+        }
+    }
+
+    /// Check that all children cover the range and have the same block size
+    fn validate(
+        handles: &[Box<dyn BlockDeviceHandle>],
+        range: &std::ops::Range<u64>,
+    ) -> bool {
+        let block_len = handles[0].get_device().block_len();
+        handles.iter().all(|h| {
+            let device: &dyn BlockDevice = h.get_device();
+            range.within(0 .. device.num_blocks())
+                && device.block_len() == block_len
+        })
+    }
+
+    /// reconcile the pending state to the current and clear the pending
+    fn reconcile(&mut self) {
+        let old = self.state();
+        let new = self.states.reconcile();
+
+        if old != new {
+            info!(
+                "Verify job of nexus {}: changing state from {:?} to {:?}",
+                self.nexus, old, new
+            );
+            if new.done() {
+                for sender in self.complete_chan.drain(..) {
+                    let _ = sender.send(new);
+                }
+            }
+            self.notify();
+        }
+    }
+
+    /// reconciles to state if it's the same as the pending value
+    fn reconcile_to_state(&mut self, state: VerifyState) -> bool {
+        if self.states.pending_equals(state) {
+            self.reconcile();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn schedule(&self) {
+        match self.state() {
+            VerifyState::Paused | VerifyState::Init => {
+                let nexus = self.nexus.clone();
+                Reactors::master().send_future(async move {
+                    let job = match VerifyJob::lookup(&nexus) {
+                        Ok(job) => job,
+                        Err(_) => {
+                            return error!(
+                                "Failed to find and start the verify job of nexus {}",
+                                nexus
+                            );
+                        }
+                    };
+
+                    if job.reconcile_to_state(VerifyState::Running) {
+                        job.run().await;
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the verify job instances container, which may only be accessed
+    /// from an SPDK thread
+    pub(super) fn get_instances() -> &'static mut HashMap<String, Box<Self>> {
+        let thread = unsafe { spdk_get_thread() };
+        if thread.is_null() {
+            panic!("not called from SPDK thread")
+        }
+
+        static VERIFY_INSTANCES: OnceCell<VerifyInstances> = OnceCell::new();
+
+        let global_instances =
+            VERIFY_INSTANCES.get_or_init(|| VerifyInstances {
+                inner: UnsafeCell::new(HashMap::new()),
+            });
+
+        unsafe { &mut *global_instances.inner.get() }
+    }
+}
+
+#[derive(Debug)]
+/// Operations used to control the state of the job
+enum VerifyOperation {
+    /// Starts the job for the first time
+    Start,
+    /// Stops the job
+    Stop,
+    /// Pauses the job
+    Pause,
+    /// Resumes the previously paused job
+    Resume,
+    /// reading or repairing a child failed
+    Fail,
+    /// all segments have been verified
+    Complete,
+}
+
+impl std::fmt::Display for VerifyOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl VerifyOperations for VerifyJob {
+    fn stats(&self) -> VerifyStats {
+        let blocks_total = self.range.end - self.range.start;
+
+        // segment size may not be aligned to the total size
+        let blocks_verified = std::cmp::min(
+            self.task_pool.segments_done * self.segment_size_blks,
+            blocks_total,
+        );
+
+        let progress = if blocks_total == 0 {
+            100
+        } else {
+            (blocks_verified * 100) / blocks_total
+        };
+
+        let blocks_mismatched =
+            self.mismatches.iter().map(|m| m.num_blks).sum();
+        let blocks_repaired = self
+            .mismatches
+            .iter()
+            .filter(|m| m.repaired)
+            .map(|m| m.num_blks)
+            .sum();
+
+        VerifyStats {
+            blocks_total,
+            blocks_verified,
+            progress,
+            segment_size_blks: self.segment_size_blks,
+            block_size: self.block_size,
+            tasks_total: self.task_pool.total as u64,
+            tasks_active: self.task_pool.active as u64,
+            blocks_mismatched,
+            blocks_repaired,
+        }
+    }
+
+    fn start(&mut self) -> Result<oneshot::Receiver<VerifyState>, VerifyError> {
+        self.exec_client_op(VerifyOperation::Start)?;
+        let end_channel = oneshot::channel();
+        self.complete_chan.push(end_channel.0);
+        Ok(end_channel.1)
+    }
+
+    fn stop(&mut self) -> Result<(), VerifyError> {
+        self.exec_client_op(VerifyOperation::Stop)
+    }
+
+    fn pause(&mut self) -> Result<(), VerifyError> {
+        self.exec_client_op(VerifyOperation::Pause)
+    }
+
+    fn resume(&mut self) -> Result<(), VerifyError> {
+        self.exec_client_op(VerifyOperation::Resume)
+    }
+
+    fn terminate(&mut self) -> oneshot::Receiver<VerifyState> {
+        let end_channel = oneshot::channel();
+        if self.state().done() {
+            let _ = end_channel.0.send(self.state());
+        } else {
+            self.complete_chan.push(end_channel.0);
+            self.exec_internal_op(VerifyOperation::Stop).ok();
+        }
+        end_channel.1
+    }
+}
+
+impl VerifyJob {
+    fn fail(&mut self) {
+        self.exec_internal_op(VerifyOperation::Fail).ok();
+    }
+
+    fn complete(&mut self) {
+        self.exec_internal_op(VerifyOperation::Complete).ok();
+    }
+
+    fn start_all_tasks(&mut self) {
+        assert_eq!(
+            self.task_pool.active, 0,
+            "{} active tasks",
+            self.task_pool.active
+        );
+
+        for n in 0 .. self.task_pool.total {
+            match self.send_segment_task(n) {
+                Some(next) => {
+                    self.task_pool.active += 1;
+                    self.next = next;
+                }
+                None => break,
+            }
+        }
+
+        if self.task_pool.active == 0 {
+            self.complete();
+        }
+    }
+
+    fn start_task_by_id(&mut self, id: usize) {
+        match self.send_segment_task(id) {
+            Some(next) => {
+                self.task_pool.active += 1;
+                self.next = next;
+            }
+            None => {
+                if self.task_pool.active == 0 {
+                    self.complete();
+                }
+            }
+        };
+    }
+
+    async fn await_one_task(&mut self) -> Option<TaskResult> {
+        self.task_pool.channel.1.next().await.map(|f| {
+            self.task_pool.active -= 1;
+            if f.error.is_none() {
+                self.task_pool.segments_done += 1;
+            }
+            f
+        })
+    }
+
+    async fn await_all_tasks(&mut self) {
+        while self.task_pool.active > 0 {
+            if self.await_one_task().await.is_none() {
+                error!(
+                    "Failed to wait for {} verify tasks due mpsc channel failure.",
+                    self.task_pool.active
+                );
+                self.fail();
+                return;
+            }
+        }
+    }
+
+    /// Verifies one segment in a reactor future which notifies the
+    /// management channel once done. Returns the next segment to verify, if
+    /// any
+    fn send_segment_task(&self, id: usize) -> Option<u64> {
+        if self.next >= self.range.end {
+            return None;
+        }
+
+        let blk = self.next;
+        let next = std::cmp::min(blk + self.segment_size_blks, self.range.end);
+        let nexus = self.nexus.clone();
+
+        Reactors::current().send_future(async move {
+            let job = Self::lookup(&nexus).unwrap();
+
+            let r = TaskResult {
+                blk,
+                id,
+                error: job.locked_verify_one(id, blk).await.err(),
+            };
+
+            let task = &mut job.task_pool.tasks[id];
+            if let Err(e) = task.sender.start_send(r) {
+                error!(
+                    "Failed to notify job of segment id: {} blk: {} completion, err: {}",
+                    id,
+                    blk,
+                    e.verbose()
+                );
+            }
+        });
+
+        Some(next)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct VerifyStates {
+    /// Current state of the verify job
+    pub current: VerifyState,
+
+    /// Pending state for the verify job
+    pending: Option<VerifyState>,
+}
+
+impl std::fmt::Display for VerifyStates {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Default for VerifyState {
+    fn default() -> Self {
+        VerifyState::Init
+    }
+}
+
+impl VerifyStates {
+    /// Set's the next pending state
+    /// if one is already set then override only if flag is set
+    fn set_pending(
+        &mut self,
+        state: VerifyState,
+        override_pending: bool,
+    ) -> Result<(), VerifyError> {
+        match self.pending {
+            Some(pending) if !override_pending && (pending != state) => {
+                Err(VerifyError::StatePending {
+                    state: pending.to_string(),
+                })
+            }
+            _ => {
+                if self.current != state {
+                    self.pending = Some(state);
+                } else {
+                    self.pending = None;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// a change to `state` is pending
+    fn pending_equals(&self, state: VerifyState) -> bool {
+        self.pending == Some(state)
+    }
+
+    /// reconcile the pending state into the current state
+    fn reconcile(&mut self) -> VerifyState {
+        if let Some(pending) = self.pending {
+            self.current = pending;
+            self.pending = None;
+        }
+
+        self.current
+    }
+}
+
+impl VerifyJob {
+    fn exec_client_op(
+        &mut self,
+        op: VerifyOperation,
+    ) -> Result<(), VerifyError> {
+        self.exec_op(op, false)
+    }
+
+    fn exec_internal_op(
+        &mut self,
+        op: VerifyOperation,
+    ) -> Result<(), VerifyError> {
+        self.exec_op(op, true)
+    }
+
+    /// Single state machine where all operations are handled
+    fn exec_op(
+        &mut self,
+        op: VerifyOperation,
+        override_pending: bool,
+    ) -> Result<(), VerifyError> {
+        type S = VerifyState;
+        let e = VerifyError::OpError {
+            operation: op.to_string(),
+            state: self.states.to_string(),
+        };
+
+        match op {
+            VerifyOperation::Start => match self.state() {
+                S::Stopped | S::Paused | S::Failed | S::Completed => Err(e),
+                S::Running => Ok(()),
+                S::Init => {
+                    self.states.set_pending(S::Running, false)?;
+                    self.schedule();
+                    Ok(())
+                }
+            },
+            VerifyOperation::Stop => match self.state() {
+                S::Failed | S::Completed => Err(e),
+                S::Stopped => Ok(()),
+                S::Running => {
+                    self.states.set_pending(S::Stopped, override_pending)?;
+                    Ok(())
+                }
+                S::Init | S::Paused => {
+                    self.states.set_pending(S::Stopped, override_pending)?;
+                    // the job is not running so we need to reconcile
+                    self.reconcile();
+                    Ok(())
+                }
+            },
+            VerifyOperation::Pause => match self.state() {
+                S::Stopped | S::Failed | S::Completed => Err(e),
+                S::Init | S::Running | S::Paused => {
+                    self.states.set_pending(S::Paused, false)?;
+                    Ok(())
+                }
+            },
+            VerifyOperation::Resume => match self.state() {
+                S::Init | S::Stopped | S::Failed | S::Completed => Err(e),
+                S::Running | S::Paused => {
+                    self.states.set_pending(S::Running, false)?;
+                    self.schedule();
+                    Ok(())
+                }
+            },
+            VerifyOperation::Fail => match self.state() {
+                S::Init | S::Stopped | S::Paused | S::Completed => Err(e),
+                S::Failed => Ok(()),
+                S::Running => {
+                    self.states.set_pending(S::Failed, override_pending)?;
+                    Ok(())
+                }
+            },
+            VerifyOperation::Complete => match self.state() {
+                S::Init | S::Paused | S::Stopped | S::Failed | S::Completed => {
+                    Err(e)
+                }
+                S::Running => {
+                    self.states.set_pending(S::Completed, override_pending)?;
+                    Ok(())
+                }
+            },
+        }
+    }
+}
@@ -454,6 +454,21 @@ async fn nvmf_io_stats() {
             "Number of bytes written mismatches"
         );
 
+        // Every operation is accounted in the latency histograms.
+        for (latency, ops) in &[
+            (stats.read_latency, 2),
+            (stats.write_latency, 2),
+            (stats.unmap_latency, 1),
+        ] {
+            assert_eq!(latency.count, *ops, "Number of latencies mismatches");
+            assert_eq!(
+                latency.buckets.iter().sum::<u64>(),
+                *ops,
+                "Number of latencies in buckets mismatches"
+            );
+            assert!(latency.percentile(0.99).is_some());
+        }
+
         device_destroy(&u).await.unwrap();
     })
     .await;
@@ -0,0 +1,20 @@
+use spdk_sys::create_delay_disk;
+
+/// Create a delay bdev on top of the given bdev, which delays every IO by
+/// the given latency in microseconds
+pub fn create_delay_bdev(delay_device: &str, base_device: &str, latency: u64) {
+    let base = std::ffi::CString::new(base_device).unwrap();
+    let name = std::ffi::CString::new(delay_device).unwrap();
+
+    let retval = unsafe {
+        create_delay_disk(
+            base.as_ptr(),
+            name.as_ptr(),
+            latency,
+            latency,
+            latency,
+            latency,
+        )
+    };
+    assert_eq!(retval, 0);
+}
@@ -21,6 +21,7 @@ use mayastor::{
 
 pub mod bdev_io;
 pub mod compose;
+pub mod delay_bdev;
 pub mod error_bdev;
 
 pub use compose::MayastorTest;
@@ -0,0 +1,52 @@
+use common::MayastorTest;
+use mayastor::{
+    core::MayastorCliArgs,
+    subsys::{Config, ConfigError},
+};
+
+pub mod common;
+
+/// Options which are safe to change at runtime are updated in the running
+/// config, updates of other options are rejected as a whole.
+#[tokio::test]
+async fn config_update() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let nvmf_enable = Config::get().nexus_opts.nvmf_enable;
+
+        let config = Config::update(
+            b"nexus_opts:\n  slow_child_factor: 5\nnvme_bdev_opts:\n  timeout_us: 3000000\nlog_level: info,mayastor=debug\n",
+        )
+        .unwrap();
+        assert_eq!(config.nexus_opts.slow_child_factor, 5);
+        assert_eq!(config.nvme_bdev_opts.timeout_us, 3_000_000);
+        assert_eq!(config.log_level.as_deref(), Some("info,mayastor=debug"));
+        assert_eq!(config.nexus_opts.nvmf_enable, nvmf_enable);
+        assert_eq!(Config::get(), config);
+
+        // an empty update leaves the config as it is
+        assert_eq!(Config::update(b"").unwrap(), config);
+
+        // the whole update is rejected when an option needs a restart
+        let update = format!(
+            "nexus_opts:\n  slow_child_factor: 2\n  nvmf_enable: {}\n",
+            !nvmf_enable
+        );
+        assert!(matches!(
+            Config::update(update.as_bytes()),
+            Err(ConfigError::RestartRequired { .. })
+        ));
+        assert_eq!(Config::get().nexus_opts.slow_child_factor, 5);
+
+        assert!(matches!(
+            Config::update(b"nexus_opts:\n  no_such_option: 1\n"),
+            Err(ConfigError::InvalidConfig { .. })
+        ));
+        assert!(matches!(
+            Config::update(b"log_level: \"mayastor=nonsense\"\n"),
+            Err(ConfigError::LogLevel { .. })
+        ));
+    })
+    .await;
+}
@@ -0,0 +1,133 @@
+use common::MayastorTest;
+use mayastor::{
+    core::{MayastorCliArgs, Protocol, Share},
+    lvs::{Cipher, EncryptionKey, Lvs, PropName, PropValue},
+};
+use rpc::mayastor::{
+    CreatePoolRequest,
+    ReplicaCipher,
+    ReplicaEncryption,
+    ReplicaKey,
+};
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/crypto_disk.img";
+static KEY: &str = "0123456789abcdef";
+static WRONG_KEY: &str = "fedcba9876543210";
+
+fn pool_request(replica_keys: Vec<ReplicaKey>) -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: "cpool".into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        overcommit: None,
+        replica_keys,
+    }
+}
+
+#[tokio::test]
+async fn lvol_crypto_test() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // keys of the wrong length, or the wrong number of them, are refused
+    assert!(EncryptionKey::new("r", Cipher::AesCbc, "short", None).is_err());
+    assert!(EncryptionKey::new("r", Cipher::AesCbc, KEY, Some(KEY)).is_err());
+    assert!(EncryptionKey::new("r", Cipher::AesXts, KEY, None).is_err());
+    assert!(EncryptionKey::new("r", Cipher::AesXts, KEY, Some(KEY)).is_ok());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_request(vec![])).await.unwrap();
+        let key =
+            EncryptionKey::new("encrypted", Cipher::AesCbc, KEY, None).unwrap();
+
+        let lvol = pool
+            .create_lvol("encrypted", 8 * 1024 * 1024, None, false)
+            .await
+            .unwrap();
+        assert_eq!(lvol.cipher(), None);
+
+        lvol.encrypt(&key).await.unwrap();
+        assert_eq!(lvol.cipher(), Some(Cipher::AesCbc));
+        assert!(!lvol.is_locked());
+
+        // the key check names the algorithm and the parameters it was
+        // derived with, and the key itself is not stored
+        match lvol.get(PropName::KeyCheck).await.unwrap() {
+            PropValue::KeyCheck(check) => {
+                assert!(check.starts_with("pbkdf2-sha256:600000:"));
+                assert!(!check.contains(KEY));
+            }
+            value => panic!("unexpected key check {:?}", value),
+        }
+
+        lvol.share_nvmf(None).await.unwrap();
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // without the key the lvol remains locked after importing the pool
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_request(vec![])).await.unwrap();
+        let lvol = pool.lvols().unwrap().next().unwrap();
+        assert_eq!(lvol.cipher(), Some(Cipher::AesCbc));
+        assert!(lvol.is_locked());
+        assert_eq!(lvol.shared(), Some(Protocol::Off));
+        assert!(lvol.share_nvmf(None).await.is_err());
+
+        // a key which the lvol was not encrypted with is refused
+        let key =
+            EncryptionKey::new("encrypted", Cipher::AesCbc, WRONG_KEY, None)
+                .unwrap();
+        assert!(lvol.open_encrypted(&key).await.is_err());
+        assert!(lvol.is_locked());
+    })
+    .await;
+
+    // providing the key opens the lvol, and shares it again
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_request(vec![ReplicaKey {
+            name: "encrypted".into(),
+            encryption: Some(ReplicaEncryption {
+                cipher: ReplicaCipher::CipherAesCbc as i32,
+                key: KEY.into(),
+                key2: String::new(),
+                key_file: String::new(),
+            }),
+        }]))
+        .await
+        .unwrap();
+
+        let lvol = pool.lvols().unwrap().next().unwrap();
+        assert!(!lvol.is_locked());
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+
+        // snapshots and clones hold data encrypted with the same key
+        let snapshot = lvol.snapshot("encrypted-snap").await.unwrap();
+        assert_eq!(snapshot.cipher(), Some(Cipher::AesCbc));
+        let clone = snapshot.create_clone("encrypted-clone").await.unwrap();
+        assert_eq!(clone.cipher(), Some(Cipher::AesCbc));
+        assert!(clone.is_locked());
+        assert!(clone.share_nvmf(None).await.is_err());
+
+        let wrong =
+            EncryptionKey::new("encrypted", Cipher::AesCbc, WRONG_KEY, None)
+                .unwrap();
+        assert!(clone.open_encrypted(&wrong).await.is_err());
+        let key =
+            EncryptionKey::new("encrypted", Cipher::AesCbc, KEY, None).unwrap();
+        clone.open_encrypted(&key).await.unwrap();
+        assert!(!clone.is_locked());
+
+        clone.destroy().await.unwrap();
+        lvol.destroy().await.unwrap();
+        snapshot.destroy().await.unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
@@ -5,7 +5,7 @@ use mayastor::{
     nexus_uri::bdev_create,
     subsys::NvmfSubsystem,
 };
-use rpc::mayastor::CreatePoolRequest;
+use rpc::mayastor::{CreatePoolRequest, PoolOvercommit};
 
 pub mod common;
 
@@ -34,6 +34,8 @@ async fn lvs_pool_test() {
         Lvs::create_or_import(CreatePoolRequest {
             name: "tpool".into(),
             disks: vec!["aio:///tmp/disk1.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -46,6 +48,8 @@ async fn lvs_pool_test() {
         assert!(Lvs::create_or_import(CreatePoolRequest {
             name: "tpool".into(),
             disks: vec!["aio:///tmp/disk1.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .is_ok())
@@ -142,6 +146,8 @@ async fn lvs_pool_test() {
         let pool2 = Lvs::create_or_import(CreatePoolRequest {
             name: "tpool2".to_string(),
             disks: vec!["malloc:///malloc0?size_mb=64".to_string()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -175,6 +181,8 @@ async fn lvs_pool_test() {
         let pool = Lvs::create_or_import(CreatePoolRequest {
             name: "tpool".to_string(),
             disks: vec!["aio:///tmp/disk1.img".to_string()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -306,6 +314,8 @@ async fn lvs_pool_test() {
         let pool = Lvs::create_or_import(CreatePoolRequest {
             name: "tpool".into(),
             disks: vec!["aio:///tmp/disk1.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -334,6 +344,8 @@ async fn lvs_pool_test() {
         Lvs::create_or_import(CreatePoolRequest {
             name: "jpool".into(),
             disks: vec!["aio:///tmp/disk1.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .err()
@@ -348,6 +360,8 @@ async fn lvs_pool_test() {
         let pool = Lvs::create_or_import(CreatePoolRequest {
             name: "tpool2".into(),
             disks: vec!["/tmp/disk2.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -355,5 +369,107 @@ async fn lvs_pool_test() {
     })
     .await;
 
+    // thin provisioned lvols may exceed the capacity of the pool up to the
+    // overcommit limit
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "tpool2".into(),
+            disks: vec!["/tmp/disk2.img".into()],
+            overcommit: Some(PoolOvercommit {
+                percent: 200,
+            }),
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+        assert_eq!(pool.overcommit(), 200);
+
+        // the limit is stored on the pool and kept as it is when a request
+        // does not set it, also when the pool is imported again
+        let tpool2 = || CreatePoolRequest {
+            name: "tpool2".into(),
+            disks: vec!["/tmp/disk2.img".into()],
+            overcommit: None,
+            replica_keys: vec![],
+        };
+        let pool = Lvs::create_or_import(tpool2()).await.unwrap();
+        assert_eq!(pool.overcommit(), 200);
+        pool.export().await.unwrap();
+        let pool = Lvs::create_or_import(tpool2()).await.unwrap();
+        assert_eq!(pool.overcommit(), 200);
+
+        let capacity = pool.capacity();
+        let first = pool
+            .create_lvol("thin1", capacity, None, true)
+            .await
+            .unwrap();
+        pool.create_lvol("thin2", capacity, None, true)
+            .await
+            .unwrap();
+        assert_eq!(pool.committed(), 2 * capacity);
+        assert!(first.allocated() < first.size());
+
+        // the overcommit limit has been reached
+        assert!(pool
+            .create_lvol("thin3", 4 * 1024 * 1024, None, true)
+            .await
+            .is_err());
+        assert!(first.resize(capacity + 4 * 1024 * 1024).await.is_err());
+
+        // lifting the limit allows for more lvols
+        pool.set_overcommit(0).await.unwrap();
+        pool.create_lvol("thin3", 4 * 1024 * 1024, None, true)
+            .await
+            .unwrap();
+
+        // a draining pool keeps its lvols but does not accept new ones
+        pool.set_draining(true).await.unwrap();
+        assert!(pool.draining());
+        assert!(pool
+            .create_lvol("thin4", 4 * 1024 * 1024, None, true)
+            .await
+            .is_err());
+        assert_eq!(pool.lvols().unwrap().count(), 3);
+
+        // the pool is still draining after it has been imported again
+        pool.export().await.unwrap();
+        let pool = Lvs::create_or_import(tpool2()).await.unwrap();
+        assert!(pool.draining());
+        let first =
+            pool.lvols().unwrap().find(|l| l.name() == "thin1").unwrap();
+
+        // reverting replaces an lvol rather than adding one, so it is still
+        // possible and keeps the lvol
+        pool.set_draining(false).await.unwrap();
+        let snapshot = first.snapshot("thin1-snap").await.unwrap();
+
+        // when the lvol can not be renamed out of the way of its replacement,
+        // as the name is taken, the lvol is kept and the clone is removed
+        let blocker = pool
+            .create_lvol("thin1-reverted", 4 * 1024 * 1024, None, true)
+            .await
+            .unwrap();
+        let uuid = first.uuid();
+        assert!(first.revert(&snapshot).await.is_err());
+        let first =
+            pool.lvols().unwrap().find(|l| l.name() == "thin1").unwrap();
+        assert_eq!(first.uuid(), uuid);
+        assert!(!pool.lvols().unwrap().any(|l| l.name() == "thin1-revert"));
+        blocker.destroy().await.unwrap();
+
+        pool.set_draining(true).await.unwrap();
+        let first = first.revert(&snapshot).await.unwrap();
+        assert_eq!(first.name(), "thin1");
+        assert_eq!(pool.lvols().unwrap().count(), 4);
+
+        pool.set_draining(false).await.unwrap();
+        pool.create_lvol("thin4", 4 * 1024 * 1024, None, true)
+            .await
+            .unwrap();
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
     common::delete_file(&[DISKNAME2.into()]);
 }
@@ -30,6 +30,8 @@ async fn lvs_pool_rpc() {
         .create_pool(CreatePoolRequest {
             name: "tpool".to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -38,6 +40,8 @@ async fn lvs_pool_rpc() {
         .create_pool(CreatePoolRequest {
             name: "tpool".to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -75,6 +79,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 1,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -84,6 +89,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 1,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -104,6 +110,7 @@ async fn lvs_pool_rpc() {
         .share_replica(ShareReplicaRequest {
             uuid: "cdc2a7db-3ac3-403a-af80-7fadc1581c47".to_string(),
             share: 0,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
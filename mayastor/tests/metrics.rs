@@ -0,0 +1,143 @@
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{LatencyHistogram, MayastorCliArgs},
+    lvs::Lvs,
+    metrics::{
+        collect,
+        encoder::{encode, Family},
+    },
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+
+#[test]
+fn metrics_encode() {
+    let mut reads = Family::counter("test_reads_total", "Number of reads");
+    reads.sample(&[("name", "a")], 1.0);
+    reads.sample(&[("name", "b\"c\\d\ne"), ("core", "0")], 42.0);
+    let empty = Family::gauge("test_empty", "Never sampled");
+
+    assert_eq!(
+        encode(&[reads, empty]),
+        "# HELP test_reads_total Number of reads\n\
+         # TYPE test_reads_total counter\n\
+         test_reads_total{name=\"a\"} 1\n\
+         test_reads_total{name=\"b\\\"c\\\\d\\ne\",core=\"0\"} 42\n"
+    );
+}
+
+#[test]
+fn metrics_latency_histogram() {
+    let mut h = LatencyHistogram::default();
+    assert_eq!(h.percentile(0.5), None);
+
+    // 1us falls in the < 2us bucket, 3us in the < 4us bucket
+    (0 .. 90).for_each(|_| h.record(1));
+    (0 .. 9).for_each(|_| h.record(3));
+    h.record(1_000);
+    assert_eq!(h.count, 100);
+    assert_eq!(h.total_us, 90 + 27 + 1_000);
+    assert_eq!(h.buckets[1], 90);
+    assert_eq!(h.buckets[2], 9);
+    assert_eq!(h.buckets[10], 1);
+
+    assert_eq!(h.percentile(0.5), Some(2));
+    assert_eq!(h.percentile(0.99), Some(4));
+    assert_eq!(h.percentile(1.0), Some(1024));
+
+    let mut merged = LatencyHistogram::default();
+    merge::Merge::merge(&mut merged, h);
+    merge::Merge::merge(&mut merged, h);
+    assert_eq!(merged.count, 200);
+    assert_eq!(merged.buckets[1], 180);
+
+    let mut family = Family::histogram("test_latency_seconds", "Latency");
+    family.histogram_sample(&[("name", "a")], &h);
+    let encoded = encode(&[family]);
+    assert!(encoded.contains("# TYPE test_latency_seconds histogram\n"));
+    assert!(encoded.contains(
+        "test_latency_seconds_bucket{name=\"a\",le=\"0.000004\"} 99\n"
+    ));
+    assert!(encoded
+        .contains("test_latency_seconds_bucket{name=\"a\",le=\"+Inf\"} 100\n"));
+    assert!(encoded.contains("test_latency_seconds_count{name=\"a\"} 100\n"));
+}
+
+#[tokio::test]
+async fn metrics_collect() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+            overcommit: None,
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+        let lvol = pool
+            .create_lvol("vol-1", 8 * 1024 * 1024, None, false)
+            .await
+            .unwrap();
+
+        nexus_create(
+            "nexus0",
+            4 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///malloc0?size_mb=16".into(),
+                format!("loopback:///{}", lvol.name()),
+            ],
+        )
+        .await
+        .unwrap();
+        bdev_io::write_some("nexus0", 0, 0xff).await.unwrap();
+
+        let metrics = collect().await;
+
+        assert!(metrics.contains(
+            "mayastor_nexus_status{nexus=\"nexus0\",status=\"online\"} 1\n"
+        ));
+        assert!(metrics
+            .contains("mayastor_nexus_size_bytes{nexus=\"nexus0\"} 4194304\n"));
+        assert!(metrics.contains("# TYPE mayastor_nexus_reads_total counter"));
+        assert!(metrics.contains(
+            "mayastor_nexus_child_state{nexus=\"nexus0\",\
+             child=\"malloc:///malloc0?size_mb=16\",state=\"open\"} 1\n"
+        ));
+        assert!(metrics.contains("mayastor_nexus_child_writes_total{"));
+        // the nexus keeps latency histograms of the IO it completes
+        assert!(metrics.contains(
+            "mayastor_nexus_write_latency_seconds_bucket{nexus=\"nexus0\",\
+             le=\"0.000001\"}"
+        ));
+        assert!(metrics.contains(
+            "mayastor_nexus_write_latency_seconds_count{nexus=\"nexus0\"} 1\n"
+        ));
+        assert!(
+            metrics.contains("mayastor_pool_capacity_bytes{pool=\"tpool\"}")
+        );
+        assert!(metrics.contains(&format!(
+            "mayastor_replica_size_bytes{{pool=\"tpool\",replica=\"{}\"}} {}\n",
+            lvol.uuid(),
+            lvol.size()
+        )));
+        assert!(metrics.contains("mayastor_replica_reads_total{"));
+        assert!(
+            metrics.contains("mayastor_reactor_busy_seconds_total{core=\"0\"}")
+        );
+
+        nexus_lookup("nexus0").unwrap().destroy().await.unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+
+use common::MayastorTest;
+use mayastor::{
+    bdev::{device_create, nexus_create, nexus_lookup},
+    core::{Bdev, BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "cache_nexus";
+static DISKNAME: &str = "/tmp/cache_disk.img";
+static CACHE_NAME: &str = "nexus_cache0";
+
+fn child() -> String {
+    format!(
+        "aio://{}?cache=malloc:///{}?size_mb=16",
+        DISKNAME, CACHE_NAME
+    )
+}
+
+/// read the first 4KiB of the nexus, returning the value of its first byte
+async fn read_line() -> u8 {
+    let h = BdevHandle::open(NEXUS_NAME, false, false).unwrap();
+    let mut buf = h.dma_malloc(4096).unwrap();
+    h.read_at(0, &mut buf).await.unwrap();
+    let value = buf.as_slice()[0];
+    assert!(buf.as_slice().iter().all(|b| *b == value));
+    value
+}
+
+async fn write_line(fill: u8) {
+    let h = BdevHandle::open(NEXUS_NAME, true, false).unwrap();
+    let mut buf = h.dma_malloc(4096).unwrap();
+    buf.fill(fill);
+    h.write_at(0, &buf).await.unwrap();
+}
+
+#[tokio::test]
+async fn nexus_cache() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let data_offset = ms
+        .spawn(async {
+            // only local devices can be used as a cache
+            assert!(device_create(&format!(
+                "aio://{}?cache=nvmf://127.0.0.1:8420/nqn:cache",
+                DISKNAME
+            ))
+            .await
+            .is_err());
+
+            nexus_create(NEXUS_NAME, 32 * 1024 * 1024, None, &[child()])
+                .await
+                .unwrap();
+            assert!(Bdev::lookup_by_name(CACHE_NAME).is_some());
+
+            write_line(0xaa).await;
+            assert_eq!(read_line().await, 0xaa);
+
+            nexus_lookup(NEXUS_NAME).unwrap().data_ent_offset
+        })
+        .await;
+
+    // change the data behind the back of the nexus
+    let mut file = OpenOptions::new().write(true).open(DISKNAME).unwrap();
+    file.seek(SeekFrom::Start(data_offset * 512)).unwrap();
+    file.write_all(&[0xbb; 4096]).unwrap();
+    file.sync_all().unwrap();
+
+    ms.spawn(async {
+        // the read is served from the cache
+        assert_eq!(read_line().await, 0xaa);
+
+        // writes invalidate the cache
+        write_line(0xcc).await;
+        assert_eq!(read_line().await, 0xcc);
+        assert_eq!(read_line().await, 0xcc);
+
+        // the cache goes away with the device
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        assert!(Bdev::lookup_by_name(CACHE_NAME).is_none());
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
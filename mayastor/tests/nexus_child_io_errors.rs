@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use common::{
+    bdev_io,
+    error_bdev::{
+        create_error_bdev,
+        inject_error,
+        SPDK_BDEV_IO_TYPE_WRITE,
+        VBDEV_IO_FAILURE,
+    },
+    MayastorTest,
+};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ChildState, Reason},
+    core::MayastorCliArgs,
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "IoErrorsNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static DISKNAME: &str = "/tmp/io_errors_disk.img";
+static ERROR_DEVICE: &str = "io_errors_device";
+static EE_ERROR_DEVICE: &str = "EE_io_errors_device";
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=12";
+static CHILD_2: &str = "bdev:///EE_io_errors_device";
+
+#[tokio::test]
+async fn nexus_child_io_errors() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        create_error_bdev(ERROR_DEVICE, DISKNAME);
+        nexus_create(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[CHILD_1.to_string(), CHILD_2.to_string()],
+        )
+        .await
+        .unwrap();
+
+        // a single error is within the error budget of the child, the write
+        // is retried and the child is kept
+        inject_error(
+            EE_ERROR_DEVICE,
+            SPDK_BDEV_IO_TYPE_WRITE,
+            VBDEV_IO_FAILURE,
+            1,
+        );
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let child = nexus.child_lookup(CHILD_2).unwrap();
+        assert_eq!(child.state(), ChildState::Open);
+        assert_eq!(child.io_errors(), (0, 1));
+
+        let listed = nexus.to_grpc_v2().await;
+        let listed = listed.children.iter().find(|c| c.uri == CHILD_2);
+        assert_eq!(listed.unwrap().transport_errors, 1);
+
+        // exhaust the error budget
+        inject_error(
+            EE_ERROR_DEVICE,
+            SPDK_BDEV_IO_TYPE_WRITE,
+            VBDEV_IO_FAILURE,
+            2,
+        );
+        let _ = bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await;
+    })
+    .await;
+
+    // the child is retired in the background
+    let mut state = ChildState::Open;
+    for _ in 0 .. 10 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        state = ms
+            .spawn(async {
+                nexus_lookup(NEXUS_NAME)
+                    .unwrap()
+                    .child_lookup(CHILD_2)
+                    .unwrap()
+                    .state()
+            })
+            .await;
+        if state != ChildState::Open {
+            break;
+        }
+    }
+    assert_eq!(state, ChildState::Faulted(Reason::IoError));
+
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.child_lookup(CHILD_2).unwrap().io_errors(), (0, 3));
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
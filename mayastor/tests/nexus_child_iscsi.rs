@@ -0,0 +1,66 @@
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    bdev::{device_create, nexus_create, nexus_lookup, ChildState},
+    core::{Bdev, MayastorCliArgs, Share},
+    nexus_uri::bdev_create,
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "iscsi_nexus";
+static REPLICA: &str = "malloc:///iscsi_replica?size_mb=64";
+static LOCAL: &str = "malloc:///iscsi_local?size_mb=64";
+
+#[tokio::test]
+async fn nexus_child_iscsi() {
+    let ms = MayastorTest::new(MayastorCliArgs {
+        reactor_mask: "0x3".into(),
+        ..Default::default()
+    });
+
+    ms.spawn(async {
+        // the URI must name a target
+        assert!(device_create("iscsi://127.0.0.1:3260").await.is_err());
+        assert!(device_create("iscsi://127.0.0.1:3260/iqn/0/1")
+            .await
+            .is_err());
+        assert!(device_create("iscsi://127.0.0.1:3260/iqn/lun")
+            .await
+            .is_err());
+
+        // export a replica over iSCSI and mirror onto it
+        let name = bdev_create(REPLICA).await.unwrap();
+        let uri = Bdev::lookup_by_name(&name)
+            .unwrap()
+            .share_iscsi()
+            .await
+            .unwrap();
+
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[uri.clone(), LOCAL.into()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let child = &nexus.children[0];
+        assert_eq!(child.state(), ChildState::Open);
+        assert_eq!(child.get_device().unwrap().driver_name(), "iscsi");
+
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        // logging out of the target removes the device
+        nexus.remove_child(&uri).await.unwrap();
+        assert!(Bdev::bdev_first()
+            .unwrap()
+            .into_iter()
+            .all(|b| b.driver() != "iscsi"));
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
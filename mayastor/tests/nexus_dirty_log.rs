@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use common::{
+    bdev_io,
+    error_bdev::{
+        create_error_bdev,
+        inject_error,
+        SPDK_BDEV_IO_TYPE_WRITE,
+        VBDEV_IO_FAILURE,
+    },
+    MayastorTest,
+};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ChildState, Reason},
+    core::{BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "DirtyLogNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static DISKNAME: &str = "/tmp/dirty_log_disk.img";
+static ERROR_DEVICE: &str = "dirty_log_device";
+static EE_ERROR_DEVICE: &str = "EE_dirty_log_device";
+static MALLOC_DEVICE: &str = "malloc_dirty_log";
+static CHILD_1: &str = "malloc:///malloc_dirty_log?blk_size=512&size_mb=12";
+static CHILD_2: &str = "bdev:///EE_dirty_log_device";
+
+/// offset of the write which fails on the error device
+static FAILED_OFFSET: u64 = 0;
+/// offset of a write which the error device misses once it is faulted
+static MISSED_OFFSET: u64 = 4 * 1024 * 1024;
+
+/// Read the first blocks at `offset` of the data partition of both children
+/// and check that they hold the same data.
+async fn compare_children(data_offset: u64, offset: u64) {
+    let mut bufs = Vec::new();
+    for name in &[MALLOC_DEVICE, EE_ERROR_DEVICE] {
+        let h = BdevHandle::open(name, false, false).unwrap();
+        let block_len = u64::from(h.get_bdev().block_len());
+        let mut buf = h.dma_malloc(block_len * 2).unwrap();
+        h.read_at(data_offset * block_len + offset, &mut buf)
+            .await
+            .unwrap();
+        bufs.push(buf.as_slice().to_vec());
+    }
+    assert_eq!(bufs[0], bufs[1], "children differ at offset {}", offset);
+}
+
+/// A write which fails on a child and exhausts its error budget is recorded
+/// in the dirty log of that child, together with the writes the child misses
+/// once it is faulted, so that a partial rebuild brings it back in sync.
+#[tokio::test]
+async fn nexus_dirty_log_failed_write() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        create_error_bdev(ERROR_DEVICE, DISKNAME);
+        nexus_create(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[CHILD_1.to_string(), CHILD_2.to_string()],
+        )
+        .await
+        .unwrap();
+
+        bdev_io::write_some(NEXUS_NAME, FAILED_OFFSET, 0xaa)
+            .await
+            .unwrap();
+
+        // the write reaches the malloc child but exhausts the error budget
+        // of the error device
+        inject_error(
+            EE_ERROR_DEVICE,
+            SPDK_BDEV_IO_TYPE_WRITE,
+            VBDEV_IO_FAILURE,
+            3,
+        );
+        let _ = bdev_io::write_some(NEXUS_NAME, FAILED_OFFSET, 0xbb).await;
+    })
+    .await;
+
+    // the child is retired in the background
+    let mut retired = false;
+    for _ in 0 .. 10 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        retired = ms
+            .spawn(async {
+                let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+                let child = nexus
+                    .children
+                    .iter()
+                    .find(|c| c.get_name() == CHILD_2)
+                    .unwrap();
+                child.state() == ChildState::Faulted(Reason::IoError)
+                    && child.get_device().is_err()
+            })
+            .await;
+        if retired {
+            break;
+        }
+    }
+    assert!(retired);
+
+    ms.spawn(async {
+        bdev_io::write_some(NEXUS_NAME, MISSED_OFFSET, 0xcc)
+            .await
+            .unwrap();
+
+        // onlining the child starts a partial rebuild
+        nexus_lookup(NEXUS_NAME)
+            .unwrap()
+            .online_child(CHILD_2)
+            .await
+            .unwrap();
+    })
+    .await;
+
+    let mut state = ChildState::Faulted(Reason::OutOfSync);
+    for _ in 0 .. 20 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        state = ms
+            .spawn(async {
+                nexus_lookup(NEXUS_NAME)
+                    .unwrap()
+                    .children
+                    .iter()
+                    .find(|c| c.get_name() == CHILD_2)
+                    .unwrap()
+                    .state()
+            })
+            .await;
+        if state == ChildState::Open {
+            break;
+        }
+    }
+    assert_eq!(state, ChildState::Open);
+
+    ms.spawn(async {
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        compare_children(nexus.data_ent_offset, FAILED_OFFSET).await;
+        compare_children(nexus.data_ent_offset, MISSED_OFFSET).await;
+        bdev_io::read_some(NEXUS_NAME, MISSED_OFFSET, 0xcc)
+            .await
+            .unwrap();
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
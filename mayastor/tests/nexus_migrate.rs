@@ -0,0 +1,102 @@
+use common::{
+    bdev_io,
+    error_bdev::{
+        create_error_bdev,
+        inject_error,
+        SPDK_BDEV_IO_TYPE_WRITE,
+        VBDEV_IO_FAILURE,
+    },
+    MayastorTest,
+};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ChildState},
+    core::{Bdev, BdevHandle, MayastorCliArgs},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "migrate_nexus";
+static CHILD0: &str = "malloc:///migrate0?size_mb=64";
+static CHILD1: &str = "malloc:///migrate1?size_mb=64";
+static TARGET: &str = "malloc:///migrate2?size_mb=64";
+static TOO_SMALL: &str = "malloc:///migrate3?size_mb=8";
+static DISKNAME: &str = "/tmp/migrate_error.img";
+static ERROR_DEVICE: &str = "migrate_error";
+static EE_ERROR_DEVICE: &str = "EE_migrate_error";
+static FAILING: &str = "bdev:///EE_migrate_error";
+
+fn child_names() -> Vec<String> {
+    nexus_lookup(NEXUS_NAME)
+        .unwrap()
+        .children
+        .iter()
+        .map(|c| c.get_name().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn nexus_migrate_child() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[CHILD0.into(), CHILD1.into()],
+        )
+        .await
+        .unwrap();
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+
+        // only existing, healthy children can be migrated
+        assert!(nexus.migrate_child(TARGET, CHILD0).await.is_err());
+        assert_eq!(child_names(), vec![CHILD0, CHILD1]);
+
+        // a failed migration leaves the nexus as it was
+        assert!(nexus.migrate_child(CHILD1, TOO_SMALL).await.is_err());
+        assert_eq!(child_names(), vec![CHILD0, CHILD1]);
+        assert!(Bdev::lookup_by_name("migrate3").is_none());
+
+        // so does a migration to a child which fails to be rebuilt
+        create_error_bdev(ERROR_DEVICE, DISKNAME);
+        inject_error(
+            EE_ERROR_DEVICE,
+            SPDK_BDEV_IO_TYPE_WRITE,
+            VBDEV_IO_FAILURE,
+            1000,
+        );
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.migrate_child(CHILD1, FAILING).await.is_err());
+        assert_eq!(child_names(), vec![CHILD0, CHILD1]);
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.children.iter().all(|c| c.state() == ChildState::Open));
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        nexus.migrate_child(CHILD1, TARGET).await.unwrap();
+        assert_eq!(child_names(), vec![CHILD0, TARGET]);
+        assert!(Bdev::lookup_by_name("migrate1").is_none());
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.children.iter().all(|c| c.state() == ChildState::Open));
+
+        // the data has been copied to the new child
+        let h = BdevHandle::open("migrate2", false, false).unwrap();
+        let mut buf = h.dma_malloc(1024).unwrap();
+        h.read_at(nexus.data_ent_offset * 512, &mut buf)
+            .await
+            .unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0xaa));
+        drop(h);
+
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
@@ -10,6 +10,7 @@ use rpc::mayastor::{
     CreatePoolRequest,
     CreateReplicaRequest,
     DestroyNexusRequest,
+    NexusReadPolicy,
     Null,
     NvmeAnaState,
     PublishNexusRequest,
@@ -120,6 +121,8 @@ async fn nexus_multipath() {
         .create_pool(CreatePoolRequest {
             name: POOL_NAME.to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -179,6 +182,7 @@ async fn nexus_multipath() {
             uuid: UUID.to_string(),
             key: "".to_string(),
             share: ShareProtocolNexus::NexusNvmf as i32,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -346,6 +350,8 @@ async fn nexus_resv_acquire() {
         .create_pool(CreatePoolRequest {
             name: POOL_NAME.to_string(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -436,6 +442,7 @@ async fn nexus_resv_acquire() {
             resv_key: resv_key2,
             children: [format!("nvmf://{}:8420/{}:{}", ip0, HOSTNQN, UUID)]
                 .to_vec(),
+            read_policy: NexusReadPolicy::NexusReadRoundRobin as i32,
         })
         .await
         .unwrap();
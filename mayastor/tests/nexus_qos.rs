@@ -0,0 +1,60 @@
+use common::bdev_io;
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::{MayastorCliArgs, QosLimits},
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "qos_nexus";
+
+#[tokio::test]
+async fn nexus_qos() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            1024 * 1024 * 50,
+            None,
+            &[
+                "malloc:///malloc0?blk_size=512&size_mb=100".into(),
+                "malloc:///malloc1?blk_size=512&size_mb=100".into(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.qos_limits(), QosLimits::default());
+        assert_eq!(nexus.qos_throttled(), 0);
+
+        let limits = QosLimits {
+            iops: 4,
+            ..Default::default()
+        };
+        nexus.set_qos_limits(limits);
+        assert_eq!(nexus.qos_limits(), limits);
+
+        // the burst allows for a single IO, and as the limit may be overdrawn
+        // by one IO, the IOs that follow have to wait for the bucket to refill
+        for _ in 0 .. 4 {
+            bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        }
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        assert!(nexus_lookup(NEXUS_NAME).unwrap().qos_throttled() > 0);
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.set_qos_limits(QosLimits::default());
+        assert_eq!(nexus.qos_limits(), QosLimits::default());
+        assert_eq!(nexus.qos_throttled(), 0);
+
+        for _ in 0 .. 4 {
+            bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        }
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,173 @@
+use common::bdev_io;
+use futures::future::join_all;
+use mayastor::{
+    bdev::{
+        device_create,
+        device_lookup,
+        nexus_create,
+        nexus_lookup,
+        NexusReadPolicy,
+    },
+    core::{Bdev, MayastorCliArgs, Share},
+    subsys::NvmfSubsystem,
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "read_policy_nexus";
+static REMOVE_NEXUS_NAME: &str = "read_policy_remove_nexus";
+static LOCAL: &str = "malloc0";
+static REMOTE: &str = "malloc1";
+/// number of reads issued for every policy
+static READS: u64 = 16;
+
+/// the number of reads completed by each of the given devices so far
+async fn read_counts_of(names: &[&str]) -> Vec<u64> {
+    let mut counts = Vec::new();
+    for name in names {
+        let stats = device_lookup(name).unwrap().io_stats().await.unwrap();
+        counts.push(stats.num_read_ops);
+    }
+    counts
+}
+
+/// the number of reads completed by the local and the remote child so far
+async fn read_counts() -> (u64, u64) {
+    let counts = read_counts_of(&[LOCAL, REMOTE]).await;
+    (counts[0], counts[1])
+}
+
+/// read from the nexus with the given policy and return the number of reads
+/// which went to the local and to the remote child
+async fn read_with(policy: NexusReadPolicy) -> (u64, u64) {
+    nexus_lookup(NEXUS_NAME).unwrap().set_read_policy(policy);
+    assert_eq!(nexus_lookup(NEXUS_NAME).unwrap().read_policy(), policy);
+
+    let (local, remote) = read_counts().await;
+    // both children hold the same data so every read must succeed
+    // regardless of the child it is sent to
+    for _ in 0 .. READS {
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+    }
+    let (local_now, remote_now) = read_counts().await;
+    (local_now - local, remote_now - remote)
+}
+
+#[tokio::test]
+async fn nexus_read_policy() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        // the remote child is a malloc bdev of this node which the nexus
+        // connects to over nvmf
+        device_create("malloc:///malloc1?blk_size=512&size_mb=100")
+            .await
+            .unwrap();
+        Bdev::lookup_by_name(REMOTE)
+            .unwrap()
+            .share_nvmf(None)
+            .await
+            .unwrap();
+        let remote = NvmfSubsystem::nqn_lookup(REMOTE)
+            .unwrap()
+            .uri_endpoints()
+            .unwrap()
+            .remove(0);
+
+        nexus_create(
+            NEXUS_NAME,
+            1024 * 1024 * 50,
+            None,
+            &["malloc:///malloc0?blk_size=512&size_mb=100".into(), remote],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.read_policy(), NexusReadPolicy::RoundRobin);
+
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        // the children take turns
+        assert_eq!(
+            read_with(NexusReadPolicy::RoundRobin).await,
+            (READS / 2, READS / 2)
+        );
+
+        // the remote child is not read from while the local one is healthy
+        assert_eq!(read_with(NexusReadPolicy::PreferLocal).await, (READS, 0));
+
+        // the reads are issued one after another, so no child ever has more
+        // reads in flight than the other and the children take turns
+        assert_eq!(
+            read_with(NexusReadPolicy::LeastOutstanding).await,
+            (READS / 2, READS / 2)
+        );
+
+        // the local child responds faster and gets most of the reads
+        let (local, remote) = read_with(NexusReadPolicy::LowestLatency).await;
+        assert_eq!(local + remote, READS);
+        assert!(local > remote);
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Bdev::lookup_by_name(REMOTE)
+            .unwrap()
+            .unshare()
+            .await
+            .unwrap();
+    })
+    .await;
+
+    // removing a child while reads are in flight must not leave the reads
+    // of the remaining children accounted as outstanding
+    ms.spawn(async {
+        let children = [
+            "malloc:///malloc2?blk_size=512&size_mb=100".to_string(),
+            "malloc:///malloc3?blk_size=512&size_mb=100".to_string(),
+            "malloc:///malloc4?blk_size=512&size_mb=100".to_string(),
+        ];
+        nexus_create(REMOVE_NEXUS_NAME, 1024 * 1024 * 50, None, &children)
+            .await
+            .unwrap();
+        bdev_io::write_some(REMOVE_NEXUS_NAME, 0, 0xaa)
+            .await
+            .unwrap();
+
+        let nexus = nexus_lookup(REMOVE_NEXUS_NAME).unwrap();
+        nexus.set_read_policy(NexusReadPolicy::LeastOutstanding);
+
+        let reads = join_all(
+            (0 .. READS)
+                .map(|_| bdev_io::read_some(REMOVE_NEXUS_NAME, 0, 0xaa)),
+        );
+        let remove = async {
+            nexus_lookup(REMOVE_NEXUS_NAME)
+                .unwrap()
+                .remove_child(&children[2])
+                .await
+        };
+        let (reads, removed) = futures::join!(reads, remove);
+        reads.into_iter().for_each(|r| r.unwrap());
+        removed.unwrap();
+
+        // no reads are in flight anymore so the remaining children take
+        // turns again
+        let before = read_counts_of(&["malloc2", "malloc3"]).await;
+        for _ in 0 .. READS {
+            bdev_io::read_some(REMOVE_NEXUS_NAME, 0, 0xaa)
+                .await
+                .unwrap();
+        }
+        let after = read_counts_of(&["malloc2", "malloc3"]).await;
+        assert_eq!(after[0] - before[0], READS / 2);
+        assert_eq!(after[1] - before[1], READS / 2);
+
+        nexus_lookup(REMOVE_NEXUS_NAME)
+            .unwrap()
+            .destroy()
+            .await
+            .unwrap();
+    })
+    .await;
+}
@@ -7,7 +7,7 @@ use tracing::error;
 use mayastor::{
     bdev::{device_open, nexus_lookup},
     core::{MayastorCliArgs, Mthread},
-    rebuild::{RebuildJob, RebuildState},
+    rebuild::{RebuildJob, RebuildMode, RebuildState},
 };
 use rpc::mayastor::ShareProtocolNexus;
 
@@ -224,7 +224,10 @@ async fn rebuild_replica() {
                 .any(|_| panic!("Should not have found any jobs!"));
         }
 
-        let _ = nexus.start_rebuild(&get_dev(NUM_CHILDREN)).await.unwrap();
+        let _ = nexus
+            .start_rebuild(&get_dev(NUM_CHILDREN), RebuildMode::Full)
+            .await
+            .unwrap();
         for child in 0 .. NUM_CHILDREN {
             RebuildJob::lookup(&get_dev(child))
                 .expect_err("rebuild job not created yet");
@@ -274,7 +277,7 @@ async fn rebuild_replica() {
             .await
             .unwrap();
         let _ = nexus
-            .start_rebuild(&get_dev(NUM_CHILDREN + 1))
+            .start_rebuild(&get_dev(NUM_CHILDREN + 1), RebuildMode::Full)
             .await
             .unwrap();
         assert_eq!(RebuildJob::lookup_src(&src).len(), 2);
@@ -0,0 +1,103 @@
+use common::MayastorTest;
+use mayastor::{
+    bdev::{nexus_lookup, nexus_recover, ChildInfo, NexusInfo},
+    core::MayastorCliArgs,
+    persistent_store::PersistentStore,
+};
+use rpc::mayastor::ShareProtocolNexus;
+
+pub mod common;
+
+static NEXUS_UUID: &str = "0e3b7b67-1b5e-4cb5-8f0b-7d8a9a4e6c11";
+static OTHER_UUID: &str = "5b8f8f43-1c69-4b8e-a8a5-1c0a3c6e2d22";
+static CLEAN_UUID: &str = "9a6d2f1c-7e4b-4f3a-b2c1-4d5e6f7a8b33";
+static STRAY_UUID: &str = "3e7c1a9d-5b2f-4c8e-9d0a-6f1b2c3d4e44";
+
+static CHILD0_UUID: &str = "c1a2b3c4-d5e6-4f70-8192-a3b4c5d6e7f0";
+static CHILD1_UUID: &str = "d2b3c4d5-e6f7-4081-92a3-b4c5d6e7f801";
+
+fn child(name: &str, uuid: &str, healthy: bool) -> ChildInfo {
+    ChildInfo {
+        uuid: uuid.into(),
+        healthy,
+        uri: format!("malloc:///{}?size_mb=64&uuid={}", name, uuid),
+    }
+}
+
+fn nexus_info(uuid: &str, node: &str, clean_shutdown: bool) -> NexusInfo {
+    NexusInfo {
+        clean_shutdown,
+        children: vec![
+            child("recover0", CHILD0_UUID, true),
+            child("recover1", CHILD1_UUID, false),
+        ],
+        name: format!("nexus-{}", uuid),
+        size: 32 * 1024 * 1024,
+        node: node.into(),
+        share: Some(ShareProtocolNexus::NexusNvmf),
+    }
+}
+
+/// Nexuses that were on this node are recreated from the persistent store,
+/// those of other nodes or that have been destroyed are not.
+#[tokio::test]
+async fn nexus_recovery() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    PersistentStore::init(Some("memory://".into())).await;
+
+    ms.spawn(async {
+        let infos = vec![
+            (NEXUS_UUID, nexus_info(NEXUS_UUID, "mayastor-node", false)),
+            (OTHER_UUID, nexus_info(OTHER_UUID, "other-node", false)),
+            (CLEAN_UUID, nexus_info(CLEAN_UUID, "mayastor-node", true)),
+        ];
+        for (uuid, info) in infos {
+            PersistentStore::put(&NexusInfo::key(uuid), &info)
+                .await
+                .unwrap();
+        }
+        // entries outside of the nexus prefix are not looked at
+        PersistentStore::put(
+            &STRAY_UUID,
+            &nexus_info(STRAY_UUID, "mayastor-node", false),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(nexus_recover().await, 1);
+        assert!(nexus_lookup(&format!("nexus-{}", OTHER_UUID)).is_none());
+        assert!(nexus_lookup(&format!("nexus-{}", CLEAN_UUID)).is_none());
+        assert!(nexus_lookup(&format!("nexus-{}", STRAY_UUID)).is_none());
+
+        // the unhealthy child has been added back to be rebuilt and the nexus
+        // is shared again
+        let name = format!("nexus-{}", NEXUS_UUID);
+        let nexus = nexus_lookup(&name).unwrap();
+        assert_eq!(nexus.children.len(), 2);
+        assert_eq!(
+            nexus.children[0].get_name(),
+            child("recover0", CHILD0_UUID, true).uri
+        );
+        assert_eq!(
+            nexus.children[1].get_name(),
+            child("recover1", CHILD1_UUID, false).uri
+        );
+        assert!(nexus.get_share_uri().unwrap().starts_with("nvmf://"));
+
+        let info: NexusInfo = serde_json::from_value(
+            PersistentStore::get(&NexusInfo::key(NEXUS_UUID))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(info.name, name);
+        assert_eq!(info.share, Some(ShareProtocolNexus::NexusNvmf));
+        assert_eq!(info.children.len(), 2);
+
+        // a nexus that exists already is left alone
+        assert_eq!(nexus_recover().await, 0);
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use common::{bdev_io, delay_bdev::create_delay_bdev};
+use mayastor::{
+    bdev::{device_create, nexus_create, nexus_lookup, ChildState, Reason},
+    core::MayastorCliArgs,
+    subsys::{Config, NexusOpts},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "SlowChildNexus";
+static NEXUS_SIZE: u64 = 10 * 1024 * 1024;
+static CHILD_1: &str = "malloc:///malloc0?blk_size=512&size_mb=12";
+static CHILD_2: &str = "malloc:///malloc1?blk_size=512&size_mb=12";
+
+static DELAY_NEXUS_NAME: &str = "DelayedChildNexus";
+static DELAY_CHILD_1: &str = "malloc:///malloc2?blk_size=512&size_mb=12";
+static DELAY_CHILD_2: &str = "malloc:///malloc3?blk_size=512&size_mb=12";
+static DELAY_BASE: &str = "malloc:///malloc4?blk_size=512&size_mb=12";
+static DELAY_DEVICE: &str = "delayed_child";
+static DELAY_CHILD_3: &str = "bdev:///delayed_child";
+/// latency of the delayed child in microseconds
+static DELAY_US: u64 = 50_000;
+
+#[tokio::test]
+async fn nexus_slow_child() {
+    Config::get_or_init(|| Config {
+        nexus_opts: NexusOpts {
+            slow_child_factor: 0,
+            slow_child_window_secs: 1,
+            slow_child_min_latency_ms: 10,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let ms = common::MayastorTest::new(MayastorCliArgs::default());
+
+    // a child which completes its IOs far slower than the other children
+    // is faulted once it has been slow for the whole window, provided the
+    // detection is enabled, which can be done at runtime
+    ms.spawn(async {
+        let base = device_create(DELAY_BASE).await.unwrap();
+        create_delay_bdev(DELAY_DEVICE, &base, DELAY_US);
+        nexus_create(
+            DELAY_NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[
+                DELAY_CHILD_1.to_string(),
+                DELAY_CHILD_2.to_string(),
+                DELAY_CHILD_3.to_string(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        // the delayed child is left alone for as long as the detection is
+        // disabled
+        for _ in 0 .. 40 {
+            bdev_io::write_some(DELAY_NEXUS_NAME, 0, 0xaa)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            nexus_lookup(DELAY_NEXUS_NAME)
+                .unwrap()
+                .get_child_by_name(DELAY_CHILD_3)
+                .unwrap()
+                .state(),
+            ChildState::Open
+        );
+        Config::update(b"nexus_opts:\n  slow_child_factor: 10\n").unwrap();
+
+        // keep writing, every write takes as long as the delayed child
+        let mut state = ChildState::Open;
+        for _ in 0 .. 100 {
+            bdev_io::write_some(DELAY_NEXUS_NAME, 0, 0xaa)
+                .await
+                .unwrap();
+            state = nexus_lookup(DELAY_NEXUS_NAME)
+                .unwrap()
+                .get_child_by_name(DELAY_CHILD_3)
+                .unwrap()
+                .state();
+            if state != ChildState::Open {
+                break;
+            }
+        }
+        assert_eq!(state, ChildState::Faulted(Reason::Slow));
+
+        let nexus = nexus_lookup(DELAY_NEXUS_NAME).unwrap();
+        assert!(nexus
+            .children
+            .iter()
+            .filter(|c| c.get_name() != DELAY_CHILD_3)
+            .all(|c| c.state() == ChildState::Open));
+
+        // the nexus carries on with the other children
+        bdev_io::write_some(DELAY_NEXUS_NAME, 0, 0xbb)
+            .await
+            .unwrap();
+        bdev_io::read_some(DELAY_NEXUS_NAME, 0, 0xbb).await.unwrap();
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[CHILD_1.to_string(), CHILD_2.to_string()],
+        )
+        .await
+        .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        nexus.fault_child(CHILD_2, Reason::Slow).await.unwrap();
+
+        // a slow child is kept open so that its latency can be probed
+        let child = nexus.get_child_by_name(CHILD_2).unwrap();
+        assert_eq!(child.state(), ChildState::Faulted(Reason::Slow));
+        assert!(child.get_device().is_ok());
+    })
+    .await;
+
+    // the child responds quickly, so it gets rebuilt once it has done so for
+    // the whole window
+    let mut state = ChildState::Faulted(Reason::Slow);
+    for _ in 0 .. 20 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        state = ms
+            .spawn(async {
+                nexus_lookup(NEXUS_NAME)
+                    .unwrap()
+                    .get_child_by_name(CHILD_2)
+                    .unwrap()
+                    .state()
+            })
+            .await;
+        if state == ChildState::Open {
+            break;
+        }
+    }
+    assert_eq!(state, ChildState::Open);
+
+    ms.spawn(async {
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
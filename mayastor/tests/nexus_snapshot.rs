@@ -0,0 +1,111 @@
+use std::convert::TryFrom;
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, ChildState},
+    core::{Bdev, MayastorCliArgs},
+    lvs::{Cipher, EncryptionKey, Lvol, Lvs},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/nexus_snapshot.img";
+static POOL_NAME: &str = "snapshot_pool";
+static LVOL_NAME: &str = "snapshot_lvol";
+static CRYPT_LVOL_NAME: &str = "snapshot_crypt_lvol";
+static NEXUS_NAME: &str = "snapshot_nexus";
+static CRYPT_NEXUS_NAME: &str = "snapshot_crypt_nexus";
+static MALLOC_CHILD: &str = "malloc:///snapshot_malloc?size_mb=32";
+static KEY: &str = "0123456789abcdef";
+
+static LVOL_SIZE: u64 = 16 * 1024 * 1024;
+static NEXUS_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Snapshots of a nexus cover all of its children or none: the snapshots of
+/// the children which were snapshotted before another child failed are
+/// destroyed again. Local children may be encrypted lvols, in which case the
+/// snapshot is taken of the lvol underneath the crypto bdev.
+#[tokio::test]
+async fn nexus_snapshot_children() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: POOL_NAME.into(),
+            disks: vec![format!("aio://{}", DISKNAME)],
+            overcommit: None,
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+        pool.create_lvol(LVOL_NAME, LVOL_SIZE, None, false)
+            .await
+            .unwrap();
+
+        // the malloc child can not be snapshotted, after the lvol has been
+        nexus_create(
+            NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[format!("bdev:///{}", LVOL_NAME), MALLOC_CHILD.to_string()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.create_snapshot().await.is_err());
+        assert_eq!(
+            pool.lvols().unwrap().filter(|l| l.is_snapshot()).count(),
+            0
+        );
+        assert!(nexus.children.iter().all(|c| c.state() == ChildState::Open));
+
+        // the nexus is no longer paused
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        let pool = Lvs::lookup(POOL_NAME).unwrap();
+        let lvol = pool
+            .create_lvol(CRYPT_LVOL_NAME, LVOL_SIZE, None, false)
+            .await
+            .unwrap();
+        let key =
+            EncryptionKey::new(CRYPT_LVOL_NAME, Cipher::AesCbc, KEY, None)
+                .unwrap();
+        lvol.encrypt(&key).await.unwrap();
+
+        nexus_create(
+            CRYPT_NEXUS_NAME,
+            NEXUS_SIZE,
+            None,
+            &[format!("bdev:///{}-crypt", CRYPT_LVOL_NAME)],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup(CRYPT_NEXUS_NAME).unwrap();
+        let reply = nexus.create_snapshot().await.unwrap();
+        let name =
+            Lvol::format_snapshot_name(CRYPT_LVOL_NAME, reply.snapshot_id);
+        assert_eq!(reply.children.len(), 1);
+        assert_eq!(reply.children[0].name, name);
+
+        let snapshot =
+            Lvol::try_from(Bdev::lookup_by_name(&name).unwrap()).unwrap();
+        assert!(snapshot.is_snapshot());
+        assert_eq!(snapshot.cipher(), Some(Cipher::AesCbc));
+
+        nexus.destroy().await.unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
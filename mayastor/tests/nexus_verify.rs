@@ -0,0 +1,109 @@
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+};
+
+use common::bdev_io;
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup},
+    core::MayastorCliArgs,
+    verify::VerifyState,
+};
+
+pub mod common;
+use common::MayastorTest;
+
+static NEXUS_NAME: &str = "verify_nexus";
+static DISKNAME0: &str = "/tmp/verify_disk0.img";
+static DISKNAME1: &str = "/tmp/verify_disk1.img";
+
+fn child(disk: &str) -> String {
+    format!("aio://{}?blk_size=512", disk)
+}
+
+/// run a verify of the nexus to completion
+async fn verify(repair: bool, source: Option<&str>) {
+    let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+    let rx = nexus.start_verify(repair, source).await.unwrap();
+    assert_eq!(rx.await.unwrap(), VerifyState::Completed);
+}
+
+#[tokio::test]
+async fn nexus_verify() {
+    common::delete_file(&[DISKNAME0.into(), DISKNAME1.into()]);
+    common::truncate_file(DISKNAME0, 64 * 1024);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    let data_offset = ms
+        .spawn(async {
+            nexus_create(
+                NEXUS_NAME,
+                32 * 1024 * 1024,
+                None,
+                &[child(DISKNAME0), child(DISKNAME1)],
+            )
+            .await
+            .unwrap();
+
+            bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+            // in sync children do not differ
+            verify(false, None).await;
+            let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+            let stats = nexus.get_verify_stats().await.unwrap();
+            assert_eq!(stats.progress, 100);
+            assert_eq!(stats.blocks_mismatched, 0);
+            assert!(stats.mismatches.is_empty());
+
+            nexus.data_ent_offset
+        })
+        .await;
+
+    // corrupt the second block of the data of the second child
+    let mut file = OpenOptions::new().write(true).open(DISKNAME1).unwrap();
+    file.seek(SeekFrom::Start((data_offset + 1) * 512)).unwrap();
+    file.write_all(&[0xbb; 512]).unwrap();
+    file.sync_all().unwrap();
+
+    ms.spawn(async {
+        verify(false, None).await;
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        let stats = nexus.get_verify_stats().await.unwrap();
+        assert_eq!(stats.source, child(DISKNAME0));
+        assert_eq!(stats.blocks_mismatched, 1);
+        assert_eq!(stats.blocks_repaired, 0);
+        assert_eq!(stats.mismatches.len(), 1);
+        assert_eq!(stats.mismatches[0].offset_blks, 1);
+        assert_eq!(stats.mismatches[0].num_blks, 1);
+        assert_eq!(stats.mismatches[0].children, vec![child(DISKNAME1)]);
+        assert!(!stats.mismatches[0].repaired);
+
+        // with the corrupted child as the source the other one differs
+        verify(false, Some(&child(DISKNAME1))).await;
+        let stats = nexus.get_verify_stats().await.unwrap();
+        assert_eq!(stats.mismatches[0].children, vec![child(DISKNAME0)]);
+
+        verify(true, None).await;
+        let stats = nexus.get_verify_stats().await.unwrap();
+        assert_eq!(stats.blocks_mismatched, 1);
+        assert_eq!(stats.blocks_repaired, 1);
+        assert!(stats.mismatches[0].repaired);
+
+        // the children are consistent again, and hold the data of the source
+        verify(false, None).await;
+        let stats = nexus.get_verify_stats().await.unwrap();
+        assert_eq!(stats.blocks_mismatched, 0);
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        // stopping a finished verify discards its results
+        nexus.stop_verify().await.unwrap();
+        assert!(nexus.get_verify_stats().await.is_err());
+        assert!(nexus.pause_verify().await.is_err());
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME0.into(), DISKNAME1.into()]);
+}
@@ -25,6 +25,8 @@ async fn create_replicas(h: &mut RpcHandle) {
         .create_pool(CreatePoolRequest {
             name: pool(),
             disks: vec!["malloc:///disk0?size_mb=64".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
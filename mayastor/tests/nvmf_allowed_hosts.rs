@@ -0,0 +1,171 @@
+use common::MayastorTest;
+use mayastor::{
+    bdev::{host_nqn, nexus_create, nexus_lookup},
+    core::{MayastorCliArgs, Share},
+    lvs::Lvs,
+    subsys::NvmfSubsystem,
+};
+use rpc::mayastor::{CreatePoolRequest, ShareProtocolNexus};
+
+pub mod common;
+
+static DISKNAME1: &str = "/tmp/disk1.img";
+static HOST1: &str = "nqn.2019-05.io.openebs:host1";
+static HOST2: &str = "nqn.2019-05.io.openebs:host2";
+
+/// the hosts allowed to connect to the subsystem of the given bdev, or None
+/// if any host may connect
+fn subsystem_hosts(bdev: &str) -> Option<Vec<String>> {
+    let subsystem = NvmfSubsystem::nqn_lookup(bdev).unwrap();
+    if subsystem.allows_any() {
+        None
+    } else {
+        let mut hosts = subsystem.allowed_hosts();
+        hosts.sort();
+        Some(hosts)
+    }
+}
+
+#[tokio::test]
+async fn nvmf_allowed_hosts() {
+    common::delete_file(&[DISKNAME1.into()]);
+    common::truncate_file(DISKNAME1, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    // restrict a replica before sharing it
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+            overcommit: None,
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+
+        let lvol = pool
+            .create_lvol("vol-1", 8 * 1024 * 1024, None, false)
+            .await
+            .unwrap();
+        lvol.set_allowed_hosts(vec![HOST1.into()]).await.unwrap();
+        lvol.share_nvmf(None).await.unwrap();
+
+        assert_eq!(lvol.allowed_hosts().unwrap(), vec![HOST1.to_string()]);
+        assert_eq!(subsystem_hosts("vol-1"), Some(vec![HOST1.to_string()]));
+
+        // changing the hosts of a shared replica takes effect immediately
+        lvol.set_allowed_hosts(vec![HOST1.into(), HOST2.into()])
+            .await
+            .unwrap();
+        assert_eq!(
+            subsystem_hosts("vol-1"),
+            Some(vec![HOST1.to_string(), HOST2.to_string()])
+        );
+
+        lvol.set_allowed_hosts(vec![HOST2.into()]).await.unwrap();
+        assert_eq!(subsystem_hosts("vol-1"), Some(vec![HOST2.to_string()]));
+
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    // the allowed hosts are stored on disk and reapplied on import
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(CreatePoolRequest {
+            name: "tpool".into(),
+            disks: vec![format!("aio://{}", DISKNAME1)],
+            overcommit: None,
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+
+        let lvol = pool.lvols().unwrap().next().unwrap();
+        assert_eq!(lvol.allowed_hosts().unwrap(), vec![HOST2.to_string()]);
+        assert_eq!(subsystem_hosts("vol-1"), Some(vec![HOST2.to_string()]));
+
+        // removing all hosts is rejected, allowing any host has to be
+        // asked for explicitly
+        lvol.set_allowed_hosts(vec![]).await.unwrap_err();
+        assert_eq!(subsystem_hosts("vol-1"), Some(vec![HOST2.to_string()]));
+        lvol.allow_any_host().await.unwrap();
+        assert_eq!(subsystem_hosts("vol-1"), None);
+        assert!(lvol.allowed_hosts().unwrap().is_empty());
+
+        // a nexus restricts the replicas of this node it connects to
+        let lvol = pool
+            .create_lvol("vol-2", 8 * 1024 * 1024, None, false)
+            .await
+            .unwrap();
+        lvol.share_nvmf(None).await.unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), None);
+
+        let uri = lvol.share_uri().unwrap();
+        nexus_create(
+            "nexus1",
+            4 * 1024 * 1024,
+            None,
+            &[uri.clone(), "malloc:///malloc1?size_mb=8".into()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), Some(vec![host_nqn()]));
+        assert_eq!(lvol.allowed_hosts().unwrap(), vec![host_nqn()]);
+
+        // and lifts the restriction when the child is removed
+        let nexus = nexus_lookup("nexus1").unwrap();
+        nexus.remove_child(&uri).await.unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), None);
+        assert!(lvol.allowed_hosts().unwrap().is_empty());
+
+        // or the nexus is destroyed
+        nexus.add_child(&uri, true).await.unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), Some(vec![host_nqn()]));
+        nexus.destroy().await.unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), None);
+
+        // hosts allowed explicitly before are kept
+        lvol.set_allowed_hosts(vec![HOST1.into()]).await.unwrap();
+        nexus_create("nexus1", 4 * 1024 * 1024, None, &[uri.clone()])
+            .await
+            .unwrap();
+        let mut hosts = vec![host_nqn(), HOST1.to_string()];
+        hosts.sort();
+        assert_eq!(subsystem_hosts("vol-2"), Some(hosts));
+        nexus_lookup("nexus1").unwrap().destroy().await.unwrap();
+        assert_eq!(subsystem_hosts("vol-2"), Some(vec![HOST1.to_string()]));
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    // the same for a nexus
+    ms.spawn(async {
+        nexus_create(
+            "nexus0",
+            8 * 1024 * 1024,
+            None,
+            &["malloc:///malloc0?size_mb=16".into()],
+        )
+        .await
+        .unwrap();
+
+        let nexus = nexus_lookup("nexus0").unwrap();
+        nexus.set_allowed_hosts(vec![HOST1.into()]).unwrap();
+        nexus
+            .share(ShareProtocolNexus::NexusNvmf, None)
+            .await
+            .unwrap();
+
+        assert_eq!(nexus.allowed_hosts(), vec![HOST1.to_string()]);
+        assert_eq!(subsystem_hosts("nexus0"), Some(vec![HOST1.to_string()]));
+
+        nexus.set_allowed_hosts(vec![]).unwrap_err();
+        assert_eq!(subsystem_hosts("nexus0"), Some(vec![HOST1.to_string()]));
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME1.into()]);
+}
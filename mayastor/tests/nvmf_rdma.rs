@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use common::{bdev_io, MayastorTest};
+use mayastor::{
+    bdev::{device_create, nexus_create, nexus_lookup},
+    core::{Bdev, MayastorCliArgs, Share},
+    subsys::{Config, NvmfRdmaTransportOpts, NvmfSubsystem},
+};
+
+pub mod common;
+
+static NEXUS_NAME: &str = "rdma_nexus";
+static BDEV: &str = "malloc:///malloc0?size_mb=64";
+
+/// true when there is a soft-RoCE (rxe) device, which has to be set up on
+/// the interface of the target address beforehand, for instance with
+/// `rdma link add rxe0 type rxe netdev eth0`
+fn have_rxe_device() -> bool {
+    std::fs::read_dir(Path::new("/sys/class/infiniband"))
+        .map(|devices| {
+            devices
+                .filter_map(Result::ok)
+                .any(|d| d.file_name().to_string_lossy().starts_with("rxe"))
+        })
+        .unwrap_or(false)
+}
+
+/// The target listens on RDMA in addition to TCP when it is enabled, and a
+/// nexus can connect to its children over RDMA.
+#[tokio::test]
+async fn nvmf_rdma() {
+    if !have_rxe_device() {
+        println!("skipping, no soft-RoCE (rxe) device found");
+        return;
+    }
+
+    Config::get_or_init(|| {
+        let mut nvmf_rdma_tgt_conf = NvmfRdmaTransportOpts::default();
+        nvmf_rdma_tgt_conf.enable = true;
+        Config {
+            nvmf_rdma_tgt_conf,
+            ..Default::default()
+        }
+    });
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let name = device_create(BDEV).await.unwrap();
+        let bdev = Bdev::lookup_by_name(&name).unwrap();
+        bdev.share_nvmf(None).await.unwrap();
+
+        // the subsystem listens on both transports
+        let endpoints = NvmfSubsystem::nqn_lookup(&name)
+            .unwrap()
+            .uri_endpoints()
+            .unwrap();
+        assert!(endpoints.iter().any(|uri| uri.starts_with("nvmf://")));
+        let rdma = endpoints
+            .into_iter()
+            .find(|uri| uri.starts_with("nvmf+rdma://"))
+            .unwrap();
+
+        nexus_create(NEXUS_NAME, 32 * 1024 * 1024, None, &[rdma.clone()])
+            .await
+            .unwrap();
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert!(nexus.get_child_by_name(&rdma).is_ok());
+
+        bdev_io::write_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+        bdev_io::read_some(NEXUS_NAME, 0, 0xaa).await.unwrap();
+
+        nexus.destroy().await.unwrap();
+        bdev.unshare().await.unwrap();
+    })
+    .await;
+}
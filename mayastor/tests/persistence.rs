@@ -48,7 +48,10 @@ async fn persist_unexpected_restart() {
     // Retrieve the nexus info from the store.
 
     let mut etcd = Client::connect([ETCD_ENDPOINT], None).await.unwrap();
-    let response = etcd.get(nexus_uuid, None).await.expect("No entry found");
+    let response = etcd
+        .get(NexusInfo::key(nexus_uuid), None)
+        .await
+        .expect("No entry found");
     let value = response.kvs().first().unwrap().value();
     let nexus_info: NexusInfo = serde_json::from_slice(value).unwrap();
 
@@ -67,7 +70,10 @@ async fn persist_unexpected_restart() {
         .await
         .expect("Failed to restart container.");
 
-    let response = etcd.get(nexus_uuid, None).await.expect("No entry found");
+    let response = etcd
+        .get(NexusInfo::key(nexus_uuid), None)
+        .await
+        .expect("No entry found");
     let value = response.kvs().first().unwrap().value();
     let nexus_info: NexusInfo = serde_json::from_slice(value).unwrap();
 
@@ -102,7 +108,10 @@ async fn persist_clean_shutdown() {
     // Retrieve the nexus info from the store.
 
     let mut etcd = Client::connect([ETCD_ENDPOINT], None).await.unwrap();
-    let response = etcd.get(nexus_uuid, None).await.expect("No entry found");
+    let response = etcd
+        .get(NexusInfo::key(nexus_uuid), None)
+        .await
+        .expect("No entry found");
     let value = response.kvs().first().unwrap().value();
     let nexus_info: NexusInfo = serde_json::from_slice(value).unwrap();
 
@@ -124,7 +133,10 @@ async fn persist_clean_shutdown() {
         .await
         .expect("Failed to destroy nexus");
 
-    let response = etcd.get(nexus_uuid, None).await.expect("No entry found");
+    let response = etcd
+        .get(NexusInfo::key(nexus_uuid), None)
+        .await
+        .expect("No entry found");
     let value = response.kvs().first().unwrap().value();
     let nexus_info: NexusInfo = serde_json::from_slice(value).unwrap();
 
@@ -202,7 +214,10 @@ async fn persist_io_failure() {
     // Use etcd-client to check the persisted entry.
 
     let mut etcd = Client::connect([ETCD_ENDPOINT], None).await.unwrap();
-    let response = etcd.get(nexus_uuid, None).await.expect("No entry found");
+    let response = etcd
+        .get(NexusInfo::key(nexus_uuid), None)
+        .await
+        .expect("No entry found");
     let value = response.kvs().first().unwrap().value();
     let nexus_info: NexusInfo = serde_json::from_slice(value).unwrap();
     assert!(!nexus_info.clean_shutdown);
@@ -316,6 +331,7 @@ async fn publish_nexus(hdl: &mut RpcHandle, uuid: &str) -> String {
             uuid: uuid.to_string(),
             key: "".to_string(),
             share: ShareProtocolNexus::NexusNvmf as i32,
+            allowed_hosts: vec![],
         })
         .await
         .expect("Failed to publish nexus")
@@ -0,0 +1,250 @@
+use common::MayastorTest;
+use futures::StreamExt;
+use mayastor::{
+    bdev::{nexus_create, nexus_lookup, NexusInfo},
+    core::MayastorCliArgs,
+    persistent_store::PersistentStore,
+    store::{
+        file::FileStore,
+        memory::MemoryStore,
+        store_defs::{LeaseId, Store, WatchEvent},
+    },
+};
+use serde_json::json;
+use std::time::Duration;
+
+pub mod common;
+
+static STORE_FILE: &str = "/tmp/persistence_local.json";
+static NEXUS_NAME: &str = "persist_local_nexus";
+static NEXUS_UUID: &str = "3a4bf0d3-1f0c-4a4e-9a7b-e6f1b4ea5a28";
+static CHILD0: &str =
+    "malloc:///persist0?size_mb=64&uuid=d61b2fdf-1be8-457a-a481-70a42d0a2223";
+static CHILD1: &str =
+    "malloc:///persist1?size_mb=64&uuid=094ae8c6-46aa-4139-b4f2-550d39645db3";
+static FENCED_NAME: &str = "persist_fenced_nexus";
+static FENCED_UUID: &str = "5c1f5e4e-9a5b-4d0e-8f0a-3d5f8d3c7b21";
+static FENCED_CHILD: &str =
+    "malloc:///persist2?size_mb=64&uuid=7f0f5d1e-62a4-4a7c-9c3d-2b8e6f1a4c55";
+
+/// The entries of a file backed store survive reopening the store.
+#[tokio::test]
+async fn persist_file_store() {
+    common::delete_file(&[STORE_FILE.into()]);
+
+    let mut store = FileStore::new(STORE_FILE).unwrap();
+    store.put_kv(&"key1", &"value1").await.unwrap();
+    store.put_kv(&"key2", &vec![1, 2, 3]).await.unwrap();
+    store.delete_kv(&"key1").await.unwrap();
+
+    let mut store = FileStore::new(STORE_FILE).unwrap();
+    assert!(store.get_kv(&"key1").await.is_err());
+    assert_eq!(store.get_kv(&"key2").await.unwrap(), json!([1, 2, 3]));
+
+    // entries put under a lease do not outlive the process
+    let lease = store.grant_lease(Duration::from_secs(60)).await.unwrap();
+    assert!(store
+        .compare_and_swap(&"key3", None, &"value3", Some(lease))
+        .await
+        .unwrap());
+    let mut store = FileStore::new(STORE_FILE).unwrap();
+    assert!(store.get_kv(&"key3").await.is_err());
+    assert!(store.get_kv(&"key2").await.is_ok());
+
+    common::delete_file(&[STORE_FILE.into()]);
+}
+
+/// Prefix listing, watches, compare and swap and leases of a local store.
+#[tokio::test]
+async fn persist_store_operations() {
+    let mut store = MemoryStore::new();
+    let mut watch = store.watch_prefix("a/").await.unwrap();
+
+    store.put_kv(&"a/1", &1).await.unwrap();
+    store.put_kv(&"a/2", &2).await.unwrap();
+    store.put_kv(&"b/1", &3).await.unwrap();
+    assert_eq!(
+        store.get_values_prefix("a/").await.unwrap(),
+        vec![("a/1".to_string(), json!(1)), ("a/2".to_string(), json!(2))]
+    );
+
+    // the value must match for the swap to take place
+    assert!(!store
+        .compare_and_swap(&"a/1", Some(&json!(2)), &4, None)
+        .await
+        .unwrap());
+    assert!(!store
+        .compare_and_swap(&"a/1", None, &4, None)
+        .await
+        .unwrap());
+    assert!(store
+        .compare_and_swap(&"a/1", Some(&json!(1)), &4, None)
+        .await
+        .unwrap());
+    assert_eq!(store.get_kv(&"a/1").await.unwrap(), json!(4));
+
+    // an entry put under a lease goes away with the lease
+    let lease = store.grant_lease(Duration::from_millis(100)).await.unwrap();
+    assert!(store
+        .compare_and_swap(&"a/3", None, &5, Some(lease))
+        .await
+        .unwrap());
+    store.keep_alive_lease(lease).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(store.get_kv(&"a/3").await.is_err());
+    assert!(store.keep_alive_lease(lease).await.is_err());
+
+    store.delete_kv(&"a/2").await.unwrap();
+
+    // only changes to the watched prefix are reported, in order
+    let events = vec![
+        WatchEvent::Put("a/1".into(), json!(1)),
+        WatchEvent::Put("a/2".into(), json!(2)),
+        WatchEvent::Put("a/1".into(), json!(4)),
+        WatchEvent::Put("a/3".into(), json!(5)),
+        WatchEvent::Delete("a/3".into()),
+        WatchEvent::Delete("a/2".into()),
+    ];
+    for event in events {
+        assert_eq!(watch.next().await.unwrap(), event);
+    }
+}
+
+/// Try to claim the nexus and to put its information as the given node,
+/// returns whether the information was put.
+async fn claim_and_put(
+    mut store: MemoryStore,
+    node: &str,
+    lease: LeaseId,
+) -> bool {
+    let owner = format!("owner/{}", NEXUS_UUID);
+    let node = json!(node);
+    // losing the claim is not an error, the put below fails instead
+    store
+        .compare_and_swap(&owner, None, &node, Some(lease))
+        .await
+        .unwrap();
+    store
+        .put_owned_kv(&NEXUS_UUID, &node, &owner, &node, lease)
+        .await
+        .unwrap()
+}
+
+/// Two nodes racing for the same nexus: only the node which owns it can put
+/// its information, also once the ownership has moved to the other node.
+#[tokio::test]
+async fn persist_owner_race() {
+    let mut store = MemoryStore::new();
+    let owner = format!("owner/{}", NEXUS_UUID);
+    let lease_a = store.grant_lease(Duration::from_secs(60)).await.unwrap();
+    let lease_b = store.grant_lease(Duration::from_secs(60)).await.unwrap();
+
+    let (a, b) = tokio::join!(
+        claim_and_put(store.clone(), "node-a", lease_a),
+        claim_and_put(store.clone(), "node-b", lease_b),
+    );
+    assert!(a ^ b, "exactly one node must win the race");
+    let ((winner, winner_lease), (loser, loser_lease)) = if a {
+        (("node-a", lease_a), ("node-b", lease_b))
+    } else {
+        (("node-b", lease_b), ("node-a", lease_a))
+    };
+    assert_eq!(store.get_kv(&NEXUS_UUID).await.unwrap(), json!(winner));
+    assert_eq!(store.get_kv(&owner).await.unwrap(), json!(winner));
+
+    // the owner key alone is not enough, it must be held under the lease
+    assert!(!store
+        .put_owned_kv(&NEXUS_UUID, &1, &owner, &json!(winner), loser_lease)
+        .await
+        .unwrap());
+
+    // once the lease of the winner is gone the other node takes over and
+    // the stale owner can no longer overwrite the information
+    store.revoke_lease(winner_lease).await.unwrap();
+    assert!(claim_and_put(store.clone(), loser, loser_lease).await);
+    assert!(!claim_and_put(store.clone(), winner, winner_lease).await);
+    assert_eq!(store.get_kv(&NEXUS_UUID).await.unwrap(), json!(loser));
+}
+
+/// Nexus information is persisted without an etcd cluster when the store is
+/// kept in memory.
+#[tokio::test]
+async fn persist_memory_store() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    PersistentStore::init(Some("memory://".into())).await;
+    assert!(PersistentStore::enabled());
+
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            Some(NEXUS_UUID),
+            &[CHILD0.into(), CHILD1.into()],
+        )
+        .await
+        .unwrap();
+
+        let info: NexusInfo = serde_json::from_value(
+            PersistentStore::get(&NexusInfo::key(NEXUS_UUID))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(!info.clean_shutdown);
+        assert_eq!(info.children.len(), 2);
+        assert!(info.children.iter().all(|c| c.healthy));
+        assert_eq!(
+            PersistentStore::get(&format!(
+                "owner/{}",
+                NexusInfo::key(NEXUS_UUID)
+            ))
+            .await
+            .unwrap(),
+            json!("mayastor-node")
+        );
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+
+        let info: NexusInfo = serde_json::from_value(
+            PersistentStore::get(&NexusInfo::key(NEXUS_UUID))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(info.clean_shutdown);
+
+        // the nexus is only owned while it exists
+        let owner = format!("owner/{}", NexusInfo::key(NEXUS_UUID));
+        assert!(PersistentStore::get(&owner).await.is_err());
+    })
+    .await;
+
+    // a nexus owned by another node is not persisted by this one
+    ms.spawn(async {
+        let owner = format!("owner/{}", NexusInfo::key(FENCED_UUID));
+        assert!(
+            PersistentStore::compare_and_swap(&owner, None, &"other-node")
+                .await
+                .unwrap()
+        );
+
+        nexus_create(
+            FENCED_NAME,
+            32 * 1024 * 1024,
+            Some(FENCED_UUID),
+            &[FENCED_CHILD.into()],
+        )
+        .await
+        .unwrap();
+        assert!(PersistentStore::get(&NexusInfo::key(FENCED_UUID))
+            .await
+            .is_err());
+        assert_eq!(
+            PersistentStore::get(&owner).await.unwrap(),
+            json!("other-node")
+        );
+
+        nexus_lookup(FENCED_NAME).unwrap().destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,117 @@
+use std::convert::TryFrom;
+
+use common::MayastorTest;
+use mayastor::{
+    core::{Bdev, BdevHandle, MayastorCliArgs, Protocol, QosLimits, Share},
+    lvs::{Lvol, Lvs},
+};
+use rpc::mayastor::CreatePoolRequest;
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/replica_qos.img";
+static POOL_NAME: &str = "qos_pool";
+static REPLICA: &str = "qos_replica";
+static QOS_BDEV: &str = "qos_replica-qos";
+
+fn pool_request() -> CreatePoolRequest {
+    CreatePoolRequest {
+        name: POOL_NAME.into(),
+        disks: vec![format!("aio://{}", DISKNAME)],
+        overcommit: None,
+        replica_keys: vec![],
+    }
+}
+
+/// read from the replica the way a host connected over NVMe-OF does, the
+/// namespace is claimed by the target so it can only be opened read-only
+async fn read_replica(times: usize) {
+    let h = BdevHandle::open(QOS_BDEV, false, false).unwrap();
+    let mut buf = h.dma_malloc(4096).unwrap();
+    for _ in 0 .. times {
+        h.read_at(0, &mut buf).await.unwrap();
+    }
+}
+
+/// Replicas support all QoS limits, which apply to the IO submitted over
+/// NVMe-OF and are kept on disk.
+#[tokio::test]
+async fn replica_qos() {
+    common::delete_file(&[DISKNAME.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(pool_request()).await.unwrap();
+        let lvol = pool
+            .create_lvol(REPLICA, 8 * 1024 * 1024, None, false)
+            .await
+            .unwrap();
+        assert_eq!(lvol.qos_limits(), QosLimits::default());
+
+        let limits = QosLimits {
+            read_iops: 4,
+            burst_ms: 500,
+            ..Default::default()
+        };
+        lvol.set_qos_limits(limits).await.unwrap();
+        assert_eq!(lvol.qos_limits(), limits);
+
+        // the replica keeps its NQN with the QoS bdev as the namespace
+        lvol.share_nvmf(None).await.unwrap();
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+        assert!(lvol
+            .share_uri()
+            .unwrap()
+            .contains(&format!(":{}?uuid=", REPLICA)));
+
+        // the burst allows for two reads and the limit may be overdrawn by
+        // one, after which the reads have to wait for the bucket to refill
+        read_replica(3).await;
+        assert_eq!(lvol.qos_throttled(), 0);
+        read_replica(2).await;
+        assert!(lvol.qos_throttled() > 0);
+
+        // the IO submitted over NVMe-OF is tracked in latency histograms
+        let stats = lvol.stats().await.unwrap();
+        assert_eq!(stats.read_latency.count, 5);
+        assert!(stats.read_latency.percentile(0.99).is_some());
+
+        // the limits of a shared replica can be changed
+        lvol.set_qos_limits(QosLimits::default()).await.unwrap();
+        assert_eq!(lvol.qos_throttled(), 0);
+        read_replica(8).await;
+        assert_eq!(lvol.qos_throttled(), 0);
+
+        lvol.set_qos_limits(limits).await.unwrap();
+        pool.export().await.unwrap();
+    })
+    .await;
+
+    ms.spawn(async {
+        // the limits apply again once the replica is shared after an import
+        let pool = Lvs::create_or_import(pool_request()).await.unwrap();
+        let lvol =
+            Lvol::try_from(Bdev::lookup_by_name(REPLICA).unwrap()).unwrap();
+        assert_eq!(
+            lvol.qos_limits(),
+            QosLimits {
+                read_iops: 4,
+                burst_ms: 500,
+                ..Default::default()
+            }
+        );
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+        assert!(Bdev::lookup_by_name(QOS_BDEV).is_some());
+
+        lvol.unshare().await.unwrap();
+        assert_eq!(lvol.shared(), Some(Protocol::Off));
+        assert!(Bdev::lookup_by_name(QOS_BDEV).is_none());
+        assert_eq!(lvol.qos_limits().read_iops, 4);
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into()]);
+}
@@ -1,12 +1,20 @@
 use common::bdev_io;
 use mayastor::{
-    bdev::nexus_create,
+    bdev::{nexus_create, nexus_lookup},
     core::{BdevHandle, CoreError, MayastorCliArgs},
     lvs::{Lvol, Lvs},
 };
 use rpc::mayastor::{
+    AllowedHostRequest,
+    CreateCloneRequest,
     CreatePoolRequest,
     CreateReplicaRequest,
+    DestroyReplicaRequest,
+    DestroySnapshotRequest,
+    ListAllowedHostsRequest,
+    ListSnapshotsRequest,
+    Null,
+    RevertReplicaRequest,
     ShareProtocolReplica,
     ShareReplicaRequest,
 };
@@ -22,6 +30,9 @@ static POOL2_NAME: &str = "pool2";
 static DISKSIZE_KB: u64 = 96 * 1024;
 
 static UUID1: &str = "00000000-76b6-4fcf-864d-1027d4038756";
+static UUID2: &str = "11111111-76b6-4fcf-864d-1027d4038756";
+
+static HOSTNQN: &str = "nqn.2019-05.io.openebs:revert-host";
 
 static NXNAME: &str = "replica_snapshot_test";
 static NXNAME_SNAP: &str = "replica_snapshot_test-snap";
@@ -50,6 +61,8 @@ async fn replica_snapshot() {
         .create_pool(CreatePoolRequest {
             name: POOL2_NAME.to_string(),
             disks: vec!["malloc:///disk0?size_mb=96".into()],
+            overcommit: None,
+            replica_keys: vec![],
         })
         .await
         .unwrap();
@@ -75,6 +88,8 @@ async fn replica_snapshot() {
             Lvs::create_or_import(CreatePoolRequest {
                 name: POOL1_NAME.to_string(),
                 disks: vec![format!("aio://{}", DISKNAME1)],
+                overcommit: None,
+                replica_keys: vec![],
             })
             .await
             .unwrap();
@@ -87,7 +102,7 @@ async fn replica_snapshot() {
             // Issue an unimplemented vendor command
             // This checks that the target is correctly rejecting such commands
             // In practice the nexus will not send such commands
-            custom_nvme_admin(0xc1).await.expect_err(
+            custom_nvme_admin(0xc2).await.expect_err(
                 "unexpectedly succeeded invalid nvme admin command",
             );
             bdev_io::read_some(NXNAME, 0, 0xff).await.unwrap();
@@ -111,6 +126,7 @@ async fn replica_snapshot() {
         .share_replica(ShareReplicaRequest {
             uuid: format!("{}-snap-{}", UUID1, t),
             share: ShareProtocolReplica::ReplicaNvmf as i32,
+            allowed_hosts: vec![],
         })
         .await
         .unwrap();
@@ -129,9 +145,120 @@ async fn replica_snapshot() {
             bdev_io::read_some(NXNAME, 0, 0x55).await.unwrap();
             bdev_io::read_some(NXNAME_SNAP, 0, 0xff).await.unwrap();
             bdev_io::read_some(NXNAME_SNAP, 1024, 0).await.unwrap();
+            nexus_lookup(NXNAME_SNAP).unwrap().destroy().await.unwrap();
+            nexus_lookup(NXNAME).unwrap().destroy().await.unwrap();
         })
         .await;
 
+    let snap_name = Lvol::format_snapshot_name(UUID1, t);
+
+    // the snapshot is listed as part of the replica's snapshot chain
+    let snapshots = hdls[0]
+        .mayastor
+        .list_snapshots(ListSnapshotsRequest {
+            replica: UUID1.to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .snapshots;
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(snapshots[0].name, snap_name);
+    assert_eq!(snapshots[0].pool, POOL2_NAME);
+
+    // a clone is a regular (writable) replica
+    let clone = hdls[0]
+        .mayastor
+        .create_clone(CreateCloneRequest {
+            snapshot: snap_name.clone(),
+            uuid: UUID2.to_string(),
+            share: ShareProtocolReplica::ReplicaNone as i32,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(clone.uuid, UUID2);
+    assert!(hdls[0]
+        .mayastor
+        .list_replicas(Null {})
+        .await
+        .unwrap()
+        .into_inner()
+        .replicas
+        .iter()
+        .any(|r| r.uuid == UUID2));
+
+    // the snapshot can not be removed while it has multiple clones
+    hdls[0]
+        .mayastor
+        .destroy_snapshot(DestroySnapshotRequest {
+            name: snap_name.clone(),
+        })
+        .await
+        .expect_err("snapshot with two clones should not be destroyed");
+
+    hdls[0]
+        .mayastor
+        .destroy_replica(DestroyReplicaRequest {
+            uuid: UUID2.to_string(),
+        })
+        .await
+        .unwrap();
+
+    hdls[0]
+        .mayastor
+        .add_allowed_host(AllowedHostRequest {
+            uuid: UUID1.to_string(),
+            host_nqn: HOSTNQN.to_string(),
+        })
+        .await
+        .unwrap();
+
+    // reverting keeps the replica shared under the same name, with the same
+    // allowed hosts
+    let replica = hdls[0]
+        .mayastor
+        .revert_replica(RevertReplicaRequest {
+            uuid: UUID1.to_string(),
+            snapshot: snap_name.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(replica.uuid, UUID1);
+    assert_eq!(replica.share, ShareProtocolReplica::ReplicaNvmf as i32);
+    assert_eq!(
+        hdls[0]
+            .mayastor
+            .list_allowed_hosts(ListAllowedHostsRequest {
+                uuid: UUID1.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .host_nqns,
+        vec![HOSTNQN.to_string()]
+    );
+
+    hdls[0]
+        .mayastor
+        .destroy_snapshot(DestroySnapshotRequest {
+            name: snap_name,
+        })
+        .await
+        .unwrap();
+
+    assert!(hdls[0]
+        .mayastor
+        .list_snapshots(ListSnapshotsRequest {
+            replica: String::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .snapshots
+        .is_empty());
+
     common::delete_file(&[DISKNAME1.to_string()]);
 }
 
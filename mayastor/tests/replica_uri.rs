@@ -53,6 +53,8 @@ async fn replica_uri() {
                     "malloc:///disk0?size_mb={}",
                     DISKSIZE_KB / 1024
                 )],
+                overcommit: None,
+                replica_keys: vec![],
             })
             .await
             .unwrap();
@@ -97,6 +99,7 @@ async fn replica_uri() {
         .share_replica(ShareReplicaRequest {
             uuid: VOLUME_UUID.to_string(),
             share: ShareProtocolReplica::ReplicaNvmf as i32,
+            allowed_hosts: vec![],
         })
         .await;
     info!("Replica: {:?}", replica_uri);
@@ -108,6 +111,7 @@ async fn replica_uri() {
         .share_replica(ShareReplicaRequest {
             uuid: VOLUME_UUID.to_string(),
             share: ShareProtocolReplica::ReplicaNone as i32,
+            allowed_hosts: vec![],
         })
         .await;
     info!("Replica: {:?}", replica_uri);
@@ -0,0 +1,120 @@
+use common::MayastorTest;
+use mayastor::{
+    bdev::{nexus_lookup, ChildState, Reason},
+    core::{Bdev, MayastorCliArgs, Protocol, Share},
+    lvs::{Lvol, Lvs},
+    subsys::ResourceConfig,
+};
+use rpc::mayastor::CreatePoolRequest;
+use std::{convert::TryFrom, time::Duration};
+
+pub mod common;
+
+static DISKNAME: &str = "/tmp/resource-disk.img";
+static CONFIG: &str = "/tmp/resource-config.yaml";
+
+static REPLICA_UUID: &str = "3f6a4c2e-8d1b-4e7a-9c05-2b8d6e4f1a90";
+static NEXUS_UUID: &str = "7c2e9a14-5b3d-4f86-a0e1-9d4b2c6f8e37";
+static NEXUS_NAME: &str = "nexus-7c2e9a14-5b3d-4f86-a0e1-9d4b2c6f8e37";
+static CHILD_UUID: &str = "a41d7e2b-6c98-4f05-b3a2-1e8c5d9f0b64";
+static CHILD2_UUID: &str = "e85b1f3c-2a47-4d9e-8c61-5f0a3b7d2e19";
+
+fn resource_config() -> String {
+    format!(
+        r#"replicas:
+  - name: {replica}
+    uuid: {replica}
+    pool: rpool
+    size: 8388608
+    share: Nvmf
+nexuses:
+  - name: {name}
+    uuid: {nexus}
+    size: 8388608
+    children:
+      - uri: "malloc:///rchild?size_mb=16&uuid={child}"
+      - uri: "malloc:///rchild2?size_mb=16&uuid={child2}"
+        healthy: false
+    share: NexusNvmf
+"#,
+        replica = REPLICA_UUID,
+        name = NEXUS_NAME,
+        nexus = NEXUS_UUID,
+        child = CHILD_UUID,
+        child2 = CHILD2_UUID,
+    )
+}
+
+/// Replicas and nexuses declared in the resource configuration are created
+/// and shared, applying it again leaves them untouched and the configuration
+/// captured from them can be loaded back. Children which were not healthy
+/// are rebuilt.
+#[tokio::test]
+async fn resource_config_test() {
+    common::delete_file(&[DISKNAME.into(), CONFIG.into()]);
+    common::truncate_file(DISKNAME, 64 * 1024);
+    std::fs::write(CONFIG, resource_config()).unwrap();
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+
+    ms.spawn(async {
+        Lvs::create_or_import(CreatePoolRequest {
+            name: "rpool".into(),
+            disks: vec![format!("aio://{}", DISKNAME)],
+            overcommit: None,
+            replica_keys: vec![],
+        })
+        .await
+        .unwrap();
+
+        let config = ResourceConfig::load(CONFIG).unwrap();
+        assert_eq!(config.apply().await, 0);
+
+        let lvol = Lvol::try_from(Bdev::lookup_by_name(REPLICA_UUID).unwrap())
+            .unwrap();
+        assert_eq!(lvol.uuid(), REPLICA_UUID);
+        assert_eq!(lvol.shared(), Some(Protocol::Nvmf));
+
+        let nexus = nexus_lookup(NEXUS_NAME).unwrap();
+        assert_eq!(nexus.children.len(), 2);
+        assert_eq!(nexus.children[0].state(), ChildState::Open);
+        assert!(nexus.get_share_uri().unwrap().starts_with("nvmf://"));
+
+        // applying the configuration again is a no-op
+        assert_eq!(config.apply().await, 0);
+        let pool = Lvs::lookup("rpool").unwrap();
+        assert_eq!(pool.lvols().unwrap().count(), 1);
+        assert_eq!(nexus_lookup(NEXUS_NAME).unwrap().children.len(), 2);
+    })
+    .await;
+
+    // the child which was not healthy is rebuilt in the background
+    let mut state = ChildState::Faulted(Reason::OutOfSync);
+    for _ in 0 .. 20 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        state = ms
+            .spawn(async {
+                nexus_lookup(NEXUS_NAME).unwrap().children[1].state()
+            })
+            .await;
+        if state == ChildState::Open {
+            break;
+        }
+    }
+    assert_eq!(state, ChildState::Open);
+
+    ms.spawn(async {
+        // the exported configuration matches the resources
+        ResourceConfig::capture().export().await;
+        assert_eq!(
+            ResourceConfig::load(CONFIG).unwrap(),
+            ResourceConfig::capture()
+        );
+
+        nexus_lookup(NEXUS_NAME).unwrap().destroy().await.unwrap();
+        Lvs::lookup("rpool").unwrap().destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISKNAME.into(), CONFIG.into()]);
+}
@@ -70,6 +70,8 @@ pub enum MessageIdVs {
     CreatePool,
     /// Destroy Pool,
     DestroyPool,
+    /// Pool is running low on space
+    PoolLowSpace,
     /// Get replicas with filter
     GetReplicas,
     /// Create Replica,
@@ -407,6 +409,8 @@ pub enum PoolState {
     Degraded = 2,
     /// the pool is completely inaccessible
     Faulted = 3,
+    /// the pool is in working order but does not accept new replicas
+    Draining = 4,
 }
 
 impl Default for PoolState {
@@ -420,6 +424,7 @@ impl From<i32> for PoolState {
             1 => Self::Online,
             2 => Self::Degraded,
             3 => Self::Faulted,
+            4 => Self::Draining,
             _ => Self::Unknown,
         }
     }
@@ -443,31 +448,42 @@ pub struct Pool {
     pub used: u64,
 }
 
-// online > degraded > unknown/faulted
+// online > draining > degraded > unknown/faulted
 impl PartialOrd for PoolState {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match self {
             PoolState::Unknown => match other {
                 PoolState::Unknown => None,
                 PoolState::Online => Some(Ordering::Less),
+                PoolState::Draining => Some(Ordering::Less),
                 PoolState::Degraded => Some(Ordering::Less),
                 PoolState::Faulted => None,
             },
             PoolState::Online => match other {
                 PoolState::Unknown => Some(Ordering::Greater),
                 PoolState::Online => Some(Ordering::Equal),
+                PoolState::Draining => Some(Ordering::Greater),
+                PoolState::Degraded => Some(Ordering::Greater),
+                PoolState::Faulted => Some(Ordering::Greater),
+            },
+            PoolState::Draining => match other {
+                PoolState::Unknown => Some(Ordering::Greater),
+                PoolState::Online => Some(Ordering::Less),
+                PoolState::Draining => Some(Ordering::Equal),
                 PoolState::Degraded => Some(Ordering::Greater),
                 PoolState::Faulted => Some(Ordering::Greater),
             },
             PoolState::Degraded => match other {
                 PoolState::Unknown => Some(Ordering::Greater),
                 PoolState::Online => Some(Ordering::Less),
+                PoolState::Draining => Some(Ordering::Less),
                 PoolState::Degraded => Some(Ordering::Equal),
                 PoolState::Faulted => Some(Ordering::Greater),
             },
             PoolState::Faulted => match other {
                 PoolState::Unknown => None,
                 PoolState::Online => Some(Ordering::Less),
+                PoolState::Draining => Some(Ordering::Less),
                 PoolState::Degraded => Some(Ordering::Less),
                 PoolState::Faulted => Some(Ordering::Equal),
             },
@@ -502,6 +518,24 @@ bus_impl_message_all!(DestroyPool, DestroyPool, (), Pool);
 bus_impl_vector_request!(Pools, Pool);
 bus_impl_message_all!(GetPools, GetPools, Pools, Pool);
 
+/// Pool low space event, published by mayastor when the used capacity of a
+/// pool crosses the low space threshold
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolLowSpace {
+    /// id of the mayastor instance
+    pub node: NodeId,
+    /// id of the pool
+    pub id: PoolId,
+    /// size of the pool in bytes
+    pub capacity: u64,
+    /// used bytes from the pool
+    pub used: u64,
+    /// sum of the sizes of all replicas on the pool in bytes
+    pub committed: u64,
+}
+bus_impl_message_all!(PoolLowSpace, PoolLowSpace, (), Pool);
+
 /// Get all the replicas from specific node and pool
 /// or None for all nodes or all pools
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
@@ -7,6 +7,7 @@
 , libaio
 , libbpf
 , libelf
+, libiscsi
 , liburing
 , libuuid
 , libpcap
@@ -65,6 +66,7 @@ let
       libbsd
       libelf
       libexecinfo
+      libiscsi
       libpcap
       libtool
       liburing
@@ -92,6 +94,7 @@ let
     [
       "--without-isal"
       "--with-uring"
+      "--with-iscsi-initiator"
       "--disable-unit-tests"
       "--disable-tests"
     ];
@@ -119,7 +122,7 @@ let
       find . -type f -name 'libspdk_bdev_zone_block.a' -delete
 
       $CC -shared -o libspdk.so \
-        -lc -laio -lnuma -ldl -lrt -luuid -lpthread -lcrypto -luring \
+        -lc -laio -lnuma -ldl -lrt -luuid -lpthread -lcrypto -luring -liscsi \
         -Wl,--whole-archive \
         $(find build/lib -type f -name 'libspdk_*.a*' -o -name 'librte_*.a*') \
         $(find dpdk/build/lib -type f -name 'librte_*.a*') \
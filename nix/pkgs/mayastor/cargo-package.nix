@@ -4,6 +4,7 @@
 , e2fsprogs
 , lib
 , libaio
+, libiscsi
 , libspdk
 , libspdk-dev
 , libudev
@@ -65,6 +66,7 @@ let
       llvmPackages_11.libclang
       protobuf
       libaio
+      libiscsi
       libudev
       liburing
       numactl
@@ -36,5 +36,18 @@ pub mod mayastor {
         }
     }
 
+    impl FromStr for NexusReadPolicy {
+        type Err = Error;
+        fn from_str(policy: &str) -> Result<Self, Self::Err> {
+            match policy {
+                "round_robin" => Ok(Self::NexusReadRoundRobin),
+                "prefer_local" => Ok(Self::NexusReadPreferLocal),
+                "least_outstanding" => Ok(Self::NexusReadLeastOutstanding),
+                "lowest_latency" => Ok(Self::NexusReadLowestLatency),
+                _ => Err(Error::ParseError),
+            }
+        }
+    }
+
     include!(concat!(env!("OUT_DIR"), "/mayastor.rs"));
 }
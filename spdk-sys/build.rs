@@ -36,6 +36,11 @@ fn build_wrapper() {
         .include(".")
         .file("nvme_helper.c")
         .compile("nvme_helper");
+    cc::Build::new()
+        .include("spdk/include")
+        .include(".")
+        .file("lvol_helper.c")
+        .compile("lvol_helper");
 }
 
 fn main() {
@@ -70,6 +75,7 @@ fn main() {
         .rustfmt_bindings(true)
         .allowlist_function("*.aio.*")
         .allowlist_function("*.crypto_disk.*")
+        .allowlist_function("*.delay_disk.*")
         .allowlist_function("*.iscsi.*")
         .allowlist_function("*.lock_lba_range")
         .allowlist_function("*.lvol.*")
@@ -130,9 +136,11 @@ fn main() {
     println!("cargo:rustc-link-lib=numa");
     println!("cargo:rustc-link-lib=crypto");
     println!("cargo:rustc-link-lib=uring");
+    println!("cargo:rustc-link-lib=iscsi");
 
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=wrapper.h");
     println!("cargo:rerun-if-changed=logwrapper.c");
     println!("cargo:rerun-if-changed=nvme_helper.c");
+    println!("cargo:rerun-if-changed=lvol_helper.c");
 }
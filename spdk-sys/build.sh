@@ -14,6 +14,7 @@ rm libspdk.so
 	--without-isal \
 	--with-crypto \
 	--with-uring \
+	--with-iscsi-initiator \
 	--disable-unit-tests \
 	--disable-tests \
 	--with-fio=$(which fio | sed s';bin/fio;include;')
@@ -38,6 +39,7 @@ find . -type f -name 'libspdk_bdev_zone_block.a' -delete
 $CC -shared -o libspdk.so \
 	-lc  -laio -lnuma -ldl -lrt -luuid -lpthread -lcrypto \
 	-luring \
+	-liscsi \
 	-Wl,--whole-archive \
 	$(find build/lib -type f -name 'libspdk_*.a*' -o -name 'librte_*.a*') \
 	$(find dpdk/build/lib -type f -name 'librte_*.a*') \
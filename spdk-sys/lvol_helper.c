@@ -0,0 +1,76 @@
+#include "lvol_helper.h"
+
+#include <spdk/stdinc.h>
+#include <spdk/lib/blob/blobstore.h>
+#include <spdk_internal/lvolstore.h>
+
+/*
+ * Returns the number of clusters allocated to the lvol itself, clusters which
+ * are still shared with a parent snapshot are not included.
+ */
+uint64_t
+lvol_get_num_allocated_clusters(struct spdk_lvol *lvol)
+{
+	struct spdk_blob *blob = lvol->blob;
+	uint64_t i, allocated = 0;
+
+	for (i = 0; i < blob->active.num_clusters; i++) {
+		if (blob->active.clusters[i] != 0) {
+			allocated++;
+		}
+	}
+
+	return allocated;
+}
+
+struct snapshot_xattr_ctx {
+	struct spdk_blob *blob;
+	spdk_blob_op_complete cb_fn;
+	void *cb_arg;
+};
+
+static void
+snapshot_xattr_sync_cb(void *cb_arg, int bserrno)
+{
+	struct snapshot_xattr_ctx *ctx = cb_arg;
+
+	ctx->blob->md_ro = true;
+	ctx->cb_fn(ctx->cb_arg, bserrno);
+	free(ctx);
+}
+
+/*
+ * Sets an xattr on the blob of a snapshot and syncs its metadata. The
+ * metadata of a snapshot is read-only, so it is made writable for as long as
+ * it takes to update it, the same way the blobstore updates the internal
+ * xattrs of a snapshot.
+ */
+void
+lvol_set_snapshot_xattr(struct spdk_lvol *lvol, const char *name,
+			const void *value, uint16_t value_len,
+			spdk_blob_op_complete cb_fn, void *cb_arg)
+{
+	struct spdk_blob *blob = lvol->blob;
+	struct snapshot_xattr_ctx *ctx;
+	int rc;
+
+	ctx = calloc(1, sizeof(*ctx));
+	if (ctx == NULL) {
+		cb_fn(cb_arg, -ENOMEM);
+		return;
+	}
+	ctx->blob = blob;
+	ctx->cb_fn = cb_fn;
+	ctx->cb_arg = cb_arg;
+
+	blob->md_ro = false;
+	rc = spdk_blob_set_xattr(blob, name, value, value_len);
+	if (rc != 0) {
+		blob->md_ro = true;
+		free(ctx);
+		cb_fn(cb_arg, rc);
+		return;
+	}
+
+	spdk_blob_sync_md(blob, snapshot_xattr_sync_cb, ctx);
+}
@@ -0,0 +1,11 @@
+#include <stdint.h>
+
+#include <spdk/blob.h>
+
+struct spdk_lvol;
+
+uint64_t lvol_get_num_allocated_clusters(struct spdk_lvol *lvol);
+
+void lvol_set_snapshot_xattr(struct spdk_lvol *lvol, const char *name,
+			     const void *value, uint16_t value_len,
+			     spdk_blob_op_complete cb_fn, void *cb_arg);
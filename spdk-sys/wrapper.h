@@ -1,6 +1,8 @@
 #include <bdev/aio/bdev_aio.h>
 #include <bdev/crypto/vbdev_crypto.h>
+#include <bdev/delay/vbdev_delay.h>
 #include <bdev/error/vbdev_error.h>
+#include <bdev/iscsi/bdev_iscsi.h>
 #include <bdev/lvol/vbdev_lvol.h>
 #include <bdev/nvme/bdev_nvme.h>
 #include <bdev/malloc/bdev_malloc.h>
@@ -36,4 +38,5 @@
 #include <spdk_internal/lvolstore.h>
 
 #include "logwrapper.h"
+#include "lvol_helper.h"
 #include "nvme_helper.h"